@@ -0,0 +1,110 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+use tracing::error;
+use uuid::Uuid;
+
+use crate::logging;
+use crate::tasks::FailureReason;
+
+/// Report of a group whose tasks fail unusually often
+#[derive(Clone, Debug, PartialEq)]
+pub struct FailureAlert {
+    pub group_id: Vec<u8>,
+    /// Number of failures within the window
+    pub failures: u32,
+    pub window: Duration,
+    /// The failure which triggered the alert
+    pub task_id: Uuid,
+    pub reason: FailureReason,
+    pub message: String,
+}
+
+/// Receiver of operator alerts
+pub trait AlertSink: Send + Sync {
+    fn group_failures(&self, alert: &FailureAlert);
+}
+
+/// Sink which discards every alert
+pub struct NoopSink;
+
+impl AlertSink for NoopSink {
+    fn group_failures(&self, _: &FailureAlert) {}
+}
+
+/// Sink which emits every alert as an error event of the server log
+pub struct LogSink;
+
+impl AlertSink for LogSink {
+    fn group_failures(&self, alert: &FailureAlert) {
+        error!(
+            group_id = %logging::log_id(&alert.group_id),
+            failures = alert.failures,
+            window_seconds = alert.window.as_secs(),
+            task_id = %logging::log_id(alert.task_id.as_bytes()),
+            reason = ?alert.reason,
+            message = alert.message,
+            "Group tasks failing repeatedly"
+        );
+    }
+}
+
+/// Sliding-window counter of task failures per group
+pub struct FailureTracker {
+    threshold: u32,
+    window: Duration,
+    failures: HashMap<Vec<u8>, VecDeque<u64>>,
+}
+
+impl FailureTracker {
+    pub fn new(threshold: u32, window: Duration) -> Self {
+        assert!(threshold > 0);
+        FailureTracker {
+            threshold,
+            window,
+            failures: HashMap::new(),
+        }
+    }
+
+    /// Count a failure of a task of `group_id` at `timestamp`
+    ///
+    /// # Returns
+    /// The number of failures within the window once it reaches the threshold;
+    /// the count then starts again, so that a burst of failures raises a single alert.
+    pub fn record(&mut self, group_id: &[u8], timestamp: u64) -> Option<u32> {
+        let failures = self.failures.entry(group_id.to_vec()).or_default();
+        while failures
+            .front()
+            .is_some_and(|failed| failed + self.window.as_secs() <= timestamp)
+        {
+            failures.pop_front();
+        }
+        failures.push_back(timestamp);
+        if failures.len() < self.threshold as usize {
+            return None;
+        }
+        let count = failures.len() as u32;
+        failures.clear();
+        Some(count)
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failures_outside_window_expire() {
+        let mut tracker = FailureTracker::new(3, Duration::from_secs(60));
+        assert_eq!(tracker.record(&[0xaa], 0), None);
+        assert_eq!(tracker.record(&[0xaa], 30), None);
+        assert_eq!(tracker.record(&[0xbb], 40), None);
+        assert_eq!(tracker.record(&[0xaa], 70), None);
+        assert_eq!(tracker.record(&[0xaa], 80), Some(3));
+        assert_eq!(tracker.record(&[0xaa], 81), None);
+    }
+}
@@ -0,0 +1,168 @@
+use openssl::ec::EcKey;
+use openssl::ecdsa::EcdsaSig;
+use openssl::pkey::Private;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::proto::AuditEvent;
+use crate::tasks::{Task, TaskResult};
+
+/// Record of a task event: who requested what, who took part, and when
+#[derive(Clone, Debug, Serialize)]
+pub struct AuditRecord {
+    pub event: String,
+    pub task_id: String,
+    pub task_type: String,
+    /// Group performing the task, or the group established by a finished group task
+    pub group_id: Option<String>,
+    pub participants: Vec<String>,
+    pub requester: Option<String>,
+    pub timestamp: u64,
+    /// Coordinator signature of the record digest; not part of the signed content
+    #[serde(skip)]
+    pub signature: Option<Vec<u8>>,
+}
+
+impl AuditRecord {
+    pub fn new(task_id: &Uuid, task: &dyn Task, event: AuditEvent, timestamp: u64) -> Self {
+        let group_id = task
+            .get_group_id()
+            .map(hex::encode)
+            .or_else(|| match task.get_result() {
+                Some(TaskResult::GroupEstablished(group)) => Some(hex::encode(group.identifier())),
+                _ => None,
+            });
+        let mut participants: Vec<_> = task
+            .get_devices()
+            .iter()
+            .map(|device| hex::encode(device.identifier()))
+            .collect();
+        participants.sort();
+        participants.dedup();
+        AuditRecord {
+            event: event.as_str_name().to_owned(),
+            task_id: hex::encode(task_id.as_bytes()),
+            task_type: task.get_type().as_str_name().to_owned(),
+            group_id,
+            participants,
+            requester: task.requester().map(String::from),
+            timestamp,
+            signature: None,
+        }
+    }
+
+    /// SHA-256 digest of the record content
+    pub fn digest(&self) -> [u8; 32] {
+        let content = serde_json::to_vec(self).expect("audit records are serializable");
+        Sha256::digest(content).into()
+    }
+}
+
+/// Device which committed to a signing protocol but did not submit its round message
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommitmentViolation {
+    pub task_id: Uuid,
+    pub device_id: Vec<u8>,
+    pub round: u16,
+    pub timestamp: u64,
+}
+
+/// Device which repeated a Musig2 nonce commitment of an earlier signing session of the group
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NonceReuse {
+    pub task_id: Uuid,
+    pub group_id: Vec<u8>,
+    pub device_id: Vec<u8>,
+    pub timestamp: u64,
+}
+
+/// Holder of the coordinator key attesting audit records
+///
+/// Implementations may keep the key in a file or delegate to an HSM.
+pub trait RecordSigner: Send + Sync {
+    /// Produce a DER-encoded ECDSA signature of `digest`
+    fn sign_digest(&self, digest: &[u8; 32]) -> Result<Vec<u8>, String>;
+}
+
+/// Coordinator key loaded from a PEM file
+pub struct KeyFileSigner {
+    key: EcKey<Private>,
+}
+
+impl KeyFileSigner {
+    pub fn new(key: EcKey<Private>) -> Self {
+        KeyFileSigner { key }
+    }
+
+    pub fn from_pem(pem: &[u8]) -> Result<Self, String> {
+        EcKey::private_key_from_pem(pem)
+            .map(KeyFileSigner::new)
+            .map_err(|_| "Coordinator key must be a PEM-encoded EC private key".to_string())
+    }
+}
+
+impl RecordSigner for KeyFileSigner {
+    fn sign_digest(&self, digest: &[u8; 32]) -> Result<Vec<u8>, String> {
+        EcdsaSig::sign(digest, &self.key)
+            .and_then(|signature| signature.to_der())
+            .map_err(|err| format!("Could not sign audit record: {}", err))
+    }
+}
+
+/// Attach the coordinator signature of `record` made by `signer`
+pub fn sign_record(record: &mut AuditRecord, signer: &dyn RecordSigner) -> Result<(), String> {
+    record.signature = Some(signer.sign_digest(&record.digest())?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::ec::{EcGroup, EcKeyRef};
+    use openssl::nid::Nid;
+    use openssl::pkey::HasPublic;
+
+    /// Check that `record` carries a valid signature by the coordinator `public_key`,
+    /// as an auditor holding the coordinator public key would
+    fn verify_record<T: HasPublic>(record: &AuditRecord, public_key: &EcKeyRef<T>) -> bool {
+        record
+            .signature
+            .as_ref()
+            .and_then(|signature| EcdsaSig::from_der(signature).ok())
+            .and_then(|signature| signature.verify(&record.digest(), public_key).ok())
+            .unwrap_or(false)
+    }
+
+    fn record() -> AuditRecord {
+        AuditRecord {
+            event: "CREATED".into(),
+            task_id: "00".repeat(16),
+            task_type: "SIGN_CHALLENGE".into(),
+            group_id: Some("aa".repeat(4)),
+            participants: vec!["01".into(), "02".into()],
+            requester: Some("admin".into()),
+            timestamp: 1_700_000_000,
+            signature: None,
+        }
+    }
+
+    #[test]
+    fn coordinator_signature_verifies() {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let key = EcKey::generate(&group).unwrap();
+        let public_key = EcKey::from_public_key(&group, key.public_key()).unwrap();
+        let signer = KeyFileSigner::from_pem(&key.private_key_to_pem().unwrap()).unwrap();
+
+        let mut record = record();
+        assert!(!verify_record(&record, &public_key));
+        sign_record(&mut record, &signer).unwrap();
+        assert!(verify_record(&record, &public_key));
+
+        let other = EcKey::generate(&group).unwrap();
+        assert!(!verify_record(&record, &other));
+
+        record.requester = Some("mallory".into());
+        assert!(!verify_record(&record, &public_key));
+    }
+}
@@ -0,0 +1,74 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+/// Counts consecutive protocol failures of devices
+///
+/// A device whose failures within the window reach the threshold trips the breaker
+/// and is left out of signing until it completes a protocol round again.
+pub struct CircuitBreaker {
+    threshold: u32,
+    window: Duration,
+    failures: HashMap<Vec<u8>, VecDeque<u64>>,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, window: Duration) -> Self {
+        assert!(threshold > 0);
+        CircuitBreaker {
+            threshold,
+            window,
+            failures: HashMap::new(),
+        }
+    }
+
+    /// Count a protocol failure of `device_id` at `timestamp`
+    ///
+    /// # Returns
+    /// `true` if the failures of the device within the window reached the threshold
+    pub fn record_failure(&mut self, device_id: &[u8], timestamp: u64) -> bool {
+        let failures = self.failures.entry(device_id.to_vec()).or_default();
+        while failures
+            .front()
+            .is_some_and(|failed| failed + self.window.as_secs() <= timestamp)
+        {
+            failures.pop_front();
+        }
+        failures.push_back(timestamp);
+        failures.len() >= self.threshold as usize
+    }
+
+    /// Forget the failures of `device_id` once it completed its protocol share
+    pub fn record_success(&mut self, device_id: &[u8]) {
+        self.failures.remove(device_id);
+    }
+
+    /// Get the number of consecutive failures of `device_id` within the window ending at `timestamp`
+    pub fn failures(&self, device_id: &[u8], timestamp: u64) -> u32 {
+        self.failures.get(device_id).map_or(0, |failures| {
+            failures
+                .iter()
+                .filter(|failed| *failed + self.window.as_secs() > timestamp)
+                .count() as u32
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn consecutive_failures_trip() {
+        let mut breaker = CircuitBreaker::new(3, Duration::from_secs(60));
+        assert!(!breaker.record_failure(&[0xaa], 0));
+        assert!(!breaker.record_failure(&[0xaa], 30));
+        assert!(!breaker.record_failure(&[0xbb], 40));
+        assert!(!breaker.record_failure(&[0xaa], 70));
+        assert_eq!(breaker.failures(&[0xaa], 70), 2);
+        assert!(breaker.record_failure(&[0xaa], 80));
+
+        breaker.record_success(&[0xaa]);
+        assert_eq!(breaker.failures(&[0xaa], 80), 0);
+        assert!(!breaker.record_failure(&[0xaa], 90));
+    }
+}
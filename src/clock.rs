@@ -0,0 +1,43 @@
+use crate::get_timestamp;
+
+/// Source of the current time in seconds since the Unix epoch
+pub trait Clock: Send + Sync {
+    fn now(&self) -> u64;
+}
+
+/// Clock reading the system time
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        get_timestamp()
+    }
+}
+
+#[cfg(test)]
+pub use mock::MockClock;
+
+#[cfg(test)]
+mod mock {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    /// Clock which stands still until advanced manually
+    pub struct MockClock(AtomicU64);
+
+    impl MockClock {
+        pub fn new(now: u64) -> Self {
+            MockClock(AtomicU64::new(now))
+        }
+
+        pub fn advance(&self, seconds: u64) {
+            self.0.fetch_add(seconds, Ordering::Relaxed);
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> u64 {
+            self.0.load(Ordering::Relaxed)
+        }
+    }
+}
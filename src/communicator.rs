@@ -1,31 +1,80 @@
+use crate::clock::Clock;
 use crate::device::Device;
-use crate::get_timestamp;
+use crate::group::Group;
 use crate::proto::ProtocolType;
 use meesign_crypto::proto::{ClientMessage, Message, ServerMessage};
 use rand::prelude::SliceRandom;
 use rand::thread_rng;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::ops::Deref;
+use std::time::Duration;
 use tonic::codegen::Arc;
 
+/// Version of the header prefixed to work handed out to devices
+///
+/// The header consists of this version followed by the protocol type, one byte each.
+pub const WORK_VERSION: u8 = 1;
+
+/// Traffic of a single protocol round
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RoundStats {
+    pub received_messages: u32,
+    pub received_bytes: u64,
+    pub sent_messages: u32,
+    pub sent_bytes: u64,
+}
+
+/// Traffic relayed by a Communicator since its creation
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CommunicatorStats {
+    /// Statistics of each round in the order they were relayed, across all attempts
+    pub rounds: Vec<RoundStats>,
+    /// Total size of the messages received from each device
+    pub device_bytes: HashMap<Vec<u8>, u64>,
+    /// Time between handing out the inputs of each relayed round and relaying its messages
+    pub round_durations: Vec<Duration>,
+}
+
+/// Standing of a participant in the current phase of a task
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParticipantStatus {
+    /// Expected to decide on the task or to submit its message for the current round
+    Pending,
+    /// Accepted the task or submitted its message for the current round
+    Submitted,
+    Declined,
+    /// Not selected for the running protocol, or not asked to decide on the task
+    Idle,
+}
+
 /// Communication state of a Task
 pub struct Communicator {
     /// The minimal number of parties needed to successfully complete the task
     threshold: u32,
     /// Ordered list of devices
     device_list: Vec<Arc<Device>>,
+    /// Protocol party indices of the entries of `device_list`, before the protocol offset
+    indices: Vec<u32>,
     /// Ordered list of active devices (participating in the protocol)
     active_devices: Option<Vec<Vec<u8>>>,
     /// A mapping of device identifiers to their Task decision weight (0 - no decision, positive - accept, negative - reject)
     decisions: HashMap<Vec<u8>, i8>,
     /// A mapping of device identifiers to their Task acknowledgement
     acknowledgements: HashMap<Vec<u8>, bool>,
+    /// Identifiers of accepting devices which must not be picked as active devices
+    excluded: HashSet<Vec<u8>>,
     /// A mapping of protocol indices to incoming messages
     input: HashMap<u32, ClientMessage>,
     /// A mapping of protocol indices to outgoing messages
     output: HashMap<u32, Vec<u8>>,
     /// Relayed protocol type
     protocol_type: ProtocolType,
+    /// Relayed traffic
+    stats: CommunicatorStats,
+    /// Time at which the inputs of the current round were handed out
+    round_started: Option<u64>,
+    /// Source of the time against which device activity and round durations are judged
+    clock: Arc<dyn Clock>,
 }
 
 impl Communicator {
@@ -35,16 +84,45 @@ impl Communicator {
     /// * `devices` - a list of devices
     /// * `threshold` - the minimal number of devices to successfully complete the task
     /// * `protocol_type` - the type of the threshold protocol
-    pub fn new(devices: &[Arc<Device>], threshold: u32, protocol_type: ProtocolType) -> Self {
-        assert!(devices.len() > 1);
-        assert!(threshold <= devices.len() as u32);
-
+    /// * `clock` - the source of the current time
+    pub fn new(
+        devices: &[Arc<Device>],
+        threshold: u32,
+        protocol_type: ProtocolType,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
         let mut devices: Vec<Arc<Device>> = devices.to_vec();
         devices.sort_by_key(|x| x.identifier().to_vec());
 
+        let members = (0..).zip(devices).collect();
+        Communicator::with_indices(members, threshold, protocol_type, clock)
+    }
+
+    /// Constructs a new Communicator for a task of `group`, keeping the party indices of its members
+    pub fn for_group(group: &Group, clock: Arc<dyn Clock>) -> Self {
+        Communicator::with_indices(
+            group.members(),
+            group.signer_count(),
+            group.protocol(),
+            clock,
+        )
+    }
+
+    fn with_indices(
+        members: Vec<(u32, Arc<Device>)>,
+        threshold: u32,
+        protocol_type: ProtocolType,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        assert!(members.len() > 1);
+        assert!(threshold <= members.len() as u32);
+
+        let (indices, devices): (Vec<_>, Vec<_>) = members.into_iter().unzip();
+
         let mut communicator = Communicator {
             threshold,
             device_list: devices.iter().map(Arc::clone).collect(),
+            indices,
             active_devices: None,
             decisions: devices
                 .iter()
@@ -54,9 +132,13 @@ impl Communicator {
                 .iter()
                 .map(|x| (x.identifier().to_vec(), false))
                 .collect(),
+            excluded: HashSet::new(),
             input: HashMap::new(),
             output: HashMap::new(),
             protocol_type,
+            stats: CommunicatorStats::default(),
+            round_started: None,
+            clock,
         };
         communicator.clear_input();
         communicator
@@ -69,10 +151,16 @@ impl Communicator {
 
     /// Receive messages from a given device
     ///
+    /// Each message carries a broadcast payload for all other active devices,
+    /// payloads keyed by the protocol index of their recipient, or both.
+    ///
     /// # Arguments
     ///
     /// * `from_identifier` - identifier of the sender device
     /// * `messages` - vector containing messages from each of the sender device's shares
+    ///
+    /// # Returns
+    /// `false` if the messages were rejected as malformed
     pub fn receive_messages(
         &mut self,
         from_identifier: &[u8],
@@ -83,15 +171,38 @@ impl Communicator {
             return false;
         }
 
-        for msg in &messages {
-            assert!(msg.broadcast.is_some() || msg.unicasts.len() == self.threshold as usize - 1);
+        let indices = self.get_protocol_indices();
+        if !from_indices
+            .iter()
+            .zip(&messages)
+            .all(|(&idx, msg)| Communicator::is_well_formed(idx, msg, &indices))
+        {
+            return false;
         }
 
+        *self
+            .stats
+            .device_bytes
+            .entry(from_identifier.to_vec())
+            .or_default() += messages
+            .iter()
+            .map(|msg| msg.encoded_len() as u64)
+            .sum::<u64>();
         self.input.extend(from_indices.into_iter().zip(messages));
 
         true
     }
 
+    /// Check that a message of the protocol index `sender` carries a payload
+    /// and addresses its point-to-point payloads only to the other `indices`
+    fn is_well_formed(sender: u32, msg: &ClientMessage, indices: &[u32]) -> bool {
+        (msg.broadcast.is_some() || !msg.unicasts.is_empty())
+            && msg
+                .unicasts
+                .keys()
+                .all(|recipient| *recipient != sender && indices.contains(recipient))
+    }
+
     /// Is waiting for a message from the given device id
     pub fn waiting_for(&self, device_id: &[u8]) -> bool {
         self.identifier_to_indices(device_id)
@@ -100,6 +211,9 @@ impl Communicator {
     }
 
     /// Moves messages from incoming buffers to outgoing buffers
+    ///
+    /// Every active device receives the broadcasts of all other active devices
+    /// and only the point-to-point payloads addressed to its protocol index.
     pub fn relay(&mut self) {
         self.output = self
             .get_protocol_indices()
@@ -131,6 +245,7 @@ impl Communicator {
             })
             .collect();
 
+        self.record_round();
         self.clear_input();
     }
 
@@ -145,9 +260,36 @@ impl Communicator {
             .map(|idx| (idx, f(idx)))
             .collect();
 
+        self.record_round();
         self.clear_input();
     }
 
+    /// Account the buffered incoming and outgoing messages as a finished round
+    fn record_round(&mut self) {
+        let now = self.clock.now();
+        if let (false, Some(started)) = (self.input.is_empty(), self.round_started) {
+            self.stats
+                .round_durations
+                .push(Duration::from_secs(now.saturating_sub(started)));
+        }
+        self.round_started = Some(now);
+        self.stats.rounds.push(RoundStats {
+            received_messages: self.input.len() as u32,
+            received_bytes: self
+                .input
+                .values()
+                .map(|msg| msg.encoded_len() as u64)
+                .sum(),
+            sent_messages: self.output.len() as u32,
+            sent_bytes: self.output.values().map(|msg| msg.len() as u64).sum(),
+        });
+    }
+
+    /// Get the traffic relayed so far
+    pub fn stats(&self) -> &CommunicatorStats {
+        &self.stats
+    }
+
     /// Check whether incoming buffers contain messages from all active devices
     pub fn round_received(&self) -> bool {
         if self.active_devices.is_none() {
@@ -165,6 +307,18 @@ impl Communicator {
             .collect()
     }
 
+    /// Get all messages for a given device prefixed with the work header
+    pub fn get_work(&self, device_id: &[u8]) -> Vec<Vec<u8>> {
+        self.get_messages(device_id)
+            .into_iter()
+            .map(|msg| {
+                let mut work = vec![WORK_VERSION, self.protocol_type as u8];
+                work.extend(msg);
+                work
+            })
+            .collect()
+    }
+
     /// Get the final message
     pub fn get_final_message(&self) -> Option<Vec<u8>> {
         let results: Vec<_> = self
@@ -177,8 +331,8 @@ impl Communicator {
             return None;
         }
 
-        for msg in &results {
-            assert_eq!(msg, &results[0]);
+        if results.iter().any(|msg| msg != &results[0]) {
+            return None;
         }
 
         results[0].clone()
@@ -187,20 +341,28 @@ impl Communicator {
     /// Sets the active devices
     ///
     /// Picks which devices shall participate in the protocol
-    /// Considers only those devices which accepted participation
+    /// Considers only those devices which accepted participation and were not excluded
     /// If enough devices are available, additionaly filters by response latency
     pub fn set_active_devices(&mut self) -> Vec<Vec<u8>> {
-        assert!(self.accept_count() >= self.threshold);
-        let agreeing_devices = self
+        assert!(self.eligible_count() >= self.threshold);
+        let mut agreeing_devices = self
             .device_list
             .iter()
-            .filter(|device| self.decisions.get(device.identifier()) > Some(&0))
+            .filter(|device| self.is_eligible(device.identifier()))
             .collect::<Vec<_>>();
+        // devices with a tripped circuit breaker sign only when the others do not suffice
+        let healthy_count = agreeing_devices
+            .iter()
+            .filter(|device| !device.breaker_tripped())
+            .count();
+        if healthy_count >= self.threshold as usize {
+            agreeing_devices.retain(|device| !device.breaker_tripped());
+        }
 
-        let timestamp = get_timestamp();
+        let timestamp = self.clock.now();
         let connected_devices = agreeing_devices
             .iter()
-            .filter(|device| device.last_active() > timestamp - 5)
+            .filter(|device| device.last_active() > timestamp.saturating_sub(5))
             .map(Deref::deref)
             .collect::<Vec<_>>();
 
@@ -237,6 +399,36 @@ impl Communicator {
         self.active_devices.clone()
     }
 
+    /// Check whether a device is one of the active devices
+    pub fn is_active(&self, device_id: &[u8]) -> bool {
+        self.active_devices
+            .as_ref()
+            .map(|devices| devices.iter().any(|device| device == device_id))
+            .unwrap_or(false)
+    }
+
+    /// Exclude a device from subsequent active device selection
+    ///
+    /// # Returns
+    /// `true` if the remaining accepting devices still reach the threshold
+    pub fn exclude_device(&mut self, device_id: &[u8]) -> bool {
+        self.excluded.insert(device_id.to_vec());
+        self.eligible_count() >= self.threshold
+    }
+
+    /// Check whether a device accepted participation and was not excluded
+    fn is_eligible(&self, device_id: &[u8]) -> bool {
+        self.decisions.get(device_id) > Some(&0) && !self.excluded.contains(device_id)
+    }
+
+    /// Get the number of shares held by devices eligible for active device selection
+    fn eligible_count(&self) -> u32 {
+        self.device_list
+            .iter()
+            .filter(|device| self.is_eligible(device.identifier()))
+            .count() as u32
+    }
+
     /// Save a decision by the given device
     ///
     /// # Returns
@@ -256,6 +448,21 @@ impl Communicator {
         true
     }
 
+    /// Withdraw an acceptance by the given device so that it may decide again
+    ///
+    /// # Returns
+    /// `false` if the `device_id` is invalid or has not accepted
+    /// `true` otherwise
+    pub fn withdraw(&mut self, device_id: &[u8]) -> bool {
+        match self.decisions.get_mut(device_id) {
+            Some(decision) if *decision > 0 => {
+                *decision = 0;
+                true
+            }
+            _ => false,
+        }
+    }
+
     /// Get the number of Task accepts
     pub fn accept_count(&self) -> u32 {
         self.decisions
@@ -276,6 +483,16 @@ impl Communicator {
             .abs() as u32
     }
 
+    /// Check whether a device accepted participation
+    pub fn accepted(&self, device_id: &[u8]) -> bool {
+        self.decisions.get(device_id) > Some(&0)
+    }
+
+    /// Check whether a device declined participation
+    pub fn declined(&self, device_id: &[u8]) -> bool {
+        matches!(self.decisions.get(device_id), Some(decision) if *decision < 0)
+    }
+
     /// Check whether a device submitted its decision
     pub fn device_decided(&self, device_id: &[u8]) -> bool {
         if let Some(d) = self.decisions.get(device_id) {
@@ -285,6 +502,25 @@ impl Communicator {
         }
     }
 
+    /// Get the standing of a device before the protocol has `started` or in its current round
+    pub fn participant_status(&self, device_id: &[u8], started: bool) -> ParticipantStatus {
+        if self.declined(device_id) {
+            ParticipantStatus::Declined
+        } else if !started {
+            if self.accepted(device_id) {
+                ParticipantStatus::Submitted
+            } else {
+                ParticipantStatus::Pending
+            }
+        } else if !self.is_active(device_id) {
+            ParticipantStatus::Idle
+        } else if self.waiting_for(device_id) {
+            ParticipantStatus::Pending
+        } else {
+            ParticipantStatus::Submitted
+        }
+    }
+
     /// Save an acknowledgement by the given device
     ///
     /// # Returns
@@ -314,7 +550,7 @@ impl Communicator {
         for device in &active_devices {
             while let Some((idx, dev)) = devices_iter.next() {
                 if dev.identifier() == device {
-                    indices.push(idx as u32 + self.protocol_type.index_offset());
+                    indices.push(self.indices[idx] + self.protocol_type.index_offset());
                     break;
                 }
             }
@@ -338,7 +574,7 @@ impl Communicator {
                     .find(|(_, dev)| dev.identifier() == device)
                     .unwrap();
 
-                indices.push(idx as u32 + self.protocol_type.index_offset());
+                indices.push(self.indices[idx] + self.protocol_type.index_offset());
             }
         }
 
@@ -346,8 +582,58 @@ impl Communicator {
     }
 }
 
+#[cfg(test)]
+impl Communicator {
+    /// Constructs a Communicator of `n` synthetic devices which all accepted to participate
+    ///
+    /// Together with [`Communicator::submit_mock_round`], it relays the rounds of a `Protocol`
+    /// without any devices or cryptography involved.
+    pub fn new_mock(n: u32, protocol_type: ProtocolType) -> Self {
+        use crate::clock::SystemClock;
+        use crate::proto::DeviceKind;
+
+        let devices: Vec<_> = (0..n)
+            .map(|i| {
+                Arc::new(Device::new(
+                    i.to_be_bytes().to_vec(),
+                    format!("mock {}", i),
+                    DeviceKind::Bot,
+                    vec![0xf0],
+                    0,
+                ))
+            })
+            .collect();
+        let mut communicator = Communicator::new(&devices, n, protocol_type, Arc::new(SystemClock));
+        for device in &devices {
+            communicator.decide(device.identifier(), true);
+        }
+        communicator
+    }
+
+    /// Submit a broadcast of `payload(index)` from every active protocol index
+    pub fn submit_mock_round<F>(&mut self, payload: F)
+    where
+        F: Fn(u32) -> Vec<u8>,
+    {
+        let protocol_type = meesign_crypto::proto::ProtocolType::from(self.protocol_type);
+        for device_id in self.get_active_devices().unwrap_or_default() {
+            let messages = self
+                .identifier_to_indices(&device_id)
+                .into_iter()
+                .map(|idx| ClientMessage {
+                    protocol_type: protocol_type.into(),
+                    unicasts: HashMap::new(),
+                    broadcast: Some(payload(idx)),
+                })
+                .collect();
+            assert!(self.receive_messages(&device_id, messages));
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use crate::clock::{MockClock, SystemClock};
     use crate::proto::DeviceKind;
 
     use super::*;
@@ -355,20 +641,26 @@ mod tests {
     #[test]
     #[should_panic]
     fn communicator_with_no_devices() {
-        Communicator::new(&[], 0, ProtocolType::Gg18);
+        Communicator::new(&[], 0, ProtocolType::Gg18, Arc::new(SystemClock));
     }
 
     #[test]
     #[should_panic]
     fn communicator_too_large_threshold() {
-        Communicator::new(&prepare_devices(2), 3, ProtocolType::Gg18);
+        Communicator::new(
+            &prepare_devices(2),
+            3,
+            ProtocolType::Gg18,
+            Arc::new(SystemClock),
+        );
     }
 
     #[test]
     fn empty_communicator() {
         let devices = prepare_devices(5);
         let d0 = devices[0].identifier();
-        let communicator = Communicator::new(&devices, 3, ProtocolType::Gg18);
+        let communicator =
+            Communicator::new(&devices, 3, ProtocolType::Gg18, Arc::new(SystemClock));
         assert_eq!(communicator.accept_count(), 0);
         assert_eq!(communicator.reject_count(), 0);
         assert_eq!(communicator.round_received(), false);
@@ -388,7 +680,8 @@ mod tests {
     #[test]
     fn valid_communicator() {
         let devices = prepare_devices(5);
-        let mut communicator = Communicator::new(&devices, 3, ProtocolType::Gg18);
+        let mut communicator =
+            Communicator::new(&devices, 3, ProtocolType::Gg18, Arc::new(SystemClock));
         assert_eq!(communicator.device_decided(devices[0].identifier()), false);
         communicator.decide(devices[0].identifier(), true);
         assert_eq!(communicator.accept_count(), 1);
@@ -466,7 +759,7 @@ mod tests {
             assert_eq!(
                 communicator.get_messages(devices[idx].identifier()),
                 if active_indices.contains(&idx) {
-                    vec![vec![]]
+                    vec![Vec::<u8>::new()]
                 } else {
                     vec![]
                 }
@@ -492,14 +785,16 @@ mod tests {
     #[test]
     fn unknown_device_decide() {
         let devices = prepare_devices(3);
-        let mut communicator = Communicator::new(&devices[..2], 2, ProtocolType::Gg18);
+        let mut communicator =
+            Communicator::new(&devices[..2], 2, ProtocolType::Gg18, Arc::new(SystemClock));
         assert_eq!(communicator.decide(devices[2].identifier(), true), false);
     }
 
     #[test]
     fn repeated_device_decide() {
         let devices = prepare_devices(2);
-        let mut communicator = Communicator::new(&devices, 2, ProtocolType::Gg18);
+        let mut communicator =
+            Communicator::new(&devices, 2, ProtocolType::Gg18, Arc::new(SystemClock));
         assert_eq!(communicator.decide(devices[0].identifier(), true), true);
         assert_eq!(communicator.decide(devices[0].identifier(), true), false);
     }
@@ -508,66 +803,96 @@ mod tests {
     fn repeated_devices() {
         let devices = prepare_devices(1);
         let devices = vec![devices[0].clone(), devices[0].clone()];
-        let mut communicator = Communicator::new(&devices, 2, ProtocolType::Gg18);
+        let mut communicator =
+            Communicator::new(&devices, 2, ProtocolType::Gg18, Arc::new(SystemClock));
         assert_eq!(communicator.decide(devices[0].identifier(), true), true);
         communicator.set_active_devices();
         assert_eq!(communicator.get_protocol_indices(), vec![0, 1]);
     }
 
     #[test]
-    #[should_panic]
     fn not_enough_messages() {
         let devices = prepare_devices(3);
-        let mut communicator = Communicator::new(&devices, 3, ProtocolType::Gg18);
+        let mut communicator =
+            Communicator::new(&devices, 3, ProtocolType::Gg18, Arc::new(SystemClock));
         communicator.decide(devices[0].identifier(), true);
         communicator.decide(devices[1].identifier(), true);
         communicator.decide(devices[2].identifier(), true);
         communicator.set_active_devices();
-        communicator.receive_messages(
+        assert!(!communicator.receive_messages(
             devices[0].identifier(),
             vec![ClientMessage {
                 protocol_type: 0,
                 unicasts: HashMap::new(),
                 broadcast: None,
             }],
-        );
+        ));
+        assert!(communicator.waiting_for(devices[0].identifier()));
     }
 
     #[test]
-    #[should_panic]
     fn too_many_messages() {
         let devices = prepare_devices(3);
-        let mut communicator = Communicator::new(&devices, 3, ProtocolType::Gg18);
+        let mut communicator =
+            Communicator::new(&devices, 3, ProtocolType::Gg18, Arc::new(SystemClock));
         communicator.decide(devices[0].identifier(), true);
         communicator.decide(devices[1].identifier(), true);
         communicator.decide(devices[2].identifier(), true);
         communicator.set_active_devices();
-        communicator.receive_messages(
+        assert!(!communicator.receive_messages(
             devices[0].identifier(),
             vec![ClientMessage {
                 protocol_type: 0,
                 unicasts: (0..6 as u32).map(|i| (i, vec![])).collect(),
                 broadcast: None,
             }],
-        );
+        ));
+        assert!(communicator.waiting_for(devices[0].identifier()));
     }
 
     #[test]
     #[should_panic]
     fn not_enough_accepts() {
         let devices = prepare_devices(5);
-        let mut communicator = Communicator::new(&devices, 3, ProtocolType::Gg18);
+        let mut communicator =
+            Communicator::new(&devices, 3, ProtocolType::Gg18, Arc::new(SystemClock));
         communicator.decide(devices[0].identifier(), true);
         communicator.decide(devices[2].identifier(), false);
         communicator.decide(devices[4].identifier(), true);
         communicator.set_active_devices();
     }
 
+    #[test]
+    fn excluded_device() {
+        let devices = prepare_devices(3);
+        let mut communicator =
+            Communicator::new(&devices, 2, ProtocolType::Gg18, Arc::new(SystemClock));
+        for device in &devices {
+            communicator.decide(device.identifier(), true);
+        }
+        assert!(communicator.exclude_device(devices[1].identifier()));
+        communicator.set_active_devices();
+        assert_eq!(
+            communicator.get_active_devices(),
+            Some(vec![
+                devices[0].identifier().to_vec(),
+                devices[2].identifier().to_vec()
+            ])
+        );
+        assert!(!communicator.is_active(devices[1].identifier()));
+        assert!(!communicator.exclude_device(devices[0].identifier()));
+    }
+
     #[test]
     fn more_than_threshold_accepts() {
         let threshold = 3;
         let devices = prepare_devices(5);
-        let mut communicator = Communicator::new(&devices, threshold, ProtocolType::Gg18);
+        let mut communicator = Communicator::new(
+            &devices,
+            threshold,
+            ProtocolType::Gg18,
+            Arc::new(SystemClock),
+        );
         for device in devices {
             communicator.decide(device.identifier(), true);
         }
@@ -581,7 +906,8 @@ mod tests {
     #[test]
     fn send_all() {
         let devices = prepare_devices(3);
-        let mut communicator = Communicator::new(&devices, 2, ProtocolType::Gg18);
+        let mut communicator =
+            Communicator::new(&devices, 2, ProtocolType::Gg18, Arc::new(SystemClock));
         communicator.decide(devices[0].identifier(), true);
         communicator.decide(devices[2].identifier(), true);
         communicator.set_active_devices();
@@ -607,11 +933,31 @@ mod tests {
         );
     }
 
+    #[test]
+    fn work_header() {
+        let devices = prepare_devices(3);
+        let mut communicator =
+            Communicator::new(&devices, 2, ProtocolType::Frost, Arc::new(SystemClock));
+        communicator.decide(devices[0].identifier(), true);
+        communicator.decide(devices[2].identifier(), true);
+        communicator.set_active_devices();
+        communicator.send_all(|idx| vec![idx as u8]);
+        assert_eq!(
+            communicator.get_work(devices[0].identifier()),
+            vec![vec![WORK_VERSION, ProtocolType::Frost as u8, 1]]
+        );
+        assert_eq!(
+            communicator.get_work(devices[1].identifier()),
+            Vec::<Vec<u8>>::new()
+        );
+    }
+
     #[test]
     fn protocol_init() {
         use meesign_crypto::proto::ProtocolInit;
         let devices = prepare_devices(3);
-        let mut communicator = Communicator::new(&devices, 2, ProtocolType::Frost);
+        let mut communicator =
+            Communicator::new(&devices, 2, ProtocolType::Frost, Arc::new(SystemClock));
         communicator.decide(devices[0].identifier(), true);
         communicator.decide(devices[2].identifier(), true);
         communicator.set_active_devices();
@@ -660,14 +1006,16 @@ mod tests {
     #[test]
     fn unknown_device_acknowledgement() {
         let devices = prepare_devices(3);
-        let mut communicator = Communicator::new(&devices[..2], 2, ProtocolType::Gg18);
+        let mut communicator =
+            Communicator::new(&devices[..2], 2, ProtocolType::Gg18, Arc::new(SystemClock));
         assert_eq!(communicator.acknowledge(devices[2].identifier()), false);
     }
 
     #[test]
     fn repeated_device_acknowledgement() {
         let devices = prepare_devices(2);
-        let mut communicator = Communicator::new(&devices, 2, ProtocolType::Gg18);
+        let mut communicator =
+            Communicator::new(&devices, 2, ProtocolType::Gg18, Arc::new(SystemClock));
         assert_eq!(communicator.acknowledge(devices[0].identifier()), true);
         assert_eq!(communicator.acknowledge(devices[0].identifier()), false);
     }
@@ -675,7 +1023,8 @@ mod tests {
     #[test]
     fn broadcast_messages() {
         let devices = prepare_devices(3);
-        let mut communicator = Communicator::new(&devices, 2, ProtocolType::Frost);
+        let mut communicator =
+            Communicator::new(&devices, 2, ProtocolType::Frost, Arc::new(SystemClock));
 
         communicator.decide(devices[0].identifier(), true);
         communicator.decide(devices[1].identifier(), true);
@@ -723,6 +1072,214 @@ mod tests {
         );
     }
 
+    #[test]
+    fn relay_stats() {
+        let devices = prepare_devices(3);
+        let mut communicator =
+            Communicator::new(&devices, 2, ProtocolType::Frost, Arc::new(SystemClock));
+        communicator.decide(devices[0].identifier(), true);
+        communicator.decide(devices[1].identifier(), true);
+        communicator.set_active_devices();
+        communicator.send_all(|idx| vec![idx as u8; 4]);
+        assert_eq!(
+            communicator.stats().rounds,
+            vec![RoundStats {
+                received_messages: 0,
+                received_bytes: 0,
+                sent_messages: 2,
+                sent_bytes: 8,
+            }]
+        );
+
+        let messages: Vec<_> = (0..2)
+            .map(|i| ClientMessage {
+                protocol_type: ProtocolType::Frost.into(),
+                unicasts: HashMap::new(),
+                broadcast: Some(vec![i as u8; 10 * (i + 1)]),
+            })
+            .collect();
+        for (device, msg) in devices.iter().zip(&messages) {
+            assert!(communicator.receive_messages(device.identifier(), vec![msg.clone()]));
+        }
+        communicator.relay();
+
+        let stats = communicator.stats();
+        assert_eq!(stats.rounds.len(), 2);
+        let round = &stats.rounds[1];
+        assert_eq!(round.received_messages, 2);
+        assert_eq!(
+            round.received_bytes,
+            messages
+                .iter()
+                .map(|msg| msg.encoded_len() as u64)
+                .sum::<u64>()
+        );
+        assert_eq!(round.sent_messages, 2);
+        assert!(round.sent_bytes > 0);
+        for (device, msg) in devices.iter().zip(&messages) {
+            assert_eq!(
+                stats.device_bytes[device.identifier()],
+                msg.encoded_len() as u64
+            );
+        }
+        assert!(!stats.device_bytes.contains_key(devices[2].identifier()));
+    }
+
+    #[test]
+    fn round_durations_follow_clock() {
+        let devices = prepare_devices(3);
+        let clock = Arc::new(MockClock::new(0));
+        let mut communicator = Communicator::new(&devices, 2, ProtocolType::Frost, clock.clone());
+        communicator.decide(devices[0].identifier(), true);
+        communicator.decide(devices[1].identifier(), true);
+        communicator.set_active_devices();
+        communicator.send_all(|idx| vec![idx as u8; 4]);
+
+        clock.advance(3);
+        for device in &devices[..2] {
+            let message = ClientMessage {
+                protocol_type: ProtocolType::Frost.into(),
+                unicasts: HashMap::new(),
+                broadcast: Some(vec![0x01]),
+            };
+            assert!(communicator.receive_messages(device.identifier(), vec![message]));
+        }
+        communicator.relay();
+
+        assert_eq!(
+            communicator.stats().round_durations,
+            vec![Duration::from_secs(3)]
+        );
+    }
+
+    #[test]
+    fn unicast_messages() {
+        let devices = prepare_devices(3);
+        let mut communicator =
+            Communicator::new(&devices, 3, ProtocolType::Gg18, Arc::new(SystemClock));
+        for device in &devices {
+            communicator.decide(device.identifier(), true);
+        }
+        communicator.set_active_devices();
+        let indices = communicator.get_protocol_indices();
+
+        for (device, &sender) in devices.iter().zip(&indices) {
+            let unicasts = indices
+                .iter()
+                .filter(|&&recipient| recipient != sender)
+                .map(|&recipient| (recipient, vec![sender as u8, recipient as u8]))
+                .collect();
+            assert!(communicator.receive_messages(
+                device.identifier(),
+                vec![ClientMessage {
+                    protocol_type: ProtocolType::Gg18.into(),
+                    unicasts,
+                    broadcast: None,
+                }]
+            ));
+        }
+        assert!(communicator.round_received());
+        communicator.relay();
+
+        for (device, &recipient) in devices.iter().zip(&indices) {
+            let unicasts = indices
+                .iter()
+                .filter(|&&sender| sender != recipient)
+                .map(|&sender| (sender, vec![sender as u8, recipient as u8]))
+                .collect();
+            let messages = communicator.get_messages(device.identifier());
+            assert_eq!(messages.len(), 1);
+            assert_eq!(
+                ServerMessage::decode(messages[0].as_slice()).unwrap(),
+                ServerMessage {
+                    protocol_type: ProtocolType::Gg18.into(),
+                    unicasts,
+                    broadcasts: HashMap::new(),
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn unicast_to_inactive_device() {
+        let devices = prepare_devices(3);
+        let mut communicator =
+            Communicator::new(&devices, 2, ProtocolType::Gg18, Arc::new(SystemClock));
+        communicator.decide(devices[0].identifier(), true);
+        communicator.decide(devices[1].identifier(), true);
+        communicator.decide(devices[2].identifier(), false);
+        communicator.set_active_devices();
+        assert!(!communicator.receive_messages(
+            devices[0].identifier(),
+            vec![ClientMessage {
+                protocol_type: ProtocolType::Gg18.into(),
+                unicasts: HashMap::from([(2, vec![])]),
+                broadcast: None,
+            }],
+        ));
+        assert!(communicator.waiting_for(devices[0].identifier()));
+    }
+
+    #[test]
+    fn unicast_to_sender() {
+        let devices = prepare_devices(2);
+        let mut communicator =
+            Communicator::new(&devices, 2, ProtocolType::Gg18, Arc::new(SystemClock));
+        communicator.decide(devices[0].identifier(), true);
+        communicator.decide(devices[1].identifier(), true);
+        communicator.set_active_devices();
+        let message = |recipient| ClientMessage {
+            protocol_type: ProtocolType::Gg18.into(),
+            unicasts: HashMap::from([(recipient, vec![0x01])]),
+            broadcast: Some(vec![0x02]),
+        };
+        let indices = communicator.get_protocol_indices();
+        assert!(!communicator.receive_messages(devices[0].identifier(), vec![message(indices[0])]));
+        assert!(communicator.receive_messages(devices[0].identifier(), vec![message(indices[1])]));
+    }
+
+    #[test]
+    fn group_party_indices() {
+        let devices = prepare_devices(3);
+        let mut group = Group::new(
+            vec![0xaa],
+            "Sample Group".into(),
+            vec![devices[2].clone(), devices[0].clone(), devices[1].clone()],
+            3,
+            ProtocolType::Frost,
+            crate::proto::KeyType::SignChallenge,
+            None,
+            None,
+        );
+        let rotated = Arc::new(Device::new(
+            vec![0x09],
+            "d9".into(),
+            DeviceKind::User,
+            vec![0xf9],
+            0,
+        ));
+        assert!(group.replace_device(devices[0].identifier(), rotated.clone()));
+
+        let mut communicator = Communicator::for_group(&group, Arc::new(SystemClock));
+        for (_, device) in group.members() {
+            communicator.decide(device.identifier(), true);
+        }
+        communicator.set_active_devices();
+        assert_eq!(communicator.get_protocol_indices(), vec![1, 2, 3]);
+        assert_eq!(
+            communicator.identifier_to_indices(rotated.identifier()),
+            [1]
+        );
+        assert_eq!(
+            communicator.identifier_to_indices(devices[1].identifier()),
+            [2]
+        );
+        assert_eq!(
+            communicator.identifier_to_indices(devices[2].identifier()),
+            [3]
+        );
+    }
+
     fn prepare_devices(n: usize) -> Vec<Arc<Device>> {
         assert!(n < u8::MAX as usize);
         (0..n)
@@ -732,6 +1289,7 @@ mod tests {
                     format!("d{}", i),
                     DeviceKind::User,
                     vec![0xf0 | i as u8],
+                    0,
                 ))
             })
             .collect()
@@ -0,0 +1,199 @@
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use crate::proto::ProtocolType;
+use crate::utils::DEFAULT_MAX_NAME_LENGTH;
+
+/// Handling of running tasks whose participant closed its update stream
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum DisconnectPolicy {
+    /// Fail the tasks that are waiting on the disconnected device
+    Fail,
+    /// Replace the device by another accepting participant if enough of them remain,
+    /// fail the tasks otherwise
+    Reselect,
+    /// Keep waiting for the device to reconnect or for the task to time out
+    Wait,
+}
+
+/// Characters permitted in device and group names
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum NamePolicy {
+    /// Reject any ASCII punctuation
+    Strict,
+    /// Allow Unicode letters and numbers, spaces, hyphens and apostrophes
+    Extended,
+}
+
+/// Part of a task which a protocol round timeout applies to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, ValueEnum)]
+pub enum ProtocolStage {
+    /// Establishing a group
+    Keygen,
+    /// Signing or decrypting with an established group
+    Sign,
+}
+
+/// Round timeouts of the protocols whose rounds take notably longer or shorter than usual
+pub fn default_protocol_round_timeouts() -> HashMap<(ProtocolType, ProtocolStage), Duration> {
+    HashMap::from([
+        (
+            (ProtocolType::Gg18, ProtocolStage::Keygen),
+            Duration::from_secs(120),
+        ),
+        (
+            (ProtocolType::Gg18, ProtocolStage::Sign),
+            Duration::from_secs(60),
+        ),
+        (
+            (ProtocolType::Musig2, ProtocolStage::Sign),
+            Duration::from_secs(10),
+        ),
+    ])
+}
+
+/// Limits on what a single requester may hold; a missing limit is not enforced
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Quota {
+    /// Devices registered by the requester
+    pub max_devices: Option<usize>,
+    /// Groups established or being established on behalf of the requester
+    pub max_groups: Option<usize>,
+    /// Tasks of the requester which have neither finished nor failed
+    pub max_active_tasks: Option<usize>,
+}
+
+/// Quotas of the individual requesters, keyed by their identity
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Quotas {
+    /// Quota of the requesters which are not listed
+    pub default: Quota,
+    pub requesters: HashMap<String, Quota>,
+}
+
+impl Quotas {
+    pub fn from_json(json: &[u8]) -> Result<Self, String> {
+        serde_json::from_slice(json).map_err(|err| format!("Invalid quota file: {}", err))
+    }
+
+    pub fn get(&self, requester: &str) -> &Quota {
+        self.requesters.get(requester).unwrap_or(&self.default)
+    }
+}
+
+/// Access of browser clients through gRPC-Web
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GrpcWeb {
+    /// Origins permitted by CORS to make credentialed requests; any origin is permitted
+    /// to make requests without credentials when empty
+    pub origins: Vec<String>,
+    /// Serve the mutating and administrative calls too, not only the read-only ones
+    pub full_access: bool,
+}
+
+/// Server-wide settings shared by `State` and the interfaces
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub disconnect_policy: DisconnectPolicy,
+    /// Maximum number of tasks which have neither finished nor failed
+    pub max_active_tasks: usize,
+    /// Initial bearer token required by administrative RPCs; these are disabled when unset
+    pub admin_token: Option<String>,
+    pub name_policy: NamePolicy,
+    /// Longest device name, in Unicode scalar values
+    pub max_device_name_length: usize,
+    /// Longest group name, in Unicode scalar values
+    pub max_group_name_length: usize,
+    /// Identifiers of the only devices which may register; registration is open when unset
+    pub device_allowlist: Option<HashSet<Vec<u8>>>,
+    /// Largest number of members a new group may have
+    pub max_group_size: usize,
+    /// Smallest threshold a new group may have
+    pub min_threshold: Option<u32>,
+    /// Smallest ratio of threshold to the number of members a new group may have
+    pub min_threshold_ratio: Option<f64>,
+    /// Maximum total size in bytes of the messages a device submits for a single round
+    pub max_message_size: usize,
+    /// Maximum number of devices registered by a single batch request
+    pub max_batch_size: usize,
+    /// Start signing protocols only once the selected signers commit to participate
+    pub require_commitments: bool,
+    /// Ask only as many members as signing needs to decide on a signing task, recruiting
+    /// other connected members in place of those who decline
+    pub recruit_signers: bool,
+    /// Time the participants have to decide on a task before it fails
+    pub approval_timeout: Option<Duration>,
+    /// Time a running task may wait on a protocol round before it is restarted
+    pub round_timeout: Duration,
+    /// Number of times a stalled task is restarted before it fails; unlimited when unset
+    pub max_restarts: Option<u32>,
+    /// Round timeouts of particular protocols and stages, overriding `round_timeout`
+    pub protocol_round_timeouts: HashMap<(ProtocolType, ProtocolStage), Duration>,
+    /// Number of task failures of a group within `alert_window` which raises an alert
+    pub alert_threshold: Option<u32>,
+    pub alert_window: Duration,
+    /// Consecutive protocol failures of a device within `breaker_window` which leave it out
+    /// of signing; devices are never left out when unset
+    pub breaker_threshold: Option<u32>,
+    pub breaker_window: Duration,
+    /// Period of inactivity after which devices outside of usable groups are unregistered
+    pub device_inactivity_timeout: Option<Duration>,
+    /// Interval of HTTP/2 and TCP keepalive probes on client connections
+    pub keepalive_interval: Option<Duration>,
+    /// Time to wait for a keepalive acknowledgement before closing the connection
+    pub keepalive_timeout: Option<Duration>,
+    pub quotas: Quotas,
+    /// Fail Musig2 signing tasks in which a participant repeats a nonce commitment of the group
+    pub musig2_nonce_guard: bool,
+    /// gRPC-Web gateway for browser clients; disabled when unset
+    pub grpc_web: Option<GrpcWeb>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            disconnect_policy: DisconnectPolicy::Fail,
+            max_active_tasks: 1024,
+            admin_token: None,
+            name_policy: NamePolicy::Strict,
+            max_device_name_length: DEFAULT_MAX_NAME_LENGTH,
+            max_group_name_length: DEFAULT_MAX_NAME_LENGTH,
+            device_allowlist: None,
+            max_group_size: 128,
+            min_threshold: None,
+            min_threshold_ratio: None,
+            max_message_size: 4 * 1024 * 1024,
+            max_batch_size: 256,
+            require_commitments: false,
+            recruit_signers: false,
+            approval_timeout: None,
+            round_timeout: Duration::from_secs(30),
+            max_restarts: None,
+            protocol_round_timeouts: default_protocol_round_timeouts(),
+            alert_threshold: None,
+            alert_window: Duration::from_secs(10 * 60),
+            breaker_threshold: None,
+            breaker_window: Duration::from_secs(60 * 60),
+            device_inactivity_timeout: None,
+            keepalive_interval: Some(Duration::from_secs(30)),
+            keepalive_timeout: Some(Duration::from_secs(10)),
+            quotas: Quotas::default(),
+            musig2_nonce_guard: true,
+            grpc_web: None,
+        }
+    }
+}
+
+impl Config {
+    /// Time a running task may wait on a round of `protocol` in `stage` before it is restarted
+    pub fn round_timeout_of(&self, protocol: ProtocolType, stage: ProtocolStage) -> Duration {
+        self.protocol_round_timeouts
+            .get(&(protocol, stage))
+            .copied()
+            .unwrap_or(self.round_timeout)
+    }
+}
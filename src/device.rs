@@ -1,6 +1,5 @@
 use crate::proto::DeviceKind;
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 
 #[derive(Debug)]
 pub struct Device {
@@ -8,11 +7,21 @@ pub struct Device {
     name: String,
     kind: DeviceKind,
     certificate: Vec<u8>,
+    public_key: Option<Vec<u8>>,
+    /// Time of the last activity of the device, starting at its registration
     last_active: AtomicU64,
+    /// Set while the device is left out of signing after repeated protocol failures
+    breaker_tripped: AtomicBool,
 }
 
 impl Device {
-    pub fn new(identifier: Vec<u8>, name: String, kind: DeviceKind, certificate: Vec<u8>) -> Self {
+    pub fn new(
+        identifier: Vec<u8>,
+        name: String,
+        kind: DeviceKind,
+        certificate: Vec<u8>,
+        registered_at: u64,
+    ) -> Self {
         assert!(!identifier.is_empty());
         assert!(!certificate.is_empty());
         Device {
@@ -20,12 +29,9 @@ impl Device {
             name,
             kind,
             certificate,
-            last_active: AtomicU64::new(
-                SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .unwrap()
-                    .as_secs(),
-            ),
+            public_key: None,
+            last_active: AtomicU64::new(registered_at),
+            breaker_tripped: AtomicBool::new(false),
         }
     }
 
@@ -45,20 +51,58 @@ impl Device {
         &self.certificate
     }
 
+    /// Individual public key of the device; it is not used by the server
+    pub fn public_key(&self) -> Option<&[u8]> {
+        self.public_key.as_deref()
+    }
+
+    pub fn set_public_key(&mut self, public_key: Vec<u8>) -> Result<(), String> {
+        validate_public_key(&public_key)?;
+        self.public_key = Some(public_key);
+        Ok(())
+    }
+
+    /// Copy the device under a new `identifier` and `certificate`
+    pub fn with_identifier(&self, identifier: Vec<u8>, certificate: Vec<u8>) -> Self {
+        let mut device = Device::new(
+            identifier,
+            self.name.clone(),
+            self.kind,
+            certificate,
+            self.last_active(),
+        );
+        device.public_key = self.public_key.clone();
+        device
+    }
+
     pub fn last_active(&self) -> u64 {
         self.last_active.load(Ordering::Relaxed)
     }
 
-    pub fn activated(&self) -> u64 {
-        self.last_active.store(
-            SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
-            Ordering::Relaxed,
-        );
+    /// Record that the device was active at `timestamp`
+    pub fn activated(&self, timestamp: u64) -> u64 {
+        self.last_active.store(timestamp, Ordering::Relaxed);
         self.last_active.load(Ordering::Relaxed)
     }
+
+    pub fn breaker_tripped(&self) -> bool {
+        self.breaker_tripped.load(Ordering::Relaxed)
+    }
+
+    pub fn set_breaker_tripped(&self, tripped: bool) {
+        self.breaker_tripped.store(tripped, Ordering::Relaxed);
+    }
+}
+
+/// Check that `public_key` is encoded in a format used by the supported schemes
+///
+/// Accepts SEC1 compressed or uncompressed secp256k1 points and 32-byte Ed25519 or x-only keys.
+pub fn validate_public_key(public_key: &[u8]) -> Result<(), String> {
+    match (public_key.len(), public_key.first()) {
+        (32, _) | (33, Some(0x02 | 0x03)) | (65, Some(0x04)) => Ok(()),
+        (33 | 65, _) => Err("Invalid SEC1 public key prefix".into()),
+        (length, _) => Err(format!("Invalid public key length {}", length)),
+    }
 }
 
 impl From<&Device> for crate::proto::Device {
@@ -69,6 +113,9 @@ impl From<&Device> for crate::proto::Device {
             kind: *device.kind() as i32,
             certificate: device.certificate().to_vec(),
             last_active: device.last_active(),
+            subscribed: false,
+            public_key: device.public_key().map(<[u8]>::to_vec),
+            delivery: crate::proto::DeliveryMode::Push.into(),
         }
     }
 }
@@ -85,6 +132,7 @@ mod tests {
             String::from("Sample Device"),
             DeviceKind::User,
             vec![0xff],
+            0,
         );
     }
 
@@ -96,6 +144,7 @@ mod tests {
             String::from("Sample Device"),
             DeviceKind::User,
             vec![],
+            0,
         );
     }
 
@@ -106,6 +155,7 @@ mod tests {
             String::from("Sample Device"),
             DeviceKind::User,
             vec![0xab, 0xcd, 0xef, 0x00],
+            0,
         );
         let protobuf = crate::proto::Device::from(&device);
         assert_eq!(protobuf.identifier, device.identifier());
@@ -113,6 +163,26 @@ mod tests {
         assert_eq!(protobuf.kind(), *device.kind());
         assert_eq!(protobuf.certificate, device.certificate());
         assert_eq!(protobuf.last_active, device.last_active());
+        assert_eq!(protobuf.public_key, None);
+    }
+
+    #[test]
+    fn public_key_validation() {
+        let mut device = Device::new(
+            vec![0x01],
+            String::from("Sample Device"),
+            DeviceKind::User,
+            vec![0xff],
+            0,
+        );
+        let mut compressed = vec![0x02];
+        compressed.extend([0xab; 32]);
+        assert_eq!(device.set_public_key(compressed.clone()), Ok(()));
+        assert_eq!(device.public_key(), Some(compressed.as_slice()));
+        assert!(device.set_public_key(vec![0xab; 33]).is_err());
+        assert!(device.set_public_key(vec![0x04; 20]).is_err());
+        assert!(validate_public_key(&[0xab; 32]).is_ok());
+        assert!(validate_public_key(&[0x04; 65]).is_ok());
     }
 
     #[test]
@@ -126,14 +196,15 @@ mod tests {
             name.clone(),
             kind.clone(),
             certificate.clone(),
+            1_600_000_000,
         );
         assert_eq!(device.identifier(), &identifier);
         assert_eq!(device.name(), &name);
         assert_eq!(device.kind(), &kind);
         assert_eq!(device.certificate(), &certificate);
-        let previous_active = device.last_active();
-        let activated = device.activated();
-        assert!(previous_active <= device.last_active());
+        assert_eq!(device.last_active(), 1_600_000_000);
+        let activated = device.activated(1_700_000_000);
+        assert_eq!(device.last_active(), 1_700_000_000);
         assert_eq!(device.last_active(), activated);
     }
 }
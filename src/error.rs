@@ -0,0 +1,44 @@
+use tonic::Status;
+
+/// Errors returned by `State` operations and mapped onto gRPC statuses
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The request was malformed or contained inconsistent values
+    InvalidArgument(String),
+    /// The request referred to a resource which does not exist
+    NotFound(String),
+    /// The request referred to a resource in an unsuitable state
+    FailedPrecondition(String),
+    /// A server-wide limit was reached
+    ResourceExhausted(String),
+    /// The request is forbidden by a policy
+    PermissionDenied(String),
+    /// The request cannot be served at the moment
+    Unavailable(String),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::InvalidArgument(message)
+            | Error::NotFound(message)
+            | Error::FailedPrecondition(message)
+            | Error::ResourceExhausted(message)
+            | Error::PermissionDenied(message)
+            | Error::Unavailable(message) => f.write_str(message),
+        }
+    }
+}
+
+impl From<Error> for Status {
+    fn from(error: Error) -> Self {
+        match error {
+            Error::InvalidArgument(message) => Status::invalid_argument(message),
+            Error::NotFound(message) => Status::not_found(message),
+            Error::FailedPrecondition(message) => Status::failed_precondition(message),
+            Error::ResourceExhausted(message) => Status::resource_exhausted(message),
+            Error::PermissionDenied(message) => Status::permission_denied(message),
+            Error::Unavailable(message) => Status::unavailable(message),
+        }
+    }
+}
@@ -1,5 +1,9 @@
 use crate::device::Device;
-use crate::proto::{KeyType, ProtocolType};
+use crate::proto::{KeyFormat, KeyType, ProtocolType, TaskType};
+use openssl::bn::BigNumContext;
+use openssl::ec::{EcGroup, EcKey, EcPoint};
+use openssl::nid::Nid;
+use openssl::pkey::PKey;
 use tonic::codegen::Arc;
 
 #[derive(Clone)]
@@ -7,11 +11,20 @@ pub struct Group {
     identifier: Vec<u8>,
     name: String,
     devices: Vec<Arc<Device>>,
+    /// Protocol party index of each entry of `devices`, kept when the members change
+    indices: Vec<u32>,
     threshold: u32,
     protocol: ProtocolType,
     key_type: KeyType,
     certificate: Option<Vec<u8>>,
     note: Option<String>,
+    degraded: bool,
+    tags: Vec<String>,
+    allowed_tasks: Vec<TaskType>,
+    auto_approve: bool,
+    require_all_signers: bool,
+    sign_count: u64,
+    last_signed: Option<u64>,
 }
 
 impl Group {
@@ -28,15 +41,30 @@ impl Group {
         assert!(!identifier.is_empty());
         assert!(threshold >= 1);
         assert!(threshold as usize <= devices.len());
+        // keygen assigns party indices in the order of device identifiers
+        let mut order: Vec<usize> = (0..devices.len()).collect();
+        order.sort_by(|a, b| devices[*a].identifier().cmp(devices[*b].identifier()));
+        let mut indices = vec![0; devices.len()];
+        for (index, position) in order.into_iter().enumerate() {
+            indices[position] = index as u32;
+        }
         Group {
             identifier,
             name,
             devices,
+            indices,
             threshold,
             protocol,
             key_type,
             certificate,
             note,
+            degraded: false,
+            tags: Vec::new(),
+            allowed_tasks: Vec::new(),
+            auto_approve: false,
+            require_all_signers: false,
+            sign_count: 0,
+            last_signed: None,
         }
     }
 
@@ -44,6 +72,11 @@ impl Group {
         &self.identifier
     }
 
+    /// Key ordering groups by name, then by identifier, for stable listings
+    pub fn listing_key(&self) -> (&str, &[u8]) {
+        (self.name(), self.identifier())
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
@@ -53,13 +86,68 @@ impl Group {
     }
 
     pub fn reject_threshold(&self) -> u32 {
-        self.devices.len() as u32 - self.threshold + 1 // rejects >= threshold_reject => fail
+        (self.devices.len() as u32 + 1).saturating_sub(self.signer_count()) // rejects >= threshold_reject => fail
+    }
+
+    /// Number of members taking part in each signing or decryption task of the group
+    pub fn signer_count(&self) -> u32 {
+        if self.require_all_signers {
+            self.devices.len() as u32
+        } else {
+            self.threshold
+        }
     }
 
     pub fn devices(&self) -> &[Arc<Device>] {
         &self.devices
     }
 
+    /// Members paired with their protocol party indices, ordered by the index
+    pub fn members(&self) -> Vec<(u32, Arc<Device>)> {
+        let mut members: Vec<_> = self
+            .indices
+            .iter()
+            .copied()
+            .zip(self.devices.iter().cloned())
+            .collect();
+        members.sort_by_key(|(index, _)| *index);
+        members
+    }
+
+    /// Replace the member `device_id` by `device`, keeping its position among the members
+    pub fn replace_device(&mut self, device_id: &[u8], device: Arc<Device>) -> bool {
+        match self
+            .devices
+            .iter_mut()
+            .find(|member| member.identifier() == device_id)
+        {
+            Some(member) => {
+                *member = device;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Remove the member `device_id`, keeping the party indices of the other members
+    ///
+    /// The group is marked degraded once its members no longer meet the threshold.
+    pub fn remove_device(&mut self, device_id: &[u8]) -> bool {
+        let Some(position) = self
+            .devices
+            .iter()
+            .position(|member| member.identifier() == device_id)
+        else {
+            return false;
+        };
+        self.devices.remove(position);
+        self.indices.remove(position);
+        if (self.devices.len() as u32) < self.threshold {
+            self.mark_degraded();
+        }
+        true
+    }
+
     pub fn contains(&self, device_id: &[u8]) -> bool {
         self.devices
             .iter()
@@ -81,6 +169,113 @@ impl Group {
     pub fn note(&self) -> Option<&String> {
         self.note.as_ref()
     }
+
+    /// True if a member has left and the rest of the group can no longer reach the threshold
+    pub fn is_degraded(&self) -> bool {
+        self.degraded
+    }
+
+    pub fn mark_degraded(&mut self) {
+        self.degraded = true;
+    }
+
+    /// Organizational labels; they do not affect the group key
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    pub fn set_tags(&mut self, tags: Vec<String>) {
+        self.tags = tags;
+    }
+
+    /// Kinds of tasks the group may perform; any task is allowed when empty
+    pub fn allowed_tasks(&self) -> &[TaskType] {
+        &self.allowed_tasks
+    }
+
+    pub fn set_allowed_tasks(&mut self, allowed_tasks: Vec<TaskType>) {
+        self.allowed_tasks = allowed_tasks;
+    }
+
+    pub fn permits(&self, task_type: TaskType) -> bool {
+        self.allowed_tasks.is_empty() || self.allowed_tasks.contains(&task_type)
+    }
+
+    /// True if signing tasks of the group start without approval of its members
+    pub fn auto_approve(&self) -> bool {
+        self.auto_approve
+    }
+
+    pub fn set_auto_approve(&mut self, auto_approve: bool) {
+        self.auto_approve = auto_approve;
+    }
+
+    /// True if every member takes part in the tasks of the group, not only the threshold
+    pub fn require_all_signers(&self) -> bool {
+        self.require_all_signers
+    }
+
+    pub fn set_require_all_signers(&mut self, require_all_signers: bool) {
+        self.require_all_signers = require_all_signers;
+    }
+
+    /// Number of signing tasks the group has successfully finished
+    pub fn sign_count(&self) -> u64 {
+        self.sign_count
+    }
+
+    /// Unix timestamp of the last successfully finished signing task
+    pub fn last_signed(&self) -> Option<u64> {
+        self.last_signed
+    }
+
+    pub fn record_signature(&mut self, timestamp: u64) {
+        self.sign_count += 1;
+        self.last_signed = Some(timestamp);
+    }
+
+    /// Encode the group public key, which doubles as the group identifier, in `format`
+    pub fn export_key(&self, format: KeyFormat) -> Result<Vec<u8>, String> {
+        match (self.protocol, format) {
+            (ProtocolType::Gg18, KeyFormat::Sec1Compressed) => {
+                let (parity, x) = sec1_coordinates(&self.identifier)?;
+                let mut key = vec![0x02 | parity];
+                key.extend_from_slice(x);
+                Ok(key)
+            }
+            (ProtocolType::Gg18, KeyFormat::SpkiDer) => {
+                let curve = EcGroup::from_curve_name(Nid::SECP256K1).map_err(|e| e.to_string())?;
+                let mut ctx = BigNumContext::new().map_err(|e| e.to_string())?;
+                let point = EcPoint::from_bytes(&curve, &self.identifier, &mut ctx)
+                    .map_err(|_| "Invalid public key".to_string())?;
+                EcKey::from_public_key(&curve, &point)
+                    .and_then(PKey::from_ec_key)
+                    .and_then(|key| key.public_key_to_der())
+                    .map_err(|e| e.to_string())
+            }
+            (ProtocolType::Frost | ProtocolType::Musig2, KeyFormat::XOnly) => {
+                if self.identifier.len() == 32 {
+                    return Ok(self.identifier.clone());
+                }
+                let (_, x) = sec1_coordinates(&self.identifier)?;
+                Ok(x.to_vec())
+            }
+            (protocol, format) => Err(format!(
+                "Key format {} is not supported by {} groups",
+                format.as_str_name(),
+                protocol.as_str_name()
+            )),
+        }
+    }
+}
+
+/// Split a SEC1-encoded point into the parity of its y-coordinate and its x-coordinate
+fn sec1_coordinates(key: &[u8]) -> Result<(u8, &[u8]), String> {
+    match (key.len(), key.first()) {
+        (33, Some(0x02 | 0x03)) => Ok((key[0] & 1, &key[1..])),
+        (65, Some(0x04)) => Ok((key[64] & 1, &key[1..33])),
+        _ => Err("Unsupported public key encoding".into()),
+    }
 }
 
 impl From<&Group> for crate::proto::Group {
@@ -98,6 +293,17 @@ impl From<&Group> for crate::proto::Group {
             protocol: group.protocol().into(),
             key_type: group.key_type().into(),
             note: group.note().map(String::from),
+            degraded: group.is_degraded(),
+            tags: group.tags().to_vec(),
+            allowed_tasks: group
+                .allowed_tasks()
+                .iter()
+                .map(|task_type| (*task_type).into())
+                .collect(),
+            auto_approve: group.auto_approve(),
+            require_all_signers: group.require_all_signers(),
+            sign_count: group.sign_count(),
+            last_signed: group.last_signed(),
         }
     }
 }
@@ -105,6 +311,7 @@ impl From<&Group> for crate::proto::Group {
 #[cfg(test)]
 mod tests {
     use crate::proto::DeviceKind;
+    use openssl::ec::PointConversionForm;
 
     use super::*;
 
@@ -187,6 +394,64 @@ mod tests {
         assert_eq!(group.certificate(), None);
     }
 
+    #[test]
+    fn export_sec1_key() {
+        let (compressed, uncompressed) = prepare_public_key();
+        let group = prepare_key_group(uncompressed, ProtocolType::Gg18, KeyType::SignChallenge);
+        assert_eq!(
+            group.export_key(KeyFormat::Sec1Compressed).unwrap(),
+            compressed
+        );
+        let der = group.export_key(KeyFormat::SpkiDer).unwrap();
+        assert!(PKey::public_key_from_der(&der).is_ok());
+    }
+
+    #[test]
+    fn export_x_only_key() {
+        let (compressed, _) = prepare_public_key();
+        let group = prepare_key_group(
+            compressed.clone(),
+            ProtocolType::Frost,
+            KeyType::SignChallenge,
+        );
+        assert_eq!(group.export_key(KeyFormat::XOnly).unwrap(), compressed[1..]);
+    }
+
+    #[test]
+    fn export_incompatible_format() {
+        let (compressed, _) = prepare_public_key();
+        let group = prepare_key_group(compressed.clone(), ProtocolType::Gg18, KeyType::SignPdf);
+        assert!(group.export_key(KeyFormat::XOnly).is_err());
+        let group = prepare_key_group(compressed, ProtocolType::Musig2, KeyType::SignChallenge);
+        assert!(group.export_key(KeyFormat::Sec1Compressed).is_err());
+        assert!(group.export_key(KeyFormat::SpkiDer).is_err());
+    }
+
+    /// Generate a secp256k1 public key in compressed and uncompressed SEC1 encoding
+    fn prepare_public_key() -> (Vec<u8>, Vec<u8>) {
+        let curve = EcGroup::from_curve_name(Nid::SECP256K1).unwrap();
+        let key = EcKey::generate(&curve).unwrap();
+        let mut ctx = BigNumContext::new().unwrap();
+        let mut encode = |form| key.public_key().to_bytes(&curve, form, &mut ctx).unwrap();
+        (
+            encode(PointConversionForm::COMPRESSED),
+            encode(PointConversionForm::UNCOMPRESSED),
+        )
+    }
+
+    fn prepare_key_group(identifier: Vec<u8>, protocol: ProtocolType, key_type: KeyType) -> Group {
+        Group::new(
+            identifier,
+            String::from("Sample Group"),
+            prepare_devices(3),
+            2,
+            protocol,
+            key_type,
+            None,
+            None,
+        )
+    }
+
     fn prepare_devices(n: usize) -> Vec<Arc<Device>> {
         assert!(n < u8::MAX as usize);
         (0..n)
@@ -196,6 +461,7 @@ mod tests {
                     format!("d{}", i),
                     DeviceKind::User,
                     vec![0xf0 | i as u8],
+                    0,
                 ))
             })
             .collect()
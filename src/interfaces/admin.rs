@@ -0,0 +1,98 @@
+use openssl::memcmp;
+use std::time::{Duration, Instant};
+
+/// Longest period for which a replaced admin token remains valid
+pub const MAX_TOKEN_GRACE_PERIOD: Duration = Duration::from_secs(24 * 60 * 60);
+/// Maximum length of an admin token in bytes
+pub const MAX_TOKEN_LENGTH: usize = 256;
+
+/// Bearer tokens accepted by administrative RPCs
+pub struct AdminTokens {
+    /// The active token; administration is disabled when unset
+    current: Option<String>,
+    /// A replaced token together with the instant its grace period ends
+    previous: Option<(String, Instant)>,
+}
+
+impl AdminTokens {
+    pub fn new(token: Option<String>) -> Self {
+        AdminTokens {
+            current: token,
+            previous: None,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.current.is_some()
+    }
+
+    /// Check whether `provided` matches the active token or a replaced token still within its grace period
+    pub fn verify(&self, provided: &str, now: Instant) -> bool {
+        let previous = self
+            .previous
+            .as_ref()
+            .filter(|(_, expires)| now < *expires)
+            .map(|(token, _)| token);
+        self.current
+            .iter()
+            .chain(previous)
+            .any(|token| token_eq(token, provided))
+    }
+
+    /// Replace the active token, keeping the old one valid for `grace_period`
+    pub fn rotate(&mut self, token: String, grace_period: Duration, now: Instant) {
+        self.previous = self
+            .current
+            .replace(token)
+            .filter(|_| !grace_period.is_zero())
+            .map(|previous| (previous, now + grace_period));
+    }
+}
+
+/// Check that `token` can be sent in an authorization header
+pub fn validate_token(token: &str) -> bool {
+    !token.is_empty()
+        && token.len() <= MAX_TOKEN_LENGTH
+        && token.bytes().all(|x| x.is_ascii_graphic())
+}
+
+fn token_eq(expected: &str, provided: &str) -> bool {
+    expected.len() == provided.len() && memcmp::eq(expected.as_bytes(), provided.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotation_grace_period() {
+        let now = Instant::now();
+        let mut tokens = AdminTokens::new(Some("first".into()));
+        assert!(tokens.verify("first", now));
+
+        tokens.rotate("second".into(), Duration::from_secs(60), now);
+        assert!(tokens.verify("first", now + Duration::from_secs(59)));
+        assert!(tokens.verify("second", now));
+        assert!(!tokens.verify("first", now + Duration::from_secs(60)));
+        assert!(tokens.verify("second", now + Duration::from_secs(60)));
+
+        tokens.rotate("third".into(), Duration::ZERO, now);
+        assert!(!tokens.verify("second", now));
+        assert!(tokens.verify("third", now));
+    }
+
+    #[test]
+    fn disabled_tokens() {
+        let tokens = AdminTokens::new(None);
+        assert!(!tokens.is_enabled());
+        assert!(!tokens.verify("", Instant::now()));
+    }
+
+    #[test]
+    fn token_validation() {
+        assert!(validate_token("s3cr3t-t0k3n"));
+        assert!(!validate_token(""));
+        assert!(!validate_token("with space"));
+        assert!(!validate_token(&"x".repeat(MAX_TOKEN_LENGTH + 1)));
+    }
+}
@@ -1,4 +1,4 @@
-use log::{debug, info, warn};
+use flate2::write::GzEncoder;
 use openssl::asn1::{Asn1Integer, Asn1Time};
 use openssl::bn::BigNum;
 use openssl::hash::MessageDigest;
@@ -11,26 +11,78 @@ use rand::Rng;
 use tokio::sync::mpsc;
 use tokio::sync::Mutex;
 use tokio_stream::wrappers::ReceiverStream;
-use tokio_stream::Stream;
+use tokio_stream::{Stream, StreamExt};
 use tonic::codegen::Arc;
+use tonic::metadata::MetadataMap;
 use tonic::transport::{Certificate, Identity, Server, ServerTlsConfig};
-use tonic::{Request, Response, Status};
+use tonic::{Request, Response, Status, Streaming};
+use tracing::{debug, info, warn};
 use uuid::Uuid;
 
-use crate::proto::{DeviceKind, KeyType, MeeSign, MeeSignServer, ProtocolType};
-use crate::state::State;
-use crate::tasks::{Task, TaskStatus};
-use crate::{proto as msg, utils, CA_CERT, CA_KEY};
-
+use crate::communicator::WORK_VERSION;
+use crate::config::Config;
+use crate::device::validate_public_key;
+use crate::error::Error;
+use crate::group::Group;
+use crate::interfaces::admin::{self, AdminTokens, MAX_TOKEN_GRACE_PERIOD};
+use crate::interfaces::web;
+use crate::logging;
+use crate::proto::registration_result::Outcome as RegistrationOutcome;
+use crate::proto::{
+    Compression, DeliveryMode, DeviceKind, HashAlgorithm, KeyFormat, KeyType, MeeSign,
+    MeeSignServer, ProtocolType, TaskType,
+};
+use crate::protocols::rounds_for;
+use crate::state::{
+    check_allowed_tasks, lock_with_task, submit_device_work, update_task_unlocked, QuotaResource,
+    SignTemplate, State,
+};
+use crate::tasks::group::SUPPORTED_SCHEMES;
+use crate::tasks::sign_csr::MAX_CSR_SIZE;
+use crate::tasks::sign_pdf::MAX_PDF_SIZE;
+use crate::tasks::{Task, TaskFilter, TaskStatus};
+use crate::{proto as msg, CA_CERT, CA_KEY};
+
+use std::io::Write;
+use std::net::SocketAddr;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Time limit for receiving all chunks of a streamed signing request
+const SIGN_STREAM_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Number of tasks returned by a query which does not set a limit
+const DEFAULT_QUERY_LIMIT: u32 = 100;
+/// Largest number of tasks returned by a single query
+const MAX_QUERY_LIMIT: u32 = 1000;
 
 pub struct MeeSignService {
     state: Arc<Mutex<State>>,
+    /// Kept apart from `state` so that rotations do not contend with task processing
+    admin_tokens: Mutex<AdminTokens>,
+    /// Set while new tasks are rejected ahead of a planned upgrade
+    maintenance: AtomicBool,
 }
 
 impl MeeSignService {
-    pub fn new(state: Arc<Mutex<State>>) -> Self {
-        MeeSignService { state }
+    pub async fn new(state: Arc<Mutex<State>>) -> Self {
+        let admin_token = state.lock().await.get_config().admin_token.clone();
+        MeeSignService {
+            state,
+            admin_tokens: Mutex::new(AdminTokens::new(admin_token)),
+            maintenance: AtomicBool::new(false),
+        }
+    }
+
+    /// Reject the creation of new tasks while in maintenance mode
+    fn check_maintenance(&self) -> Result<(), Error> {
+        if self.maintenance.load(Ordering::Relaxed) {
+            return Err(Error::Unavailable(
+                "Server is in maintenance mode and does not accept new tasks".into(),
+            ));
+        }
+        Ok(())
     }
 
     async fn check_client_auth(
@@ -48,12 +100,88 @@ impl MeeSignService {
         }
         Ok(())
     }
+
+    async fn check_admin_auth(&self, metadata: &MetadataMap) -> Result<(), Status> {
+        let tokens = self.admin_tokens.lock().await;
+        if !tokens.is_enabled() {
+            return Err(Status::permission_denied("Administration is disabled"));
+        }
+        let provided = metadata
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or_else(|| Status::unauthenticated("Admin token required"))?;
+        if !tokens.verify(provided, Instant::now()) {
+            return Err(Status::unauthenticated("Invalid admin token"));
+        }
+        Ok(())
+    }
+
+    /// Authenticate the client by its certificate or admin token
+    ///
+    /// Only authenticated clients own the tasks they request; tasks requested
+    /// anonymously cannot be cancelled or fetched by their requester.
+    async fn authenticate_requester(
+        &self,
+        certs: Option<Arc<Vec<Certificate>>>,
+        metadata: &MetadataMap,
+    ) -> Option<String> {
+        if let Some(device_id) = certs.and_then(|certs| certs.first().map(cert_to_id)) {
+            return Some(hex::encode(device_id));
+        }
+        let token = metadata
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))?;
+        if self.admin_tokens.lock().await.verify(token, Instant::now()) {
+            return Some("admin".into());
+        }
+        None
+    }
+
+    /// Identify the client requesting a task by its certificate, admin token or address
+    ///
+    /// Clients without credentials are identified by their IP address only, so that
+    /// opening new connections does not reset their quotas.
+    async fn identify_requester(
+        &self,
+        certs: Option<Arc<Vec<Certificate>>>,
+        remote_addr: Option<SocketAddr>,
+        metadata: &MetadataMap,
+    ) -> Option<String> {
+        if let Some(requester) = self.authenticate_requester(certs, metadata).await {
+            return Some(requester);
+        }
+        remote_addr.map(|addr| addr.ip().to_string())
+    }
+
+    /// Create a task by `create` on behalf of `requester` and describe it to the client
+    ///
+    /// See [`State::add_requested_task`].
+    async fn create_task<F>(
+        &self,
+        requester: Option<String>,
+        priority: Option<u32>,
+        create: F,
+    ) -> Result<Response<msg::Task>, Status>
+    where
+        F: FnOnce(&mut State) -> Result<Uuid, Error>,
+    {
+        let mut state = self.state.lock().await;
+        let task_id = state.add_requested_task(requester, priority, create)?;
+        let task = state.get_task(&task_id).unwrap();
+        Ok(Response::new(
+            format_task(&task_id, task, None, None).map_err(Status::internal)?,
+        ))
+    }
 }
 
 #[tonic::async_trait]
 impl MeeSign for MeeSignService {
     type SubscribeUpdatesStream =
         Pin<Box<dyn Stream<Item = Result<msg::Task, Status>> + Send + 'static>>;
+    type GetAuditTrailStream =
+        Pin<Box<dyn Stream<Item = Result<msg::AuditRecord, Status>> + Send + 'static>>;
 
     async fn get_server_info(
         &self,
@@ -64,6 +192,57 @@ impl MeeSign for MeeSignService {
         debug!("ServerInfoRequest");
         Ok(Response::new(msg::ServerInfo {
             version: crate::VERSION.unwrap_or("unknown").to_string(),
+            work_version: WORK_VERSION.into(),
+            maintenance: self.maintenance.load(Ordering::Relaxed),
+        }))
+    }
+
+    async fn get_server_capabilities(
+        &self,
+        request: Request<msg::ServerCapabilitiesRequest>,
+    ) -> Result<Response<msg::ServerCapabilities>, Status> {
+        self.check_client_auth(&request.peer_certs(), false).await?;
+
+        debug!("ServerCapabilitiesRequest");
+        let mut protocols = Vec::new();
+        let mut key_types = Vec::new();
+        for (protocol, key_type) in SUPPORTED_SCHEMES {
+            if !protocols.contains(&protocol.into()) {
+                protocols.push(protocol.into());
+            }
+            if !key_types.contains(&key_type.into()) {
+                key_types.push(key_type.into());
+            }
+        }
+        Ok(Response::new(msg::ServerCapabilities {
+            version: crate::VERSION.unwrap_or("unknown").to_string(),
+            protocols,
+            key_types,
+            schemes: SUPPORTED_SCHEMES
+                .iter()
+                .map(|(protocol, key_type)| msg::server_capabilities::Scheme {
+                    protocol: (*protocol).into(),
+                    key_type: (*key_type).into(),
+                    group_rounds: rounds_for(*protocol, true).into(),
+                    task_rounds: rounds_for(*protocol, false).into(),
+                })
+                .collect(),
+            hash_algorithms: [
+                msg::HashAlgorithm::Default,
+                msg::HashAlgorithm::Sha256,
+                msg::HashAlgorithm::Sha512,
+                msg::HashAlgorithm::Keccak256,
+            ]
+            .into_iter()
+            .map(Into::into)
+            .collect(),
+            max_pdf_size: MAX_PDF_SIZE as u64,
+            max_csr_size: MAX_CSR_SIZE as u64,
+            work_version: WORK_VERSION.into(),
+            refresh: false,
+            reshare: false,
+            administration: self.admin_tokens.lock().await.is_enabled(),
+            compressions: SUPPORTED_COMPRESSIONS.into_iter().map(Into::into).collect(),
         }))
     }
 
@@ -72,26 +251,46 @@ impl MeeSign for MeeSignService {
         request: Request<msg::RegistrationRequest>,
     ) -> Result<Response<msg::RegistrationResponse>, Status> {
         self.check_client_auth(&request.peer_certs(), false).await?;
+        let requester = self
+            .identify_requester(
+                request.peer_certs(),
+                request.remote_addr(),
+                request.metadata(),
+            )
+            .await;
 
         let request = request.into_inner();
         let name = request.name;
         let kind = DeviceKind::try_from(request.kind).unwrap();
         let csr = request.csr;
-        info!("RegistrationRequest name={:?} kind={:?}", name, kind);
+        let public_key = request.public_key;
+        let delivery = DeliveryMode::try_from(request.delivery)
+            .map_err(|_| Status::invalid_argument("Unknown delivery mode"))?;
+        info!(name, ?kind, ?delivery, "RegistrationRequest");
+        if let Some(public_key) = &public_key {
+            validate_public_key(public_key).map_err(Status::invalid_argument)?;
+        }
 
         let mut state = self.state.lock().await;
+        state.check_quota(requester.as_deref(), QuotaResource::Devices, 1)?;
 
         if let Ok(certificate) = issue_certificate(&name, &csr) {
             let device_id = cert_to_id(&certificate);
-            if state.add_device(&device_id, &name, kind, &certificate) {
-                Ok(Response::new(msg::RegistrationResponse {
-                    device_id,
-                    certificate,
-                }))
-            } else {
-                Err(Status::failed_precondition(
+            match state.add_device(&device_id, &name, kind, &certificate, public_key.as_deref()) {
+                RegistrationOutcome::Registered => {
+                    state.set_device_registrant(&device_id, requester);
+                    state.set_delivery_mode(&device_id, delivery);
+                    Ok(Response::new(msg::RegistrationResponse {
+                        device_id,
+                        certificate,
+                    }))
+                }
+                RegistrationOutcome::NotAllowed => Err(Status::permission_denied(
+                    "Request failed: device is not on the registration allowlist",
+                )),
+                _ => Err(Status::failed_precondition(
                     "Request failed: device was not added",
-                ))
+                )),
             }
         } else {
             Err(Status::failed_precondition(
@@ -100,25 +299,361 @@ impl MeeSign for MeeSignService {
         }
     }
 
+    async fn rotate_device_identifier(
+        &self,
+        request: Request<msg::RotateIdentifierRequest>,
+    ) -> Result<Response<msg::RegistrationResponse>, Status> {
+        self.check_client_auth(&request.peer_certs(), true).await?;
+
+        let device_id = request
+            .peer_certs()
+            .and_then(|certs| certs.first().map(cert_to_id))
+            .unwrap();
+        let csr = request.into_inner().csr;
+        info!(device_id = %logging::log_id(&device_id), "RotateIdentifierRequest");
+
+        // the device may have been rotated or unregistered since it was authenticated
+        let name = self
+            .state
+            .lock()
+            .await
+            .get_devices()
+            .get(&device_id)
+            .map(|device| device.name().to_owned())
+            .ok_or_else(|| Status::not_found("Unknown device"))?;
+        let certificate = issue_certificate(&name, &csr).map_err(|_| {
+            Status::failed_precondition("Request failed: certificate was not created")
+        })?;
+        let new_id = cert_to_id(&certificate);
+        self.state
+            .lock()
+            .await
+            .rotate_device_identifier(&device_id, &new_id, &certificate)?;
+        Ok(Response::new(msg::RegistrationResponse {
+            device_id: new_id,
+            certificate,
+        }))
+    }
+
+    async fn register_batch(
+        &self,
+        request: Request<msg::RegisterBatchRequest>,
+    ) -> Result<Response<msg::RegisterBatchResponse>, Status> {
+        self.check_client_auth(&request.peer_certs(), false).await?;
+        self.check_admin_auth(request.metadata()).await?;
+
+        let invoker = describe_invoker(&request);
+        let devices = request.into_inner().devices;
+        info!(invoker, count = devices.len(), "RegisterBatchRequest");
+
+        let results = self
+            .state
+            .lock()
+            .await
+            .register_devices(&devices)?
+            .into_iter()
+            .map(|(device_id, outcome)| msg::RegistrationResult {
+                device_id,
+                outcome: outcome.into(),
+            })
+            .collect();
+        Ok(Response::new(msg::RegisterBatchResponse { results }))
+    }
+
     async fn sign(
         &self,
         request: Request<msg::SignRequest>,
     ) -> Result<Response<msg::Task>, Status> {
         self.check_client_auth(&request.peer_certs(), false).await?;
+        self.check_maintenance()?;
+        let requester = self
+            .identify_requester(
+                request.peer_certs(),
+                request.remote_addr(),
+                request.metadata(),
+            )
+            .await;
 
         let request = request.into_inner();
         let group_id = request.group_id;
         let name = request.name;
         let data = request.data;
-        info!("SignRequest group_id={}", utils::hextrunc(&group_id));
+        let deadline_seconds = request.deadline_seconds;
+        let hash_algorithm = HashAlgorithm::try_from(request.hash_algorithm)
+            .map_err(|_| Status::invalid_argument("Unknown hash algorithm"))?;
+        info!(
+            group_id = %logging::log_id(&group_id),
+            deadline_seconds,
+            ?hash_algorithm,
+            requester = requester.as_deref().map(logging::log_requester),
+            "SignRequest"
+        );
+
+        let dependency = match request.depends_on {
+            Some(_) if !group_id.is_empty() => {
+                return Err(Status::invalid_argument(
+                    "Dependent task must not name its group",
+                ));
+            }
+            Some(task_id) => Some(
+                Uuid::from_slice(&task_id)
+                    .map_err(|_| Status::invalid_argument("Invalid task id"))?,
+            ),
+            None => None,
+        };
+
+        self.create_task(
+            requester,
+            Some(request.priority),
+            |state| match dependency {
+                Some(dependency) => state.add_dependent_sign_task(
+                    &dependency,
+                    &name,
+                    &data,
+                    deadline_seconds,
+                    hash_algorithm,
+                ),
+                None => {
+                    state.add_sign_task(&group_id, &name, &data, deadline_seconds, hash_algorithm)
+                }
+            },
+        )
+        .await
+    }
+
+    async fn create_sign_template(
+        &self,
+        request: Request<msg::SignTemplate>,
+    ) -> Result<Response<msg::SignTemplate>, Status> {
+        self.check_client_auth(&request.peer_certs(), false).await?;
+        self.check_admin_auth(request.metadata()).await?;
+
+        let invoker = describe_invoker(&request);
+        let request = request.into_inner();
+        let hash_algorithm = HashAlgorithm::try_from(request.hash_algorithm)
+            .map_err(|_| Status::invalid_argument("Unknown hash algorithm"))?;
+        info!(invoker, name = request.name, "CreateSignTemplateRequest");
+
+        self.state.lock().await.add_sign_template(
+            &request.name,
+            SignTemplate {
+                group_id: request.group_id.clone(),
+                hash_algorithm,
+                deadline_seconds: request.deadline_seconds,
+                priority: request.priority,
+            },
+        )?;
+        Ok(Response::new(request))
+    }
+
+    async fn sign_from_template(
+        &self,
+        request: Request<msg::SignFromTemplateRequest>,
+    ) -> Result<Response<msg::Task>, Status> {
+        self.check_client_auth(&request.peer_certs(), false).await?;
+        self.check_maintenance()?;
+        let requester = self
+            .identify_requester(
+                request.peer_certs(),
+                request.remote_addr(),
+                request.metadata(),
+            )
+            .await;
+
+        let request = request.into_inner();
+        info!(
+            template = request.template,
+            requester = requester.as_deref().map(logging::log_requester),
+            "SignFromTemplateRequest"
+        );
+
+        self.create_task(requester, None, |state| {
+            state.add_sign_task_from_template(&request.template, &request.name, &request.data)
+        })
+        .await
+    }
+
+    async fn sign_stream(
+        &self,
+        request: Request<Streaming<msg::SignChunk>>,
+    ) -> Result<Response<msg::Task>, Status> {
+        self.check_client_auth(&request.peer_certs(), false).await?;
+        self.check_maintenance()?;
+        let requester = self
+            .identify_requester(
+                request.peer_certs(),
+                request.remote_addr(),
+                request.metadata(),
+            )
+            .await;
+
+        let request = tokio::time::timeout(
+            SIGN_STREAM_TIMEOUT,
+            collect_sign_chunks(request.into_inner()),
+        )
+        .await
+        .map_err(|_| Status::deadline_exceeded("Signing payload was not received in time"))??;
+        let hash_algorithm = HashAlgorithm::try_from(request.hash_algorithm)
+            .map_err(|_| Status::invalid_argument("Unknown hash algorithm"))?;
+        info!(
+            group_id = %logging::log_id(&request.group_id),
+            len = request.data.len(),
+            ?hash_algorithm,
+            requester = requester.as_deref().map(logging::log_requester),
+            "SignStreamRequest"
+        );
+
+        self.create_task(requester, Some(request.priority), |state| {
+            state.add_sign_task(
+                &request.group_id,
+                &request.name,
+                &request.data,
+                request.deadline_seconds,
+                hash_algorithm,
+            )
+        })
+        .await
+    }
+
+    async fn sign_csr(
+        &self,
+        request: Request<msg::SignCsrRequest>,
+    ) -> Result<Response<msg::Task>, Status> {
+        self.check_client_auth(&request.peer_certs(), false).await?;
+        self.check_maintenance()?;
+        let requester = self
+            .identify_requester(
+                request.peer_certs(),
+                request.remote_addr(),
+                request.metadata(),
+            )
+            .await;
+
+        let request = request.into_inner();
+        info!(
+            group_id = %logging::log_id(&request.group_id),
+            len = request.csr.len(),
+            deadline_seconds = request.deadline_seconds,
+            requester = requester.as_deref().map(logging::log_requester),
+            "SignCsrRequest"
+        );
+
+        self.create_task(requester, Some(request.priority), |state| {
+            state.add_sign_csr_task(
+                &request.group_id,
+                &request.name,
+                &request.csr,
+                request.deadline_seconds,
+            )
+        })
+        .await
+    }
+
+    async fn sign_ethereum_tx(
+        &self,
+        request: Request<msg::SignEthereumTxRequest>,
+    ) -> Result<Response<msg::Task>, Status> {
+        self.check_client_auth(&request.peer_certs(), false).await?;
+        self.check_maintenance()?;
+        let requester = self
+            .identify_requester(
+                request.peer_certs(),
+                request.remote_addr(),
+                request.metadata(),
+            )
+            .await;
+
+        let request = request.into_inner();
+        info!(
+            group_id = %logging::log_id(&request.group_id),
+            len = request.transaction.len(),
+            chain_id = request.chain_id,
+            deadline_seconds = request.deadline_seconds,
+            requester = requester.as_deref().map(logging::log_requester),
+            "SignEthereumTxRequest"
+        );
+
+        self.create_task(requester, Some(request.priority), |state| {
+            state.add_sign_ethereum_tx_task(
+                &request.group_id,
+                &request.name,
+                &request.transaction,
+                request.chain_id,
+                request.deadline_seconds,
+            )
+        })
+        .await
+    }
+
+    async fn sign_multi_group(
+        &self,
+        request: Request<msg::SignMultiGroupRequest>,
+    ) -> Result<Response<msg::MultiGroupTask>, Status> {
+        self.check_client_auth(&request.peer_certs(), false).await?;
+        self.check_maintenance()?;
+        let requester = self
+            .identify_requester(
+                request.peer_certs(),
+                request.remote_addr(),
+                request.metadata(),
+            )
+            .await;
+
+        let request = request.into_inner();
+        info!(
+            group_ids = ?request
+                .group_ids
+                .iter()
+                .map(logging::log_id)
+                .collect::<Vec<String>>(),
+            requester = requester.as_deref().map(logging::log_requester),
+            "SignMultiGroupRequest"
+        );
 
         let mut state = self.state.lock().await;
-        if let Some(task_id) = state.add_sign_task(&group_id, &name, &data) {
-            let task = state.get_task(&task_id).unwrap();
-            Ok(Response::new(format_task(&task_id, task, None, None)))
-        } else {
-            Err(Status::failed_precondition("Request failed"))
+        state.check_quota(
+            requester.as_deref(),
+            QuotaResource::ActiveTasks,
+            request.group_ids.len(),
+        )?;
+        let multi_task_id = state.add_multi_group_sign_task(
+            &request.group_ids,
+            &request.name,
+            &request.data,
+            request.deadline_seconds,
+        )?;
+        let task_ids: Vec<_> = state
+            .get_multi_group_task(&multi_task_id)?
+            .into_iter()
+            .map(|(task_id, _)| task_id)
+            .collect();
+        for task_id in &task_ids {
+            state.adopt_requested_task(task_id, requester.clone(), Some(request.priority));
         }
+        let tasks = state.get_multi_group_task(&multi_task_id)?;
+        Ok(Response::new(
+            format_multi_group_task(&multi_task_id, &tasks).map_err(Status::internal)?,
+        ))
+    }
+
+    async fn get_multi_group_task(
+        &self,
+        request: Request<msg::MultiGroupTaskRequest>,
+    ) -> Result<Response<msg::MultiGroupTask>, Status> {
+        self.check_client_auth(&request.peer_certs(), false).await?;
+
+        let multi_task_id = Uuid::from_slice(&request.into_inner().id)
+            .map_err(|_| Status::invalid_argument("Invalid multi-group task id"))?;
+        debug!(
+            multi_task_id = %logging::log_id(multi_task_id.as_bytes()),
+            "MultiGroupTaskRequest"
+        );
+
+        let state = self.state.lock().await;
+        let tasks = state.get_multi_group_task(&multi_task_id)?;
+        Ok(Response::new(
+            format_multi_group_task(&multi_task_id, &tasks).map_err(Status::internal)?,
+        ))
     }
 
     async fn decrypt(
@@ -126,21 +661,30 @@ impl MeeSign for MeeSignService {
         request: Request<msg::DecryptRequest>,
     ) -> Result<Response<msg::Task>, Status> {
         self.check_client_auth(&request.peer_certs(), false).await?;
+        self.check_maintenance()?;
+        let requester = self
+            .identify_requester(
+                request.peer_certs(),
+                request.remote_addr(),
+                request.metadata(),
+            )
+            .await;
 
         let request = request.into_inner();
         let group_id = request.group_id;
         let name = request.name;
         let data = request.data;
         let data_type = request.data_type;
-        info!("DecryptRequest group_id={}", utils::hextrunc(&group_id));
+        info!(
+            group_id = %logging::log_id(&group_id),
+            requester = requester.as_deref().map(logging::log_requester),
+            "DecryptRequest"
+        );
 
-        let mut state = self.state.lock().await;
-        if let Some(task_id) = state.add_decrypt_task(&group_id, &name, &data, &data_type) {
-            let task = state.get_task(&task_id).unwrap();
-            Ok(Response::new(format_task(&task_id, task, None, None)))
-        } else {
-            Err(Status::failed_precondition("Request failed"))
-        }
+        self.create_task(requester, Some(request.priority), |state| {
+            state.add_decrypt_task(&group_id, &name, &data, &data_type)
+        })
+        .await
     }
 
     async fn get_task(
@@ -150,7 +694,10 @@ impl MeeSign for MeeSignService {
         self.check_client_auth(&request.peer_certs(), false).await?;
 
         let request = request.into_inner();
-        let task_id = Uuid::from_slice(&request.task_id).unwrap();
+        check_work_version(request.work_version)?;
+        let task_id = Uuid::from_slice(&request.task_id)
+            .map_err(|_| Status::invalid_argument("Invalid task id"))?;
+        let request_compressions = request.accepted_compressions;
         let device_id = request.device_id;
         let device_id = if device_id.is_none() {
             None
@@ -158,19 +705,122 @@ impl MeeSign for MeeSignService {
             Some(device_id.as_ref().unwrap().as_slice())
         };
         debug!(
-            "TaskRequest task_id={} device_id={}",
-            utils::hextrunc(task_id.as_bytes()),
-            utils::hextrunc(device_id.unwrap_or(&[]))
+            task_id = %logging::log_id(task_id.as_bytes()),
+            device_id = %logging::log_id(device_id.unwrap_or(&[])),
+            "TaskRequest"
         );
 
-        let state = self.state.lock().await;
-        let task = state.get_task(&task_id).unwrap();
+        let state = lock_with_task(&self.state, &task_id).await;
+        let task = state
+            .get_task(&task_id)
+            .ok_or_else(|| Status::not_found("Unknown task"))?;
         let request = Some(task.get_request());
 
-        let resp = format_task(&task_id, task, device_id, request);
+        let mut resp = format_task(&task_id, task, device_id, request).map_err(Status::internal)?;
+        compress_data(&mut resp, negotiate_compression(&request_compressions));
         Ok(Response::new(resp))
     }
 
+    async fn cancel_group(
+        &self,
+        request: Request<msg::CancelGroupRequest>,
+    ) -> Result<Response<msg::Resp>, Status> {
+        self.check_client_auth(&request.peer_certs(), false).await?;
+        let requester = self
+            .authenticate_requester(request.peer_certs(), request.metadata())
+            .await;
+
+        let task_id = Uuid::from_slice(&request.into_inner().task_id)
+            .map_err(|_| Status::invalid_argument("Invalid task id"))?;
+        info!(
+            task_id = %logging::log_id(task_id.as_bytes()),
+            requester = requester.as_deref().map(logging::log_requester),
+            "CancelGroupRequest"
+        );
+
+        let mut state = lock_with_task(&self.state, &task_id).await;
+        let task = state
+            .get_task(&task_id)
+            .ok_or_else(|| Status::not_found("Unknown task"))?;
+        if requester.is_none() || task.requester() != requester.as_deref() {
+            return Err(Status::permission_denied(
+                "Only the requester may cancel the task",
+            ));
+        }
+        state.cancel_group_task(&task_id)?;
+        Ok(Response::new(msg::Resp {
+            message: "OK".into(),
+        }))
+    }
+
+    async fn get_task_participants(
+        &self,
+        request: Request<msg::TaskParticipantsRequest>,
+    ) -> Result<Response<msg::TaskParticipants>, Status> {
+        self.check_client_auth(&request.peer_certs(), false).await?;
+
+        let task_id = Uuid::from_slice(&request.into_inner().task_id)
+            .map_err(|_| Status::invalid_argument("Invalid task id"))?;
+        debug!(
+            task_id = %logging::log_id(task_id.as_bytes()),
+            "TaskParticipantsRequest"
+        );
+
+        let state = lock_with_task(&self.state, &task_id).await;
+        let participants = state.get_task_participants(&task_id)?;
+        let task = format_task(&task_id, state.get_task(&task_id).unwrap(), None, None)
+            .map_err(Status::internal)?;
+        Ok(Response::new(msg::TaskParticipants {
+            state: task.state,
+            round: task.round,
+            participants,
+        }))
+    }
+
+    async fn get_result(
+        &self,
+        request: Request<msg::TaskResultRequest>,
+    ) -> Result<Response<msg::TaskResultResponse>, Status> {
+        self.check_client_auth(&request.peer_certs(), false).await?;
+        let device_id = request
+            .peer_certs()
+            .and_then(|certs| certs.first().map(cert_to_id));
+        let requester = self
+            .authenticate_requester(request.peer_certs(), request.metadata())
+            .await;
+
+        let task_id = Uuid::from_slice(&request.into_inner().task_id)
+            .map_err(|_| Status::invalid_argument("Invalid task id"))?;
+        debug!(
+            task_id = %logging::log_id(task_id.as_bytes()),
+            requester = requester.as_deref().map(logging::log_requester),
+            "TaskResultRequest"
+        );
+
+        let state = lock_with_task(&self.state, &task_id).await;
+        let task = state
+            .get_task(&task_id)
+            .ok_or_else(|| Status::not_found("Unknown task"))?;
+        let is_participant = device_id.is_some_and(|device_id| task.has_device(&device_id));
+        let is_requester = requester.is_some() && task.requester() == requester.as_deref();
+        if !is_participant && !is_requester {
+            return Err(Status::permission_denied(
+                "Only the requester or a participant may fetch the result",
+            ));
+        }
+        if task.get_status() != TaskStatus::Finished {
+            return Err(Status::failed_precondition("Task has not finished"));
+        }
+        let result = task
+            .get_result()
+            .ok_or_else(|| Status::internal("Finished task has no result"))?;
+        Ok(Response::new(msg::TaskResultResponse {
+            task_id: task_id.as_bytes().to_vec(),
+            r#type: task.get_type().into(),
+            data: result.as_bytes().to_vec(),
+        }))
+    }
+
     async fn update_task(
         &self,
         request: Request<msg::TaskUpdate>,
@@ -188,29 +838,103 @@ impl MeeSign for MeeSignService {
         let attempt = request.attempt;
         if data.is_empty() {
             warn!(
-                "TaskUpdate task_id={} device_id={} attempt={} data empty",
-                utils::hextrunc(task_id.as_bytes()),
-                utils::hextrunc(&device_id),
-                attempt
+                task_id = %logging::log_id(task_id.as_bytes()),
+                device_id = %logging::log_id(&device_id),
+                attempt,
+                "TaskUpdate data empty"
             );
             return Err(Status::invalid_argument("Data must not be empty"));
         }
         debug!(
-            "TaskUpdate task_id={} device_id={} attempt={}",
-            utils::hextrunc(task_id.as_bytes()),
-            utils::hextrunc(&device_id),
-            attempt
+            task_id = %logging::log_id(task_id.as_bytes()),
+            device_id = %logging::log_id(&device_id),
+            attempt,
+            "TaskUpdate"
         );
 
-        let mut state = self.state.lock().await;
-        let result = state.update_task(&task_id, &device_id, &data, attempt);
+        update_task_unlocked(
+            self.state.clone(),
+            task_id,
+            device_id,
+            data,
+            attempt,
+            request.sequence,
+        )
+        .await?;
+        Ok(Response::new(msg::Resp {
+            message: "OK".into(),
+        }))
+    }
 
-        match result {
-            Ok(_) => Ok(Response::new(msg::Resp {
-                message: "OK".into(),
-            })),
-            Err(e) => Err(Status::failed_precondition(e)),
-        }
+    async fn get_all_work(
+        &self,
+        request: Request<msg::AllWorkRequest>,
+    ) -> Result<Response<msg::WorkBatch>, Status> {
+        self.check_client_auth(&request.peer_certs(), true).await?;
+
+        let device_id = request
+            .peer_certs()
+            .and_then(|certs| certs.first().map(cert_to_id))
+            .unwrap();
+        debug!(device_id = %logging::log_id(&device_id), "AllWorkRequest");
+        check_work_version(request.get_ref().work_version)?;
+
+        let work = self.state.lock().await.get_device_work(&device_id);
+        Ok(Response::new(msg::WorkBatch { work }))
+    }
+
+    async fn resend_work(
+        &self,
+        request: Request<msg::ResendWorkRequest>,
+    ) -> Result<Response<msg::Work>, Status> {
+        self.check_client_auth(&request.peer_certs(), true).await?;
+
+        let device_id = request
+            .peer_certs()
+            .and_then(|certs| certs.first().map(cert_to_id))
+            .unwrap();
+        let request = request.into_inner();
+        check_work_version(request.work_version)?;
+        let task_id = Uuid::from_slice(&request.task_id)
+            .map_err(|_| Status::invalid_argument("Invalid task id"))?;
+        debug!(
+            device_id = %logging::log_id(&device_id),
+            task_id = %logging::log_id(task_id.as_bytes()),
+            "ResendWorkRequest"
+        );
+
+        let work = self.state.lock().await.resend_work(&task_id, &device_id)?;
+        Ok(Response::new(work))
+    }
+
+    async fn submit_all_work(
+        &self,
+        request: Request<msg::WorkBatch>,
+    ) -> Result<Response<msg::WorkBatchResult>, Status> {
+        self.check_client_auth(&request.peer_certs(), true).await?;
+
+        let device_id = request
+            .peer_certs()
+            .and_then(|certs| certs.first().map(cert_to_id))
+            .unwrap();
+        let work = request.into_inner().work;
+        debug!(
+            device_id = %logging::log_id(&device_id),
+            count = work.len(),
+            "WorkBatch"
+        );
+
+        let results = submit_device_work(self.state.clone(), &device_id, &work).await;
+        let outcomes = work
+            .into_iter()
+            .zip(results)
+            .map(|(work, result)| msg::work_batch_result::Outcome {
+                task_id: work.task_id,
+                accepted: result.is_ok(),
+                error: result.err(),
+            })
+            .collect();
+        Ok(Response::new(msg::WorkBatchResult { outcomes }))
     }
 
     async fn get_tasks(
@@ -223,9 +947,9 @@ impl MeeSign for MeeSignService {
         let device_id = request.device_id;
         let device_str = device_id
             .as_ref()
-            .map(utils::hextrunc)
+            .map(logging::log_id)
             .unwrap_or_else(|| "unknown".to_string());
-        debug!("TasksRequest device_id={}", device_str);
+        debug!(device_id = %device_str, "TasksRequest");
 
         let state = self.state.lock().await;
         let tasks = if let Some(device_id) = device_id {
@@ -233,18 +957,60 @@ impl MeeSign for MeeSignService {
                 .get_device_tasks(&device_id)
                 .iter()
                 .map(|(task_id, task)| format_task(task_id, *task, Some(&device_id), None))
-                .collect()
+                .collect::<Result<_, _>>()
         } else {
             state
                 .get_tasks()
                 .iter()
                 .map(|(task_id, task)| format_task(task_id, task.as_ref(), None, None))
                 .collect()
-        };
+        }
+        .map_err(Status::internal)?;
 
         Ok(Response::new(msg::Tasks { tasks }))
     }
 
+    async fn query_tasks(
+        &self,
+        request: Request<msg::QueryTasksRequest>,
+    ) -> Result<Response<msg::QueryTasksResponse>, Status> {
+        self.check_client_auth(&request.peer_certs(), false).await?;
+
+        let request = request.into_inner();
+        let state_filter = match request.state {
+            Some(state) => Some(
+                msg::task::TaskState::try_from(state)
+                    .map_err(|_| Status::invalid_argument("Unknown task state"))?,
+            ),
+            None => None,
+        };
+        let filter = TaskFilter {
+            state: state_filter,
+            group_id: request.group_id,
+            created_after: request.created_after,
+            created_before: request.created_before,
+            device_id: request.device_id,
+        };
+        let limit = match request.limit {
+            0 => DEFAULT_QUERY_LIMIT,
+            limit => limit.min(MAX_QUERY_LIMIT),
+        };
+        debug!(?filter, offset = request.offset, limit, "QueryTasksRequest");
+
+        let state = self.state.lock().await;
+        let (tasks, total) = state.query_tasks(&filter, request.offset as usize, limit as usize);
+        let tasks = tasks
+            .into_iter()
+            .map(|(task_id, task)| format_task(&task_id, task, None, None))
+            .collect::<Result<_, _>>()
+            .map_err(Status::internal)?;
+
+        Ok(Response::new(msg::QueryTasksResponse {
+            tasks,
+            total: total as u32,
+        }))
+    }
+
     async fn get_groups(
         &self,
         request: Request<msg::GroupsRequest>,
@@ -253,86 +1019,296 @@ impl MeeSign for MeeSignService {
 
         let request = request.into_inner();
         let device_id = request.device_id;
+        let tag = request.tag;
         let device_str = device_id
             .as_ref()
-            .map(utils::hextrunc)
+            .map(logging::log_id)
             .unwrap_or_else(|| "unknown".to_string());
-        debug!("GroupsRequest device_id={}", device_str);
+        debug!(device_id = %device_str, tag, "GroupsRequest");
 
         let state = self.state.lock().await;
+        let has_tag = |group: &&Group| tag.as_ref().is_none_or(|tag| group.tags().contains(tag));
         let groups = if let Some(device_id) = device_id {
             state
                 .get_device_groups(&device_id)
                 .iter()
+                .filter(has_tag)
                 .map(|group| group.into())
                 .collect()
         } else {
-            state
-                .get_groups()
-                .values()
-                .map(|group| group.into())
-                .collect()
+            let mut groups: Vec<&Group> = state.get_groups().values().filter(has_tag).collect();
+            groups.sort_by_key(|group| group.listing_key());
+            groups.into_iter().map(|group| group.into()).collect()
         };
 
         Ok(Response::new(msg::Groups { groups }))
     }
 
-    async fn group(
+    async fn set_group_tags(
         &self,
-        request: Request<msg::GroupRequest>,
-    ) -> Result<Response<msg::Task>, Status> {
+        request: Request<msg::GroupTagsRequest>,
+    ) -> Result<Response<msg::Group>, Status> {
         self.check_client_auth(&request.peer_certs(), false).await?;
+        self.check_admin_auth(request.metadata()).await?;
 
+        let invoker = describe_invoker(&request);
         let request = request.into_inner();
-        let name = request.name;
-        let device_ids = request.device_ids;
-        let threshold = request.threshold;
-        let protocol = ProtocolType::try_from(request.protocol).unwrap();
-        let key_type = KeyType::try_from(request.key_type).unwrap();
-        let note = request.note;
-
-        info!(
-            "GroupRequest name={:?} device_ids={:?} threshold={}",
-            &name,
-            device_ids
-                .iter()
-                .map(utils::hextrunc)
-                .collect::<Vec<String>>(),
-            threshold
+        warn!(
+            invoker,
+            group_id = %logging::log_id(&request.group_id),
+            tags = ?request.tags,
+            "GroupTagsRequest"
         );
 
         let mut state = self.state.lock().await;
-        if let Some(task_id) =
-            state.add_group_task(&name, &device_ids, threshold, protocol, key_type, &note)
-        {
-            let task = state.get_task(&task_id).unwrap();
-            Ok(Response::new(format_task(&task_id, task, None, None)))
-        } else {
-            Err(Status::failed_precondition("Request failed"))
-        }
+        let group = state.set_group_tags(&request.group_id, &request.tags)?;
+        Ok(Response::new(group.into()))
     }
 
-    async fn get_devices(
+    async fn set_group_usage(
         &self,
-        request: Request<msg::DevicesRequest>,
-    ) -> Result<Response<msg::Devices>, Status> {
+        request: Request<msg::GroupUsageRequest>,
+    ) -> Result<Response<msg::Group>, Status> {
         self.check_client_auth(&request.peer_certs(), false).await?;
+        self.check_admin_auth(request.metadata()).await?;
 
-        debug!("DevicesRequest");
+        let invoker = describe_invoker(&request);
+        let request = request.into_inner();
+        let allowed_tasks = parse_task_types(&request.allowed_tasks)?;
+        warn!(
+            invoker,
+            group_id = %logging::log_id(&request.group_id),
+            ?allowed_tasks,
+            "GroupUsageRequest"
+        );
+
+        let mut state = self.state.lock().await;
+        let group = state.set_group_usage(&request.group_id, &allowed_tasks)?;
+        Ok(Response::new(group.into()))
+    }
+
+    async fn leave_all_groups(
+        &self,
+        request: Request<msg::LeaveAllGroupsRequest>,
+    ) -> Result<Response<msg::LeaveAllGroupsResponse>, Status> {
+        self.check_client_auth(&request.peer_certs(), true).await?;
+
+        let device_id = request
+            .peer_certs()
+            .and_then(|certs| certs.first().map(cert_to_id))
+            .unwrap();
+        info!(device_id = %logging::log_id(&device_id), "LeaveAllGroupsRequest");
+
+        let groups = self
+            .state
+            .lock()
+            .await
+            .leave_all_groups(&device_id)?
+            .into_iter()
+            .map(|(group_id, outcome)| msg::GroupLeave {
+                group_id,
+                outcome: outcome.into(),
+            })
+            .collect();
+        Ok(Response::new(msg::LeaveAllGroupsResponse { groups }))
+    }
+
+    async fn export_group_key(
+        &self,
+        request: Request<msg::GroupKeyRequest>,
+    ) -> Result<Response<msg::GroupKey>, Status> {
+        self.check_client_auth(&request.peer_certs(), false).await?;
+
+        let request = request.into_inner();
+        let group_id = request.group_id;
+        let format = KeyFormat::try_from(request.format)
+            .map_err(|_| Status::invalid_argument("Unknown key format"))?;
+        debug!(group_id = %logging::log_id(&group_id), ?format, "GroupKeyRequest");
+
+        let state = self.state.lock().await;
+        let key = state.export_group_key(&group_id, format)?;
+        let group = state.find_group(&group_id).unwrap();
+        Ok(Response::new(msg::GroupKey {
+            key,
+            format: format.into(),
+            sign_count: group.sign_count(),
+            last_signed: group.last_signed(),
+        }))
+    }
+
+    async fn group(
+        &self,
+        request: Request<msg::GroupRequest>,
+    ) -> Result<Response<msg::Task>, Status> {
+        self.check_client_auth(&request.peer_certs(), false).await?;
+        self.check_maintenance()?;
+        let requester = self
+            .identify_requester(
+                request.peer_certs(),
+                request.remote_addr(),
+                request.metadata(),
+            )
+            .await;
+        if request.get_ref().auto_approve {
+            self.check_admin_auth(request.metadata()).await?;
+        }
+
+        let request = request.into_inner();
+        let name = request.name;
+        let device_ids = request.device_ids;
+        let threshold = request.threshold;
+        let protocol = ProtocolType::try_from(request.protocol).unwrap();
+        let key_type = KeyType::try_from(request.key_type).unwrap();
+        let note = request.note;
+        let tags = request.tags;
+        let deadline_seconds = request.deadline_seconds;
+        let allowed_tasks = check_allowed_tasks(&parse_task_types(&request.allowed_tasks)?)?;
+
+        info!(
+            name,
+            device_ids = ?device_ids
+                .iter()
+                .map(logging::log_id)
+                .collect::<Vec<String>>(),
+            threshold,
+            ?tags,
+            auto_approve = request.auto_approve,
+            require_all_signers = request.require_all_signers,
+            requester = requester.as_deref().map(logging::log_requester),
+            "GroupRequest"
+        );
+
+        let quota_requester = requester.clone();
+        self.create_task(requester, Some(request.priority), |state| {
+            state.check_quota(quota_requester.as_deref(), QuotaResource::Groups, 1)?;
+            let task_id = state.add_group_task(
+                &name,
+                &device_ids,
+                threshold,
+                protocol,
+                key_type,
+                &note,
+                &tags,
+                deadline_seconds,
+            )?;
+            state.restrict_group_task(&task_id, allowed_tasks);
+            if request.auto_approve {
+                state.auto_approve_group_task(&task_id);
+            }
+            if request.require_all_signers {
+                state.require_all_signers_group_task(&task_id);
+            }
+            Ok(task_id)
+        })
+        .await
+    }
+
+    async fn refresh_group(
+        &self,
+        request: Request<msg::RefreshGroupRequest>,
+    ) -> Result<Response<msg::Task>, Status> {
+        self.check_client_auth(&request.peer_certs(), false).await?;
+
+        let group_id = request.into_inner().group_id;
+        info!(group_id = %logging::log_id(&group_id), "RefreshGroupRequest");
+
+        if self.state.lock().await.find_group(&group_id).is_none() {
+            return Err(Status::not_found("Unknown group"));
+        }
+        // share refresh runs on the devices and meesign-crypto has no resharing protocol yet
+        Err(Status::unimplemented(
+            "Key refresh is not supported by the client protocol library",
+        ))
+    }
 
+    async fn reshare_group(
+        &self,
+        request: Request<msg::ReshareGroupRequest>,
+    ) -> Result<Response<msg::Task>, Status> {
+        self.check_client_auth(&request.peer_certs(), false).await?;
+
+        let request = request.into_inner();
+        info!(
+            group_id = %logging::log_id(&request.group_id),
+            device_ids = ?request
+                .device_ids
+                .iter()
+                .map(logging::log_id)
+                .collect::<Vec<String>>(),
+            threshold = request.threshold,
+            "ReshareGroupRequest"
+        );
+
+        self.state.lock().await.check_reshare(
+            &request.group_id,
+            &request.device_ids,
+            request.threshold,
+        )?;
+        // resharing runs on the devices and meesign-crypto has no resharing protocol yet
+        Err(Status::unimplemented(
+            "Resharing is not supported by the client protocol library",
+        ))
+    }
+
+    async fn get_devices(
+        &self,
+        request: Request<msg::DevicesRequest>,
+    ) -> Result<Response<msg::Devices>, Status> {
+        self.check_client_auth(&request.peer_certs(), false).await?;
+
+        debug!("DevicesRequest");
+
+        let state = self.state.lock().await;
         let resp = msg::Devices {
-            devices: self
-                .state
-                .lock()
-                .await
+            devices: state
                 .get_devices()
                 .values()
-                .map(|device| device.as_ref().into())
+                .map(|device| msg::Device {
+                    subscribed: state.is_subscribed(device.identifier()),
+                    delivery: state.delivery_mode(device.identifier()).into(),
+                    ..device.as_ref().into()
+                })
                 .collect(),
         };
         Ok(Response::new(resp))
     }
 
+    async fn get_device_info(
+        &self,
+        request: Request<msg::DeviceInfoRequest>,
+    ) -> Result<Response<msg::DeviceInfo>, Status> {
+        self.check_client_auth(&request.peer_certs(), false).await?;
+
+        let device_id = request.into_inner().device_id;
+        debug!(device_id = %logging::log_id(&device_id), "DeviceInfoRequest");
+
+        let state = self.state.lock().await;
+        let info = format_device_info(&state, &device_id)
+            .ok_or_else(|| Status::not_found("Unknown device"))?;
+        Ok(Response::new(info))
+    }
+
+    async fn reset_circuit_breaker(
+        &self,
+        request: Request<msg::DeviceInfoRequest>,
+    ) -> Result<Response<msg::DeviceInfo>, Status> {
+        self.check_client_auth(&request.peer_certs(), false).await?;
+        self.check_admin_auth(request.metadata()).await?;
+
+        let invoker = describe_invoker(&request);
+        let device_id = request.into_inner().device_id;
+        let mut state = self.state.lock().await;
+        state.reset_circuit_breaker(&device_id)?;
+        warn!(
+            invoker,
+            device_id = %logging::log_id(&device_id),
+            "Circuit breaker reset"
+        );
+        let info = format_device_info(&state, &device_id)
+            .ok_or_else(|| Status::not_found("Unknown device"))?;
+        Ok(Response::new(info))
+    }
+
     async fn log(&self, request: Request<msg::LogRequest>) -> Result<Response<msg::Resp>, Status> {
         self.check_client_auth(&request.peer_certs(), false).await?;
 
@@ -342,16 +1318,42 @@ impl MeeSign for MeeSignService {
 
         let device_str = device_id
             .as_ref()
-            .map(utils::hextrunc)
+            .map(logging::log_id)
             .unwrap_or_else(|| "unknown".to_string());
         let message = request.into_inner().message.replace('\n', "\\n");
-        debug!("LogRequest device_id={} message={}", device_str, message);
+        debug!(device_id = %device_str, message, "LogRequest");
 
         Ok(Response::new(msg::Resp {
             message: "OK".into(),
         }))
     }
 
+    async fn commit_task(
+        &self,
+        request: Request<msg::TaskCommitment>,
+    ) -> Result<Response<msg::Resp>, Status> {
+        self.check_client_auth(&request.peer_certs(), true).await?;
+
+        let device_id = request
+            .peer_certs()
+            .and_then(|certs| certs.first().map(cert_to_id))
+            .unwrap();
+        let task_id = Uuid::from_slice(&request.into_inner().task_id)
+            .map_err(|_| Status::invalid_argument("Invalid task id"))?;
+        info!(
+            task_id = %logging::log_id(task_id.as_bytes()),
+            device_id = %logging::log_id(&device_id),
+            "TaskCommitment"
+        );
+
+        lock_with_task(&self.state, &task_id)
+            .await
+            .commit_task(&task_id, &device_id)?;
+        Ok(Response::new(msg::Resp {
+            message: "OK".into(),
+        }))
+    }
+
     async fn decide_task(
         &self,
         request: Request<msg::TaskDecision>,
@@ -367,16 +1369,30 @@ impl MeeSign for MeeSignService {
         let task_id = Uuid::from_slice(&request.task).unwrap();
         let accept = request.accept;
 
+        if request.withdraw {
+            info!(
+                task_id = %logging::log_id(task_id.as_bytes()),
+                device_id = %logging::log_id(&device_id),
+                "TaskDecision withdrawal"
+            );
+            lock_with_task(&self.state, &task_id)
+                .await
+                .withdraw_decision(&task_id, &device_id)?;
+            return Ok(Response::new(msg::Resp {
+                message: "OK".into(),
+            }));
+        }
+
         info!(
-            "TaskDecision task_id={} device_id={} accept={}",
-            utils::hextrunc(task_id.as_bytes()),
-            utils::hextrunc(&device_id),
-            accept
+            task_id = %logging::log_id(task_id.as_bytes()),
+            device_id = %logging::log_id(&device_id),
+            accept,
+            "TaskDecision"
         );
 
         let state = self.state.clone();
         tokio::task::spawn(async move {
-            let mut state = state.lock().await;
+            let mut state = lock_with_task(&state, &task_id).await;
             state.decide_task(&task_id, &device_id, accept);
         });
 
@@ -399,13 +1415,14 @@ impl MeeSign for MeeSignService {
         let task_id = request.into_inner().task_id;
 
         debug!(
-            "TaskAcknowledgement task_id={} device_id={}",
-            utils::hextrunc(&task_id),
-            utils::hextrunc(&device_id)
+            task_id = %logging::log_id(&task_id),
+            device_id = %logging::log_id(&device_id),
+            "TaskAcknowledgement"
         );
 
-        let mut state = self.state.lock().await;
-        state.acknowledge_task(&Uuid::from_slice(&task_id).unwrap(), &device_id);
+        let task_id = Uuid::from_slice(&task_id).unwrap();
+        let mut state = lock_with_task(&self.state, &task_id).await;
+        state.acknowledge_task(&task_id, &device_id);
 
         Ok(Response::new(msg::Resp {
             message: "OK".into(),
@@ -422,45 +1439,343 @@ impl MeeSign for MeeSignService {
             .peer_certs()
             .and_then(|certs| certs.get(0).map(cert_to_id))
             .unwrap();
+        let request = request.into_inner();
+        let compression = negotiate_compression(&request.accepted_compressions);
+        debug!(
+            device_id = %logging::log_id(&device_id),
+            last_sequence = request.last_sequence,
+            "SubscribeRequest"
+        );
+
+        let stream = subscribe(
+            self.state.clone(),
+            device_id,
+            compression,
+            request.last_sequence,
+        )
+        .await?;
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    async fn force_task_state(
+        &self,
+        request: Request<msg::ForceTaskStateRequest>,
+    ) -> Result<Response<msg::Task>, Status> {
+        self.check_client_auth(&request.peer_certs(), false).await?;
+        self.check_admin_auth(request.metadata()).await?;
+
+        let invoker = describe_invoker(&request);
+        let request = request.into_inner();
+        let task_id = Uuid::from_slice(&request.task_id)
+            .map_err(|_| Status::invalid_argument("Invalid task id"))?;
+        let target = msg::force_task_state_request::TargetState::try_from(request.target)
+            .map_err(|_| Status::invalid_argument("Unknown target state"))?;
+
+        let mut state = lock_with_task(&self.state, &task_id).await;
+        let previous_status = state.force_task_state(&task_id, target)?;
+        warn!(
+            task_id = %logging::log_id(task_id.as_bytes()),
+            invoker,
+            ?previous_status,
+            ?target,
+            "Task state forced by administrator"
+        );
+        let task = state.get_task(&task_id).unwrap();
+        Ok(Response::new(
+            format_task(&task_id, task, None, None).map_err(Status::internal)?,
+        ))
+    }
+
+    async fn rotate_admin_token(
+        &self,
+        request: Request<msg::RotateAdminTokenRequest>,
+    ) -> Result<Response<msg::Resp>, Status> {
+        self.check_client_auth(&request.peer_certs(), false).await?;
+        self.check_admin_auth(request.metadata()).await?;
+
+        let invoker = describe_invoker(&request);
+        let request = request.into_inner();
+        if !admin::validate_token(&request.token) {
+            return Err(Status::invalid_argument("Invalid admin token"));
+        }
+        let grace_period = Duration::from_secs(request.grace_period.into());
+        if grace_period > MAX_TOKEN_GRACE_PERIOD {
+            return Err(Status::invalid_argument("Grace period is too long"));
+        }
+
+        self.admin_tokens
+            .lock()
+            .await
+            .rotate(request.token, grace_period, Instant::now());
+        warn!(
+            invoker,
+            grace_period = request.grace_period,
+            "Admin token rotated"
+        );
+        Ok(Response::new(msg::Resp {
+            message: "OK".into(),
+        }))
+    }
+
+    async fn set_maintenance_mode(
+        &self,
+        request: Request<msg::MaintenanceModeRequest>,
+    ) -> Result<Response<msg::Resp>, Status> {
+        self.check_client_auth(&request.peer_certs(), false).await?;
+        self.check_admin_auth(request.metadata()).await?;
+
+        let invoker = describe_invoker(&request);
+        let enabled = request.into_inner().enabled;
+        self.maintenance.store(enabled, Ordering::Relaxed);
+        warn!(invoker, enabled, "Maintenance mode set");
+        Ok(Response::new(msg::Resp {
+            message: "OK".into(),
+        }))
+    }
+
+    async fn export_snapshot(
+        &self,
+        request: Request<msg::ExportSnapshotRequest>,
+    ) -> Result<Response<msg::ExportSnapshotResponse>, Status> {
+        self.check_client_auth(&request.peer_certs(), false).await?;
+        self.check_admin_auth(request.metadata()).await?;
+
+        let invoker = describe_invoker(&request);
+        let snapshot = self.state.lock().await.snapshot();
+        info!(
+            invoker,
+            devices = snapshot.devices.len(),
+            groups = snapshot.groups.len(),
+            tasks = snapshot.tasks.len(),
+            "State snapshot exported"
+        );
+        let snapshot = serde_json::to_string(&snapshot)
+            .map_err(|_| Status::internal("Unable to serialize snapshot"))?;
+        Ok(Response::new(msg::ExportSnapshotResponse { snapshot }))
+    }
+
+    async fn get_task_stats(
+        &self,
+        request: Request<msg::TaskStatsRequest>,
+    ) -> Result<Response<msg::TaskStats>, Status> {
+        self.check_client_auth(&request.peer_certs(), false).await?;
+        self.check_admin_auth(request.metadata()).await?;
+
+        let task_id = Uuid::from_slice(&request.into_inner().task_id)
+            .map_err(|_| Status::invalid_argument("Invalid task id"))?;
+        debug!(task_id = %logging::log_id(task_id.as_bytes()), "TaskStatsRequest");
+
+        let stats = self.state.lock().await.task_stats(&task_id)?;
+        Ok(Response::new(msg::TaskStats {
+            rounds: stats
+                .rounds
+                .into_iter()
+                .map(|round| msg::task_stats::Round {
+                    received_messages: round.received_messages,
+                    received_bytes: round.received_bytes,
+                    sent_messages: round.sent_messages,
+                    sent_bytes: round.sent_bytes,
+                })
+                .collect(),
+            devices: stats
+                .device_bytes
+                .into_iter()
+                .map(|(device_id, bytes)| msg::task_stats::DeviceTraffic { device_id, bytes })
+                .collect(),
+        }))
+    }
+
+    async fn estimate_sign(
+        &self,
+        request: Request<msg::EstimateSignRequest>,
+    ) -> Result<Response<msg::SignEstimate>, Status> {
+        self.check_client_auth(&request.peer_certs(), false).await?;
+
+        let group_id = request.into_inner().group_id;
+        debug!(group_id = %logging::log_id(&group_id), "EstimateSignRequest");
+
+        let estimate = self.state.lock().await.estimate_sign(&group_id)?;
+        Ok(Response::new(msg::SignEstimate {
+            rounds: estimate.rounds,
+            messages: estimate.messages,
+            duration_ms: estimate
+                .duration
+                .map(|duration| duration.as_millis() as u64),
+            samples: estimate.samples,
+        }))
+    }
+
+    async fn get_audit_trail(
+        &self,
+        request: Request<msg::AuditTrailRequest>,
+    ) -> Result<Response<Self::GetAuditTrailStream>, Status> {
+        self.check_client_auth(&request.peer_certs(), false).await?;
+        self.check_admin_auth(request.metadata()).await?;
+
+        let request = request.into_inner();
+        debug!(
+            group_id = %logging::log_id(&request.group_id),
+            since = request.since,
+            until = request.until,
+            "AuditTrailRequest"
+        );
 
-        let (tx, rx) = mpsc::channel(8);
+        let records =
+            self.state
+                .lock()
+                .await
+                .audit_trail(&request.group_id, request.since, request.until)?;
+        let records = records.into_iter().map(|record| msg::AuditRecord {
+            event: msg::AuditEvent::from_str_name(&record.event)
+                .unwrap_or_default()
+                .into(),
+            timestamp: record.timestamp,
+            task_id: hex::decode(&record.task_id).unwrap_or_default(),
+            task_type: msg::TaskType::from_str_name(&record.task_type)
+                .unwrap_or_default()
+                .into(),
+            participants: record
+                .participants
+                .iter()
+                .map(|device_id| hex::decode(device_id).unwrap_or_default())
+                .collect(),
+            requester: record.requester,
+            signature: record.signature,
+        });
+        Ok(Response::new(Box::pin(tokio_stream::iter(records.map(Ok)))))
+    }
 
-        self.state.lock().await.add_subscriber(device_id, tx);
+    async fn update_allowlist(
+        &self,
+        request: Request<msg::UpdateAllowlistRequest>,
+    ) -> Result<Response<msg::Allowlist>, Status> {
+        self.check_client_auth(&request.peer_certs(), false).await?;
+        self.check_admin_auth(request.metadata()).await?;
 
-        Ok(Response::new(Box::pin(ReceiverStream::new(rx))))
+        let invoker = describe_invoker(&request);
+        let request = request.into_inner();
+        let mut state = self.state.lock().await;
+        let allowlist = state.update_allowlist(&request.add, &request.remove, request.disable)?;
+        warn!(
+            invoker,
+            added = request.add.len(),
+            removed = request.remove.len(),
+            enabled = allowlist.is_some(),
+            "Registration allowlist updated"
+        );
+        Ok(Response::new(msg::Allowlist {
+            enabled: allowlist.is_some(),
+            device_ids: allowlist
+                .map(|allowlist| allowlist.iter().cloned().collect())
+                .unwrap_or_default(),
+        }))
     }
 }
 
+fn parse_task_types(task_types: &[i32]) -> Result<Vec<TaskType>, Error> {
+    task_types
+        .iter()
+        .map(|task_type| {
+            TaskType::try_from(*task_type)
+                .map_err(|_| Error::InvalidArgument("Unknown task type".into()))
+        })
+        .collect()
+}
+
+/// Identify the caller of an administrative RPC for audit logs
+fn describe_invoker<T>(request: &Request<T>) -> String {
+    request
+        .peer_certs()
+        .and_then(|certs| certs.first().map(cert_to_id))
+        .map(|device_id| logging::log_id(&device_id))
+        .or_else(|| request.remote_addr().map(|addr| addr.to_string()))
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+fn format_device_info(state: &State, device_id: &[u8]) -> Option<msg::DeviceInfo> {
+    let device = state.get_devices().get(device_id)?;
+    Some(msg::DeviceInfo {
+        device: Some(msg::Device {
+            subscribed: state.is_subscribed(device_id),
+            delivery: state.delivery_mode(device_id).into(),
+            ..device.as_ref().into()
+        }),
+        group_count: state.get_device_groups(device_id).len() as u32,
+        breaker_tripped: device.breaker_tripped(),
+        protocol_failures: state.device_failures(device_id),
+    })
+}
+
 pub fn format_task(
     task_id: &Uuid,
     task: &dyn Task,
     device_id: Option<&[u8]>,
     request: Option<&[u8]>,
-) -> msg::Task {
+) -> Result<msg::Task, String> {
     let task_status = task.get_status();
 
-    let (task_status, round, data) = match task_status {
-        TaskStatus::Created => (msg::task::TaskState::Created, 0, task.get_work(device_id)),
+    let (task_status, round, data, failure_reason) = match task_status {
+        TaskStatus::Created => (
+            msg::task::TaskState::Created,
+            0,
+            task.get_work(device_id),
+            None,
+        ),
         TaskStatus::Running(round) => (
             msg::task::TaskState::Running,
             round,
             task.get_work(device_id),
+            None,
         ),
         TaskStatus::Finished => (
             msg::task::TaskState::Finished,
             u16::MAX,
-            vec![task.get_result().unwrap().as_bytes().to_vec()],
+            vec![task
+                .get_result()
+                .ok_or_else(|| "Finished task has no result".to_string())?
+                .as_bytes()
+                .to_vec()],
+            None,
         ),
-        TaskStatus::Failed(data) => (
+        TaskStatus::Failed(reason, data) => (
             msg::task::TaskState::Failed,
             u16::MAX,
             vec![data.as_bytes().to_vec()],
+            Some(msg::task::FailureReason::from(reason) as i32),
         ),
     };
 
     let (accept, reject) = task.get_decisions();
+    let data_digest = if task_status == msg::task::TaskState::Finished {
+        task.get_data_digest()
+    } else {
+        None
+    };
+    let work_status = device_id.map(|device_id| {
+        if !task.has_device(device_id) {
+            msg::task::WorkStatus::NotParticipant
+        } else if matches!(
+            task_status,
+            msg::task::TaskState::Created | msg::task::TaskState::Running
+        ) && !data.is_empty()
+        {
+            msg::task::WorkStatus::Work
+        } else {
+            msg::task::WorkStatus::NoWorkYet
+        }
+    });
+    let action_required = device_id.map(|device_id| match task_status {
+        msg::task::TaskState::Created => task.has_device(device_id) && task.waiting_for(device_id),
+        msg::task::TaskState::Running => task.has_device(device_id) && !data.is_empty(),
+        _ => false,
+    });
+    let waiting_on = if task_status == msg::task::TaskState::Running {
+        waiting_devices(task)
+    } else {
+        Vec::new()
+    };
 
-    msg::Task {
+    Ok(msg::Task {
         id: task_id.as_bytes().to_vec(),
         r#type: task.get_type() as i32,
         state: task_status as i32,
@@ -470,9 +1785,204 @@ pub fn format_task(
         data,
         request: request.map(Vec::from),
         attempt: task.get_attempts(),
+        failure_reason,
+        waiting_on,
+        data_digest,
+        work_status: work_status.map(Into::into),
+        created_at: task.created_at(),
+        failed_round: task.failed_round().map(Into::into),
+        work_version: WORK_VERSION.into(),
+        requester: task.requester().map(String::from),
+        data_compression: Compression::None.into(),
+        priority: task.priority(),
+        action_required,
+        update_sequence: None,
+    })
+}
+
+/// Format the signing tasks of a multi-group request together with their combined state
+pub fn format_multi_group_task(
+    multi_task_id: &Uuid,
+    tasks: &[(Uuid, &dyn Task)],
+) -> Result<msg::MultiGroupTask, String> {
+    let items = tasks
+        .iter()
+        .map(|(_, task)| {
+            let (status, signature) = match task.get_status() {
+                TaskStatus::Finished => (
+                    msg::multi_group_task::item::Status::Done,
+                    task.get_result().map(|result| result.as_bytes().to_vec()),
+                ),
+                TaskStatus::Failed(..) => (msg::multi_group_task::item::Status::Failed, None),
+                TaskStatus::Created | TaskStatus::Running(_) => {
+                    (msg::multi_group_task::item::Status::Pending, None)
+                }
+            };
+            msg::multi_group_task::Item {
+                group_id: task.get_group_id().unwrap_or_default().to_vec(),
+                status: status.into(),
+                signature,
+            }
+        })
+        .collect();
+    let tasks = tasks
+        .iter()
+        .map(|(task_id, task)| format_task(task_id, *task, None, None))
+        .collect::<Result<Vec<_>, _>>()?;
+    let has_state = |state| tasks.iter().any(|task| task.state() == state);
+    let state = if has_state(msg::task::TaskState::Failed) {
+        msg::task::TaskState::Failed
+    } else if tasks
+        .iter()
+        .all(|task| task.state() == msg::task::TaskState::Finished)
+    {
+        msg::task::TaskState::Finished
+    } else if has_state(msg::task::TaskState::Running) || has_state(msg::task::TaskState::Finished)
+    {
+        msg::task::TaskState::Running
+    } else {
+        msg::task::TaskState::Created
+    };
+    Ok(msg::MultiGroupTask {
+        id: multi_task_id.as_bytes().to_vec(),
+        state: state.into(),
+        tasks,
+        items,
+    })
+}
+
+/// Compressions of task data the server can produce
+const SUPPORTED_COMPRESSIONS: [Compression; 1] = [Compression::Gzip];
+
+/// Smallest total size of task data worth compressing, in bytes
+const COMPRESSION_THRESHOLD: usize = 1024;
+
+/// Pick the first compression accepted by the client which the server supports
+fn negotiate_compression(accepted: &[i32]) -> Compression {
+    accepted
+        .iter()
+        .filter_map(|&compression| Compression::try_from(compression).ok())
+        .find(|compression| SUPPORTED_COMPRESSIONS.contains(compression))
+        .unwrap_or(Compression::None)
+}
+
+/// Compress the data entries of a formatted task if they are large enough to benefit from it
+pub fn compress_data(task: &mut msg::Task, compression: Compression) {
+    let size: usize = task.data.iter().map(Vec::len).sum();
+    if compression == Compression::None || size < COMPRESSION_THRESHOLD {
+        return;
+    }
+    let compressed: Option<Vec<_>> = task
+        .data
+        .iter()
+        .map(|data| {
+            let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(data).ok()?;
+            encoder.finish().ok()
+        })
+        .collect();
+    match compressed {
+        Some(compressed) if compressed.iter().map(Vec::len).sum::<usize>() < size => {
+            task.data = compressed;
+            task.data_compression = compression.into();
+        }
+        _ => {}
     }
 }
 
+/// Reject clients which cannot parse work encoded with the current version
+fn check_work_version(version: Option<u32>) -> Result<(), Error> {
+    match version {
+        Some(version) if version != u32::from(WORK_VERSION) => {
+            Err(Error::FailedPrecondition(format!(
+                "Unsupported work version {}; the server encodes work with version {}",
+                version, WORK_VERSION
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Register a subscriber for `device_id` and remove it as soon as the client drops the stream
+async fn subscribe(
+    state: Arc<Mutex<State>>,
+    device_id: Vec<u8>,
+    compression: Compression,
+    last_sequence: Option<u64>,
+) -> Result<ReceiverStream<Result<msg::Task, Status>>, Error> {
+    let (watcher, rx) = {
+        let mut state = state.lock().await;
+        let missed = match last_sequence {
+            Some(last_sequence) => state.missed_updates(&device_id, last_sequence)?,
+            None => Vec::new(),
+        };
+        let (tx, rx) = mpsc::channel(8 + missed.len());
+        for mut update in missed {
+            compress_data(&mut update, compression);
+            // the channel has room for every missed update
+            let _ = tx.try_send(Ok(update));
+        }
+        state.add_subscriber(device_id.clone(), tx.clone());
+        state.set_data_compression(device_id.clone(), compression);
+        (tx, rx)
+    };
+
+    tokio::spawn(async move {
+        watcher.closed().await;
+        debug!(device_id = %logging::log_id(&device_id), "Update stream closed");
+        state
+            .lock()
+            .await
+            .remove_closed_subscriber(&device_id, &watcher);
+    });
+    Ok(ReceiverStream::new(rx))
+}
+
+/// Reassemble a signing request from a stream of chunks
+///
+/// The first chunk provides the request metadata; the payload ends with a chunk marked as last.
+async fn collect_sign_chunks<S>(mut chunks: S) -> Result<msg::SignRequest, Status>
+where
+    S: Stream<Item = Result<msg::SignChunk, Status>> + Unpin,
+{
+    let mut request: Option<msg::SignRequest> = None;
+    while let Some(chunk) = chunks.next().await {
+        let chunk = chunk?;
+        let request = request.get_or_insert_with(|| msg::SignRequest {
+            name: chunk.name.clone(),
+            group_id: chunk.group_id.clone(),
+            data: Vec::new(),
+            deadline_seconds: chunk.deadline_seconds,
+            hash_algorithm: chunk.hash_algorithm,
+            priority: chunk.priority,
+            depends_on: None,
+        });
+        if request.data.len() + chunk.data.len() > MAX_PDF_SIZE {
+            return Err(Status::invalid_argument("Signing payload is too large"));
+        }
+        request.data.extend_from_slice(&chunk.data);
+        if chunk.last {
+            return Ok(std::mem::take(request));
+        }
+    }
+    Err(Status::invalid_argument(
+        "Signing payload ended without a final chunk",
+    ))
+}
+
+/// Get identifiers of the task devices which the current round is waiting for
+pub fn waiting_devices(task: &dyn Task) -> Vec<Vec<u8>> {
+    let mut devices: Vec<Vec<u8>> = Vec::new();
+    for device in task.get_devices() {
+        if task.waiting_for(device.identifier())
+            && !devices.iter().any(|listed| listed == device.identifier())
+        {
+            devices.push(device.identifier().to_vec());
+        }
+    }
+    devices
+}
+
 pub fn issue_certificate(device_name: &str, csr: &[u8]) -> Result<Vec<u8>, String> {
     let csr = X509Req::from_der(csr).unwrap();
     let public_key = csr.public_key().unwrap();
@@ -557,11 +2067,9 @@ pub async fn run_grpc(state: Arc<Mutex<State>>, addr: &str, port: u16) -> Result
     let addr = format!("{}:{}", addr, port)
         .parse()
         .map_err(|_| String::from("Unable to parse server address"))?;
-    let node = MeeSignService::new(state);
+    let config = state.lock().await.get_config().clone();
+    let node = MeeSignService::new(state).await;
 
-    let ca_cert = CA_CERT
-        .to_pem()
-        .map_err(|_| "Unable to load CA certificate".to_string())?;
     let cert = tokio::fs::read("keys/meesign-server-cert.pem")
         .await
         .map_err(|_| "Unable to load server certificate".to_string())?;
@@ -569,18 +2077,1176 @@ pub async fn run_grpc(state: Arc<Mutex<State>>, addr: &str, port: u16) -> Result
         .await
         .map_err(|_| "Unable to load server key".to_string())?;
 
+    let mut server = build_server(&config, Identity::from_pem(&cert, &key))?
+        .accept_http1(config.grpc_web.is_some());
+    let router = match &config.grpc_web {
+        Some(settings) => server.add_service(web::gateway(MeeSignServer::new(node), settings)?),
+        None => server.add_service(MeeSignServer::new(node)),
+    };
+    router
+        .serve(addr)
+        .await
+        .map_err(|_| String::from("Unable to run gRPC server"))?;
+
+    Ok(())
+}
+
+/// Configure the transport of the gRPC server presenting `identity` to the clients
+pub(crate) fn build_server(config: &Config, identity: Identity) -> Result<Server, String> {
+    let ca_cert = CA_CERT
+        .to_pem()
+        .map_err(|_| "Unable to load CA certificate".to_string())?;
+
     Server::builder()
+        .http2_keepalive_interval(config.keepalive_interval)
+        .http2_keepalive_timeout(config.keepalive_timeout)
+        .tcp_keepalive(config.keepalive_interval)
         .tls_config(
             ServerTlsConfig::new()
-                .identity(Identity::from_pem(&cert, &key))
+                .identity(identity)
                 .client_ca_root(Certificate::from_pem(ca_cert))
                 .client_auth_optional(true),
         )
-        .map_err(|_| "Unable to setup TLS for gRPC server")?
-        .add_service(MeeSignServer::new(node))
-        .serve(addr)
-        .await
-        .map_err(|_| String::from("Unable to run gRPC server"))?;
+        .map_err(|_| "Unable to setup TLS for gRPC server".to_string())
+}
 
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proto::SignRequest;
+    use prost::Message as _;
+    use tonic::transport::server::TcpConnectInfo;
+
+    #[tokio::test]
+    async fn force_task_state_requires_admin_token() {
+        let mut state = State::new(Config {
+            admin_token: Some("secret".into()),
+            ..Config::default()
+        });
+        let devices: Vec<Vec<u8>> = (0..2).map(|i| vec![i]).collect();
+        for device in &devices {
+            state.add_device(device, "device", DeviceKind::User, &[0xf0], None);
+        }
+        let task_id = state
+            .add_group_task(
+                "Sample Group",
+                &devices,
+                2,
+                ProtocolType::Gg18,
+                KeyType::SignChallenge,
+                &None,
+                &[],
+                None,
+            )
+            .unwrap();
+        let service = MeeSignService::new(Arc::new(Mutex::new(state))).await;
+        let force_request = |token: Option<&str>| {
+            let mut request = Request::new(msg::ForceTaskStateRequest {
+                task_id: task_id.as_bytes().to_vec(),
+                target: msg::force_task_state_request::TargetState::Failed.into(),
+            });
+            if let Some(token) = token {
+                request
+                    .metadata_mut()
+                    .insert("authorization", token.parse().unwrap());
+            }
+            request
+        };
+
+        for token in [None, Some("Bearer wrong"), Some("secret")] {
+            let status = service
+                .force_task_state(force_request(token))
+                .await
+                .unwrap_err();
+            assert_eq!(status.code(), tonic::Code::Unauthenticated);
+        }
+        let task = service
+            .force_task_state(force_request(Some("Bearer secret")))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(task.state, msg::task::TaskState::Failed as i32);
+    }
+
+    #[tokio::test]
+    async fn advertised_schemes_match_group_tasks() {
+        let service =
+            MeeSignService::new(Arc::new(Mutex::new(State::new(Config::default())))).await;
+        let capabilities = service
+            .get_server_capabilities(Request::new(msg::ServerCapabilitiesRequest {}))
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(!capabilities.administration);
+
+        let protocols = [
+            ProtocolType::Gg18,
+            ProtocolType::Elgamal,
+            ProtocolType::Frost,
+            ProtocolType::Musig2,
+        ];
+        let key_types = [KeyType::SignPdf, KeyType::SignChallenge, KeyType::Decrypt];
+        for protocol in protocols {
+            for key_type in key_types {
+                let mut state = State::new(Config::default());
+                let devices: Vec<Vec<u8>> = (0..2).map(|i| vec![i]).collect();
+                for device in &devices {
+                    state.add_device(device, "device", DeviceKind::User, &[0xf0], None);
+                }
+                let accepted = state
+                    .add_group_task(
+                        "Sample Group",
+                        &devices,
+                        2,
+                        protocol,
+                        key_type,
+                        &None,
+                        &[],
+                        None,
+                    )
+                    .is_ok();
+                let advertised = capabilities
+                    .schemes
+                    .iter()
+                    .any(|scheme| scheme.protocol() == protocol && scheme.key_type() == key_type);
+                assert_eq!(accepted, advertised, "{:?} {:?}", protocol, key_type);
+            }
+        }
+        let mut advertised: Vec<_> = capabilities.protocols().collect();
+        advertised.sort();
+        assert_eq!(advertised, protocols);
+    }
+
+    #[tokio::test]
+    async fn admin_token_rotation() {
+        let state = State::new(Config {
+            admin_token: Some("first".into()),
+            ..Config::default()
+        });
+        let service = MeeSignService::new(Arc::new(Mutex::new(state))).await;
+        let rotate_request = |token: &str, new_token: &str, grace_period: u32| {
+            let mut request = Request::new(msg::RotateAdminTokenRequest {
+                token: new_token.into(),
+                grace_period,
+            });
+            request.metadata_mut().insert(
+                "authorization",
+                format!("Bearer {}", token).parse().unwrap(),
+            );
+            request
+        };
+
+        let status = service
+            .rotate_admin_token(rotate_request("first", "", 0))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+
+        service
+            .rotate_admin_token(rotate_request("first", "second", 0))
+            .await
+            .unwrap();
+        let status = service
+            .rotate_admin_token(rotate_request("first", "third", 0))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+
+        service
+            .rotate_admin_token(rotate_request("second", "third", 3600))
+            .await
+            .unwrap();
+        service
+            .rotate_admin_token(rotate_request("second", "fourth", 0))
+            .await
+            .unwrap();
+        for (token, code) in [
+            ("second", tonic::Code::Unauthenticated),
+            ("third", tonic::Code::Unauthenticated),
+            ("fourth", tonic::Code::InvalidArgument),
+        ] {
+            let status = service
+                .rotate_admin_token(rotate_request(token, "", 0))
+                .await
+                .unwrap_err();
+            assert_eq!(status.code(), code);
+        }
+    }
+
+    #[tokio::test]
+    async fn task_reports_requester() {
+        let mut state = State::new(Config {
+            admin_token: Some("secret".into()),
+            ..Config::default()
+        });
+        let devices: Vec<Vec<u8>> = (0..2).map(|i| vec![i]).collect();
+        for device in &devices {
+            state.add_device(device, "device", DeviceKind::User, &[0xf0], None);
+        }
+        let service = MeeSignService::new(Arc::new(Mutex::new(state))).await;
+        let group_request = |token: Option<&str>| {
+            let mut request = Request::new(msg::GroupRequest {
+                name: "Sample Group".into(),
+                device_ids: devices.clone(),
+                threshold: 2,
+                protocol: ProtocolType::Gg18.into(),
+                key_type: KeyType::SignChallenge.into(),
+                note: None,
+                deadline_seconds: None,
+                tags: Vec::new(),
+                priority: 0,
+                allowed_tasks: Vec::new(),
+                auto_approve: false,
+                require_all_signers: false,
+            });
+            if let Some(token) = token {
+                request.metadata_mut().insert(
+                    "authorization",
+                    format!("Bearer {}", token).parse().unwrap(),
+                );
+            }
+            request
+        };
+
+        for (token, requester) in [
+            (Some("secret"), Some("admin")),
+            (Some("wrong"), None),
+            (None, None),
+        ] {
+            let task = service
+                .group(group_request(token))
+                .await
+                .unwrap()
+                .into_inner();
+            let task = service
+                .get_task(Request::new(msg::TaskRequest {
+                    task_id: task.id,
+                    device_id: None,
+                    work_version: None,
+                    accepted_compressions: Vec::new(),
+                }))
+                .await
+                .unwrap()
+                .into_inner();
+            assert_eq!(task.requester.as_deref(), requester);
+        }
+    }
+
+    #[tokio::test]
+    async fn anonymous_requester_identified_by_ip() {
+        let service =
+            MeeSignService::new(Arc::new(Mutex::new(State::new(Config::default())))).await;
+        let metadata = MetadataMap::new();
+        let first = service
+            .identify_requester(None, Some("10.0.0.1:40000".parse().unwrap()), &metadata)
+            .await;
+        let second = service
+            .identify_requester(None, Some("10.0.0.1:40001".parse().unwrap()), &metadata)
+            .await;
+        assert_eq!(first.as_deref(), Some("10.0.0.1"));
+        assert_eq!(first, second);
+    }
+
+    #[tokio::test]
+    async fn anonymous_requester_does_not_own_task() {
+        let mut state = State::new(Config {
+            admin_token: Some("secret".into()),
+            ..Config::default()
+        });
+        let devices: Vec<Vec<u8>> = (0..2).map(|i| vec![i]).collect();
+        for device in &devices {
+            state.add_device(device, "device", DeviceKind::User, &[0xf0], None);
+        }
+        let service = MeeSignService::new(Arc::new(Mutex::new(state))).await;
+        let with_client = |mut request: Request<_>, token: Option<&str>| {
+            request.extensions_mut().insert(TcpConnectInfo {
+                local_addr: None,
+                remote_addr: Some("10.0.0.1:40000".parse().unwrap()),
+            });
+            if let Some(token) = token {
+                request.metadata_mut().insert(
+                    "authorization",
+                    format!("Bearer {}", token).parse().unwrap(),
+                );
+            }
+            request
+        };
+
+        for (token, owned) in [(None, false), (Some("secret"), true)] {
+            let group_request = msg::GroupRequest {
+                name: "Sample Group".into(),
+                device_ids: devices.clone(),
+                threshold: 2,
+                protocol: ProtocolType::Gg18.into(),
+                key_type: KeyType::SignChallenge.into(),
+                note: None,
+                deadline_seconds: None,
+                tags: Vec::new(),
+                priority: 0,
+                allowed_tasks: Vec::new(),
+                auto_approve: false,
+                require_all_signers: false,
+            };
+            let task = service
+                .group(with_client(Request::new(group_request), token))
+                .await
+                .unwrap()
+                .into_inner();
+            let cancelled = service
+                .cancel_group(with_client(
+                    Request::new(msg::CancelGroupRequest { task_id: task.id }),
+                    token,
+                ))
+                .await;
+            match cancelled {
+                Ok(_) => assert!(owned),
+                Err(status) => {
+                    assert!(!owned);
+                    assert_eq!(status.code(), tonic::Code::PermissionDenied);
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn result_available_after_acknowledgement() {
+        let mut state = State::new(Config {
+            admin_token: Some("secret".into()),
+            ..Config::default()
+        });
+        let devices: Vec<Vec<u8>> = (0..2).map(|i| vec![i]).collect();
+        for device in &devices {
+            state.add_device(device, "device", DeviceKind::User, &[0xf0], None);
+        }
+        let group = Group::new(
+            vec![0xaa; 4],
+            "Sample Group".into(),
+            state.get_devices().values().cloned().collect(),
+            2,
+            ProtocolType::Gg18,
+            KeyType::SignChallenge,
+            None,
+            None,
+        );
+        state.add_group(group).unwrap();
+        let task_id = state
+            .add_sign_task(
+                &[0xaa; 4],
+                "Sample Task",
+                &[0x01],
+                None,
+                HashAlgorithm::Default,
+            )
+            .unwrap();
+        state.set_task_requester(&task_id, Some("admin".into()));
+        for device in &devices {
+            state.decide_task(&task_id, device, true);
+        }
+        let message = vec![meesign_crypto::proto::ClientMessage {
+            protocol_type: ProtocolType::Gg18.into(),
+            unicasts: std::collections::HashMap::new(),
+            broadcast: Some(vec![0x01]),
+        }
+        .encode_to_vec()];
+        while state.get_task(&task_id).unwrap().get_status() != TaskStatus::Finished {
+            for device in &devices {
+                state
+                    .update_task(&task_id, device, &message, 0, None)
+                    .unwrap();
+            }
+        }
+        for device in &devices {
+            state.acknowledge_task(&task_id, device);
+            assert!(state.get_device_tasks(device).is_empty());
+        }
+        let signature = state
+            .get_task(&task_id)
+            .unwrap()
+            .get_result()
+            .unwrap()
+            .as_bytes()
+            .to_vec();
+        let service = MeeSignService::new(Arc::new(Mutex::new(state))).await;
+
+        let result_request = |token: Option<&str>| {
+            let mut request = Request::new(msg::TaskResultRequest {
+                task_id: task_id.as_bytes().to_vec(),
+            });
+            if let Some(token) = token {
+                request.metadata_mut().insert(
+                    "authorization",
+                    format!("Bearer {}", token).parse().unwrap(),
+                );
+            }
+            request
+        };
+        let result = service
+            .get_result(result_request(Some("secret")))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(result.task_id, task_id.as_bytes());
+        assert_eq!(result.r#type(), msg::TaskType::SignChallenge);
+        assert_eq!(result.data, signature);
+
+        let status = service.get_result(result_request(None)).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::PermissionDenied);
+    }
+
+    #[test]
+    fn large_data_compressed() {
+        use flate2::read::GzDecoder;
+        use std::io::Read;
+
+        let work: Vec<u8> = (0..4096).map(|i| (i % 7) as u8).collect();
+        let format = |accepted: &[Compression]| {
+            let mut task = msg::Task {
+                data: vec![work.clone()],
+                ..Default::default()
+            };
+            let accepted: Vec<i32> = accepted.iter().map(|&x| x.into()).collect();
+            compress_data(&mut task, negotiate_compression(&accepted));
+            task
+        };
+
+        let raw = format(&[]);
+        assert_eq!(raw.data_compression(), Compression::None);
+        assert_eq!(raw.data, vec![work.clone()]);
+
+        let compressed = format(&[Compression::None, Compression::Gzip]);
+        assert_eq!(compressed.data_compression(), Compression::Gzip);
+        assert!(compressed.data[0].len() < work.len());
+        let mut decompressed = Vec::new();
+        GzDecoder::new(compressed.data[0].as_slice())
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, work);
+
+        let mut small = msg::Task {
+            data: vec![vec![0x01; 16]],
+            ..Default::default()
+        };
+        compress_data(&mut small, Compression::Gzip);
+        assert_eq!(small.data_compression(), Compression::None);
+    }
+
+    #[tokio::test]
+    async fn groups_filtered_by_tag() {
+        let mut state = State::new(Config {
+            admin_token: Some("secret".into()),
+            ..Config::default()
+        });
+        let devices: Vec<Vec<u8>> = (0..2).map(|i| vec![i]).collect();
+        for device in &devices {
+            state.add_device(device, "device", DeviceKind::User, &[0xf0], None);
+        }
+        for identifier in [0xaa, 0xbb, 0xcc] {
+            state
+                .add_group(Group::new(
+                    vec![identifier; 4],
+                    "Sample Group".into(),
+                    state.get_devices().values().cloned().collect(),
+                    2,
+                    ProtocolType::Gg18,
+                    KeyType::SignChallenge,
+                    None,
+                    None,
+                ))
+                .unwrap();
+        }
+        let service = MeeSignService::new(Arc::new(Mutex::new(state))).await;
+        let tags_request = |group_id: Vec<u8>, tags: Vec<String>| {
+            let mut request = Request::new(msg::GroupTagsRequest { group_id, tags });
+            request
+                .metadata_mut()
+                .insert("authorization", "Bearer secret".parse().unwrap());
+            request
+        };
+        for (identifier, tags) in [
+            (0xaa, vec!["treasury", "dev"]),
+            (0xbb, vec!["dev"]),
+            (0xcc, vec!["test"]),
+        ] {
+            let group = service
+                .set_group_tags(tags_request(
+                    vec![identifier; 4],
+                    tags.into_iter().map(String::from).collect(),
+                ))
+                .await
+                .unwrap()
+                .into_inner();
+            assert!(group.tags.windows(2).all(|pair| pair[0] < pair[1]));
+        }
+        let status = service
+            .set_group_tags(tags_request(vec![0xcc; 4], vec!["a".repeat(33)]))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        let status = service
+            .set_group_tags(Request::new(msg::GroupTagsRequest {
+                group_id: vec![0xcc; 4],
+                tags: vec!["prod".into()],
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+
+        let mut groups: Vec<_> = service
+            .get_groups(Request::new(msg::GroupsRequest {
+                device_id: None,
+                tag: Some("dev".into()),
+            }))
+            .await
+            .unwrap()
+            .into_inner()
+            .groups
+            .into_iter()
+            .map(|group| group.identifier)
+            .collect();
+        groups.sort();
+        assert_eq!(groups, vec![vec![0xaa; 4], vec![0xbb; 4]]);
+    }
+
+    #[tokio::test]
+    async fn group_tags_require_admin() {
+        let mut state = State::new(Config::default());
+        let devices: Vec<Vec<u8>> = (0..2).map(|i| vec![i]).collect();
+        for device in &devices {
+            state.add_device(device, "device", DeviceKind::User, &[0xf0], None);
+        }
+        state
+            .add_group(Group::new(
+                vec![0xaa; 4],
+                "Sample Group".into(),
+                state.get_devices().values().cloned().collect(),
+                2,
+                ProtocolType::Gg18,
+                KeyType::SignChallenge,
+                None,
+                None,
+            ))
+            .unwrap();
+        let service = MeeSignService::new(Arc::new(Mutex::new(state))).await;
+
+        let status = service
+            .set_group_tags(Request::new(msg::GroupTagsRequest {
+                group_id: vec![0xaa; 4],
+                tags: vec!["prod".into()],
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::PermissionDenied);
+        let state = service.state.lock().await;
+        assert!(state.find_group(&[0xaa; 4]).unwrap().tags().is_empty());
+    }
+
+    #[tokio::test]
+    async fn device_groups_describe_protocol() {
+        let mut state = State::new(Config::default());
+        let devices: Vec<Vec<u8>> = (0..2).map(|i| vec![i]).collect();
+        for device in &devices {
+            state.add_device(device, "device", DeviceKind::User, &[0xf0], None);
+        }
+        for (identifier, protocol, key_type) in [
+            (0xaa, ProtocolType::Gg18, KeyType::SignPdf),
+            (0xbb, ProtocolType::Musig2, KeyType::SignChallenge),
+        ] {
+            state
+                .add_group(Group::new(
+                    vec![identifier; 4],
+                    format!("Group {:x}", identifier),
+                    state.get_devices().values().cloned().collect(),
+                    2,
+                    protocol,
+                    key_type,
+                    None,
+                    None,
+                ))
+                .unwrap();
+        }
+        let service = MeeSignService::new(Arc::new(Mutex::new(state))).await;
+
+        let mut groups = service
+            .get_groups(Request::new(msg::GroupsRequest {
+                device_id: Some(devices[0].clone()),
+                tag: None,
+            }))
+            .await
+            .unwrap()
+            .into_inner()
+            .groups;
+        groups.sort_by(|a, b| a.identifier.cmp(&b.identifier));
+        let described: Vec<_> = groups
+            .iter()
+            .map(|group| {
+                (
+                    group.name.as_str(),
+                    group.protocol(),
+                    group.key_type(),
+                    group.threshold,
+                    group.device_ids.len(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            described,
+            vec![
+                ("Group aa", ProtocolType::Gg18, KeyType::SignPdf, 2, 2),
+                (
+                    "Group bb",
+                    ProtocolType::Musig2,
+                    KeyType::SignChallenge,
+                    2,
+                    2
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn device_subscription_status() {
+        let mut state = State::new(Config::default());
+        for device in [vec![0x01], vec![0x02]] {
+            state.add_device(&device, "device", DeviceKind::User, &[0xf0], None);
+        }
+        let (tx, _rx) = mpsc::channel(8);
+        state.add_subscriber(vec![0x01], tx);
+        let service = MeeSignService::new(Arc::new(Mutex::new(state))).await;
+
+        let devices = service
+            .get_devices(Request::new(msg::DevicesRequest {}))
+            .await
+            .unwrap()
+            .into_inner()
+            .devices;
+        assert_eq!(devices.len(), 2);
+        for device in devices {
+            assert_eq!(device.subscribed, device.identifier == vec![0x01]);
+        }
+    }
+
+    #[tokio::test]
+    async fn device_info() {
+        let mut state = State::new(Config::default());
+        for device in [vec![0x01], vec![0x02]] {
+            state.add_device(&device, "device", DeviceKind::User, &[0xf0], None);
+        }
+        state
+            .add_group(Group::new(
+                vec![0xaa; 4],
+                "Sample Group".into(),
+                state.get_devices().values().cloned().collect(),
+                2,
+                ProtocolType::Gg18,
+                KeyType::SignChallenge,
+                None,
+                None,
+            ))
+            .unwrap();
+        let service = MeeSignService::new(Arc::new(Mutex::new(state))).await;
+
+        let status = service
+            .get_device_info(Request::new(msg::DeviceInfoRequest {
+                device_id: vec![0x03],
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+
+        let info = service
+            .get_device_info(Request::new(msg::DeviceInfoRequest {
+                device_id: vec![0x01],
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        let device = info.device.unwrap();
+        assert_eq!(device.identifier, vec![0x01]);
+        assert_eq!(device.name, "device");
+        assert!(device.last_active > 0);
+        assert_eq!(info.group_count, 1);
+    }
+
+    #[tokio::test]
+    async fn dropped_stream_removes_subscriber() {
+        let state = Arc::new(Mutex::new(State::new(Config::default())));
+        let device_id = vec![0x01];
+        state
+            .lock()
+            .await
+            .add_device(&device_id, "device", DeviceKind::User, &[0xf0], None);
+
+        let is_subscribed = || async { state.lock().await.is_subscribed(&device_id) };
+        let wait_for_removal = || async {
+            tokio::time::timeout(Duration::from_secs(1), async {
+                while is_subscribed().await {
+                    tokio::task::yield_now().await;
+                }
+            })
+            .await
+        };
+
+        let first = subscribe(state.clone(), device_id.clone(), Compression::None, None)
+            .await
+            .unwrap();
+        let second = subscribe(state.clone(), device_id.clone(), Compression::None, None)
+            .await
+            .unwrap();
+        drop(first);
+        assert!(wait_for_removal().await.is_err());
+        assert!(is_subscribed().await);
+
+        drop(second);
+        assert!(wait_for_removal().await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn resubscribe_replays_missed_updates() {
+        let state = Arc::new(Mutex::new(State::new(Config::default())));
+        let devices: Vec<Vec<u8>> = (0..2).map(|i| vec![i]).collect();
+        let task_id = {
+            let mut state = state.lock().await;
+            for device in &devices {
+                state.add_device(device, "device", DeviceKind::User, &[0xf0], None);
+            }
+            state
+                .add_group_task(
+                    "Sample Group",
+                    &devices,
+                    2,
+                    ProtocolType::Gg18,
+                    KeyType::SignChallenge,
+                    &None,
+                    &[],
+                    None,
+                )
+                .unwrap()
+        };
+        let resubscribe = |last_sequence| {
+            subscribe(
+                state.clone(),
+                devices[0].clone(),
+                Compression::None,
+                Some(last_sequence),
+            )
+        };
+
+        let mut stream = resubscribe(0).await.unwrap();
+        let update = stream.next().await.unwrap().unwrap();
+        assert_eq!(update.update_sequence, Some(1));
+        drop(stream);
+
+        {
+            let mut state = state.lock().await;
+            for device in &devices {
+                state.decide_task(&task_id, device, true);
+            }
+        }
+        let mut stream = resubscribe(1).await.unwrap();
+        let update = stream.next().await.unwrap().unwrap();
+        assert_eq!(update.update_sequence, Some(2));
+        assert_eq!(update.state, msg::task::TaskState::Running as i32);
+        drop(stream);
+
+        assert!(matches!(
+            resubscribe(100).await,
+            Err(Error::FailedPrecondition(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn streamed_sign_request() {
+        let mut state = State::new(Config::default());
+        let devices: Vec<Vec<u8>> = (0..2).map(|i| vec![i]).collect();
+        for device in &devices {
+            state.add_device(device, "device", DeviceKind::User, &[0xf0], None);
+        }
+        let group = Group::new(
+            vec![0xaa; 4],
+            "Sample Group".into(),
+            state.get_devices().values().cloned().collect(),
+            2,
+            ProtocolType::Gg18,
+            KeyType::SignChallenge,
+            None,
+            None,
+        );
+        state.add_group(group).unwrap();
+
+        let payload: Vec<u8> = (0..10_000u32).map(|i| i as u8).collect();
+        let mut chunks: Vec<_> = payload
+            .chunks(4096)
+            .map(|data| msg::SignChunk {
+                data: data.to_vec(),
+                ..Default::default()
+            })
+            .collect();
+        chunks[0].name = "Sample Task".into();
+        chunks[0].group_id = vec![0xaa; 4];
+        chunks.last_mut().unwrap().last = true;
+        let request = collect_sign_chunks(tokio_stream::iter(chunks.clone()).map(Ok))
+            .await
+            .unwrap();
+        assert_eq!(request.data, payload);
+
+        let task_id = state
+            .add_sign_task(
+                &request.group_id,
+                &request.name,
+                &request.data,
+                None,
+                HashAlgorithm::Default,
+            )
+            .unwrap();
+        let task = state.get_task(&task_id).unwrap();
+        let signed = SignRequest::decode(task.get_request()).unwrap();
+        assert_eq!(signed.data, payload);
+
+        chunks.pop();
+        let result = collect_sign_chunks(tokio_stream::iter(chunks).map(Ok)).await;
+        assert_eq!(result.unwrap_err().code(), tonic::Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn oversized_sign_stream() {
+        let chunk = msg::SignChunk {
+            data: vec![0; MAX_PDF_SIZE / 2 + 1],
+            ..Default::default()
+        };
+        let chunks = vec![chunk.clone(), chunk];
+        let result = collect_sign_chunks(tokio_stream::iter(chunks).map(Ok)).await;
+        assert_eq!(result.unwrap_err().code(), tonic::Code::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn multi_group_signing() {
+        let mut state = State::new(Config::default());
+        let devices: Vec<Vec<u8>> = (0..3).map(|i| vec![i]).collect();
+        for device in &devices {
+            state.add_device(device, "device", DeviceKind::User, &[0xf0], None);
+        }
+        for identifier in [0xaa, 0xbb] {
+            state
+                .add_group(Group::new(
+                    vec![identifier; 4],
+                    "Sample Group".into(),
+                    state.get_devices().values().cloned().collect(),
+                    2,
+                    ProtocolType::Gg18,
+                    KeyType::SignChallenge,
+                    None,
+                    None,
+                ))
+                .unwrap();
+        }
+        let service = MeeSignService::new(Arc::new(Mutex::new(state))).await;
+
+        let multi_task = service
+            .sign_multi_group(Request::new(msg::SignMultiGroupRequest {
+                name: "Sample Task".into(),
+                group_ids: vec![vec![0xaa; 4], vec![0xbb; 4]],
+                data: vec![0x01],
+                deadline_seconds: None,
+                priority: 0,
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(multi_task.state(), msg::task::TaskState::Created);
+        assert_eq!(multi_task.tasks.len(), 2);
+
+        let message = vec![meesign_crypto::proto::ClientMessage {
+            protocol_type: ProtocolType::Gg18.into(),
+            unicasts: std::collections::HashMap::new(),
+            broadcast: Some(vec![0x01]),
+        }
+        .encode_to_vec()];
+        {
+            let mut state = service.state.lock().await;
+            for task in &multi_task.tasks {
+                let task_id = Uuid::from_slice(&task.id).unwrap();
+                for device in &devices[..2] {
+                    state.decide_task(&task_id, device, true);
+                }
+                while state.get_task(&task_id).unwrap().get_status() != TaskStatus::Finished {
+                    for device in &devices[..2] {
+                        state
+                            .update_task(&task_id, device, &message, 0, None)
+                            .unwrap();
+                    }
+                }
+            }
+        }
+
+        let multi_task = service
+            .get_multi_group_task(Request::new(msg::MultiGroupTaskRequest {
+                id: multi_task.id,
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(multi_task.state(), msg::task::TaskState::Finished);
+        assert!(multi_task
+            .tasks
+            .iter()
+            .all(|task| task.data.len() == 1 && !task.data[0].is_empty()));
+
+        let status = service
+            .sign_multi_group(Request::new(msg::SignMultiGroupRequest {
+                name: "Sample Task".into(),
+                group_ids: vec![vec![0xaa; 4], vec![0xcc; 4]],
+                data: vec![0x01],
+                deadline_seconds: None,
+                priority: 0,
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+        assert_eq!(service.state.lock().await.get_tasks().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn multi_group_partial_results() {
+        let mut state = State::new(Config::default());
+        let devices: Vec<Vec<u8>> = (0..3).map(|i| vec![i]).collect();
+        for device in &devices {
+            state.add_device(device, "device", DeviceKind::User, &[0xf0], None);
+        }
+        let group_ids: Vec<Vec<u8>> = [0xaa, 0xbb, 0xcc].map(|id| vec![id; 4]).into();
+        for group_id in &group_ids {
+            state
+                .add_group(Group::new(
+                    group_id.clone(),
+                    "Sample Group".into(),
+                    state.get_devices().values().cloned().collect(),
+                    2,
+                    ProtocolType::Gg18,
+                    KeyType::SignChallenge,
+                    None,
+                    None,
+                ))
+                .unwrap();
+        }
+        let service = MeeSignService::new(Arc::new(Mutex::new(state))).await;
+
+        let multi_task = service
+            .sign_multi_group(Request::new(msg::SignMultiGroupRequest {
+                name: "Sample Task".into(),
+                group_ids: group_ids.clone(),
+                data: vec![0x01],
+                deadline_seconds: None,
+                priority: 0,
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        let message = vec![meesign_crypto::proto::ClientMessage {
+            protocol_type: ProtocolType::Gg18.into(),
+            unicasts: std::collections::HashMap::new(),
+            broadcast: Some(vec![0x01]),
+        }
+        .encode_to_vec()];
+        {
+            let mut state = service.state.lock().await;
+            for task in &multi_task.tasks[..2] {
+                let task_id = Uuid::from_slice(&task.id).unwrap();
+                for device in &devices[..2] {
+                    state.decide_task(&task_id, device, true);
+                }
+                while state.get_task(&task_id).unwrap().get_status() != TaskStatus::Finished {
+                    for device in &devices[..2] {
+                        state
+                            .update_task(&task_id, device, &message, 0, None)
+                            .unwrap();
+                    }
+                }
+            }
+        }
+
+        let multi_task = service
+            .get_multi_group_task(Request::new(msg::MultiGroupTaskRequest {
+                id: multi_task.id,
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(multi_task.state(), msg::task::TaskState::Running);
+        let items: Vec<_> = multi_task
+            .items
+            .iter()
+            .map(|item| {
+                (
+                    item.group_id.clone(),
+                    item.status(),
+                    item.signature.is_some(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            items,
+            vec![
+                (
+                    group_ids[0].clone(),
+                    msg::multi_group_task::item::Status::Done,
+                    true
+                ),
+                (
+                    group_ids[1].clone(),
+                    msg::multi_group_task::item::Status::Done,
+                    true
+                ),
+                (
+                    group_ids[2].clone(),
+                    msg::multi_group_task::item::Status::Pending,
+                    false
+                ),
+            ]
+        );
+        for (item, task) in multi_task.items.iter().zip(&multi_task.tasks).take(2) {
+            assert_eq!(item.signature.as_ref(), task.data.first());
+        }
+    }
+
+    #[test]
+    fn action_required_for_pending_device() {
+        let mut state = State::new(Config::default());
+        let devices: Vec<Vec<u8>> = (0..2).map(|i| vec![i]).collect();
+        for device in &devices {
+            state.add_device(device, "device", DeviceKind::User, &[0xf0], None);
+        }
+        let task_id = state
+            .add_group_task(
+                "Sample Group",
+                &devices,
+                2,
+                ProtocolType::Gg18,
+                KeyType::SignChallenge,
+                &None,
+                &[],
+                None,
+            )
+            .unwrap();
+        let action_required = |state: &State, device_id: &[u8]| {
+            format_task(
+                &task_id,
+                state.get_task(&task_id).unwrap(),
+                Some(device_id),
+                None,
+            )
+            .unwrap()
+            .action_required
+        };
+
+        state.decide_task(&task_id, &devices[0], true);
+        assert_eq!(action_required(&state, &devices[0]), Some(false));
+        assert_eq!(action_required(&state, &devices[1]), Some(true));
+
+        state.decide_task(&task_id, &devices[1], true);
+        let message = vec![meesign_crypto::proto::ClientMessage {
+            protocol_type: ProtocolType::Gg18.into(),
+            unicasts: std::collections::HashMap::new(),
+            broadcast: Some(vec![0x01]),
+        }
+        .encode_to_vec()];
+        state
+            .update_task(&task_id, &devices[0], &message, 0, None)
+            .unwrap();
+        assert_eq!(action_required(&state, &devices[0]), Some(false));
+        assert_eq!(action_required(&state, &devices[1]), Some(true));
+        assert_eq!(action_required(&state, &[0xee]), Some(false));
+    }
+
+    #[tokio::test]
+    async fn task_work_status() {
+        let mut state = State::new(Config::default());
+        let devices: Vec<Vec<u8>> = (0..3).map(|i| vec![i]).collect();
+        for device in &devices {
+            state.add_device(device, "device", DeviceKind::User, &[0xf0], None);
+        }
+        let task_id = state
+            .add_group_task(
+                "Sample Group",
+                &devices[..2],
+                2,
+                ProtocolType::Gg18,
+                KeyType::SignChallenge,
+                &None,
+                &[],
+                None,
+            )
+            .unwrap();
+        let service = MeeSignService::new(Arc::new(Mutex::new(state))).await;
+        let work_status = |device_id: &[u8]| {
+            let request = Request::new(msg::TaskRequest {
+                task_id: task_id.as_bytes().to_vec(),
+                device_id: Some(device_id.to_vec()),
+                work_version: None,
+                accepted_compressions: Vec::new(),
+            });
+            let service = &service;
+            async move {
+                service
+                    .get_task(request)
+                    .await
+                    .unwrap()
+                    .into_inner()
+                    .work_status
+            }
+        };
+
+        assert_eq!(
+            work_status(&devices[0]).await,
+            Some(msg::task::WorkStatus::NoWorkYet.into())
+        );
+        assert_eq!(
+            work_status(&devices[2]).await,
+            Some(msg::task::WorkStatus::NotParticipant.into())
+        );
+        for device in &devices[..2] {
+            service
+                .state
+                .lock()
+                .await
+                .decide_task(&task_id, device, true);
+        }
+        assert_eq!(
+            work_status(&devices[0]).await,
+            Some(msg::task::WorkStatus::Work.into())
+        );
+
+        let status = service
+            .get_task(Request::new(msg::TaskRequest {
+                task_id: Uuid::new_v4().as_bytes().to_vec(),
+                device_id: None,
+                work_version: None,
+                accepted_compressions: Vec::new(),
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+
+        let task = service
+            .get_task(Request::new(msg::TaskRequest {
+                task_id: task_id.as_bytes().to_vec(),
+                device_id: Some(devices[0].to_vec()),
+                work_version: Some(WORK_VERSION.into()),
+                accepted_compressions: Vec::new(),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(task.work_version, u32::from(WORK_VERSION));
+        assert!(task.data.iter().all(|work| work[0] == WORK_VERSION));
+
+        let status = service
+            .get_task(Request::new(msg::TaskRequest {
+                task_id: task_id.as_bytes().to_vec(),
+                device_id: Some(devices[0].to_vec()),
+                work_version: Some(u32::from(WORK_VERSION) + 1),
+                accepted_compressions: Vec::new(),
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::FailedPrecondition);
+    }
 }
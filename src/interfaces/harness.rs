@@ -0,0 +1,574 @@
+//! Test harness serving the gRPC interface on an ephemeral port
+//!
+//! Simulated devices talk to the server over mutually authenticated TLS, exactly as the
+//! real clients do. The protocol outputs are fixed values, see [`GROUP_KEY`] and [`SIGNATURE`].
+
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use openssl::asn1::{Asn1Integer, Asn1Time};
+use openssl::bn::BigNum;
+use openssl::ec::{EcGroup, EcKey};
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{PKey, Private};
+use openssl::x509::extension::{
+    BasicConstraints, ExtendedKeyUsage, KeyUsage, SubjectAlternativeName,
+};
+use openssl::x509::{X509Builder, X509NameBuilder, X509ReqBuilder, X509};
+use prost::Message as _;
+use tokio::net::TcpListener;
+use tokio::sync::{oneshot, Mutex};
+use tokio::task::JoinHandle;
+use tonic::codegen::Arc;
+use tonic::transport::server::TcpIncoming;
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Identity};
+
+use crate::config::Config;
+use crate::interfaces::grpc::{build_server, MeeSignService};
+use crate::proto::{self as msg, DeviceKind, MeeSignClient, MeeSignServer, ProtocolType};
+use crate::state::State;
+use crate::{CA_CERT, CA_KEY};
+
+/// Key output by every simulated keygen
+pub const GROUP_KEY: [u8; 33] = [0x02; 33];
+/// Signature output by every simulated signing protocol
+pub const SIGNATURE: [u8; 64] = [0x5a; 64];
+
+/// Interval between two polls of a simulated device
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Generate a throwaway CA used in place of the one in `keys/`
+pub fn generate_ca() -> (X509, PKey<Private>) {
+    let key = generate_key();
+    let mut name = X509NameBuilder::new().unwrap();
+    name.append_entry_by_text("CN", "MeeSign Test CA").unwrap();
+    let name = name.build();
+
+    let mut builder = certificate_builder(&key);
+    builder.set_subject_name(&name).unwrap();
+    builder.set_issuer_name(&name).unwrap();
+    builder
+        .append_extension(BasicConstraints::new().critical().ca().build().unwrap())
+        .unwrap();
+    builder
+        .append_extension(
+            KeyUsage::new()
+                .critical()
+                .key_cert_sign()
+                .crl_sign()
+                .digital_signature()
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+    builder.sign(&key, MessageDigest::sha256()).unwrap();
+    (builder.build(), key)
+}
+
+fn generate_key() -> PKey<Private> {
+    let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+    PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap()
+}
+
+fn certificate_builder(key: &PKey<Private>) -> X509Builder {
+    let mut builder = X509Builder::new().unwrap();
+    builder.set_version(2).unwrap();
+    let serial = BigNum::from_u32(rand::random()).unwrap();
+    builder
+        .set_serial_number(&Asn1Integer::from_bn(&serial).unwrap())
+        .unwrap();
+    builder
+        .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+        .unwrap();
+    builder
+        .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+        .unwrap();
+    builder.set_pubkey(key).unwrap();
+    builder
+}
+
+/// Issue a server certificate for `localhost` by the CA
+fn server_identity() -> Identity {
+    let key = generate_key();
+    let mut name = X509NameBuilder::new().unwrap();
+    name.append_entry_by_text("CN", "MeeSign Test Server")
+        .unwrap();
+
+    let mut builder = certificate_builder(&key);
+    builder.set_subject_name(&name.build()).unwrap();
+    builder.set_issuer_name(CA_CERT.subject_name()).unwrap();
+    let alternative_name = SubjectAlternativeName::new()
+        .dns("localhost")
+        .build(&builder.x509v3_context(Some(&CA_CERT), None))
+        .unwrap();
+    builder.append_extension(alternative_name).unwrap();
+    builder
+        .append_extension(BasicConstraints::new().critical().build().unwrap())
+        .unwrap();
+    builder
+        .append_extension(ExtendedKeyUsage::new().server_auth().build().unwrap())
+        .unwrap();
+    builder.sign(&CA_KEY, MessageDigest::sha256()).unwrap();
+
+    Identity::from_pem(
+        builder.build().to_pem().unwrap(),
+        key.private_key_to_pem_pkcs8().unwrap(),
+    )
+}
+
+/// Server running in the background until [`TestServer::shutdown`]
+pub struct TestServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    shutdown: oneshot::Sender<()>,
+    handle: JoinHandle<Result<(), tonic::transport::Error>>,
+}
+
+impl TestServer {
+    pub async fn start(config: Config) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let incoming = TcpIncoming::from_listener(listener, true, None).unwrap();
+
+        let state = Arc::new(Mutex::new(State::new(config.clone())));
+        let service = MeeSignService::new(state.clone()).await;
+        let (shutdown, signal) = oneshot::channel();
+        let server = build_server(&config, server_identity())
+            .unwrap()
+            .add_service(MeeSignServer::new(service))
+            .serve_with_incoming_shutdown(incoming, async {
+                signal.await.ok();
+            });
+
+        TestServer {
+            addr,
+            state,
+            shutdown,
+            handle: tokio::spawn(server),
+        }
+    }
+
+    pub fn state(&self) -> &Arc<Mutex<State>> {
+        &self.state
+    }
+
+    /// Connect a client, authenticated by `identity` if given
+    pub async fn connect(&self, identity: Option<Identity>) -> MeeSignClient<Channel> {
+        let mut tls = ClientTlsConfig::new()
+            .ca_certificate(Certificate::from_pem(CA_CERT.to_pem().unwrap()))
+            .domain_name("localhost");
+        if let Some(identity) = identity {
+            tls = tls.identity(identity);
+        }
+        let channel = Channel::from_shared(format!("https://localhost:{}", self.addr.port()))
+            .unwrap()
+            .tls_config(tls)
+            .unwrap()
+            .connect()
+            .await
+            .unwrap();
+        MeeSignClient::new(channel)
+    }
+
+    /// Register a device through the `Register` call and connect it with the issued certificate
+    pub async fn register_device(&self, name: &str) -> SimulatedDevice {
+        let key = generate_key();
+        let mut subject = X509NameBuilder::new().unwrap();
+        subject.append_entry_by_text("CN", name).unwrap();
+        let mut csr = X509ReqBuilder::new().unwrap();
+        csr.set_subject_name(&subject.build()).unwrap();
+        csr.set_pubkey(&key).unwrap();
+        csr.sign(&key, MessageDigest::sha256()).unwrap();
+
+        let response = self
+            .connect(None)
+            .await
+            .register(msg::RegistrationRequest {
+                name: name.into(),
+                kind: DeviceKind::User.into(),
+                csr: csr.build().to_der().unwrap(),
+                public_key: None,
+                delivery: msg::DeliveryMode::Push.into(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        let certificate = X509::from_der(&response.certificate).unwrap();
+        let identity = Identity::from_pem(
+            certificate.to_pem().unwrap(),
+            key.private_key_to_pem_pkcs8().unwrap(),
+        );
+
+        SimulatedDevice {
+            id: response.device_id,
+            client: self.connect(Some(identity)).await,
+        }
+    }
+
+    /// Stop accepting requests and wait for the server to finish
+    pub async fn shutdown(self) {
+        self.shutdown.send(()).unwrap();
+        self.handle.await.unwrap().unwrap();
+    }
+}
+
+/// Registered device which accepts all its tasks and answers every round
+pub struct SimulatedDevice {
+    pub id: Vec<u8>,
+    pub client: MeeSignClient<Channel>,
+}
+
+impl SimulatedDevice {
+    /// Process tasks in the background until the returned handle is aborted
+    pub fn spawn(self, protocol: ProtocolType) -> JoinHandle<()> {
+        tokio::spawn(self.run(protocol))
+    }
+
+    async fn run(mut self, protocol: ProtocolType) {
+        let mut decided = HashSet::new();
+        let mut answered = HashMap::new();
+        loop {
+            let tasks = self
+                .client
+                .get_tasks(msg::TasksRequest {
+                    device_id: Some(self.id.clone()),
+                })
+                .await
+                .unwrap()
+                .into_inner()
+                .tasks;
+            for task in tasks {
+                match task.state() {
+                    msg::task::TaskState::Created if decided.insert(task.id.clone()) => {
+                        self.client
+                            .decide_task(msg::TaskDecision {
+                                task: task.id,
+                                accept: true,
+                                withdraw: false,
+                            })
+                            .await
+                            .unwrap();
+                    }
+                    msg::task::TaskState::Running
+                        if !task.data.is_empty()
+                            && answered.get(&task.id) != Some(&(task.attempt, task.round)) =>
+                    {
+                        answered.insert(task.id.clone(), (task.attempt, task.round));
+                        self.answer(&task, protocol).await;
+                    }
+                    _ => {}
+                }
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    async fn answer(&mut self, task: &msg::Task, protocol: ProtocolType) {
+        let output = match task.r#type() {
+            msg::TaskType::Group => GROUP_KEY.to_vec(),
+            _ => SIGNATURE.to_vec(),
+        };
+        let message = meesign_crypto::proto::ClientMessage {
+            protocol_type: meesign_crypto::proto::ProtocolType::from(protocol).into(),
+            unicasts: HashMap::new(),
+            broadcast: Some(output),
+        }
+        .encode_to_vec();
+        self.client
+            .update_task(msg::TaskUpdate {
+                task: task.id.clone(),
+                data: vec![message; task.data.len()],
+                attempt: task.attempt,
+                sequence: None,
+            })
+            .await
+            .unwrap();
+    }
+}
+
+/// Poll the task until it finishes or fails
+pub async fn wait_for_task(client: &mut MeeSignClient<Channel>, task_id: &[u8]) -> msg::Task {
+    loop {
+        let task = client
+            .get_task(msg::TaskRequest {
+                task_id: task_id.to_vec(),
+                device_id: None,
+                work_version: None,
+                accepted_compressions: Vec::new(),
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        if matches!(
+            task.state(),
+            msg::task::TaskState::Finished | msg::task::TaskState::Failed
+        ) {
+            return task;
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::group::Group;
+    use crate::proto::{HashAlgorithm, KeyType};
+
+    #[tokio::test]
+    async fn register_group_and_sign() {
+        let server = TestServer::start(Config::default()).await;
+        let mut devices = Vec::new();
+        for i in 0..3 {
+            devices.push(server.register_device(&format!("device {}", i)).await);
+        }
+        let device_ids: Vec<_> = devices.iter().map(|device| device.id.clone()).collect();
+        let workers: Vec<_> = devices
+            .into_iter()
+            .map(|device| device.spawn(ProtocolType::Frost))
+            .collect();
+        let mut client = server.connect(None).await;
+
+        let lifecycle = async {
+            let task = client
+                .group(msg::GroupRequest {
+                    name: "Sample Group".into(),
+                    device_ids: device_ids.clone(),
+                    threshold: 2,
+                    protocol: ProtocolType::Frost.into(),
+                    key_type: KeyType::SignChallenge.into(),
+                    note: None,
+                    deadline_seconds: None,
+                    tags: Vec::new(),
+                    priority: 0,
+                    allowed_tasks: Vec::new(),
+                    auto_approve: false,
+                    require_all_signers: false,
+                })
+                .await
+                .unwrap()
+                .into_inner();
+            let task = wait_for_task(&mut client, &task.id).await;
+            assert_eq!(task.state(), msg::task::TaskState::Finished);
+            assert_eq!(task.data, vec![GROUP_KEY.to_vec()]);
+            assert!(server.state().lock().await.find_group(&GROUP_KEY).is_some());
+
+            let task = client
+                .sign(msg::SignRequest {
+                    name: "Sample Task".into(),
+                    group_id: GROUP_KEY.to_vec(),
+                    data: b"Hello, world!".to_vec(),
+                    deadline_seconds: None,
+                    hash_algorithm: HashAlgorithm::Default.into(),
+                    priority: 0,
+                    depends_on: None,
+                })
+                .await
+                .unwrap()
+                .into_inner();
+            wait_for_task(&mut client, &task.id).await
+        };
+        let task = tokio::time::timeout(Duration::from_secs(30), lifecycle)
+            .await
+            .expect("lifecycle did not complete in time");
+        assert_eq!(task.state(), msg::task::TaskState::Finished);
+        assert_eq!(task.data, vec![SIGNATURE.to_vec()]);
+
+        drop(client);
+        for worker in workers {
+            worker.abort();
+        }
+        server.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn maintenance_mode_serves_existing_tasks() {
+        let server = TestServer::start(Config {
+            admin_token: Some("secret".into()),
+            ..Config::default()
+        })
+        .await;
+        let mut devices = Vec::new();
+        for i in 0..2 {
+            devices.push(server.register_device(&format!("device {}", i)).await);
+        }
+        {
+            let mut state = server.state().lock().await;
+            let group = Group::new(
+                GROUP_KEY.to_vec(),
+                "Sample Group".into(),
+                state.get_devices().values().cloned().collect(),
+                2,
+                ProtocolType::Frost,
+                KeyType::SignChallenge,
+                None,
+                None,
+            );
+            state.add_group(group).unwrap();
+        }
+        let mut client = server.connect(None).await;
+        let sign_request = || msg::SignRequest {
+            name: "Sample Task".into(),
+            group_id: GROUP_KEY.to_vec(),
+            data: b"Hello, world!".to_vec(),
+            deadline_seconds: None,
+            hash_algorithm: HashAlgorithm::Default.into(),
+            priority: 0,
+            depends_on: None,
+        };
+        let task = client.sign(sign_request()).await.unwrap().into_inner();
+
+        let mut request = tonic::Request::new(msg::MaintenanceModeRequest { enabled: true });
+        request
+            .metadata_mut()
+            .insert("authorization", "Bearer secret".parse().unwrap());
+        client.set_maintenance_mode(request).await.unwrap();
+        let info = client
+            .get_server_info(msg::ServerInfoRequest {})
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(info.maintenance);
+        let status = client.sign(sign_request()).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unavailable);
+
+        let workers: Vec<_> = devices
+            .into_iter()
+            .map(|device| device.spawn(ProtocolType::Frost))
+            .collect();
+        let task = tokio::time::timeout(
+            Duration::from_secs(30),
+            wait_for_task(&mut client, &task.id),
+        )
+        .await
+        .expect("task did not complete in time");
+        assert_eq!(task.state(), msg::task::TaskState::Finished);
+        assert_eq!(task.data, vec![SIGNATURE.to_vec()]);
+
+        drop(client);
+        for worker in workers {
+            worker.abort();
+        }
+        server.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn audit_trail_of_group() {
+        let server = TestServer::start(Config {
+            admin_token: Some("secret".into()),
+            ..Config::default()
+        })
+        .await;
+        let mut devices = Vec::new();
+        for i in 0..2 {
+            devices.push(server.register_device(&format!("device {}", i)).await);
+        }
+        let device_ids: Vec<_> = devices.iter().map(|device| device.id.clone()).collect();
+        let workers: Vec<_> = devices
+            .into_iter()
+            .map(|device| device.spawn(ProtocolType::Frost))
+            .collect();
+        let mut client = server.connect(None).await;
+
+        let lifecycle = async {
+            let task = client
+                .group(msg::GroupRequest {
+                    name: "Sample Group".into(),
+                    device_ids: device_ids.clone(),
+                    threshold: 2,
+                    protocol: ProtocolType::Frost.into(),
+                    key_type: KeyType::SignChallenge.into(),
+                    note: None,
+                    deadline_seconds: None,
+                    tags: Vec::new(),
+                    priority: 0,
+                    allowed_tasks: Vec::new(),
+                    auto_approve: false,
+                    require_all_signers: false,
+                })
+                .await
+                .unwrap()
+                .into_inner();
+            let mut task_ids = vec![wait_for_task(&mut client, &task.id).await.id];
+            for _ in 0..2 {
+                let task = client
+                    .sign(msg::SignRequest {
+                        name: "Sample Task".into(),
+                        group_id: GROUP_KEY.to_vec(),
+                        data: b"Hello, world!".to_vec(),
+                        deadline_seconds: None,
+                        hash_algorithm: HashAlgorithm::Default.into(),
+                        priority: 0,
+                        depends_on: None,
+                    })
+                    .await
+                    .unwrap()
+                    .into_inner();
+                task_ids.push(wait_for_task(&mut client, &task.id).await.id);
+            }
+            task_ids
+        };
+        let task_ids = tokio::time::timeout(Duration::from_secs(30), lifecycle)
+            .await
+            .expect("lifecycle did not complete in time");
+
+        let trail_request = |since| {
+            let mut request = tonic::Request::new(msg::AuditTrailRequest {
+                group_id: GROUP_KEY.to_vec(),
+                since,
+                until: None,
+            });
+            request
+                .metadata_mut()
+                .insert("authorization", "Bearer secret".parse().unwrap());
+            request
+        };
+        let mut stream = client
+            .get_audit_trail(trail_request(None))
+            .await
+            .unwrap()
+            .into_inner();
+        let mut records = Vec::new();
+        while let Some(record) = stream.message().await.unwrap() {
+            records.push(record);
+        }
+        let events: Vec<_> = records
+            .iter()
+            .map(|record| (record.task_id.clone(), record.task_type(), record.event()))
+            .collect();
+        let mut expected = Vec::new();
+        for (task_id, task_type) in task_ids.iter().zip([
+            msg::TaskType::Group,
+            msg::TaskType::SignChallenge,
+            msg::TaskType::SignChallenge,
+        ]) {
+            expected.push((task_id.clone(), task_type, msg::AuditEvent::Created));
+            expected.push((task_id.clone(), task_type, msg::AuditEvent::Finished));
+        }
+        assert_eq!(events, expected);
+        assert!(records
+            .windows(2)
+            .all(|pair| pair[0].timestamp <= pair[1].timestamp));
+        let mut participants = device_ids.clone();
+        participants.sort();
+        assert!(records
+            .iter()
+            .all(|record| record.participants == participants && record.signature.is_none()));
+
+        let since = records.last().unwrap().timestamp + 1;
+        let mut stream = client
+            .get_audit_trail(trail_request(Some(since)))
+            .await
+            .unwrap()
+            .into_inner();
+        assert!(stream.message().await.unwrap().is_none());
+
+        drop(client);
+        for worker in workers {
+            worker.abort();
+        }
+        server.shutdown().await;
+    }
+}
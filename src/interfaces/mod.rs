@@ -1,2 +1,6 @@
+pub mod admin;
 pub mod grpc;
+#[cfg(test)]
+pub mod harness;
 pub mod timer;
+pub mod web;
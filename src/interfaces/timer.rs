@@ -1,36 +1,61 @@
+use crate::config::ProtocolStage;
+use crate::logging;
+use crate::proto::TaskType;
 use crate::state::State;
 use crate::tasks::TaskStatus;
-use crate::{get_timestamp, utils};
 
-use log::debug;
 use tokio::sync::MutexGuard;
 use tokio::{sync::Mutex, time};
 use tonic::codegen::Arc;
+use tracing::debug;
 
 pub async fn run_timer(state: Arc<Mutex<State>>) -> Result<(), String> {
     let mut interval = time::interval(time::Duration::from_secs(1));
     loop {
         interval.tick().await;
-        let mut state = state.lock().await;
-        check_tasks(&mut state);
-        check_subscribers(&mut state);
+        tick(&mut state.lock().await);
     }
 }
 
+/// Expire overdue tasks and inactive devices at the current time of the state clock
+fn tick(state: &mut MutexGuard<State>) {
+    let timestamp = state.now();
+    state.fail_overdue_tasks(timestamp);
+    state.unregister_inactive_devices(timestamp);
+    check_tasks(state);
+    check_subscribers(state);
+}
+
 fn check_tasks(state: &mut MutexGuard<State>) {
     let mut restarts = Vec::new();
-    let timestamp = get_timestamp();
+    let timestamp = state.now();
     for (task_id, task) in state.get_tasks() {
+        // the update of a checked out task is in progress, so the task is not stale
+        if state.pending_update(task_id).is_some() {
+            continue;
+        }
+        let stage = match task.get_type() {
+            TaskType::Group => ProtocolStage::Keygen,
+            _ => ProtocolStage::Sign,
+        };
+        let round_timeout = state
+            .get_config()
+            .round_timeout_of(task.get_protocol(), stage)
+            .as_secs();
         if task.get_status() != TaskStatus::Finished
             && task.is_approved()
-            && timestamp - task.last_update() > 30
+            && timestamp - task.last_update() > round_timeout
         {
-            debug!("Stale task detected task_id={:?}", utils::hextrunc(task_id));
+            debug!(task_id = %logging::log_id(task_id), "Stale task detected");
             restarts.push(*task_id);
         }
     }
     for task_id in restarts {
-        state.restart_task(&task_id);
+        state.record_commitment_violations(&task_id);
+        state.record_stalled_devices(&task_id);
+        if !state.fail_stalled_keygen(&task_id) {
+            state.restart_task(&task_id);
+        }
     }
 }
 
@@ -39,8 +64,8 @@ fn check_subscribers(state: &mut MutexGuard<State>) {
     for (device_id, tx) in state.get_subscribers() {
         if tx.is_closed() {
             debug!(
-                "Closed channel detected device_id={:?}",
-                utils::hextrunc(device_id)
+                device_id = %logging::log_id(device_id),
+                "Closed channel detected"
             );
             remove.push(device_id.clone());
         } else {
@@ -51,3 +76,133 @@ fn check_subscribers(state: &mut MutexGuard<State>) {
         state.remove_subscriber(&device_id);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use crate::config::Config;
+    use crate::group::Group;
+    use crate::proto::{DeviceKind, HashAlgorithm, KeyType, ProtocolType};
+    use crate::tasks::FailureReason;
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn mock_clock_expires_tasks() {
+        let clock = Arc::new(MockClock::new(1_700_000_000));
+        let mut state = State::new(Config::default());
+        state.set_clock(clock.clone());
+        let devices: Vec<Vec<u8>> = (0..2).map(|i| vec![i]).collect();
+        for device in &devices {
+            state.add_device(device, "device", DeviceKind::User, &[0xf0], None);
+        }
+        state
+            .add_group(Group::new(
+                vec![0xaa; 4],
+                "Sample Group".into(),
+                state.get_devices().values().cloned().collect(),
+                2,
+                ProtocolType::Gg18,
+                KeyType::SignChallenge,
+                None,
+                None,
+            ))
+            .unwrap();
+        let task_id = state
+            .add_sign_task(
+                &[0xaa; 4],
+                "Sample Task",
+                &[0x01],
+                Some(30),
+                HashAlgorithm::Default,
+            )
+            .unwrap();
+        let state = Mutex::new(state);
+
+        clock.advance(29);
+        tick(&mut state.lock().await);
+        assert_eq!(
+            state.lock().await.get_task(&task_id).unwrap().get_status(),
+            TaskStatus::Created
+        );
+
+        clock.advance(1);
+        tick(&mut state.lock().await);
+        assert_eq!(
+            state.lock().await.get_task(&task_id).unwrap().get_status(),
+            TaskStatus::Failed(
+                FailureReason::Timeout,
+                "Task failed (requester deadline exceeded)".into()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn protocol_round_timeouts() {
+        let clock = Arc::new(MockClock::new(1_700_000_000));
+        let mut state = State::new(Config {
+            protocol_round_timeouts: HashMap::from([
+                (
+                    (ProtocolType::Gg18, ProtocolStage::Sign),
+                    Duration::from_secs(60),
+                ),
+                (
+                    (ProtocolType::Musig2, ProtocolStage::Sign),
+                    Duration::from_secs(10),
+                ),
+            ]),
+            ..Config::default()
+        });
+        state.set_clock(clock.clone());
+        let devices: Vec<Vec<u8>> = (0..2).map(|i| vec![i]).collect();
+        for device in &devices {
+            state.add_device(device, "device", DeviceKind::User, &[0xf0], None);
+        }
+        let mut add_task = |group_id: Vec<u8>, protocol| {
+            state
+                .add_group(Group::new(
+                    group_id.clone(),
+                    "Sample Group".into(),
+                    state.get_devices().values().cloned().collect(),
+                    2,
+                    protocol,
+                    KeyType::SignChallenge,
+                    None,
+                    None,
+                ))
+                .unwrap();
+            let task_id = state
+                .add_sign_task(
+                    &group_id,
+                    "Sample Task",
+                    &[0x01],
+                    None,
+                    HashAlgorithm::Default,
+                )
+                .unwrap();
+            for device in &devices {
+                state.decide_task(&task_id, device, true);
+            }
+            task_id
+        };
+        let gg18_task = add_task(vec![0xaa; 4], ProtocolType::Gg18);
+        let musig2_task = add_task(vec![0xbb; 4], ProtocolType::Musig2);
+        let state = Mutex::new(state);
+        let attempts = |state: &State| {
+            (
+                state.get_task(&gg18_task).unwrap().get_attempts(),
+                state.get_task(&musig2_task).unwrap().get_attempts(),
+            )
+        };
+        assert_eq!(attempts(&*state.lock().await), (0, 0));
+
+        clock.advance(11);
+        tick(&mut state.lock().await);
+        assert_eq!(attempts(&*state.lock().await), (0, 1));
+
+        clock.advance(50);
+        tick(&mut state.lock().await);
+        assert_eq!(attempts(&*state.lock().await), (1, 2));
+    }
+}
@@ -0,0 +1,285 @@
+use std::convert::Infallible;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use tonic::body::BoxBody;
+use tonic::codegen::http::{header, HeaderName, HeaderValue, Request, Response};
+use tonic::codegen::{BoxFuture, Service};
+use tonic::server::NamedService;
+use tonic::transport::Body;
+use tonic::Status;
+use tonic_web::{GrpcWebLayer, GrpcWebService};
+use tower::Layer;
+use tower_http::cors::{AllowOrigin, Cors, CorsLayer};
+
+use crate::config::GrpcWeb;
+
+/// Calls served over gRPC-Web unless full access is configured
+const READ_ONLY_CALLS: [&str; 13] = [
+    "/meesign.MeeSign/GetServerInfo",
+    "/meesign.MeeSign/GetServerCapabilities",
+    "/meesign.MeeSign/GetTask",
+    "/meesign.MeeSign/GetTaskParticipants",
+    "/meesign.MeeSign/GetResult",
+    "/meesign.MeeSign/GetTasks",
+    "/meesign.MeeSign/QueryTasks",
+    "/meesign.MeeSign/GetMultiGroupTask",
+    "/meesign.MeeSign/GetGroups",
+    "/meesign.MeeSign/ExportGroupKey",
+    "/meesign.MeeSign/GetDevices",
+    "/meesign.MeeSign/GetDeviceInfo",
+    "/meesign.MeeSign/EstimateSign",
+];
+
+/// Time for which browsers may cache the response to a preflight request
+const PREFLIGHT_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Response headers readable by browser clients
+const EXPOSED_HEADERS: [&str; 3] = ["grpc-status", "grpc-message", "grpc-status-details-bin"];
+
+/// Request headers browser clients may send
+const ALLOWED_HEADERS: [&str; 5] = [
+    "x-grpc-web",
+    "content-type",
+    "x-user-agent",
+    "grpc-timeout",
+    "authorization",
+];
+
+/// Service accepting gRPC-Web requests of browser clients next to native gRPC requests
+#[derive(Clone)]
+pub struct WebGateway<S>(Cors<ReadOnlyFilter<GrpcWebService<S>>>);
+
+/// Wrap `service` to translate gRPC-Web requests permitted by `settings`
+pub fn gateway<S>(service: S, settings: &GrpcWeb) -> Result<WebGateway<S>, String>
+where
+    S: Service<Request<Body>, Response = Response<BoxBody>, Error = Infallible>
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send + 'static,
+{
+    // credentialed requests are permitted only from the listed origins, so that an arbitrary
+    // website cannot make calls with the client certificate of the browser
+    let allow_credentials = !settings.origins.is_empty();
+    let allow_origin = if settings.origins.is_empty() {
+        AllowOrigin::mirror_request()
+    } else {
+        let origins = settings
+            .origins
+            .iter()
+            .map(|origin| HeaderValue::from_str(origin))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| "Invalid gRPC-Web origin".to_string())?;
+        AllowOrigin::list(origins)
+    };
+    let cors = CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_credentials(allow_credentials)
+        .max_age(PREFLIGHT_MAX_AGE)
+        .expose_headers(EXPOSED_HEADERS.map(HeaderName::from_static))
+        .allow_headers(ALLOWED_HEADERS.map(HeaderName::from_static));
+
+    Ok(WebGateway(cors.layer(ReadOnlyFilter {
+        inner: GrpcWebLayer::new().layer(service),
+        full_access: settings.full_access,
+    })))
+}
+
+impl<S> Service<Request<Body>> for WebGateway<S>
+where
+    S: Service<Request<Body>, Response = Response<BoxBody>, Error = Infallible>
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response<BoxBody>;
+    type Error = Infallible;
+    type Future = <Cors<ReadOnlyFilter<GrpcWebService<S>>> as Service<Request<Body>>>::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.0.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        self.0.call(request)
+    }
+}
+
+impl<S: NamedService> NamedService for WebGateway<S> {
+    const NAME: &'static str = S::NAME;
+}
+
+/// Reject gRPC-Web requests of the calls which are not read-only, unless `full_access` is set
+#[derive(Clone)]
+pub struct ReadOnlyFilter<S> {
+    inner: S,
+    full_access: bool,
+}
+
+impl<S> Service<Request<Body>> for ReadOnlyFilter<S>
+where
+    S: Service<Request<Body>, Response = Response<BoxBody>, Error = Infallible>,
+    S::Future: Send + 'static,
+{
+    type Response = Response<BoxBody>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        let content_type = request.headers().get(header::CONTENT_TYPE).cloned();
+        let is_web = content_type
+            .as_ref()
+            .and_then(|content_type| content_type.to_str().ok())
+            .is_some_and(|content_type| content_type.starts_with("application/grpc-web"));
+        if is_web && !self.full_access && !READ_ONLY_CALLS.contains(&request.uri().path()) {
+            let mut response =
+                Status::permission_denied("The call is not available over gRPC-Web").to_http();
+            if let Some(content_type) = content_type {
+                response
+                    .headers_mut()
+                    .insert(header::CONTENT_TYPE, content_type);
+            }
+            return Box::pin(async move { Ok(response) });
+        }
+        Box::pin(self.inner.call(request))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::group::Group;
+    use crate::interfaces::grpc::MeeSignService;
+    use crate::proto::{self as msg, DeviceKind, KeyType, MeeSignServer, ProtocolType};
+    use crate::state::State;
+    use prost::Message as _;
+    use tokio::sync::Mutex;
+    use tonic::codegen::{Arc, Body as _};
+    use tower::ServiceExt;
+
+    /// Frame `message` as the body of a gRPC-Web request
+    fn web_request(path: &str, message: impl prost::Message) -> Request<Body> {
+        let message = message.encode_to_vec();
+        let mut body = vec![0x00];
+        body.extend((message.len() as u32).to_be_bytes());
+        body.extend(message);
+        Request::post(path)
+            .header(header::CONTENT_TYPE, "application/grpc-web+proto")
+            .header(header::ORIGIN, "https://dashboard.example")
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    async fn read_body(response: Response<BoxBody>) -> Vec<u8> {
+        let mut body = response.into_body();
+        let mut content = Vec::new();
+        while let Some(chunk) = body.data().await {
+            content.extend(chunk.unwrap());
+        }
+        content
+    }
+
+    #[tokio::test]
+    async fn read_call_over_grpc_web() {
+        let mut state = State::new(Config::default());
+        let devices: Vec<Vec<u8>> = (0..2).map(|i| vec![i]).collect();
+        for device in &devices {
+            state.add_device(device, "device", DeviceKind::User, &[0xf0], None);
+        }
+        state
+            .add_group(Group::new(
+                vec![0xaa; 4],
+                "Sample Group".into(),
+                state.get_devices().values().cloned().collect(),
+                2,
+                ProtocolType::Gg18,
+                KeyType::SignChallenge,
+                None,
+                None,
+            ))
+            .unwrap();
+        let service = MeeSignServer::new(MeeSignService::new(Arc::new(Mutex::new(state))).await);
+        let gateway = gateway(service, &GrpcWeb::default()).unwrap();
+
+        let response = gateway
+            .clone()
+            .oneshot(web_request(
+                "/meesign.MeeSign/GetGroups",
+                msg::GroupsRequest::default(),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "application/grpc-web+proto"
+        );
+        assert_eq!(
+            response.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://dashboard.example"
+        );
+        assert!(!response
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_ALLOW_CREDENTIALS));
+        let body = read_body(response).await;
+        assert_eq!(body[0], 0x00);
+        let length = u32::from_be_bytes(body[1..5].try_into().unwrap()) as usize;
+        let groups = msg::Groups::decode(&body[5..5 + length]).unwrap();
+        assert_eq!(groups.groups.len(), 1);
+        assert_eq!(groups.groups[0].identifier, vec![0xaa; 4]);
+        let trailers = String::from_utf8_lossy(&body[5 + length + 5..]).to_lowercase();
+        assert!(trailers.contains("grpc-status:0"));
+
+        let response = gateway
+            .oneshot(web_request(
+                "/meesign.MeeSign/SetMaintenanceMode",
+                msg::MaintenanceModeRequest { enabled: true },
+            ))
+            .await
+            .unwrap();
+        assert_eq!(
+            response.headers()["grpc-status"],
+            (tonic::Code::PermissionDenied as i32).to_string().as_str()
+        );
+    }
+
+    #[tokio::test]
+    async fn credentials_only_for_listed_origins() {
+        let state = State::new(Config::default());
+        let service = MeeSignServer::new(MeeSignService::new(Arc::new(Mutex::new(state))).await);
+        let settings = GrpcWeb {
+            origins: vec!["https://dashboard.example".into()],
+            full_access: false,
+        };
+        let gateway = gateway(service, &settings).unwrap();
+
+        let response = gateway
+            .clone()
+            .oneshot(web_request(
+                "/meesign.MeeSign/GetGroups",
+                msg::GroupsRequest::default(),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(
+            response.headers()[header::ACCESS_CONTROL_ALLOW_CREDENTIALS],
+            "true"
+        );
+
+        let mut request = web_request("/meesign.MeeSign/GetGroups", msg::GroupsRequest::default());
+        request.headers_mut().insert(
+            header::ORIGIN,
+            HeaderValue::from_static("https://attacker.example"),
+        );
+        let response = gateway.oneshot(request).await.unwrap();
+        assert!(!response
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN));
+    }
+}
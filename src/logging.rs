@@ -0,0 +1,161 @@
+use clap::ValueEnum;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::Subscriber;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+
+/// Output format of the server log
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// Human-readable lines
+    Text,
+    /// One JSON object per event with its structured fields
+    Json,
+}
+
+/// Number of leading hex characters kept of a redacted identifier
+const REDACTED_ID_LENGTH: usize = 8;
+
+static REDACT_IDS: AtomicBool = AtomicBool::new(false);
+
+/// Installs the global logger; the verbosity is taken from `RUST_LOG`
+///
+/// With `redact_ids`, identifiers formatted by [`log_id`] are shortened to their prefix.
+pub fn init(format: LogFormat, redact_ids: bool) {
+    REDACT_IDS.store(redact_ids, Ordering::Relaxed);
+    match format {
+        LogFormat::Text => tracing_subscriber::fmt()
+            .with_writer(std::io::stderr)
+            .finish()
+            .with(EnvFilter::from_default_env())
+            .init(),
+        LogFormat::Json => json_subscriber(std::io::stderr)
+            .with(EnvFilter::from_default_env())
+            .init(),
+    }
+}
+
+/// Formats a device, group or task identifier for the log
+///
+/// Full identifiers are kept in the audit trail regardless of the redaction.
+pub fn log_id<T: AsRef<[u8]>>(id: T) -> String {
+    format_id(id.as_ref(), REDACT_IDS.load(Ordering::Relaxed))
+}
+
+/// Formats the identity of a task requester for the log
+///
+/// Requesting devices are formatted like [`log_id`]; addresses of anonymous requesters
+/// are left out when identifiers are redacted.
+pub fn log_requester(requester: &str) -> String {
+    format_requester(requester, REDACT_IDS.load(Ordering::Relaxed))
+}
+
+fn format_requester(requester: &str, redact: bool) -> String {
+    match hex::decode(requester) {
+        Ok(device_id) => format_id(&device_id, redact),
+        Err(_) if redact && requester != "admin" => "anonymous".to_string(),
+        Err(_) => requester.to_string(),
+    }
+}
+
+fn format_id(id: &[u8], redact: bool) -> String {
+    let trunc_len = if redact {
+        REDACTED_ID_LENGTH / 2
+    } else {
+        std::env::var("TRUNC")
+            .ok()
+            .as_ref()
+            .and_then(|x| x.parse().ok())
+            .unwrap_or(0)
+    };
+    if trunc_len == 0 || trunc_len >= id.len() {
+        hex::encode(id)
+    } else {
+        format!("{}...", hex::encode(&id[..trunc_len]))
+    }
+}
+
+/// Builds a subscriber emitting each event as a flat JSON object
+/// containing `level`, `message`, and the event fields (e.g. `task_id`)
+fn json_subscriber<W>(writer: W) -> impl Subscriber + Send + Sync
+where
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    tracing_subscriber::fmt()
+        .json()
+        .flatten_event(true)
+        .with_current_span(false)
+        .with_span_list(false)
+        .with_writer(writer)
+        .finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn json_event_fields() {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber = json_subscriber(move || writer.clone());
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(
+                task_id = %hex::encode([0x01, 0x02]),
+                device_id = %hex::encode([0xab, 0xcd]),
+                "Task approved"
+            );
+        });
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(output.lines().count(), 1);
+        assert!(output.starts_with('{') && output.trim_end().ends_with('}'));
+        assert!(output.contains(r#""level":"INFO""#));
+        assert!(output.contains(r#""message":"Task approved""#));
+        assert!(output.contains(r#""task_id":"0102""#));
+        assert!(output.contains(r#""device_id":"abcd""#));
+    }
+
+    #[test]
+    fn redacted_ids() {
+        let id = [0x0f, 0x1e, 0x2d, 0x3c, 0x4b, 0x5a, 0x69, 0x78];
+        assert_eq!(format_id(&id, true), "0f1e2d3c...");
+        assert_eq!(format_id(&[0xab, 0xcd], true), "abcd");
+
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber = json_subscriber(move || writer.clone());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(device_id = %format_id(&id, true), "Device activated");
+        });
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert!(output.contains(r#""device_id":"0f1e2d3c...""#));
+        assert!(!output.contains(&hex::encode(id)));
+    }
+
+    #[test]
+    fn redacted_requesters() {
+        let device_id = hex::encode([0x0f, 0x1e, 0x2d, 0x3c, 0x4b, 0x5a, 0x69, 0x78]);
+        assert_eq!(format_requester(&device_id, true), "0f1e2d3c...");
+        assert_eq!(format_requester("admin", true), "admin");
+        assert_eq!(format_requester("192.0.2.1", true), "anonymous");
+        assert_eq!(format_requester("192.0.2.1", false), "192.0.2.1");
+    }
+}
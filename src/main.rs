@@ -1,19 +1,33 @@
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use lazy_static::lazy_static;
 use openssl::pkey::{PKey, Private};
 use openssl::x509::X509;
 
+use crate::config::{
+    default_protocol_round_timeouts, Config, DisconnectPolicy, GrpcWeb, NamePolicy, ProtocolStage,
+    Quotas,
+};
+use crate::logging::LogFormat;
+use crate::proto::ProtocolType;
 use crate::state::State;
 use tokio::{sync::Mutex, try_join};
 use tonic::codegen::Arc;
 
+mod alerts;
+mod audit;
+mod breaker;
+mod clock;
 mod communicator;
+mod config;
 mod device;
+mod error;
 mod group;
 mod interfaces;
+mod logging;
 mod protocols;
+mod snapshot;
 mod state;
 mod tasks;
 mod utils;
@@ -57,6 +71,7 @@ mod proto {
     }
 }
 
+#[cfg(not(test))]
 lazy_static! {
     static ref CA_CERT: X509 =
         X509::from_pem(&std::fs::read("keys/meesign-ca-cert.pem").unwrap()).unwrap();
@@ -64,6 +79,13 @@ lazy_static! {
         PKey::private_key_from_pem(&std::fs::read("keys/meesign-ca-key.pem").unwrap()).unwrap();
 }
 
+#[cfg(test)]
+lazy_static! {
+    static ref TEST_CA: (X509, PKey<Private>) = interfaces::harness::generate_ca();
+    static ref CA_CERT: X509 = TEST_CA.0.clone();
+    static ref CA_KEY: PKey<Private> = TEST_CA.1.clone();
+}
+
 const VERSION: Option<&str> = option_env!("CARGO_PKG_VERSION");
 
 #[derive(Parser)]
@@ -78,11 +100,273 @@ struct Args {
     #[clap(short, long, default_value_t = String::from("meesign.local"))]
     host: String,
 
+    #[clap(long, value_enum, default_value_t = DisconnectPolicy::Fail)]
+    on_disconnect: DisconnectPolicy,
+
+    #[clap(long, default_value_t = 1024)]
+    max_tasks: usize,
+
+    #[clap(long)]
+    admin_token: Option<String>,
+
+    #[clap(long, value_enum, default_value_t = NamePolicy::Strict)]
+    name_policy: NamePolicy,
+
+    #[clap(
+        long,
+        default_value_t = utils::DEFAULT_MAX_NAME_LENGTH,
+        help = "Longest device name in characters"
+    )]
+    max_device_name_length: usize,
+
+    #[clap(
+        long,
+        default_value_t = utils::DEFAULT_MAX_NAME_LENGTH,
+        help = "Longest group name in characters"
+    )]
+    max_group_name_length: usize,
+
+    #[clap(
+        long,
+        value_delimiter = ',',
+        value_parser = parse_device_id,
+        help = "Comma-separated hex identifiers of the only devices allowed to register"
+    )]
+    device_allowlist: Option<Vec<Vec<u8>>>,
+
+    #[clap(
+        long,
+        default_value_t = 128,
+        help = "Largest number of members of newly created groups"
+    )]
+    max_group_size: usize,
+
+    #[clap(long, help = "Smallest threshold of newly created groups")]
+    min_threshold: Option<u32>,
+
+    #[clap(
+        long,
+        help = "Smallest ratio of threshold to group size of newly created groups"
+    )]
+    min_threshold_ratio: Option<f64>,
+
+    #[clap(
+        long,
+        default_value_t = 4 * 1024 * 1024,
+        help = "Maximum size in bytes of the messages a device submits in one protocol round"
+    )]
+    max_message_size: usize,
+
+    #[clap(
+        long,
+        default_value_t = 256,
+        help = "Maximum number of devices registered by a single batch request"
+    )]
+    max_batch_size: usize,
+
+    #[clap(
+        long,
+        help = "Require accepting signers to commit before a signing protocol starts"
+    )]
+    require_commitments: bool,
+
+    #[clap(
+        long,
+        help = "Ask only the needed signers to approve a signing task and recruit others on a decline"
+    )]
+    recruit_signers: bool,
+
+    #[clap(
+        long,
+        help = "Do not fail Musig2 signing tasks whose participants reuse nonce commitments"
+    )]
+    disable_nonce_guard: bool,
+
+    #[clap(
+        long,
+        help = "Fail tasks which are not decided within this many minutes"
+    )]
+    approval_timeout_minutes: Option<u64>,
+
+    #[clap(
+        long,
+        default_value_t = 30,
+        help = "Restart running tasks which make no progress for this many seconds"
+    )]
+    round_timeout: u64,
+
+    #[clap(
+        long,
+        help = "Fail stalled tasks instead of restarting them once they were restarted this many times"
+    )]
+    max_restarts: Option<u32>,
+
+    #[clap(
+        long,
+        value_parser = parse_protocol_round_timeout,
+        help = "Round timeout of a protocol stage in seconds, e.g. gg18:keygen=120; repeatable"
+    )]
+    protocol_round_timeout: Vec<(ProtocolType, ProtocolStage, u64)>,
+
+    #[clap(
+        long,
+        value_parser = clap::value_parser!(u32).range(1..),
+        help = "Alert when this many tasks of a group fail within the alert window"
+    )]
+    alert_failures: Option<u32>,
+
+    #[clap(
+        long,
+        default_value_t = 10,
+        help = "Length of the alert window in minutes"
+    )]
+    alert_window_minutes: u64,
+
+    #[clap(
+        long,
+        value_parser = clap::value_parser!(u32).range(1..),
+        help = "Leave a device out of signing after this many consecutive protocol failures within the breaker window"
+    )]
+    breaker_failures: Option<u32>,
+
+    #[clap(
+        long,
+        default_value_t = 60,
+        help = "Length of the circuit breaker window in minutes"
+    )]
+    breaker_window_minutes: u64,
+
+    #[clap(
+        long,
+        help = "Unregister devices inactive for this many days unless they belong to a usable group"
+    )]
+    unregister_inactive_days: Option<u64>,
+
+    #[clap(
+        long,
+        help = "PEM file with the EC private key signing the task creation records"
+    )]
+    coordinator_key: Option<String>,
+
+    #[clap(
+        long,
+        help = "JSON file with the device, group and active task quotas of the requesters"
+    )]
+    quota_file: Option<String>,
+
+    #[clap(long, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+
+    #[clap(
+        long,
+        help = "Log only a short prefix of device, group and task identifiers"
+    )]
+    redact_log_ids: bool,
+
+    #[clap(
+        long,
+        default_value_t = 30,
+        help = "Keepalive interval in seconds, 0 to disable"
+    )]
+    keepalive_interval: u64,
+
+    #[clap(long, default_value_t = 10, help = "Keepalive timeout in seconds")]
+    keepalive_timeout: u64,
+
+    #[clap(long, help = "Accept gRPC-Web requests of browser clients")]
+    grpc_web: bool,
+
+    #[clap(
+        long,
+        multiple_occurrences = true,
+        help = "Origin permitted to make credentialed gRPC-Web requests; if none is given, any origin may make requests without credentials"
+    )]
+    grpc_web_origin: Vec<String>,
+
+    #[clap(
+        long,
+        help = "Serve mutating and administrative calls over gRPC-Web, not only the read-only ones"
+    )]
+    grpc_web_full_access: bool,
+
     #[cfg(feature = "cli")]
     #[clap(subcommand)]
     command: Option<cli::Commands>,
 }
 
+impl From<&Args> for Config {
+    fn from(args: &Args) -> Self {
+        let keepalive_interval =
+            (args.keepalive_interval > 0).then(|| Duration::from_secs(args.keepalive_interval));
+        Config {
+            disconnect_policy: args.on_disconnect,
+            max_active_tasks: args.max_tasks,
+            admin_token: args.admin_token.clone(),
+            name_policy: args.name_policy,
+            max_device_name_length: args.max_device_name_length,
+            max_group_name_length: args.max_group_name_length,
+            device_allowlist: args
+                .device_allowlist
+                .as_ref()
+                .map(|device_ids| device_ids.iter().cloned().collect()),
+            max_group_size: args.max_group_size,
+            min_threshold: args.min_threshold,
+            min_threshold_ratio: args.min_threshold_ratio,
+            max_message_size: args.max_message_size,
+            max_batch_size: args.max_batch_size,
+            require_commitments: args.require_commitments,
+            recruit_signers: args.recruit_signers,
+            approval_timeout: args
+                .approval_timeout_minutes
+                .map(|minutes| Duration::from_secs(minutes * 60)),
+            round_timeout: Duration::from_secs(args.round_timeout),
+            max_restarts: args.max_restarts,
+            protocol_round_timeouts: {
+                let mut timeouts = default_protocol_round_timeouts();
+                for (protocol, stage, seconds) in &args.protocol_round_timeout {
+                    timeouts.insert((*protocol, *stage), Duration::from_secs(*seconds));
+                }
+                timeouts
+            },
+            alert_threshold: args.alert_failures,
+            alert_window: Duration::from_secs(args.alert_window_minutes * 60),
+            breaker_threshold: args.breaker_failures,
+            breaker_window: Duration::from_secs(args.breaker_window_minutes * 60),
+            device_inactivity_timeout: args
+                .unregister_inactive_days
+                .map(|days| Duration::from_secs(days * 24 * 60 * 60)),
+            keepalive_interval,
+            keepalive_timeout: keepalive_interval
+                .map(|_| Duration::from_secs(args.keepalive_timeout)),
+            quotas: Quotas::default(),
+            musig2_nonce_guard: !args.disable_nonce_guard,
+            grpc_web: args.grpc_web.then(|| GrpcWeb {
+                origins: args.grpc_web_origin.clone(),
+                full_access: args.grpc_web_full_access,
+            }),
+        }
+    }
+}
+
+fn parse_device_id(device_id: &str) -> Result<Vec<u8>, String> {
+    hex::decode(device_id)
+        .ok()
+        .filter(|device_id| !device_id.is_empty())
+        .ok_or_else(|| format!("Invalid device identifier {}", device_id))
+}
+
+fn parse_protocol_round_timeout(
+    timeout: &str,
+) -> Result<(ProtocolType, ProtocolStage, u64), String> {
+    let invalid = || format!("Invalid protocol round timeout {}", timeout);
+    let (protocol, rest) = timeout.split_once(':').ok_or_else(invalid)?;
+    let (stage, seconds) = rest.split_once('=').ok_or_else(invalid)?;
+    let protocol = ProtocolType::from_str_name(&protocol.to_uppercase()).ok_or_else(invalid)?;
+    let stage = ProtocolStage::from_str(stage, true).map_err(|_| invalid())?;
+    let seconds = seconds.parse().map_err(|_| invalid())?;
+    Ok((protocol, stage, seconds))
+}
+
 pub fn get_timestamp() -> u64 {
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
@@ -92,15 +376,28 @@ pub fn get_timestamp() -> u64 {
 
 #[tokio::main]
 async fn main() -> Result<(), String> {
-    env_logger::init();
     let args = Args::parse();
+    logging::init(args.log_format, args.redact_log_ids);
 
     #[cfg(feature = "cli")]
     if args.command.is_some() {
         return cli::handle_command(args).await;
     }
 
-    let state = Arc::new(Mutex::new(State::new()));
+    let mut config = Config::from(&args);
+    if let Some(path) = &args.quota_file {
+        let json = std::fs::read(path).map_err(|_| "Unable to read quota file".to_string())?;
+        config.quotas = Quotas::from_json(&json)?;
+    }
+    let mut state = State::new(config);
+    if args.alert_failures.is_some() {
+        state.set_alert_sink(Arc::new(alerts::LogSink));
+    }
+    if let Some(path) = &args.coordinator_key {
+        let pem = std::fs::read(path).map_err(|_| "Unable to read coordinator key".to_string())?;
+        state.set_audit_signer(Arc::new(audit::KeyFileSigner::from_pem(&pem)?));
+    }
+    let state = Arc::new(Mutex::new(state));
 
     let grpc = interfaces::grpc::run_grpc(state.clone(), &args.addr, args.port);
     let timer = interfaces::timer::run_timer(state);
@@ -197,7 +494,10 @@ mod cli {
                 }
                 Commands::GetGroups { device_id } => {
                     let device_id = device_id.map(|x| hex::decode(x).unwrap());
-                    let request = tonic::Request::new(crate::proto::GroupsRequest { device_id });
+                    let request = tonic::Request::new(crate::proto::GroupsRequest {
+                        device_id,
+                        tag: None,
+                    });
 
                     let response = client
                         .get_groups(request)
@@ -263,6 +563,12 @@ mod cli {
                             _ => panic!("Incorrect key type"),
                         } as i32,
                         note: None,
+                        deadline_seconds: None,
+                        tags: Vec::new(),
+                        priority: 0,
+                        allowed_tasks: Vec::new(),
+                        auto_approve: false,
+                        require_all_signers: false,
                     });
 
                     let response = client
@@ -296,6 +602,10 @@ mod cli {
                         name,
                         group_id,
                         data,
+                        deadline_seconds: None,
+                        hash_algorithm: crate::proto::HashAlgorithm::Default as i32,
+                        priority: 0,
+                        depends_on: None,
                     });
 
                     let response = client
@@ -330,6 +640,10 @@ mod cli {
                         name,
                         group_id,
                         data,
+                        deadline_seconds: None,
+                        hash_algorithm: crate::proto::HashAlgorithm::Default as i32,
+                        priority: 0,
+                        depends_on: None,
                     });
 
                     let response = client
@@ -357,3 +671,34 @@ mod cli {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keepalive_config() {
+        let config = Config::from(&Args::parse_from(["meesign-server"]));
+        assert_eq!(config.keepalive_interval, Some(Duration::from_secs(30)));
+        assert_eq!(config.keepalive_timeout, Some(Duration::from_secs(10)));
+
+        let args = Args::parse_from([
+            "meesign-server",
+            "--keepalive-interval",
+            "60",
+            "--keepalive-timeout",
+            "5",
+        ]);
+        let config = Config::from(&args);
+        assert_eq!(config.keepalive_interval, Some(Duration::from_secs(60)));
+        assert_eq!(config.keepalive_timeout, Some(Duration::from_secs(5)));
+
+        let config = Config::from(&Args::parse_from([
+            "meesign-server",
+            "--keepalive-interval",
+            "0",
+        ]));
+        assert_eq!(config.keepalive_interval, None);
+        assert_eq!(config.keepalive_timeout, None);
+    }
+}
@@ -1,6 +1,6 @@
 use crate::communicator::Communicator;
 use crate::proto::ProtocolType;
-use crate::protocols::Protocol;
+use crate::protocols::{check_advance, check_finalize, rounds_for, Protocol};
 use meesign_crypto::proto::{Message, ProtocolGroupInit, ProtocolInit};
 
 pub struct FROSTGroup {
@@ -37,17 +37,18 @@ impl Protocol for FROSTGroup {
         self.round = 1;
     }
 
-    fn advance(&mut self, communicator: &mut Communicator) {
-        assert!((0..self.last_round()).contains(&self.round));
+    fn advance(&mut self, communicator: &mut Communicator) -> Result<(), String> {
+        check_advance(self)?;
 
         communicator.relay();
         self.round += 1;
+        Ok(())
     }
 
-    fn finalize(&mut self, communicator: &mut Communicator) -> Option<Vec<u8>> {
-        assert_eq!(self.last_round(), self.round);
+    fn finalize(&mut self, communicator: &mut Communicator) -> Result<Option<Vec<u8>>, String> {
+        check_finalize(self)?;
         self.round += 1;
-        communicator.get_final_message()
+        Ok(communicator.get_final_message())
     }
 
     fn round(&self) -> u16 {
@@ -55,7 +56,7 @@ impl Protocol for FROSTGroup {
     }
 
     fn last_round(&self) -> u16 {
-        3
+        rounds_for(ProtocolType::Frost, true)
     }
 
     fn get_type(&self) -> ProtocolType {
@@ -90,17 +91,18 @@ impl Protocol for FROSTSign {
         self.round = 1;
     }
 
-    fn advance(&mut self, communicator: &mut Communicator) {
-        assert!((0..self.last_round()).contains(&self.round));
+    fn advance(&mut self, communicator: &mut Communicator) -> Result<(), String> {
+        check_advance(self)?;
 
         communicator.relay();
         self.round += 1;
+        Ok(())
     }
 
-    fn finalize(&mut self, communicator: &mut Communicator) -> Option<Vec<u8>> {
-        assert_eq!(self.last_round(), self.round);
+    fn finalize(&mut self, communicator: &mut Communicator) -> Result<Option<Vec<u8>>, String> {
+        check_finalize(self)?;
         self.round += 1;
-        communicator.get_final_message()
+        Ok(communicator.get_final_message())
     }
 
     fn round(&self) -> u16 {
@@ -108,7 +110,7 @@ impl Protocol for FROSTSign {
     }
 
     fn last_round(&self) -> u16 {
-        3
+        rounds_for(ProtocolType::Frost, false)
     }
 
     fn get_type(&self) -> ProtocolType {
@@ -12,18 +12,79 @@ impl ProtocolType {
             ProtocolType::Gg18 | ProtocolType::Elgamal | ProtocolType::Frost => {
                 threshold >= 2 && threshold <= group_size
             }
-            ProtocolType::Musig2 => {
-                threshold >= 2 && threshold == group_size
-            }
+            ProtocolType::Musig2 => threshold >= 2 && threshold == group_size,
         }
     }
 }
 
+/// Number of rounds after which an instance of `protocol` can be finalized
+///
+/// Covers group establishment if `is_keygen` is set, and signing or decryption otherwise.
+pub fn rounds_for(protocol: ProtocolType, is_keygen: bool) -> u16 {
+    match (protocol, is_keygen) {
+        (ProtocolType::Gg18, true) => 6,
+        (ProtocolType::Gg18, false) => 10,
+        (ProtocolType::Elgamal, true) => 4,
+        (ProtocolType::Elgamal, false) => 2,
+        (ProtocolType::Frost, _) => 3,
+        (ProtocolType::Musig2, true) => 2,
+        (ProtocolType::Musig2, false) => 3,
+    }
+}
+
 pub trait Protocol {
     fn initialize(&mut self, communicator: &mut Communicator, data: &[u8]);
-    fn advance(&mut self, communicator: &mut Communicator);
-    fn finalize(&mut self, communicator: &mut Communicator) -> Option<Vec<u8>>;
+    /// Relay messages of the current round; fails if the protocol is not in an intermediate round
+    fn advance(&mut self, communicator: &mut Communicator) -> Result<(), String>;
+    /// Collect the protocol output; fails if the protocol is not in its last round
+    fn finalize(&mut self, communicator: &mut Communicator) -> Result<Option<Vec<u8>>, String>;
     fn round(&self) -> u16;
     fn last_round(&self) -> u16;
     fn get_type(&self) -> ProtocolType;
 }
+
+fn check_advance(protocol: &dyn Protocol) -> Result<(), String> {
+    if !(0..protocol.last_round()).contains(&protocol.round()) {
+        return Err(format!(
+            "cannot advance protocol in round {}",
+            protocol.round()
+        ));
+    }
+    Ok(())
+}
+
+fn check_finalize(protocol: &dyn Protocol) -> Result<(), String> {
+    if protocol.round() != protocol.last_round() {
+        return Err(format!(
+            "cannot finalize protocol in round {} of {}",
+            protocol.round(),
+            protocol.last_round()
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rounds_match_instances() {
+        let instances: Vec<(Box<dyn Protocol>, bool)> = vec![
+            (Box::new(gg18::GG18Group::new(3, 2)), true),
+            (Box::new(gg18::GG18Sign::new()), false),
+            (Box::new(elgamal::ElgamalGroup::new(3, 2)), true),
+            (Box::new(elgamal::ElgamalDecrypt::new()), false),
+            (Box::new(frost::FROSTGroup::new(3, 2)), true),
+            (Box::new(frost::FROSTSign::new()), false),
+            (Box::new(musig2::Musig2Group::new(3, 3)), true),
+            (Box::new(musig2::Musig2Sign::new()), false),
+        ];
+        for (instance, is_keygen) in instances {
+            assert_eq!(
+                instance.last_round(),
+                rounds_for(instance.get_type(), is_keygen)
+            );
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use crate::communicator::Communicator;
 use crate::proto::ProtocolType;
-use crate::protocols::Protocol;
+use crate::protocols::{check_advance, check_finalize, rounds_for, Protocol};
 use meesign_crypto::proto::{Message, ProtocolGroupInit, ProtocolInit};
 
 pub struct Musig2Group {
@@ -37,17 +37,18 @@ impl Protocol for Musig2Group {
         self.round = 1;
     }
 
-    fn advance(&mut self, communicator: &mut Communicator) {
-        assert!((0..self.last_round()).contains(&self.round));
+    fn advance(&mut self, communicator: &mut Communicator) -> Result<(), String> {
+        check_advance(self)?;
 
         communicator.relay();
         self.round += 1;
+        Ok(())
     }
 
-    fn finalize(&mut self, communicator: &mut Communicator) -> Option<Vec<u8>> {
-        assert_eq!(self.last_round(), self.round);
+    fn finalize(&mut self, communicator: &mut Communicator) -> Result<Option<Vec<u8>>, String> {
+        check_finalize(self)?;
         self.round += 1;
-        communicator.get_final_message()
+        Ok(communicator.get_final_message())
     }
 
     fn round(&self) -> u16 {
@@ -55,7 +56,7 @@ impl Protocol for Musig2Group {
     }
 
     fn last_round(&self) -> u16 {
-        2
+        rounds_for(ProtocolType::Musig2, true)
     }
 
     fn get_type(&self) -> ProtocolType {
@@ -90,17 +91,18 @@ impl Protocol for Musig2Sign {
         self.round = 1;
     }
 
-    fn advance(&mut self, communicator: &mut Communicator) {
-        assert!((0..self.last_round()).contains(&self.round));
+    fn advance(&mut self, communicator: &mut Communicator) -> Result<(), String> {
+        check_advance(self)?;
 
         communicator.relay();
         self.round += 1;
+        Ok(())
     }
 
-    fn finalize(&mut self, communicator: &mut Communicator) -> Option<Vec<u8>> {
-        assert_eq!(self.last_round(), self.round);
+    fn finalize(&mut self, communicator: &mut Communicator) -> Result<Option<Vec<u8>>, String> {
+        check_finalize(self)?;
         self.round += 1;
-        communicator.get_final_message()
+        Ok(communicator.get_final_message())
     }
 
     fn round(&self) -> u16 {
@@ -108,10 +110,61 @@ impl Protocol for Musig2Sign {
     }
 
     fn last_round(&self) -> u16 {
-        3
+        rounds_for(ProtocolType::Musig2, false)
     }
 
     fn get_type(&self) -> ProtocolType {
         ProtocolType::Musig2
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use meesign_crypto::proto::ServerMessage;
+
+    #[test]
+    fn group_rounds_on_mock_communicator() {
+        let parties = 3;
+        let mut communicator = Communicator::new_mock(parties, ProtocolType::Musig2);
+        let mut protocol = Musig2Group::new(parties, parties);
+
+        protocol.initialize(&mut communicator, &[]);
+        assert_eq!(protocol.round(), 1);
+        let devices = communicator.get_active_devices().unwrap();
+        for (idx, device_id) in devices.iter().enumerate() {
+            let messages = communicator.get_messages(device_id);
+            let init = ProtocolGroupInit::decode(messages[0].as_slice()).unwrap();
+            assert_eq!(
+                (init.index, init.parties, init.threshold),
+                (idx as u32, 3, 3)
+            );
+        }
+        assert!(protocol.finalize(&mut communicator).is_err());
+
+        communicator.submit_mock_round(|idx| vec![idx as u8]);
+        assert!(communicator.round_received());
+        protocol.advance(&mut communicator).unwrap();
+        assert_eq!(protocol.round(), protocol.last_round());
+        for (idx, device_id) in devices.iter().enumerate() {
+            let messages = communicator.get_messages(device_id);
+            let relayed = ServerMessage::decode(messages[0].as_slice()).unwrap();
+            let mut senders: Vec<_> = relayed.broadcasts.into_iter().collect();
+            senders.sort();
+            let expected: Vec<_> = (0..parties)
+                .filter(|sender| *sender != idx as u32)
+                .map(|sender| (sender, vec![sender as u8]))
+                .collect();
+            assert_eq!(senders, expected);
+        }
+        assert!(protocol.advance(&mut communicator).is_err());
+
+        communicator.submit_mock_round(|_| b"group key".to_vec());
+        assert_eq!(
+            protocol.finalize(&mut communicator),
+            Ok(Some(b"group key".to_vec()))
+        );
+        assert_eq!(protocol.round(), protocol.last_round() + 1);
+        assert_eq!(communicator.stats().rounds.len(), 2);
+    }
+}
@@ -0,0 +1,176 @@
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::audit::{CommitmentViolation, NonceReuse};
+use crate::device::Device;
+use crate::group::Group;
+use crate::tasks::{Task, TaskStatus};
+
+/// Point-in-time view of the server state for backups and debugging
+///
+/// Only public metadata is captured; task inputs, results and protocol messages are omitted.
+#[derive(Debug, Serialize)]
+pub struct StateSnapshot {
+    pub timestamp: u64,
+    pub devices: Vec<DeviceSnapshot>,
+    pub groups: Vec<GroupSnapshot>,
+    pub tasks: Vec<TaskSnapshot>,
+    pub commitment_violations: Vec<ViolationSnapshot>,
+    pub nonce_reuses: Vec<NonceReuseSnapshot>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeviceSnapshot {
+    pub id: String,
+    pub name: String,
+    pub kind: String,
+    pub public_key: Option<String>,
+    pub last_active: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GroupSnapshot {
+    pub id: String,
+    pub name: String,
+    pub threshold: u32,
+    pub protocol: String,
+    pub key_type: String,
+    pub device_ids: Vec<String>,
+    pub note: Option<String>,
+    pub tags: Vec<String>,
+    pub allowed_tasks: Vec<String>,
+    pub auto_approve: bool,
+    pub require_all_signers: bool,
+    pub degraded: bool,
+    pub sign_count: u64,
+    pub last_signed: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TaskSnapshot {
+    pub id: String,
+    pub r#type: String,
+    pub state: String,
+    pub round: Option<u16>,
+    pub failure_reason: Option<String>,
+    pub attempts: u32,
+    pub group_id: Option<String>,
+    pub device_ids: Vec<String>,
+    pub accept: u32,
+    pub reject: u32,
+    pub requester: Option<String>,
+    pub created_at: u64,
+    pub last_update: u64,
+    pub deadline: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ViolationSnapshot {
+    pub task_id: String,
+    pub device_id: String,
+    pub round: u16,
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NonceReuseSnapshot {
+    pub task_id: String,
+    pub group_id: String,
+    pub device_id: String,
+    pub timestamp: u64,
+}
+
+impl From<&Device> for DeviceSnapshot {
+    fn from(device: &Device) -> Self {
+        DeviceSnapshot {
+            id: hex::encode(device.identifier()),
+            name: device.name().to_owned(),
+            kind: device.kind().as_str_name().to_owned(),
+            public_key: device.public_key().map(hex::encode),
+            last_active: device.last_active(),
+        }
+    }
+}
+
+impl From<&Group> for GroupSnapshot {
+    fn from(group: &Group) -> Self {
+        GroupSnapshot {
+            id: hex::encode(group.identifier()),
+            name: group.name().to_owned(),
+            threshold: group.threshold(),
+            protocol: group.protocol().as_str_name().to_owned(),
+            key_type: group.key_type().as_str_name().to_owned(),
+            device_ids: group
+                .devices()
+                .iter()
+                .map(|device| hex::encode(device.identifier()))
+                .collect(),
+            note: group.note().cloned(),
+            tags: group.tags().to_vec(),
+            allowed_tasks: group
+                .allowed_tasks()
+                .iter()
+                .map(|task_type| task_type.as_str_name().to_owned())
+                .collect(),
+            auto_approve: group.auto_approve(),
+            require_all_signers: group.require_all_signers(),
+            degraded: group.is_degraded(),
+            sign_count: group.sign_count(),
+            last_signed: group.last_signed(),
+        }
+    }
+}
+
+impl From<&CommitmentViolation> for ViolationSnapshot {
+    fn from(violation: &CommitmentViolation) -> Self {
+        ViolationSnapshot {
+            task_id: hex::encode(violation.task_id.as_bytes()),
+            device_id: hex::encode(&violation.device_id),
+            round: violation.round,
+            timestamp: violation.timestamp,
+        }
+    }
+}
+
+impl From<&NonceReuse> for NonceReuseSnapshot {
+    fn from(reuse: &NonceReuse) -> Self {
+        NonceReuseSnapshot {
+            task_id: hex::encode(reuse.task_id.as_bytes()),
+            group_id: hex::encode(&reuse.group_id),
+            device_id: hex::encode(&reuse.device_id),
+            timestamp: reuse.timestamp,
+        }
+    }
+}
+
+impl TaskSnapshot {
+    pub fn new(task_id: &Uuid, task: &dyn Task) -> Self {
+        let (state, round, failure_reason) = match task.get_status() {
+            TaskStatus::Created => ("CREATED", None, None),
+            TaskStatus::Running(round) => ("RUNNING", Some(round), None),
+            TaskStatus::Finished => ("FINISHED", None, None),
+            TaskStatus::Failed(reason, _) => ("FAILED", None, Some(format!("{:?}", reason))),
+        };
+        let (accept, reject) = task.get_decisions();
+        TaskSnapshot {
+            id: hex::encode(task_id.as_bytes()),
+            r#type: task.get_type().as_str_name().to_owned(),
+            state: state.to_owned(),
+            round,
+            failure_reason,
+            attempts: task.get_attempts(),
+            group_id: task.get_group_id().map(hex::encode),
+            device_ids: task
+                .get_devices()
+                .iter()
+                .map(|device| hex::encode(device.identifier()))
+                .collect(),
+            accept,
+            reject,
+            requester: task.requester().map(String::from),
+            created_at: task.created_at(),
+            last_update: task.last_update(),
+            deadline: task.deadline(),
+        }
+    }
+}
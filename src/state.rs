@@ -1,36 +1,247 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 
-use log::{debug, warn};
+use tracing::{debug, debug_span, error, info, info_span, warn};
 use uuid::Uuid;
 
+use crate::alerts::{AlertSink, FailureAlert, FailureTracker, NoopSink};
+use crate::audit::{self, AuditRecord, CommitmentViolation, NonceReuse, RecordSigner};
+use crate::breaker::CircuitBreaker;
+use crate::clock::{Clock, SystemClock};
+use crate::communicator::{CommunicatorStats, ParticipantStatus, WORK_VERSION};
+use crate::config::{Config, DisconnectPolicy};
 use crate::device::Device;
+use crate::error::Error;
 use crate::group::Group;
-use crate::interfaces::grpc::format_task;
-use crate::proto::{DeviceKind, KeyType, ProtocolType};
+use crate::interfaces::grpc::{cert_to_id, compress_data, format_task, waiting_devices};
+use crate::logging;
+use crate::proto::force_task_state_request::TargetState;
+use crate::proto::group_leave::Outcome as LeaveOutcome;
+use crate::proto::registration_result::Outcome as RegistrationOutcome;
+use crate::proto::{
+    AuditEvent, Compression, DeliveryMode, DeviceKind, DeviceRegistration, HashAlgorithm,
+    KeyFormat, KeyType, ProtocolType, SignRequest, TaskType,
+};
+use crate::protocols::rounds_for;
+use crate::snapshot::{StateSnapshot, TaskSnapshot};
+use crate::tasks::checked_out::CheckedOutTask;
 use crate::tasks::decrypt::DecryptTask;
+use crate::tasks::dependent::DependentTask;
 use crate::tasks::group::GroupTask;
 use crate::tasks::sign::SignTask;
+use crate::tasks::sign_csr::SignCsrTask;
+use crate::tasks::sign_ethereum::SignEthereumTxTask;
 use crate::tasks::sign_pdf::SignPDFTask;
-use crate::tasks::{Task, TaskResult, TaskStatus};
+use crate::tasks::{FailureReason, Task, TaskFilter, TaskResult, TaskStatus};
 use crate::utils;
+use prost::Message as _;
+use sha2::{Digest, Sha256};
+use tokio::sync::mpsc::error::TrySendError;
 use tokio::sync::mpsc::Sender;
+use tokio::sync::{Mutex, MutexGuard, Notify};
 use tonic::codegen::Arc;
 use tonic::Status;
 
+/// Longest deadline a requester may set on a task
+const MAX_DEADLINE_SECONDS: u64 = 7 * 24 * 60 * 60;
+
+/// Largest number of tags a group may carry
+const MAX_GROUP_TAGS: usize = 16;
+
+/// Longest tag a group may carry
+const MAX_TAG_LENGTH: usize = 32;
+
+/// Number of oversized submissions after which a device fails the task
+const MAX_OVERSIZED_MESSAGES: u32 = 3;
+
+/// Number of updates buffered per device for replay after a reconnect
+const MAX_BUFFERED_UPDATES: usize = 64;
+
+/// Free slots of an update stream reserved for updates which need action of the device
+const RESERVED_UPDATE_SLOTS: usize = 2;
+
+/// Largest number of groups which may sign a single message together
+const MAX_MULTI_GROUP_SIZE: usize = 16;
+
+/// Number of the most recent finished signing tasks of a group which signing estimates are based on
+const ESTIMATE_SAMPLES: usize = 10;
+
+/// Number of the most recent Musig2 nonce commitments of a group checked for reuse
+///
+/// Older commitments are forgotten, so a reuse is detected only within roughly the last
+/// `MAX_NONCE_COMMITMENTS / n` signing sessions of a group of `n` signers.
+const MAX_NONCE_COMMITMENTS: usize = 4096;
+
+/// Outcome of checking out a task for an update
+pub enum UpdateCheckout {
+    Ready(Box<dyn Task + Send + Sync>),
+    /// Another update of the task is being applied; retry once notified
+    Busy(Arc<Notify>),
+}
+
+/// Resources limited by the requester quotas
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QuotaResource {
+    Devices,
+    Groups,
+    ActiveTasks,
+}
+
+/// Stored settings of signing requests which differ only in the signed data
+#[derive(Clone, Debug, PartialEq)]
+pub struct SignTemplate {
+    pub group_id: Vec<u8>,
+    pub hash_algorithm: HashAlgorithm,
+    pub deadline_seconds: Option<u64>,
+    pub priority: u32,
+}
+
+/// Expected cost of a signing task of a group
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SignEstimate {
+    pub rounds: u32,
+    /// Messages submitted by the signers over all rounds
+    pub messages: u32,
+    /// Duration of the protocol extrapolated from the round latencies of earlier signing tasks
+    pub duration: Option<std::time::Duration>,
+    /// Number of earlier signing tasks the duration is based on
+    pub samples: u32,
+}
+
+/// Digests of nonce commitments with the task and attempt which submitted them
+#[derive(Default)]
+struct NonceCommitments {
+    sessions: HashMap<[u8; 32], (Uuid, u32)>,
+    /// Digests in the order they were recorded, the oldest first
+    order: VecDeque<[u8; 32]>,
+}
+
+impl NonceCommitments {
+    /// Get the task and attempt which submitted a commitment with `digest`
+    fn session(&self, digest: &[u8; 32]) -> Option<(Uuid, u32)> {
+        self.sessions.get(digest).copied()
+    }
+
+    /// Record a commitment, forgetting the oldest one once the history is full
+    fn record(&mut self, digest: [u8; 32], session: (Uuid, u32)) {
+        if self.sessions.insert(digest, session).is_some() {
+            return;
+        }
+        self.order.push_back(digest);
+        if self.order.len() > MAX_NONCE_COMMITMENTS {
+            if let Some(oldest) = self.order.pop_front() {
+                self.sessions.remove(&oldest);
+            }
+        }
+    }
+}
+
 pub struct State {
     devices: HashMap<Vec<u8>, Arc<Device>>,
     groups: HashMap<Vec<u8>, Group>,
     tasks: HashMap<Uuid, Box<dyn Task + Send + Sync>>,
     subscribers: HashMap<Vec<u8>, Sender<Result<crate::proto::Task, Status>>>,
+    /// Number of rejected oversized submissions per task and device
+    oversized_messages: HashMap<(Uuid, Vec<u8>), u32>,
+    /// Sequence number of the last accepted update per task and device
+    update_sequences: HashMap<(Uuid, Vec<u8>), u64>,
+    /// Identifiers of the only devices which may register; registration is open when unset
+    allowlist: Option<HashSet<Vec<u8>>>,
+    /// Compression of task data in the updates sent to each subscriber
+    data_compression: HashMap<Vec<u8>, Compression>,
+    /// Signing tasks of each multi-group request, in the order of the requested groups
+    multi_group_tasks: HashMap<Uuid, Vec<Uuid>>,
+    /// Most recent updates pushed to each device, numbered by their update sequence
+    update_log: HashMap<Vec<u8>, VecDeque<crate::proto::Task>>,
+    sign_templates: HashMap<String, SignTemplate>,
+    /// Devices which committed to a signing protocol and then stalled it
+    commitment_violations: Vec<CommitmentViolation>,
+    /// Most recent Musig2 nonce commitments accepted in each group, at most
+    /// [`MAX_NONCE_COMMITMENTS`] per group
+    musig2_commitments: HashMap<Vec<u8>, NonceCommitments>,
+    /// Musig2 nonce commitments of the update being applied to each task, with their group
+    pending_commitments: HashMap<Uuid, (Vec<u8>, Vec<[u8; 32]>)>,
+    /// Devices which repeated a Musig2 nonce commitment
+    nonce_reuses: Vec<NonceReuse>,
+    /// Group identifiers indexed by the other encodings of the group public keys
+    group_keys: HashMap<Vec<u8>, Vec<u8>>,
+    alert_sink: Arc<dyn AlertSink>,
+    /// Failures of tasks per group; alerts are disabled when unset
+    failure_tracker: Option<FailureTracker>,
+    /// Protocol failures of devices; devices are never left out of signing when unset
+    circuit_breaker: Option<CircuitBreaker>,
+    /// Failed tasks which were already counted by the failure tracker
+    counted_failures: HashSet<Uuid>,
+    /// Coordinator key attesting task creation records; records are left unsigned when unset
+    audit_signer: Option<Arc<dyn RecordSigner>>,
+    /// Audit records of task events in the order they were made
+    audit_trail: Vec<AuditRecord>,
+    /// Ended tasks whose outcome was already recorded in the audit trail
+    audited_outcomes: HashSet<Uuid>,
+    /// Tasks checked out for an update, with the requests waiting for them
+    tasks_in_update: HashMap<Uuid, Arc<Notify>>,
+    /// Participants which disconnected from checked out tasks, with the reselection policy
+    deferred_disconnects: HashMap<Uuid, Vec<(Vec<u8>, bool)>>,
+    /// Requesters which registered each device
+    device_registrants: HashMap<Vec<u8>, String>,
+    /// Requesters on whose behalf each group was established
+    group_requesters: HashMap<Vec<u8>, String>,
+    /// Kinds of tasks permitted to the groups being established, by their group task
+    pending_group_usage: HashMap<Uuid, Vec<TaskType>>,
+    /// Group tasks establishing groups whose signing tasks need no approval
+    pending_auto_approve: HashSet<Uuid>,
+    /// Group tasks establishing groups whose every member takes part in each task
+    pending_all_signers: HashSet<Uuid>,
+    /// Tasks waiting for each group task to establish their group
+    task_dependents: HashMap<Uuid, Vec<Uuid>>,
+    /// Devices which poll for their tasks instead of receiving pushed updates
+    pull_devices: HashSet<Vec<u8>>,
+    clock: Arc<dyn Clock>,
+    config: Config,
 }
 
 impl State {
-    pub fn new() -> Self {
+    pub fn new(config: Config) -> Self {
         State {
             devices: HashMap::new(),
             groups: HashMap::new(),
             tasks: HashMap::new(),
             subscribers: HashMap::new(),
+            oversized_messages: HashMap::new(),
+            update_sequences: HashMap::new(),
+            allowlist: config.device_allowlist.clone(),
+            data_compression: HashMap::new(),
+            multi_group_tasks: HashMap::new(),
+            update_log: HashMap::new(),
+            sign_templates: HashMap::new(),
+            commitment_violations: Vec::new(),
+            musig2_commitments: HashMap::new(),
+            pending_commitments: HashMap::new(),
+            nonce_reuses: Vec::new(),
+            group_keys: HashMap::new(),
+            alert_sink: Arc::new(NoopSink),
+            failure_tracker: config
+                .alert_threshold
+                .filter(|threshold| *threshold > 0)
+                .map(|threshold| FailureTracker::new(threshold, config.alert_window)),
+            circuit_breaker: config
+                .breaker_threshold
+                .filter(|threshold| *threshold > 0)
+                .map(|threshold| CircuitBreaker::new(threshold, config.breaker_window)),
+            counted_failures: HashSet::new(),
+            audit_signer: None,
+            audit_trail: Vec::new(),
+            audited_outcomes: HashSet::new(),
+            tasks_in_update: HashMap::new(),
+            deferred_disconnects: HashMap::new(),
+            device_registrants: HashMap::new(),
+            group_requesters: HashMap::new(),
+            pending_group_usage: HashMap::new(),
+            pending_auto_approve: HashSet::new(),
+            pending_all_signers: HashSet::new(),
+            task_dependents: HashMap::new(),
+            pull_devices: HashSet::new(),
+            clock: Arc::new(SystemClock),
+            config,
         }
     }
 
@@ -40,34 +251,119 @@ impl State {
         name: &str,
         kind: DeviceKind,
         certificate: &[u8],
-    ) -> bool {
-        if name.chars().count() > 64
-            || name
-                .chars()
-                .any(|x| x.is_ascii_punctuation() || x.is_control())
-        {
-            warn!("Invalid Device name {}", name);
-            return false;
+        public_key: Option<&[u8]>,
+    ) -> RegistrationOutcome {
+        if let Some(allowlist) = &self.allowlist {
+            if !allowlist.contains(identifier) {
+                warn!(
+                    device_id = %logging::log_id(identifier),
+                    "Device not on the registration allowlist"
+                );
+                return RegistrationOutcome::NotAllowed;
+            }
+        }
+        let name = utils::normalize_name(name);
+        if !utils::validate_name(
+            &name,
+            self.config.name_policy,
+            self.config.max_device_name_length,
+        ) {
+            warn!(name, "Invalid Device name");
+            return RegistrationOutcome::Invalid;
         }
 
-        let device = Device::new(
+        let mut device = Device::new(
             identifier.to_vec(),
-            name.to_owned(),
+            name,
             kind,
             certificate.to_vec(),
+            self.clock.now(),
         );
+        if let Some(public_key) = public_key {
+            if let Err(message) = device.set_public_key(public_key.to_vec()) {
+                warn!(
+                    device_id = %logging::log_id(identifier),
+                    message,
+                    "Invalid device public key"
+                );
+                return RegistrationOutcome::Invalid;
+            }
+        }
         // TODO improve when feature map_try_insert gets stabilized
         if self.devices.contains_key(identifier) {
             warn!(
-                "Device identifier already registered {}",
-                utils::hextrunc(identifier)
+                device_id = %logging::log_id(identifier),
+                "Device identifier already registered"
             );
-            return false;
+            return RegistrationOutcome::AlreadyExists;
         }
         self.devices.insert(identifier.to_vec(), Arc::new(device));
-        true
+        RegistrationOutcome::Registered
+    }
+
+    /// Modify the set of devices allowed to register
+    ///
+    /// Adding devices enables a disabled allowlist, while `disable` opens registration to any device.
+    ///
+    /// # Returns
+    /// The resulting allowlist, or `None` if registration is open
+    pub fn update_allowlist(
+        &mut self,
+        add: &[Vec<u8>],
+        remove: &[Vec<u8>],
+        disable: bool,
+    ) -> Result<Option<&HashSet<Vec<u8>>>, Error> {
+        if add.iter().any(Vec::is_empty) {
+            return Err(Error::InvalidArgument("Empty device identifier".into()));
+        }
+        if disable {
+            self.allowlist = None;
+        } else if self.allowlist.is_some() || !add.is_empty() {
+            let allowlist = self.allowlist.get_or_insert_with(HashSet::new);
+            allowlist.extend(add.iter().cloned());
+            for device_id in remove {
+                allowlist.remove(device_id);
+            }
+        }
+        Ok(self.allowlist.as_ref())
+    }
+
+    /// Register devices with certificates issued by the server CA
+    ///
+    /// Each device is registered independently, so the batch may succeed partially.
+    ///
+    /// # Returns
+    /// The identifier and outcome of each device in the order of `devices`
+    pub fn register_devices(
+        &mut self,
+        devices: &[DeviceRegistration],
+    ) -> Result<Vec<(Vec<u8>, RegistrationOutcome)>, Error> {
+        if devices.len() > self.config.max_batch_size {
+            return Err(Error::InvalidArgument(format!(
+                "At most {} devices can be registered at once",
+                self.config.max_batch_size
+            )));
+        }
+        Ok(devices
+            .iter()
+            .map(|device| {
+                let identifier = cert_to_id(&device.certificate);
+                let outcome = match DeviceKind::try_from(device.kind) {
+                    Ok(kind) if !device.certificate.is_empty() => self.add_device(
+                        &identifier,
+                        &device.name,
+                        kind,
+                        &device.certificate,
+                        device.public_key.as_deref(),
+                    ),
+                    _ => RegistrationOutcome::Invalid,
+                };
+                (identifier, outcome)
+            })
+            .collect())
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn add_group_task(
         &mut self,
         name: &str,
@@ -76,71 +372,565 @@ impl State {
         protocol: ProtocolType,
         key_type: KeyType,
         note: &Option<String>,
-    ) -> Option<Uuid> {
-        if name.chars().count() > 64
-            || name
-                .chars()
-                .any(|x| x.is_ascii_punctuation() || x.is_control())
-        {
-            warn!("Invalid Group name {}", name);
-            return None;
+        tags: &[String],
+        deadline_seconds: Option<u64>,
+    ) -> Result<Uuid, Error> {
+        let name = &utils::normalize_name(name);
+        if !utils::validate_name(
+            name,
+            self.config.name_policy,
+            self.config.max_group_name_length,
+        ) {
+            warn!(name, "Invalid Group name");
+            return Err(Error::InvalidArgument("Invalid group name".into()));
         }
 
-        let mut device_list = Vec::new();
+        let mut device_list: Vec<Arc<Device>> = Vec::new();
         for device in devices {
-            if !self.devices.contains_key(device.as_slice()) {
-                warn!("Unknown Device ID {}", utils::hextrunc(device));
-                return None;
+            if device_list
+                .iter()
+                .any(|listed| listed.identifier() == device.as_slice())
+            {
+                warn!(device_id = %logging::log_id(device), "Duplicate Device ID");
+                return Err(Error::InvalidArgument(format!(
+                    "Duplicate device {} in group request",
+                    hex::encode(device)
+                )));
+            }
+            if let Some(device) = self.devices.get(device.as_slice()) {
+                device_list.push(device.clone());
+            } else {
+                warn!(device_id = %logging::log_id(device), "Unknown Device ID");
+                return Err(Error::InvalidArgument(format!(
+                    "Unknown device {}",
+                    hex::encode(device)
+                )));
             }
-            device_list.push(self.devices.get(device.as_slice()).unwrap().clone());
         }
 
-        let task = GroupTask::try_new(name, &device_list, threshold, protocol, key_type, note)
-            .ok()
-            .map(|task| Box::new(task) as Box<dyn Task + Send + Sync>);
+        self.check_group_policy(threshold, device_list.len())?;
+        let tags = self.check_tags(tags)?;
+        check_deadline(deadline_seconds)?;
+        self.check_task_limit()?;
+        let task = GroupTask::try_new(
+            name,
+            &device_list,
+            threshold,
+            protocol,
+            key_type,
+            note,
+            &tags,
+            deadline_seconds,
+            self.clock.clone(),
+        )
+        .map_err(Error::InvalidArgument)?;
 
-        let task_id = task.map(|task| self.add_task(task));
-        if let Some(task_id) = &task_id {
-            self.send_updates(task_id);
+        let task_id = self.add_task(Box::new(task));
+        self.send_updates(&task_id);
+        Ok(task_id)
+    }
+
+    /// Check that a group of `members` devices with `threshold` satisfies the server policy
+    fn check_group_policy(&self, threshold: u32, members: usize) -> Result<(), Error> {
+        if members > self.config.max_group_size {
+            warn!(
+                members,
+                max_group_size = self.config.max_group_size,
+                "Group size above policy"
+            );
+            return Err(Error::InvalidArgument(format!(
+                "Group of {} members exceeds the maximum of {} allowed by server policy",
+                members, self.config.max_group_size
+            )));
+        }
+        if let Some(min_threshold) = self.config.min_threshold {
+            if threshold < min_threshold {
+                warn!(threshold, min_threshold, "Group threshold below policy");
+                return Err(Error::InvalidArgument(format!(
+                    "Threshold {} is below the minimum of {} required by server policy",
+                    threshold, min_threshold
+                )));
+            }
+        }
+        if let Some(min_ratio) = self.config.min_threshold_ratio {
+            if (threshold as f64) < min_ratio * members as f64 {
+                warn!(
+                    threshold,
+                    members, min_ratio, "Group threshold ratio below policy"
+                );
+                return Err(Error::InvalidArgument(format!(
+                    "Threshold {} of {} members is below the minimum ratio of {} required by server policy",
+                    threshold, members, min_ratio
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Validate group `tags`
+    ///
+    /// # Returns
+    /// The tags sorted and without duplicates
+    fn check_tags(&self, tags: &[String]) -> Result<Vec<String>, Error> {
+        let mut tags: Vec<String> = tags.iter().map(|tag| utils::normalize_name(tag)).collect();
+        tags.sort();
+        tags.dedup();
+        if tags.len() > MAX_GROUP_TAGS {
+            return Err(Error::InvalidArgument(format!(
+                "A group may carry at most {} tags",
+                MAX_GROUP_TAGS
+            )));
+        }
+        if let Some(tag) = tags.iter().find(|tag| {
+            tag.is_empty() || !utils::validate_name(tag, self.config.name_policy, MAX_TAG_LENGTH)
+        }) {
+            warn!(tag, "Invalid group tag");
+            return Err(Error::InvalidArgument(format!(
+                "Invalid group tag {:?}",
+                tag
+            )));
+        }
+        Ok(tags)
+    }
+
+    pub fn set_group_tags(&mut self, group_id: &[u8], tags: &[String]) -> Result<&Group, Error> {
+        let tags = self.check_tags(tags)?;
+        let group = self
+            .groups
+            .get_mut(group_id)
+            .ok_or_else(|| Error::NotFound(format!("Unknown group {}", hex::encode(group_id))))?;
+        info!(
+            group_id = %logging::log_id(group_id),
+            ?tags,
+            "Group tags set"
+        );
+        group.set_tags(tags);
+        Ok(group)
+    }
+
+    /// Restrict the kinds of tasks the group established by `task_id` may perform
+    pub fn restrict_group_task(&mut self, task_id: &Uuid, allowed_tasks: Vec<TaskType>) {
+        if !allowed_tasks.is_empty() {
+            self.pending_group_usage.insert(*task_id, allowed_tasks);
+        }
+    }
+
+    /// Let the group established by `task_id` sign without approval of its members
+    pub fn auto_approve_group_task(&mut self, task_id: &Uuid) {
+        self.pending_auto_approve.insert(*task_id);
+    }
+
+    /// Require every member of the group established by `task_id` to take part in its tasks
+    pub fn require_all_signers_group_task(&mut self, task_id: &Uuid) {
+        self.pending_all_signers.insert(*task_id);
+    }
+
+    /// Replace the kinds of tasks `group_id` may perform; any task is allowed when empty
+    pub fn set_group_usage(
+        &mut self,
+        group_id: &[u8],
+        allowed_tasks: &[TaskType],
+    ) -> Result<&Group, Error> {
+        let allowed_tasks = check_allowed_tasks(allowed_tasks)?;
+        let group = self
+            .groups
+            .get_mut(group_id)
+            .ok_or_else(|| Error::NotFound(format!("Unknown group {}", hex::encode(group_id))))?;
+        info!(
+            group_id = %logging::log_id(group_id),
+            ?allowed_tasks,
+            "Group usage restricted"
+        );
+        group.set_allowed_tasks(allowed_tasks);
+        Ok(group)
+    }
+
+    /// Check that `group_id` can be reshared among `devices` with the given `threshold`
+    pub fn check_reshare(
+        &self,
+        group_id: &[u8],
+        devices: &[Vec<u8>],
+        threshold: u32,
+    ) -> Result<(), Error> {
+        let group = self
+            .groups
+            .get(group_id)
+            .ok_or_else(|| Error::NotFound(format!("Unknown group {}", hex::encode(group_id))))?;
+        for (idx, device) in devices.iter().enumerate() {
+            if devices[..idx].contains(device) {
+                return Err(Error::InvalidArgument(format!(
+                    "Duplicate device {} in reshare request",
+                    hex::encode(device)
+                )));
+            }
+            if !self.devices.contains_key(device) {
+                return Err(Error::InvalidArgument(format!(
+                    "Unknown device {}",
+                    hex::encode(device)
+                )));
+            }
+        }
+        if !group
+            .protocol()
+            .check_threshold(threshold, devices.len() as u32)
+        {
+            return Err(Error::InvalidArgument("Invalid group threshold".into()));
+        }
+        Ok(())
+    }
+
+    pub fn add_sign_task(
+        &mut self,
+        group_id: &[u8],
+        name: &str,
+        data: &[u8],
+        deadline_seconds: Option<u64>,
+        hash_algorithm: HashAlgorithm,
+    ) -> Result<Uuid, Error> {
+        check_deadline(deadline_seconds)?;
+        self.check_task_limit()?;
+        let task = self.create_sign_task(group_id, name, data, deadline_seconds, hash_algorithm)?;
+        let task_id = self.add_task(task);
+        self.send_updates(&task_id);
+        Ok(task_id)
+    }
+
+    /// Create a signing task which starts once the group task `dependency` establishes its group
+    ///
+    /// The signing task is created right away if the group is already established.
+    pub fn add_dependent_sign_task(
+        &mut self,
+        dependency: &Uuid,
+        name: &str,
+        data: &[u8],
+        deadline_seconds: Option<u64>,
+        hash_algorithm: HashAlgorithm,
+    ) -> Result<Uuid, Error> {
+        check_deadline(deadline_seconds)?;
+        let group_task = self
+            .tasks
+            .get(dependency)
+            .filter(|task| task.get_type() == TaskType::Group)
+            .ok_or_else(|| Error::InvalidArgument("Dependency is not a group task".into()))?;
+        match (group_task.get_status(), group_task.get_result()) {
+            (TaskStatus::Finished, Some(TaskResult::GroupEstablished(group))) => {
+                return self.add_sign_task(
+                    group.identifier(),
+                    name,
+                    data,
+                    deadline_seconds,
+                    hash_algorithm,
+                );
+            }
+            (TaskStatus::Created | TaskStatus::Running(_), _) => {}
+            _ => return Err(Error::FailedPrecondition("Dependency failed".into())),
+        }
+        let group_request = crate::proto::GroupRequest::decode(group_task.get_request())
+            .map_err(|_| Error::FailedPrecondition("Request failed".into()))?;
+        let task_type = match group_request.key_type() {
+            KeyType::SignPdf => TaskType::SignPdf,
+            KeyType::SignChallenge => TaskType::SignChallenge,
+            KeyType::Decrypt => {
+                return Err(Error::FailedPrecondition(
+                    "Dependency establishes a decryption group".into(),
+                ))
+            }
+        };
+        self.check_task_limit()?;
+
+        let request = (SignRequest {
+            name: name.to_string(),
+            group_id: Vec::new(),
+            data: data.to_vec(),
+            deadline_seconds,
+            hash_algorithm: hash_algorithm.into(),
+            priority: 0,
+            depends_on: Some(dependency.as_bytes().to_vec()),
+        })
+        .encode_to_vec();
+        let task = DependentTask::new(
+            task_type,
+            group_request.protocol(),
+            request,
+            self.clock.clone(),
+        );
+        let task_id = self.add_task(Box::new(task));
+        self.task_dependents
+            .entry(*dependency)
+            .or_default()
+            .push(task_id);
+        info!(
+            task_id = %logging::log_id(task_id.as_bytes()),
+            dependency = %logging::log_id(dependency.as_bytes()),
+            "Task waits for its dependency"
+        );
+        Ok(task_id)
+    }
+
+    /// Start the tasks waiting for the ended task `task_id`, or fail them if it did not finish
+    fn resolve_dependents(&mut self, task_id: &Uuid) {
+        let task = &self.tasks[task_id];
+        if matches!(
+            task.get_status(),
+            TaskStatus::Created | TaskStatus::Running(_)
+        ) {
+            return;
+        }
+        let Some(dependents) = self.task_dependents.remove(task_id) else {
+            return;
+        };
+        let group_id = match task.get_result() {
+            Some(TaskResult::GroupEstablished(group)) => Some(group.identifier().to_vec()),
+            _ => None,
+        };
+        for dependent_id in dependents {
+            let dependent = &self.tasks[&dependent_id];
+            if dependent.get_status() != TaskStatus::Created {
+                continue;
+            }
+            let requester = dependent.requester().map(str::to_owned);
+            let priority = dependent.priority();
+            let created = match &group_id {
+                Some(group_id) => SignRequest::decode(dependent.get_request())
+                    .map_err(|_| Error::FailedPrecondition("Request failed".into()))
+                    .and_then(|request| {
+                        self.create_sign_task(
+                            group_id,
+                            &request.name,
+                            &request.data,
+                            request.deadline_seconds,
+                            request.hash_algorithm(),
+                        )
+                    }),
+                None => Err(Error::FailedPrecondition("Dependency failed".into())),
+            };
+            match created {
+                Ok(mut task) => {
+                    if let Some(requester) = requester {
+                        task.set_requester(requester);
+                    }
+                    task.set_priority(priority);
+                    self.tasks.insert(dependent_id, task);
+                    self.approve_automatically(&dependent_id);
+                    info!(
+                        task_id = %logging::log_id(dependent_id.as_bytes()),
+                        "Dependent task started"
+                    );
+                }
+                Err(err) => {
+                    warn!(
+                        task_id = %logging::log_id(dependent_id.as_bytes()),
+                        ?err,
+                        "Dependent task failed"
+                    );
+                    self.tasks.get_mut(&dependent_id).unwrap().fail(
+                        FailureReason::DependencyFailed,
+                        "Task failed (dependency did not finish)",
+                    );
+                }
+            }
+            self.send_updates(&dependent_id);
         }
-        task_id
     }
 
-    pub fn add_sign_task(&mut self, group_id: &[u8], name: &str, data: &[u8]) -> Option<Uuid> {
-        let group = self.groups.get(group_id);
+    /// Create a signing task of the established group `group_id`
+    fn create_sign_task(
+        &self,
+        group_id: &[u8],
+        name: &str,
+        data: &[u8],
+        deadline_seconds: Option<u64>,
+        hash_algorithm: HashAlgorithm,
+    ) -> Result<Box<dyn Task + Sync + Send>, Error> {
+        let group = self.find_group(group_id);
         if group.is_none() {
             warn!(
-                "Signing requested from an unknown group group_id={}",
-                utils::hextrunc(group_id)
+                group_id = %logging::log_id(group_id),
+                "Signing requested from an unknown group"
             );
-            return None;
+            return Err(Error::FailedPrecondition("Request failed".into()));
         }
         let group = group.unwrap();
-        let task = match group.key_type() {
-            KeyType::SignPdf => {
-                SignPDFTask::try_new(group.clone(), name.to_string(), data.to_vec())
-                    .ok()
-                    .map(|task| Box::new(task) as Box<dyn Task + Sync + Send>)
-            }
-            KeyType::SignChallenge => {
-                SignTask::try_new(group.clone(), name.to_string(), data.to_vec())
-                    .ok()
-                    .map(|task| Box::new(task) as Box<dyn Task + Sync + Send>)
-            }
+        check_signable(group)?;
+        check_hash_algorithm(group, hash_algorithm)?;
+        check_group_usage(
+            group,
+            match group.key_type() {
+                KeyType::SignPdf => TaskType::SignPdf,
+                _ => TaskType::SignChallenge,
+            },
+        )?;
+        match group.key_type() {
+            KeyType::SignPdf => SignPDFTask::try_new(
+                group.clone(),
+                name.to_string(),
+                data.to_vec(),
+                deadline_seconds,
+                self.clock.clone(),
+            )
+            .map(|mut task| {
+                if self.config.recruit_signers {
+                    task.recruit_signers(&|device_id| self.is_subscribed(device_id));
+                }
+                Box::new(task) as Box<dyn Task + Sync + Send>
+            }),
+            KeyType::SignChallenge => SignTask::try_new(
+                group.clone(),
+                name.to_string(),
+                data.to_vec(),
+                deadline_seconds,
+                hash_algorithm,
+                self.clock.clone(),
+            )
+            .map(|mut task| {
+                if self.config.require_commitments {
+                    task.require_commitments();
+                }
+                if self.config.recruit_signers {
+                    task.recruit_signers(&|device_id| self.is_subscribed(device_id));
+                }
+                Box::new(task) as Box<dyn Task + Sync + Send>
+            }),
             KeyType::Decrypt => {
                 warn!(
-                    "Signing request made for decryption group group_id={}",
-                    utils::hextrunc(group_id)
+                    group_id = %logging::log_id(group_id),
+                    "Signing request made for decryption group"
                 );
-                return None;
+                return Err(Error::FailedPrecondition("Request failed".into()));
             }
+        }
+        .map_err(|_| Error::FailedPrecondition("Request failed".into()))
+    }
+
+    /// Store the signing template `name`, replacing any template of the same name
+    pub fn add_sign_template(&mut self, name: &str, template: SignTemplate) -> Result<(), Error> {
+        let name = utils::normalize_name(name);
+        if name.is_empty()
+            || !utils::validate_name(
+                &name,
+                self.config.name_policy,
+                utils::DEFAULT_MAX_NAME_LENGTH,
+            )
+        {
+            warn!(name, "Invalid template name");
+            return Err(Error::InvalidArgument("Invalid template name".into()));
+        }
+        check_deadline(template.deadline_seconds)?;
+        let group = self.find_group(&template.group_id).ok_or_else(|| {
+            Error::NotFound(format!("Unknown group {}", hex::encode(&template.group_id)))
+        })?;
+        if group.key_type() == KeyType::Decrypt {
+            return Err(Error::InvalidArgument(
+                "Templates can only be created for signing groups".into(),
+            ));
+        }
+        check_hash_algorithm(group, template.hash_algorithm)?;
+        let template = SignTemplate {
+            group_id: group.identifier().to_vec(),
+            ..template
         };
+        info!(
+            name,
+            group_id = %logging::log_id(&template.group_id),
+            "Signing template stored"
+        );
+        self.sign_templates.insert(name, template);
+        Ok(())
+    }
 
-        let task_id = task.map(|task| self.add_task(task));
-        if let Some(task_id) = &task_id {
-            self.send_updates(task_id);
+    /// Create a signing task of `data` with the settings of the template `template`
+    pub fn add_sign_task_from_template(
+        &mut self,
+        template: &str,
+        name: &str,
+        data: &[u8],
+    ) -> Result<Uuid, Error> {
+        let template = self
+            .sign_templates
+            .get(&utils::normalize_name(template))
+            .cloned()
+            .ok_or_else(|| Error::NotFound(format!("Unknown template {:?}", template)))?;
+        let task_id = self.add_sign_task(
+            &template.group_id,
+            name,
+            data,
+            template.deadline_seconds,
+            template.hash_algorithm,
+        )?;
+        self.set_task_priority(&task_id, template.priority);
+        Ok(task_id)
+    }
+
+    pub fn add_sign_csr_task(
+        &mut self,
+        group_id: &[u8],
+        name: &str,
+        csr: &[u8],
+        deadline_seconds: Option<u64>,
+    ) -> Result<Uuid, Error> {
+        check_deadline(deadline_seconds)?;
+        self.check_task_limit()?;
+        let group = self.find_group(group_id).ok_or_else(|| {
+            warn!(
+                group_id = %logging::log_id(group_id),
+                "Certificate requested from an unknown group"
+            );
+            Error::FailedPrecondition("Request failed".into())
+        })?;
+        check_signable(group)?;
+        check_group_usage(group, TaskType::SignCsr)?;
+        let mut task = SignCsrTask::try_new(
+            group.clone(),
+            name.to_string(),
+            csr.to_vec(),
+            deadline_seconds,
+            self.clock.clone(),
+        )
+        .map_err(Error::InvalidArgument)?;
+        if self.config.recruit_signers {
+            task.recruit_signers(&|device_id| self.is_subscribed(device_id));
         }
-        task_id
+
+        let task_id = self.add_task(Box::new(task));
+        self.send_updates(&task_id);
+        Ok(task_id)
+    }
+
+    pub fn add_sign_ethereum_tx_task(
+        &mut self,
+        group_id: &[u8],
+        name: &str,
+        transaction: &[u8],
+        chain_id: u64,
+        deadline_seconds: Option<u64>,
+    ) -> Result<Uuid, Error> {
+        check_deadline(deadline_seconds)?;
+        self.check_task_limit()?;
+        let group = self.find_group(group_id).ok_or_else(|| {
+            warn!(
+                group_id = %logging::log_id(group_id),
+                "Transaction signature requested from an unknown group"
+            );
+            Error::FailedPrecondition("Request failed".into())
+        })?;
+        check_signable(group)?;
+        check_group_usage(group, TaskType::SignEthereumTx)?;
+        let mut task = SignEthereumTxTask::try_new(
+            group.clone(),
+            name.to_string(),
+            transaction.to_vec(),
+            chain_id,
+            deadline_seconds,
+            self.clock.clone(),
+        )
+        .map_err(Error::InvalidArgument)?;
+        if self.config.recruit_signers {
+            task.recruit_signers(&|device_id| self.is_subscribed(device_id));
+        }
+
+        let task_id = self.add_task(Box::new(task));
+        self.send_updates(&task_id);
+        Ok(task_id)
     }
 
     pub fn add_decrypt_task(
@@ -149,46 +939,197 @@ impl State {
         name: &str,
         data: &[u8],
         data_type: &str,
-    ) -> Option<Uuid> {
-        let group = self.groups.get(group_id);
+    ) -> Result<Uuid, Error> {
+        self.check_task_limit()?;
+        let group = self.find_group(group_id);
         if group.is_none() {
             warn!(
-                "Decryption requested from an unknown group group_id={}",
-                utils::hextrunc(group_id)
+                group_id = %logging::log_id(group_id),
+                "Decryption requested from an unknown group"
             );
-            return None;
+            return Err(Error::FailedPrecondition("Request failed".into()));
         }
         let group = group.unwrap();
+        if group.is_degraded() {
+            return Err(Error::FailedPrecondition(
+                "Group is degraded and cannot decrypt".into(),
+            ));
+        }
+        check_group_usage(group, TaskType::Decrypt)?;
         let task = match group.key_type() {
-            KeyType::Decrypt => Some(DecryptTask::new(
+            KeyType::Decrypt => Box::new(DecryptTask::new(
                 group.clone(),
                 name.to_string(),
                 data.to_vec(),
                 data_type.to_string(),
-            ))
-            .map(|task| Box::new(task) as Box<dyn Task + Sync + Send>),
+                self.clock.clone(),
+            )) as Box<dyn Task + Sync + Send>,
             KeyType::SignPdf | KeyType::SignChallenge => {
                 warn!(
-                    "Decryption request made for a signing group group_id={}",
-                    utils::hextrunc(group_id)
+                    group_id = %logging::log_id(group_id),
+                    "Decryption request made for a signing group"
                 );
-                return None;
+                return Err(Error::FailedPrecondition("Request failed".into()));
             }
         };
 
-        let task_id = task.map(|task| self.add_task(task));
-        if let Some(task_id) = &task_id {
-            self.send_updates(task_id);
+        let task_id = self.add_task(task);
+        self.send_updates(&task_id);
+        Ok(task_id)
+    }
+
+    /// Sign the same challenge by each of `group_ids`
+    ///
+    /// Either a signing task is created for every group, or none is.
+    ///
+    /// # Returns
+    /// Identifier of the multi-group request tracking the individual signing tasks
+    pub fn add_multi_group_sign_task(
+        &mut self,
+        group_ids: &[Vec<u8>],
+        name: &str,
+        data: &[u8],
+        deadline_seconds: Option<u64>,
+    ) -> Result<Uuid, Error> {
+        if group_ids.is_empty() || group_ids.len() > MAX_MULTI_GROUP_SIZE {
+            return Err(Error::InvalidArgument(format!(
+                "Between 1 and {} groups may sign together",
+                MAX_MULTI_GROUP_SIZE
+            )));
         }
-        task_id
+        for (idx, group_id) in group_ids.iter().enumerate() {
+            if group_ids[..idx].contains(group_id) {
+                return Err(Error::InvalidArgument(format!(
+                    "Duplicate group {} in multi-group request",
+                    hex::encode(group_id)
+                )));
+            }
+            let group = self.groups.get(group_id).ok_or_else(|| {
+                Error::NotFound(format!("Unknown group {}", hex::encode(group_id)))
+            })?;
+            check_signable(group)?;
+            if group.key_type() != KeyType::SignChallenge {
+                return Err(Error::InvalidArgument(format!(
+                    "Group {} does not sign challenges",
+                    hex::encode(group_id)
+                )));
+            }
+        }
+        check_deadline(deadline_seconds)?;
+        self.check_task_capacity(group_ids.len())?;
+
+        let mut task_ids = Vec::new();
+        for group_id in group_ids {
+            match self.add_sign_task(
+                group_id,
+                name,
+                data,
+                deadline_seconds,
+                HashAlgorithm::Default,
+            ) {
+                Ok(task_id) => task_ids.push(task_id),
+                Err(error) => {
+                    for task_id in &task_ids {
+                        self.tasks.get_mut(task_id).unwrap().fail(
+                            FailureReason::Cancelled,
+                            "Task cancelled (a task of another group could not be created)",
+                        );
+                        self.send_updates(task_id);
+                    }
+                    return Err(error);
+                }
+            }
+        }
+        let multi_task_id = Uuid::new_v4();
+        info!(
+            multi_task_id = %logging::log_id(multi_task_id.as_bytes()),
+            groups = group_ids.len(),
+            "Multi-group signing requested"
+        );
+        self.multi_group_tasks.insert(multi_task_id, task_ids);
+        Ok(multi_task_id)
+    }
+
+    /// Get the signing tasks of a multi-group request, in the order of the requested groups
+    pub fn get_multi_group_task(
+        &self,
+        multi_task_id: &Uuid,
+    ) -> Result<Vec<(Uuid, &dyn Task)>, Error> {
+        let task_ids = self.multi_group_tasks.get(multi_task_id).ok_or_else(|| {
+            Error::NotFound(format!(
+                "Unknown multi-group task {}",
+                hex::encode(multi_task_id.as_bytes())
+            ))
+        })?;
+        Ok(task_ids
+            .iter()
+            .filter_map(|task_id| Some((*task_id, self.get_task(task_id)?)))
+            .collect())
+    }
+
+    /// Reject new tasks once the number of active tasks reaches the configured limit
+    fn check_task_limit(&self) -> Result<(), Error> {
+        self.check_task_capacity(1)
+    }
+
+    /// Reject `count` new tasks unless they fit within the configured limit of active tasks
+    fn check_task_capacity(&self, count: usize) -> Result<(), Error> {
+        let active = self
+            .tasks
+            .values()
+            .filter(|task| {
+                !matches!(
+                    task.get_status(),
+                    TaskStatus::Finished | TaskStatus::Failed(..)
+                )
+            })
+            .count();
+        if active + count > self.config.max_active_tasks {
+            warn!(active, "Active task limit reached");
+            return Err(Error::ResourceExhausted(
+                "Too many active tasks, try again later".into(),
+            ));
+        }
+        Ok(())
     }
 
     fn add_task(&mut self, task: Box<dyn Task + Sync + Send>) -> Uuid {
         let uuid = Uuid::new_v4();
+        let _span = info_span!("add_task", task_id = %uuid).entered();
+        debug!(task_type = ?task.get_type(), "Task created");
         self.tasks.insert(uuid, task);
+        self.approve_automatically(&uuid);
         uuid
     }
 
+    /// Accept a signing task on behalf of all members of a group which signs without approval
+    fn approve_automatically(&mut self, task_id: &Uuid) {
+        let task = self.tasks.get_mut(task_id).unwrap();
+        if matches!(task.get_type(), TaskType::Group | TaskType::Decrypt)
+            || !task
+                .get_group_id()
+                .and_then(|group_id| self.groups.get(group_id))
+                .is_some_and(Group::auto_approve)
+        {
+            return;
+        }
+        let mut devices: Vec<_> = task
+            .get_devices()
+            .iter()
+            .map(|device| device.identifier().to_vec())
+            .collect();
+        devices.sort();
+        devices.dedup();
+        for device in devices {
+            task.decide(&device, true);
+        }
+        info!(
+            task_id = %logging::log_id(task_id.as_bytes()),
+            "Task approved automatically"
+        );
+    }
+
+    /// Get the tasks of `device` ordered by descending priority, then by creation time
     pub fn get_device_tasks(&self, device: &[u8]) -> Vec<(Uuid, &dyn Task)> {
         let mut tasks = Vec::new();
         for (uuid, task) in self.tasks.iter() {
@@ -201,6 +1142,7 @@ impl State {
                 tasks.push((*uuid, task.as_ref() as &dyn Task));
             }
         }
+        tasks.sort_by_key(|(task_id, task)| scheduling_key(task_id, *task));
         tasks
     }
 
@@ -211,147 +1153,5052 @@ impl State {
                 groups.push(group.clone());
             }
         }
+        groups.sort_by(|a, b| a.listing_key().cmp(&b.listing_key()));
         groups
     }
 
-    pub fn get_groups(&self) -> &HashMap<Vec<u8>, Group> {
-        &self.groups
-    }
-
-    pub fn get_tasks(&self) -> &HashMap<Uuid, Box<dyn Task + Send + Sync>> {
-        &self.tasks
-    }
-
-    pub fn get_task(&self, task: &Uuid) -> Option<&dyn Task> {
-        self.tasks.get(task).map(|task| task.as_ref() as &dyn Task)
-    }
-
-    pub fn update_task(
+    /// Move the device `device_id` to `new_id`, keeping its group memberships and update stream
+    ///
+    /// Protocol messages are bound to the identity a task was started with, so the device
+    /// must not participate in unfinished tasks. Finished tasks keep the former identity.
+    pub fn rotate_device_identifier(
         &mut self,
-        task_id: &Uuid,
-        device: &[u8],
-        data: &Vec<Vec<u8>>,
-        attempt: u32,
-    ) -> Result<bool, String> {
-        let task = self.tasks.get_mut(task_id).unwrap();
-        if attempt != task.get_attempts() {
+        device_id: &[u8],
+        new_id: &[u8],
+        certificate: &[u8],
+    ) -> Result<(), Error> {
+        let device = self
+            .devices
+            .get(device_id)
+            .ok_or_else(|| Error::NotFound(format!("Unknown device {}", hex::encode(device_id))))?;
+        if self.devices.contains_key(new_id) {
             warn!(
-                "Stale update discarded task_id={} device_id={} attempt={}",
-                utils::hextrunc(task_id.as_bytes()),
-                utils::hextrunc(device),
-                attempt
+                device_id = %logging::log_id(new_id),
+                "Device identifier already registered"
             );
-            return Err("Stale update".to_string());
+            return Err(Error::InvalidArgument(
+                "New device identifier is already registered".into(),
+            ));
+        }
+        if self.tasks.values().any(|task| {
+            task.has_device(device_id)
+                && matches!(
+                    task.get_status(),
+                    TaskStatus::Created | TaskStatus::Running(_)
+                )
+        }) {
+            return Err(Error::FailedPrecondition(
+                "Device participates in unfinished tasks".into(),
+            ));
         }
 
-        let previous_status = task.get_status();
-        let update_result = task.update(device, data);
-        if previous_status != TaskStatus::Finished && task.get_status() == TaskStatus::Finished {
-            // TODO join if statements once #![feature(let_chains)] gets stabilized
-            if let TaskResult::GroupEstablished(group) = task.get_result().unwrap() {
-                self.groups.insert(group.identifier().to_vec(), group);
-            }
+        let rotated = Arc::new(device.with_identifier(new_id.to_vec(), certificate.to_vec()));
+        rotated.activated(self.clock.now());
+        self.devices.remove(device_id);
+        self.devices.insert(new_id.to_vec(), rotated.clone());
+        for group in self.groups.values_mut() {
+            group.replace_device(device_id, rotated.clone());
         }
-        if let Ok(true) = update_result {
-            self.send_updates(task_id);
+        if let Some(allowlist) = &mut self.allowlist {
+            allowlist.insert(new_id.to_vec());
         }
-        update_result
+        if let Some(tx) = self.subscribers.remove(device_id) {
+            self.subscribers.insert(new_id.to_vec(), tx);
+        }
+        if let Some(compression) = self.data_compression.remove(device_id) {
+            self.data_compression.insert(new_id.to_vec(), compression);
+        }
+        if let Some(log) = self.update_log.remove(device_id) {
+            self.update_log.insert(new_id.to_vec(), log);
+        }
+        if let Some(registrant) = self.device_registrants.remove(device_id) {
+            self.device_registrants.insert(new_id.to_vec(), registrant);
+        }
+        if self.pull_devices.remove(device_id) {
+            self.pull_devices.insert(new_id.to_vec());
+        }
+        info!(
+            device_id = %logging::log_id(device_id),
+            new_id = %logging::log_id(new_id),
+            "Device identifier rotated"
+        );
+        Ok(())
     }
 
-    pub fn decide_task(&mut self, task_id: &Uuid, device: &[u8], decision: bool) -> bool {
-        let task = self.tasks.get_mut(task_id).unwrap();
-        let change = task.decide(device, decision);
-        if change.is_some() {
-            self.send_updates(task_id);
-            if change.unwrap() {
-                log::info!(
-                    "Task approved task_id={}",
-                    utils::hextrunc(task_id.as_bytes())
-                );
+    /// Remove `device` from every group it participates in
+    ///
+    /// Groups whose remaining members still meet the threshold keep signing without the device
+    /// and should be reshared to revoke its key share, while the others are marked degraded.
+    /// The device must not participate in unfinished tasks.
+    pub fn leave_all_groups(
+        &mut self,
+        device: &[u8],
+    ) -> Result<Vec<(Vec<u8>, LeaveOutcome)>, Error> {
+        if self.tasks.values().any(|task| {
+            task.has_device(device)
+                && matches!(
+                    task.get_status(),
+                    TaskStatus::Created | TaskStatus::Running(_)
+                )
+        }) {
+            return Err(Error::FailedPrecondition(
+                "Device participates in unfinished tasks".into(),
+            ));
+        }
+        let group_ids: Vec<_> = self
+            .get_device_groups(device)
+            .iter()
+            .map(|group| group.identifier().to_vec())
+            .collect();
+        let mut outcomes = Vec::new();
+        for group_id in group_ids {
+            let group = self.groups.get_mut(&group_id).unwrap();
+            group.remove_device(device);
+            let outcome = if group.is_degraded() {
+                LeaveOutcome::Degraded
             } else {
-                log::info!(
-                    "Task declined task_id={}",
-                    utils::hextrunc(task_id.as_bytes())
-                );
-            }
-            return true;
+                LeaveOutcome::ReshareRequired
+            };
+            info!(
+                group_id = %logging::log_id(&group_id),
+                device_id = %logging::log_id(device),
+                ?outcome,
+                "Device left group"
+            );
+            outcomes.push((group_id, outcome));
         }
-        false
+        Ok(outcomes)
     }
 
-    pub fn acknowledge_task(&mut self, task: &Uuid, device: &[u8]) {
-        let task = self.tasks.get_mut(task).unwrap();
-        task.acknowledge(device);
+    /// Look up a group by its identifier or by its public key in any exportable format
+    pub fn find_group(&self, key: &[u8]) -> Option<&Group> {
+        self.groups.get(key).or_else(|| {
+            self.group_keys
+                .get(key)
+                .and_then(|identifier| self.groups.get(identifier))
+        })
     }
 
-    pub fn get_devices(&self) -> &HashMap<Vec<u8>, Arc<Device>> {
-        &self.devices
+    /// Store an established group, indexing the encodings of its public key
+    ///
+    /// A group whose public key is already used by another group is not stored.
+    fn insert_group(&mut self, group: Group) -> Result<(), Error> {
+        let identifier = group.identifier().to_vec();
+        let encodings: Vec<Vec<u8>> = [
+            KeyFormat::Sec1Compressed,
+            KeyFormat::XOnly,
+            KeyFormat::SpkiDer,
+        ]
+        .into_iter()
+        .filter_map(|format| group.export_key(format).ok())
+        .filter(|key| *key != identifier)
+        .collect();
+        if std::iter::once(&identifier)
+            .chain(&encodings)
+            .any(|key| self.find_group(key).is_some())
+        {
+            error!(
+                group_id = %logging::log_id(&identifier),
+                "Public key shared with an existing group"
+            );
+            return Err(Error::FailedPrecondition(
+                "Public key shared with an existing group".into(),
+            ));
+        }
+        for key in encodings {
+            self.group_keys.insert(key, identifier.clone());
+        }
+        self.groups.insert(identifier, group);
+        Ok(())
     }
 
-    pub fn device_activated(&self, device_id: &[u8]) -> bool {
-        if let Some(device) = self.devices.get(device_id) {
-            device.activated();
-            true
-        } else {
-            debug!("Unknown Device ID {}", utils::hextrunc(device_id));
-            false
+    pub fn get_groups(&self) -> &HashMap<Vec<u8>, Group> {
+        &self.groups
+    }
+
+    pub fn export_group_key(&self, identifier: &[u8], format: KeyFormat) -> Result<Vec<u8>, Error> {
+        let group = self
+            .find_group(identifier)
+            .ok_or_else(|| Error::NotFound(format!("Unknown group {}", hex::encode(identifier))))?;
+        group.export_key(format).map_err(|message| {
+            warn!(
+                group_id = %logging::log_id(identifier),
+                message,
+                "Group key export rejected"
+            );
+            Error::InvalidArgument(message)
+        })
+    }
+
+    pub fn get_tasks(&self) -> &HashMap<Uuid, Box<dyn Task + Send + Sync>> {
+        &self.tasks
+    }
+
+    /// Select tasks matching `filter`, ordered by their creation
+    ///
+    /// # Returns
+    /// At most `limit` tasks following the first `offset` matches, and the total number of matches
+    pub fn query_tasks(
+        &self,
+        filter: &TaskFilter,
+        offset: usize,
+        limit: usize,
+    ) -> (Vec<(Uuid, &dyn Task)>, usize) {
+        let mut tasks: Vec<_> = self
+            .tasks
+            .iter()
+            .filter(|(_, task)| filter.matches(task.as_ref()))
+            .map(|(uuid, task)| (*uuid, task.as_ref() as &dyn Task))
+            .collect();
+        tasks.sort_by_key(|(uuid, task)| (task.created_at(), *uuid));
+        let total = tasks.len();
+        let page = tasks.into_iter().skip(offset).take(limit).collect();
+        (page, total)
+    }
+
+    pub fn get_task(&self, task: &Uuid) -> Option<&dyn Task> {
+        self.tasks.get(task).map(|task| task.as_ref() as &dyn Task)
+    }
+
+    /// Capture devices, groups and task summaries without any secret material
+    pub fn snapshot(&self) -> StateSnapshot {
+        StateSnapshot {
+            timestamp: self.clock.now(),
+            devices: self
+                .devices
+                .values()
+                .map(|device| device.as_ref().into())
+                .collect(),
+            groups: self.groups.values().map(Into::into).collect(),
+            tasks: self
+                .tasks
+                .iter()
+                .map(|(task_id, task)| TaskSnapshot::new(task_id, task.as_ref()))
+                .collect(),
+            commitment_violations: self.commitment_violations.iter().map(Into::into).collect(),
+            nonce_reuses: self.nonce_reuses.iter().map(Into::into).collect(),
         }
     }
 
-    pub fn restart_task(&mut self, task_id: &Uuid) -> bool {
-        if self
+    /// Get the traffic relayed between the participants of a task
+    pub fn task_stats(&self, task_id: &Uuid) -> Result<CommunicatorStats, Error> {
+        self.tasks
+            .get(task_id)
+            .map(|task| task.relay_stats())
+            .ok_or_else(|| {
+                Error::NotFound(format!("Unknown task {}", hex::encode(task_id.as_bytes())))
+            })
+    }
+
+    /// Estimate the cost of a signing task of `group_id`
+    ///
+    /// The duration is best-effort: it averages the relayed round latencies of the
+    /// most recent finished signing tasks of the group and is unknown without them.
+    pub fn estimate_sign(&self, group_id: &[u8]) -> Result<SignEstimate, Error> {
+        let group = self
+            .find_group(group_id)
+            .ok_or_else(|| Error::NotFound(format!("Unknown group {}", hex::encode(group_id))))?;
+        let rounds = u32::from(rounds_for(group.protocol(), false));
+
+        let mut history: Vec<&dyn Task> = self
             .tasks
-            .get_mut(task_id)
-            .and_then(|task| task.restart().ok())
-            .unwrap_or(false)
+            .values()
+            .map(|task| task.as_ref() as &dyn Task)
+            .filter(|task| {
+                task.get_group_id() == Some(group.identifier())
+                    && task.get_type() != TaskType::Decrypt
+                    && task.get_status() == TaskStatus::Finished
+            })
+            .collect();
+        history.sort_by_key(|task| std::cmp::Reverse(task.created_at()));
+        history.truncate(ESTIMATE_SAMPLES);
+        let latencies: Vec<_> = history
+            .iter()
+            .flat_map(|task| task.relay_stats().round_durations)
+            .collect();
+        let duration = (!latencies.is_empty()).then(|| {
+            latencies.iter().sum::<std::time::Duration>() / latencies.len() as u32 * rounds
+        });
+
+        Ok(SignEstimate {
+            rounds,
+            messages: rounds * group.signer_count(),
+            duration,
+            samples: history.len() as u32,
+        })
+    }
+
+    /// Set the priority of a task
+    pub fn set_task_priority(&mut self, task_id: &Uuid, priority: u32) {
+        if let Some(task) = self.tasks.get_mut(task_id) {
+            task.set_priority(priority);
+        }
+    }
+
+    /// Send updates of several tasks, starting with those scheduled first
+    fn send_updates_in_order(&mut self, mut task_ids: Vec<Uuid>) {
+        task_ids.sort_by_key(|task_id| scheduling_key(task_id, self.tasks[task_id].as_ref()));
+        for task_id in task_ids {
+            self.send_updates(&task_id);
+        }
+    }
+
+    /// Record the identity of the client which requested a task
+    pub fn set_task_requester(&mut self, task_id: &Uuid, requester: Option<String>) {
+        if let (Some(task), Some(requester)) = (self.tasks.get_mut(task_id), requester) {
+            task.set_requester(requester);
+        }
+    }
+
+    /// Record the identity of the client which registered a device
+    pub fn set_device_registrant(&mut self, device_id: &[u8], requester: Option<String>) {
+        if let Some(requester) = requester {
+            self.device_registrants
+                .insert(device_id.to_vec(), requester);
+        }
+    }
+
+    /// Reject `count` new resources of `requester` which would exceed its quota
+    ///
+    /// Clients which could not be identified are not limited by quotas.
+    pub fn check_quota(
+        &self,
+        requester: Option<&str>,
+        resource: QuotaResource,
+        count: usize,
+    ) -> Result<(), Error> {
+        let Some(requester) = requester else {
+            return Ok(());
+        };
+        let quota = self.config.quotas.get(requester);
+        let limit = match resource {
+            QuotaResource::Devices => quota.max_devices,
+            QuotaResource::Groups => quota.max_groups,
+            QuotaResource::ActiveTasks => quota.max_active_tasks,
+        };
+        let Some(limit) = limit else {
+            return Ok(());
+        };
+
+        let active_tasks = self.tasks.values().filter(|task| {
+            task.requester() == Some(requester)
+                && !matches!(
+                    task.get_status(),
+                    TaskStatus::Finished | TaskStatus::Failed(..)
+                )
+        });
+        let used = match resource {
+            QuotaResource::Devices => self
+                .device_registrants
+                .iter()
+                .filter(|(device_id, registrant)| {
+                    *registrant == requester && self.devices.contains_key(*device_id)
+                })
+                .count(),
+            QuotaResource::Groups => {
+                let established = self
+                    .group_requesters
+                    .iter()
+                    .filter(|(group_id, owner)| {
+                        *owner == requester && self.groups.contains_key(*group_id)
+                    })
+                    .count();
+                let pending = active_tasks
+                    .filter(|task| task.get_type() == TaskType::Group)
+                    .count();
+                established + pending
+            }
+            QuotaResource::ActiveTasks => active_tasks.count(),
+        };
+        if used + count > limit {
+            warn!(requester, ?resource, used, limit, "Requester quota reached");
+            return Err(Error::ResourceExhausted(format!(
+                "Quota of {} reached ({} of {})",
+                match resource {
+                    QuotaResource::Devices => "devices",
+                    QuotaResource::Groups => "groups",
+                    QuotaResource::ActiveTasks => "active tasks",
+                },
+                used,
+                limit
+            )));
+        }
+        Ok(())
+    }
+
+    /// Add a task created by `create` on behalf of `requester`
+    ///
+    /// The task is created only if the active task quota of the requester allows it.
+    /// See [`State::adopt_requested_task`] for the bookkeeping of the created task.
+    pub fn add_requested_task<F>(
+        &mut self,
+        requester: Option<String>,
+        priority: Option<u32>,
+        create: F,
+    ) -> Result<Uuid, Error>
+    where
+        F: FnOnce(&mut State) -> Result<Uuid, Error>,
+    {
+        self.check_quota(requester.as_deref(), QuotaResource::ActiveTasks, 1)?;
+        let task_id = create(self)?;
+        self.adopt_requested_task(&task_id, requester, priority);
+        Ok(task_id)
+    }
+
+    /// Attribute a newly created task to `requester` and record its creation in the audit trail
+    ///
+    /// A `priority` replaces the priority the task was created with.
+    pub fn adopt_requested_task(
+        &mut self,
+        task_id: &Uuid,
+        requester: Option<String>,
+        priority: Option<u32>,
+    ) {
+        self.set_task_requester(task_id, requester);
+        if let Some(priority) = priority {
+            self.set_task_priority(task_id, priority);
+        }
+        self.audit_task_creation(task_id);
+    }
+
+    pub fn set_alert_sink(&mut self, sink: Arc<dyn AlertSink>) {
+        self.alert_sink = sink;
+    }
+
+    /// Replace the source of time used for task and device bookkeeping
+    #[cfg(test)]
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// Current time of the state clock
+    pub fn now(&self) -> u64 {
+        self.clock.now()
+    }
+
+    /// Count a protocol failure of every device the stale task `task_id` is waiting on,
+    /// leaving the devices which fail too often out of signing
+    pub fn record_stalled_devices(&mut self, task_id: &Uuid) {
+        let (Some(breaker), Some(task)) = (&mut self.circuit_breaker, self.tasks.get(task_id))
+        else {
+            return;
+        };
+        if !matches!(task.get_status(), TaskStatus::Running(_)) {
+            return;
+        }
+        let timestamp = self.clock.now();
+        for device_id in waiting_devices(task.as_ref()) {
+            let Some(device) = self.devices.get(&device_id) else {
+                continue;
+            };
+            if breaker.record_failure(&device_id, timestamp) && !device.breaker_tripped() {
+                warn!(
+                    device_id = %logging::log_id(&device_id),
+                    "Circuit breaker tripped, device left out of signing"
+                );
+                device.set_breaker_tripped(true);
+            }
+        }
+    }
+
+    /// Close the circuit breaker of `device` once it completed its protocol share
+    fn device_succeeded(&mut self, device: &[u8]) {
+        let Some(breaker) = &mut self.circuit_breaker else {
+            return;
+        };
+        breaker.record_success(device);
+        if let Some(device) = self
+            .devices
+            .get(device)
+            .filter(|device| device.breaker_tripped())
         {
+            info!(
+                device_id = %logging::log_id(device.identifier()),
+                "Circuit breaker closed"
+            );
+            device.set_breaker_tripped(false);
+        }
+    }
+
+    /// Include `device` in signing again regardless of its recent protocol failures
+    pub fn reset_circuit_breaker(&mut self, device: &[u8]) -> Result<(), Error> {
+        if !self.devices.contains_key(device) {
+            return Err(Error::NotFound("Unknown device".into()));
+        }
+        self.device_succeeded(device);
+        Ok(())
+    }
+
+    /// Get the number of consecutive protocol failures of `device` counted by the circuit breaker
+    pub fn device_failures(&self, device: &[u8]) -> u32 {
+        self.circuit_breaker
+            .as_ref()
+            .map_or(0, |breaker| breaker.failures(device, self.clock.now()))
+    }
+
+    /// Count a failure of a group task, alerting once the group fails too often
+    fn track_failure(&mut self, task_id: &Uuid) {
+        let Some(tracker) = &mut self.failure_tracker else {
+            return;
+        };
+        let task = self.tasks[task_id].as_ref();
+        let (TaskStatus::Failed(reason, message), Some(group_id)) =
+            (task.get_status(), task.get_group_id())
+        else {
+            return;
+        };
+        if !self.counted_failures.insert(*task_id) {
+            return;
+        }
+        if let Some(failures) = tracker.record(group_id, self.clock.now()) {
+            self.alert_sink.group_failures(&FailureAlert {
+                group_id: group_id.to_vec(),
+                failures,
+                window: tracker.window(),
+                task_id: *task_id,
+                reason,
+                message,
+            });
+        }
+    }
+
+    pub fn set_audit_signer(&mut self, signer: Arc<dyn RecordSigner>) {
+        self.audit_signer = Some(signer);
+    }
+
+    /// Record the creation of a task in the audit trail
+    pub fn audit_task_creation(&mut self, task_id: &Uuid) {
+        let Some(task) = self.tasks.get(task_id) else {
+            return;
+        };
+        let timestamp = task.created_at();
+        self.record_audit(task_id, AuditEvent::Created, timestamp);
+    }
+
+    /// Record the outcome of a task in the audit trail once it has ended
+    fn audit_task_outcome(&mut self, task_id: &Uuid) {
+        let event = match self.tasks[task_id].get_status() {
+            TaskStatus::Finished => AuditEvent::Finished,
+            TaskStatus::Failed(..) => AuditEvent::Failed,
+            _ => return,
+        };
+        if self.audited_outcomes.insert(*task_id) {
+            self.record_audit(task_id, event, self.clock.now());
+        }
+    }
+
+    /// Log and keep an audit record of a task, signed by the coordinator key if one is configured
+    fn record_audit(&mut self, task_id: &Uuid, event: AuditEvent, timestamp: u64) {
+        let task = self.tasks[task_id].as_ref();
+        let mut record = AuditRecord::new(task_id, task, event, timestamp);
+        if let Some(signer) = &self.audit_signer {
+            if let Err(message) = audit::sign_record(&mut record, signer.as_ref()) {
+                error!(
+                    task_id = %logging::log_id(task_id.as_bytes()),
+                    message,
+                    "Audit record left unsigned"
+                );
+            }
+        }
+        info!(
+            event = record.event,
+            task_id = %logging::log_id(task_id.as_bytes()),
+            task_type = record.task_type,
+            group_id = record
+                .group_id
+                .as_deref()
+                .and_then(|group_id| hex::decode(group_id).ok())
+                .map(logging::log_id),
+            requester = record.requester.as_deref().map(logging::log_requester),
+            timestamp = record.timestamp,
+            signature = record.signature.as_ref().map(hex::encode),
+            "Task event recorded"
+        );
+        self.audit_trail.push(record);
+    }
+
+    /// Get the audit records of the tasks of `group_id` made between `since` and `until`
+    ///
+    /// The records of the group task which established the group are included.
+    pub fn audit_trail(
+        &self,
+        group_id: &[u8],
+        since: Option<u64>,
+        until: Option<u64>,
+    ) -> Result<Vec<AuditRecord>, Error> {
+        if !self.groups.contains_key(group_id) {
+            return Err(Error::NotFound(format!(
+                "Unknown group {}",
+                hex::encode(group_id)
+            )));
+        }
+        let group_id = hex::encode(group_id);
+        let task_ids: HashSet<_> = self
+            .audit_trail
+            .iter()
+            .filter(|record| record.group_id.as_ref() == Some(&group_id))
+            .map(|record| record.task_id.as_str())
+            .collect();
+        Ok(self
+            .audit_trail
+            .iter()
+            .filter(|record| task_ids.contains(record.task_id.as_str()))
+            .filter(|record| since.is_none_or(|since| since <= record.timestamp))
+            .filter(|record| until.is_none_or(|until| record.timestamp <= until))
+            .cloned()
+            .collect())
+    }
+
+    /// Apply a protocol message of `device` to a task
+    ///
+    /// Updates, restarts and cancellations of a task are serialized by the state lock.
+    /// An update is accepted only if it targets the current attempt of a task which
+    /// has neither finished nor failed, so an update racing a restart or cancellation
+    /// is rejected instead of being applied to the new attempt or to a terminated task.
+    ///
+    /// Once a device numbers its updates of a task by `sequence`, every further update
+    /// must carry a greater number; anything else is rejected as a replay.
+    pub fn update_task(
+        &mut self,
+        task_id: &Uuid,
+        device: &[u8],
+        data: &Vec<Vec<u8>>,
+        attempt: u32,
+        sequence: Option<u64>,
+    ) -> Result<bool, Error> {
+        let _span = info_span!(
+            "update_task",
+            %task_id,
+            device_id = %logging::log_id(device)
+        )
+        .entered();
+        self.check_update(task_id, device, data, attempt, sequence)?;
+        let task = self.tasks.get_mut(task_id).unwrap();
+        let previous_status = task.get_status();
+        let update_result = task.update(device, data).map_err(Error::FailedPrecondition);
+        self.complete_update(task_id, device, sequence, previous_status, update_result)
+    }
+
+    /// Take a task out of the state to apply an update of `device` without holding the state lock
+    ///
+    /// The task must be returned by [`State::return_updated_task`]; meanwhile, a read-only
+    /// stand-in answers for it, so that it stays visible to listings, limits and timers.
+    pub fn check_out_for_update(
+        &mut self,
+        task_id: &Uuid,
+        device: &[u8],
+        data: &[Vec<u8>],
+        attempt: u32,
+        sequence: Option<u64>,
+    ) -> Result<UpdateCheckout, Error> {
+        if let Some(notify) = self.tasks_in_update.get(task_id) {
+            return Ok(UpdateCheckout::Busy(notify.clone()));
+        }
+        self.check_update(task_id, device, data, attempt, sequence)?;
+        self.tasks_in_update
+            .insert(*task_id, Arc::new(Notify::new()));
+        let stand_in = CheckedOutTask::of(self.tasks[task_id].as_ref());
+        let task = std::mem::replace(self.tasks.get_mut(task_id).unwrap(), Box::new(stand_in));
+        Ok(UpdateCheckout::Ready(task))
+    }
+
+    /// Put back a task checked out by [`State::check_out_for_update`] and process the result
+    /// of the update
+    pub fn return_updated_task(
+        &mut self,
+        task_id: &Uuid,
+        task: Box<dyn Task + Send + Sync>,
+        device: &[u8],
+        sequence: Option<u64>,
+        previous_status: TaskStatus,
+        update_result: Result<bool, String>,
+    ) -> Result<bool, Error> {
+        self.tasks.insert(*task_id, task);
+        self.release_checkout(task_id);
+        let result = self.complete_update(
+            task_id,
+            device,
+            sequence,
+            previous_status,
+            update_result.map_err(Error::FailedPrecondition),
+        );
+        self.handle_deferred_disconnects(task_id);
+        result
+    }
+
+    /// Put back a task checked out by [`State::check_out_for_update`] whose update panicked,
+    /// failing the task
+    pub fn return_panicked_task(
+        &mut self,
+        task_id: &Uuid,
+        mut task: Box<dyn Task + Send + Sync>,
+        device: &[u8],
+    ) -> Error {
+        error!(
+            task_id = %logging::log_id(task_id.as_bytes()),
+            device_id = %logging::log_id(device),
+            "Task update panicked"
+        );
+        let failed = task.fail(
+            FailureReason::ProtocolError,
+            "Task failed (update could not be processed)",
+        );
+        self.tasks.insert(*task_id, task);
+        self.release_checkout(task_id);
+        self.deferred_disconnects.remove(task_id);
+        self.pending_commitments.remove(task_id);
+        if failed {
             self.send_updates(task_id);
-            true
-        } else {
-            false
         }
+        Error::FailedPrecondition("Task update failed".into())
     }
 
-    pub fn add_subscriber(
+    /// Replace the stand-in of a checked out task which was never returned by a failed record
+    pub fn abandon_checkout(&mut self, task_id: &Uuid) {
+        if let Some(task) = self.tasks.get_mut(task_id) {
+            *task = Box::new(CheckedOutTask::of(task.as_ref()).abandoned(
+                FailureReason::ProtocolError,
+                "Task failed (update could not be processed)",
+            ));
+        }
+        self.release_checkout(task_id);
+        self.deferred_disconnects.remove(task_id);
+        self.pending_commitments.remove(task_id);
+        self.send_updates(task_id);
+    }
+
+    /// Get the notification of the running update of `task_id`, if the task is checked out
+    pub fn pending_update(&self, task_id: &Uuid) -> Option<Arc<Notify>> {
+        self.tasks_in_update.get(task_id).cloned()
+    }
+
+    /// Wake the requests waiting for the update of a checked out task to finish
+    pub fn release_checkout(&mut self, task_id: &Uuid) {
+        if let Some(notify) = self.tasks_in_update.remove(task_id) {
+            notify.notify_waiters();
+        }
+    }
+
+    /// Get a task which may be changed, i.e. which is not checked out for an update
+    fn task_mut(&mut self, task_id: &Uuid) -> Result<&mut Box<dyn Task + Send + Sync>, Error> {
+        if self.tasks_in_update.contains_key(task_id) {
+            return Err(Error::FailedPrecondition(
+                "Task is being updated, try again".into(),
+            ));
+        }
+        self.tasks.get_mut(task_id).ok_or_else(|| {
+            Error::NotFound(format!("Unknown task {}", hex::encode(task_id.as_bytes())))
+        })
+    }
+
+    /// Reject an update of `task_id` which is not acceptable in the current state of the task
+    fn check_update(
         &mut self,
-        device_id: Vec<u8>,
-        tx: Sender<Result<crate::proto::Task, Status>>,
-    ) {
-        self.subscribers.insert(device_id, tx);
+        task_id: &Uuid,
+        device: &[u8],
+        data: &[Vec<u8>],
+        attempt: u32,
+        sequence: Option<u64>,
+    ) -> Result<(), Error> {
+        if self.tasks_in_update.contains_key(task_id) {
+            return Err(Error::FailedPrecondition(
+                "Another update of the task is being processed, try again".into(),
+            ));
+        }
+        let task = self.tasks.get(task_id).ok_or_else(|| {
+            Error::NotFound(format!("Unknown task {}", hex::encode(task_id.as_bytes())))
+        })?;
+        if matches!(
+            task.get_status(),
+            TaskStatus::Finished | TaskStatus::Failed(..)
+        ) {
+            warn!(
+                task_id = %logging::log_id(task_id.as_bytes()),
+                device_id = %logging::log_id(device),
+                "Update of a terminated task discarded"
+            );
+            return Err(Error::FailedPrecondition(
+                "Task is no longer running".to_string(),
+            ));
+        }
+        if attempt != task.get_attempts() {
+            warn!(
+                task_id = %logging::log_id(task_id.as_bytes()),
+                device_id = %logging::log_id(device),
+                attempt,
+                "Stale update discarded"
+            );
+            return Err(Error::FailedPrecondition(format!(
+                "Stale update (task was restarted, current attempt is {})",
+                task.get_attempts()
+            )));
+        }
+        let size: usize = data.iter().map(Vec::len).sum();
+        if size > self.config.max_message_size {
+            return Err(self.reject_oversized(task_id, device, size));
+        }
+        let sequence_key = (*task_id, device.to_vec());
+        if let Some(last) = self.update_sequences.get(&sequence_key) {
+            if sequence.is_none_or(|sequence| sequence <= *last) {
+                warn!(
+                    task_id = %logging::log_id(task_id.as_bytes()),
+                    device_id = %logging::log_id(device),
+                    sequence,
+                    last,
+                    "Replayed update discarded"
+                );
+                return Err(Error::InvalidArgument(format!(
+                    "Replayed update (sequence number must be greater than {})",
+                    last
+                )));
+            }
+        }
+        if self.config.musig2_nonce_guard {
+            self.check_nonce_reuse(task_id, device, data, attempt)?;
+        }
+        Ok(())
     }
 
-    pub fn remove_subscriber(&mut self, device_id: &Vec<u8>) {
-        self.subscribers.remove(device_id);
-        debug!(
-            "Removing subscriber device_id={}",
-            utils::hextrunc(device_id)
+    /// Fail the task if a Musig2 nonce commitment of `device` was seen before
+    ///
+    /// Commitments are submitted in the first round of a Musig2 signing protocol. Honest
+    /// devices draw fresh nonces for every session, so a commitment repeated across tasks
+    /// or attempts of the group means a nonce reuse, which would expose the signing key.
+    /// The commitments are recorded only once the update is accepted.
+    fn check_nonce_reuse(
+        &mut self,
+        task_id: &Uuid,
+        device: &[u8],
+        data: &[Vec<u8>],
+        attempt: u32,
+    ) -> Result<(), Error> {
+        let task = &self.tasks[task_id];
+        if task.get_status() != TaskStatus::Running(1) {
+            return Ok(());
+        }
+        let Some(group_id) = task.get_group_id() else {
+            return Ok(());
+        };
+        if self.groups.get(group_id).map(Group::protocol) != Some(ProtocolType::Musig2) {
+            return Ok(());
+        }
+        let group_id = group_id.to_vec();
+        let commitments = self.musig2_commitments.get(&group_id);
+        let mut digests = Vec::new();
+        let mut reused = false;
+        for message in data {
+            let Ok(message) = meesign_crypto::proto::ClientMessage::decode(message.as_slice())
+            else {
+                continue;
+            };
+            let Some(broadcast) = message.broadcast else {
+                continue;
+            };
+            let digest: [u8; 32] = Sha256::digest(&broadcast).into();
+            let session = commitments.and_then(|commitments| commitments.session(&digest));
+            reused |= session.is_some_and(|session| session != (*task_id, attempt));
+            digests.push(digest);
+        }
+        if !reused {
+            if !digests.is_empty() {
+                self.pending_commitments
+                    .insert(*task_id, (group_id, digests));
+            }
+            return Ok(());
+        }
+
+        error!(
+            task_id = %logging::log_id(task_id.as_bytes()),
+            group_id = %logging::log_id(&group_id),
+            device_id = %logging::log_id(device),
+            "Musig2 nonce commitment reused"
         );
+        self.nonce_reuses.push(NonceReuse {
+            task_id: *task_id,
+            group_id,
+            device_id: device.to_vec(),
+            timestamp: self.clock.now(),
+        });
+        if self.tasks.get_mut(task_id).unwrap().fail(
+            FailureReason::NonceReuse,
+            "Task failed (participant reused a Musig2 nonce commitment)",
+        ) {
+            self.send_updates(task_id);
+        }
+        Err(Error::InvalidArgument(
+            "Nonce commitment was already used in an earlier signing session".into(),
+        ))
     }
 
-    pub fn get_subscribers(&self) -> &HashMap<Vec<u8>, Sender<Result<crate::proto::Task, Status>>> {
-        &self.subscribers
+    /// Record the outcome of an update applied to `task_id`
+    fn complete_update(
+        &mut self,
+        task_id: &Uuid,
+        device: &[u8],
+        sequence: Option<u64>,
+        previous_status: TaskStatus,
+        update_result: Result<bool, Error>,
+    ) -> Result<bool, Error> {
+        if let (Ok(_), Some(sequence)) = (&update_result, sequence) {
+            self.update_sequences
+                .insert((*task_id, device.to_vec()), sequence);
+        }
+        if let Some((group_id, digests)) = self.pending_commitments.remove(task_id) {
+            if update_result.is_ok() {
+                let attempt = self.tasks[task_id].get_attempts();
+                let commitments = self.musig2_commitments.entry(group_id).or_default();
+                for digest in digests {
+                    commitments.record(digest, (*task_id, attempt));
+                }
+            }
+        }
+        let task = self.tasks.get_mut(task_id).unwrap();
+        if previous_status != task.get_status() {
+            debug!(?previous_status, status = ?task.get_status(), "Task advanced");
+        }
+        let failed = !matches!(previous_status, TaskStatus::Failed(..))
+            && matches!(task.get_status(), TaskStatus::Failed(..));
+        if previous_status != TaskStatus::Finished && task.get_status() == TaskStatus::Finished {
+            match task.get_result().unwrap() {
+                TaskResult::GroupEstablished(mut group) => {
+                    if let Some(allowed_tasks) = self.pending_group_usage.remove(task_id) {
+                        group.set_allowed_tasks(allowed_tasks);
+                    }
+                    if self.pending_auto_approve.remove(task_id) {
+                        group.set_auto_approve(true);
+                    }
+                    if self.pending_all_signers.remove(task_id) {
+                        group.set_require_all_signers(true);
+                    }
+                    if let Some(requester) = task.requester() {
+                        self.group_requesters
+                            .insert(group.identifier().to_vec(), requester.to_owned());
+                    }
+                    // the group task has finished, so a shared key only withholds the group
+                    let _ = self.insert_group(*group);
+                }
+                TaskResult::Signed(_)
+                | TaskResult::SignedPdf(_)
+                | TaskResult::SignedCertificate(_)
+                | TaskResult::SignedEthereumTx(_) => {
+                    if let Some(group) = task.get_group_id().and_then(|id| self.groups.get_mut(id))
+                    {
+                        group.record_signature(self.clock.now());
+                    }
+                }
+                TaskResult::Decrypted(_) => {}
+            }
+        }
+        if update_result.is_ok() {
+            self.device_succeeded(device);
+        }
+        if failed || matches!(update_result, Ok(true)) {
+            self.send_updates(task_id);
+        }
+        update_result
     }
 
-    fn send_updates(&mut self, task_id: &Uuid) {
-        let task = self.get_task(task_id).unwrap();
-        let mut remove = Vec::new();
+    /// Record an oversized submission of `device`, failing the task once the device keeps sending them
+    fn reject_oversized(&mut self, task_id: &Uuid, device: &[u8], size: usize) -> Error {
+        let violations = self
+            .oversized_messages
+            .entry((*task_id, device.to_vec()))
+            .or_default();
+        *violations += 1;
+        warn!(
+            task_id = %logging::log_id(task_id.as_bytes()),
+            device_id = %logging::log_id(device),
+            size,
+            violations = *violations,
+            "Oversized update discarded"
+        );
+        if *violations >= MAX_OVERSIZED_MESSAGES
+            && self.tasks.get_mut(task_id).unwrap().fail(
+                FailureReason::ProtocolError,
+                "Task failed (participant repeatedly sent oversized messages)",
+            )
+        {
+            self.send_updates(task_id);
+        }
+        Error::InvalidArgument(format!(
+            "Messages of {} bytes exceed the limit of {} bytes",
+            size, self.config.max_message_size
+        ))
+    }
 
-        for device_id in task.get_devices().iter().map(|device| device.identifier()) {
-            if let Some(tx) = self.subscribers.get(device_id) {
-                let result = tx.try_send(Ok(format_task(task_id, task, Some(device_id), None)));
+    /// Collect the inputs `device` has to process across all of its running tasks
+    ///
+    /// Work is ordered in the same way as [`State::get_device_tasks`].
+    pub fn get_device_work(&self, device: &[u8]) -> Vec<crate::proto::Work> {
+        self.get_device_tasks(device)
+            .into_iter()
+            .filter_map(|(task_id, task)| match task.get_status() {
+                TaskStatus::Running(round) => Some(crate::proto::Work {
+                    task_id: task_id.as_bytes().to_vec(),
+                    round: round.into(),
+                    attempt: task.get_attempts(),
+                    data: task.get_work(Some(device)),
+                    version: WORK_VERSION.into(),
+                    sequence: None,
+                }),
+                _ => None,
+            })
+            .filter(|work| !work.data.is_empty())
+            .collect()
+    }
 
-                if result.is_err() {
-                    debug!(
-                        "Closed channel detected device_id={}…",
-                        utils::hextrunc(&device_id[..4])
+    /// Get the inputs of the current round of a task again, e.g. after `device` lost its state
+    ///
+    /// Fails if `device` is not an active participant or has already submitted the current round.
+    pub fn resend_work(&self, task_id: &Uuid, device: &[u8]) -> Result<crate::proto::Work, Error> {
+        let task = self.tasks.get(task_id).ok_or_else(|| {
+            Error::NotFound(format!("Unknown task {}", hex::encode(task_id.as_bytes())))
+        })?;
+        let TaskStatus::Running(round) = task.get_status() else {
+            return Err(Error::FailedPrecondition("Task is not running".into()));
+        };
+        if !task.has_device(device) {
+            return Err(Error::InvalidArgument(
+                "Device is not a participant of the task".into(),
+            ));
+        }
+        let data = task.get_work(Some(device));
+        if data.is_empty() {
+            return Err(Error::FailedPrecondition(format!(
+                "Device has no pending work in round {}",
+                round
+            )));
+        }
+        debug!(
+            task_id = %logging::log_id(task_id.as_bytes()),
+            device_id = %logging::log_id(device),
+            round,
+            "Resending work"
+        );
+        Ok(crate::proto::Work {
+            task_id: task_id.as_bytes().to_vec(),
+            round: round.into(),
+            attempt: task.get_attempts(),
+            data,
+            version: WORK_VERSION.into(),
+            sequence: None,
+        })
+    }
+
+    /// Check that `work` submitted by `device` targets the round which the task `task_id` is in
+    fn check_submitted_work(
+        &self,
+        task_id: &Uuid,
+        device: &[u8],
+        work: &crate::proto::Work,
+    ) -> Result<(), String> {
+        let task = self
+            .get_task(task_id)
+            .ok_or_else(|| "Unknown task".to_string())?;
+        if task.get_status() != TaskStatus::Running(work.round as u16) {
+            warn!(
+                task_id = %logging::log_id(task_id.as_bytes()),
+                device_id = %logging::log_id(device),
+                round = work.round,
+                "Stale work discarded"
+            );
+            return Err("Stale round".to_string());
+        }
+        if work.data.is_empty() {
+            return Err("Data must not be empty".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn decide_task(&mut self, task_id: &Uuid, device: &[u8], decision: bool) -> bool {
+        let _span = info_span!(
+            "decide_task",
+            %task_id,
+            device_id = %logging::log_id(device)
+        )
+        .entered();
+        let Some(task) = self.tasks.get_mut(task_id) else {
+            warn!("Decision on an unavailable task discarded");
+            return false;
+        };
+        let mut change = task.decide(device, decision);
+        if change.is_none() && !decision {
+            let subscribers = &self.subscribers;
+            match task.recruit(&|device_id| subscribers.contains_key(device_id)) {
+                Some(true) => {
+                    info!(
+                        task_id = %logging::log_id(task_id.as_bytes()),
+                        "Members recruited in place of a declining participant"
                     );
-                    remove.push(device_id.to_vec());
+                    self.send_updates(task_id);
+                    return true;
                 }
+                failed => change = failed,
             }
         }
-
-        for device_id in remove {
-            self.remove_subscriber(&device_id);
+        if change.is_some() {
+            self.send_updates(task_id);
+            if change.unwrap() {
+                info!(
+                    task_id = %logging::log_id(task_id.as_bytes()),
+                    "Task approved"
+                );
+            } else {
+                info!(
+                    task_id = %logging::log_id(task_id.as_bytes()),
+                    "Task declined"
+                );
+            }
+            return true;
         }
+        false
+    }
+
+    /// Cancel a group task before key generation starts and forget it
+    ///
+    /// The participants receive a final update of the cancelled task before it is removed.
+    pub fn cancel_group_task(&mut self, task_id: &Uuid) -> Result<(), Error> {
+        let task = self.task_mut(task_id)?;
+        if task.get_type() != TaskType::Group || task.get_status() != TaskStatus::Created {
+            return Err(Error::FailedPrecondition(
+                "Only a group task which has not started can be cancelled".into(),
+            ));
+        }
+        task.fail(FailureReason::Cancelled, "Task cancelled by the requester");
+        self.send_updates(task_id);
+
+        self.tasks.remove(task_id);
+        self.pending_group_usage.remove(task_id);
+        self.pending_auto_approve.remove(task_id);
+        self.pending_all_signers.remove(task_id);
+        self.update_sequences
+            .retain(|(sequence_task, _), _| sequence_task != task_id);
+        self.oversized_messages
+            .retain(|(oversized_task, _), _| oversized_task != task_id);
+        info!(
+            task_id = %logging::log_id(task_id.as_bytes()),
+            "Group task cancelled"
+        );
+        Ok(())
+    }
+
+    /// Store a commitment of `device` to participate in the protocol of a task
+    pub fn commit_task(&mut self, task_id: &Uuid, device: &[u8]) -> Result<(), Error> {
+        let task = self.task_mut(task_id)?;
+        let started = task.commit(device).map_err(Error::FailedPrecondition)?;
+        info!(
+            task_id = %logging::log_id(task_id.as_bytes()),
+            device_id = %logging::log_id(device),
+            "Task commitment received"
+        );
+        if started {
+            self.send_updates(task_id);
+        }
+        Ok(())
+    }
+
+    /// Record the committed devices which a stalled task is still waiting on
+    pub fn record_commitment_violations(&mut self, task_id: &Uuid) {
+        let Some(task) = self.tasks.get(task_id) else {
+            return;
+        };
+        let TaskStatus::Running(round) = task.get_status() else {
+            return;
+        };
+        for device_id in waiting_devices(task.as_ref()) {
+            if !task.committed(&device_id) {
+                continue;
+            }
+            warn!(
+                task_id = %logging::log_id(task_id.as_bytes()),
+                device_id = %logging::log_id(&device_id),
+                round,
+                "Committed device did not submit its round message"
+            );
+            self.commitment_violations.push(CommitmentViolation {
+                task_id: *task_id,
+                device_id,
+                round,
+                timestamp: self.clock.now(),
+            });
+        }
+    }
+
+    /// Withdraw an acceptance by `device` before the protocol of the task starts
+    pub fn withdraw_decision(&mut self, task_id: &Uuid, device: &[u8]) -> Result<(), Error> {
+        let task = self.task_mut(task_id)?;
+        if !task.withdraw(device) {
+            return Err(Error::FailedPrecondition(
+                "Only an acceptance of a task which has not started can be withdrawn".into(),
+            ));
+        }
+        info!(
+            task_id = %logging::log_id(task_id.as_bytes()),
+            device_id = %logging::log_id(device),
+            "Task acceptance withdrawn"
+        );
+        self.send_updates(task_id);
+        Ok(())
+    }
+
+    pub fn acknowledge_task(&mut self, task: &Uuid, device: &[u8]) {
+        if let Some(task) = self.tasks.get_mut(task) {
+            task.acknowledge(device);
+        }
+    }
+
+    pub fn get_devices(&self) -> &HashMap<Vec<u8>, Arc<Device>> {
+        &self.devices
+    }
+
+    pub fn device_activated(&self, device_id: &[u8]) -> bool {
+        if let Some(device) = self.devices.get(device_id) {
+            device.activated(self.clock.now());
+            true
+        } else {
+            debug!(device_id = %logging::log_id(device_id), "Unknown Device ID");
+            false
+        }
+    }
+
+    /// Restart a stalled task, or fail it once it was restarted `max_restarts` times
+    ///
+    /// # Returns
+    /// `true` if the task restarted
+    pub fn restart_task(&mut self, task_id: &Uuid) -> bool {
+        let _span = info_span!("restart_task", %task_id).entered();
+        let Some(task) = self.tasks.get_mut(task_id) else {
+            return false;
+        };
+        if self
+            .config
+            .max_restarts
+            .is_some_and(|max_restarts| task.get_attempts() >= max_restarts)
+        {
+            if task.fail(
+                FailureReason::RestartLimitExceeded,
+                "Task failed (restart limit exceeded)",
+            ) {
+                info!(
+                    task_id = %logging::log_id(task_id.as_bytes()),
+                    attempts = task.get_attempts(),
+                    "Task failed after too many restarts"
+                );
+                self.send_updates(task_id);
+            }
+            return false;
+        }
+        if task.restart().unwrap_or(false) {
+            self.send_updates(task_id);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Fail unfinished tasks whose requester deadline passed before `timestamp`,
+    /// and tasks which were not decided within the approval timeout
+    pub fn fail_overdue_tasks(&mut self, timestamp: u64) {
+        let approval_timeout = self
+            .config
+            .approval_timeout
+            .map(|timeout| timeout.as_secs());
+        let mut failed = Vec::new();
+        for (task_id, task) in self.tasks.iter_mut() {
+            // checked out tasks are failed on a later tick, once their update is stored
+            if self.tasks_in_update.contains_key(task_id) {
+                continue;
+            }
+            if task
+                .deadline()
+                .is_some_and(|deadline| deadline <= timestamp)
+                && task.fail(
+                    FailureReason::Timeout,
+                    "Task failed (requester deadline exceeded)",
+                )
+            {
+                info!(
+                    task_id = %logging::log_id(task_id.as_bytes()),
+                    "Task deadline exceeded"
+                );
+                failed.push(*task_id);
+            } else if task.get_status() == TaskStatus::Created
+                && approval_timeout.is_some_and(|timeout| task.created_at() + timeout <= timestamp)
+                && task.fail(
+                    FailureReason::ApprovalTimeout,
+                    "Task failed (approval timed out)",
+                )
+            {
+                info!(
+                    task_id = %logging::log_id(task_id.as_bytes()),
+                    "Task approval timed out"
+                );
+                failed.push(*task_id);
+            }
+        }
+        self.send_updates_in_order(failed);
+    }
+
+    /// Unregister devices which have not been active for the configured period before `timestamp`
+    ///
+    /// Devices which belong to a group that is not degraded or which participate in
+    /// an unfinished task are kept, so that no usable key material becomes unreachable.
+    ///
+    /// # Returns
+    /// Identifiers of the unregistered devices
+    pub fn unregister_inactive_devices(&mut self, timestamp: u64) -> Vec<Vec<u8>> {
+        let Some(timeout) = self.config.device_inactivity_timeout else {
+            return Vec::new();
+        };
+        let inactive: Vec<_> = self
+            .devices
+            .values()
+            .filter(|device| device.last_active() + timeout.as_secs() <= timestamp)
+            .map(|device| device.identifier().to_vec())
+            .filter(|device_id| {
+                !self
+                    .groups
+                    .values()
+                    .any(|group| group.contains(device_id) && !group.is_degraded())
+                    && !self.tasks.values().any(|task| {
+                        task.has_device(device_id)
+                            && !matches!(
+                                task.get_status(),
+                                TaskStatus::Finished | TaskStatus::Failed(..)
+                            )
+                    })
+            })
+            .collect();
+        for device_id in &inactive {
+            let device = self.devices.remove(device_id).unwrap();
+            self.subscribers.remove(device_id);
+            self.update_log.remove(device_id);
+            self.data_compression.remove(device_id);
+            warn!(
+                device_id = %logging::log_id(device_id),
+                name = device.name(),
+                last_active = device.last_active(),
+                "Unregistered inactive device"
+            );
+        }
+        inactive
+    }
+
+    /// Fail a stale key generation task, naming the devices which did not submit their messages
+    ///
+    /// Key generation needs every group member, so restarting cannot get past a missing device.
+    pub fn fail_stalled_keygen(&mut self, task_id: &Uuid) -> bool {
+        let Some(task) = self.tasks.get_mut(task_id) else {
+            return false;
+        };
+        if task.get_type() != TaskType::Group
+            || !matches!(task.get_status(), TaskStatus::Running(_))
+        {
+            return false;
+        }
+        let stalled = waiting_devices(task.as_ref());
+        if stalled.is_empty() {
+            return false;
+        }
+        let message = format!(
+            "Task failed (devices did not complete keygen: {})",
+            stalled
+                .iter()
+                .map(logging::log_id)
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        if !task.fail(FailureReason::Timeout, &message) {
+            return false;
+        }
+        info!(
+            task_id = %logging::log_id(task_id.as_bytes()),
+            devices = ?stalled.iter().map(logging::log_id).collect::<Vec<_>>(),
+            "Key generation stalled"
+        );
+        self.send_updates(task_id);
+        true
+    }
+
+    /// Forcibly fail or restart a task regardless of its progress
+    ///
+    /// # Returns
+    /// The status of the task before the intervention
+    pub fn force_task_state(
+        &mut self,
+        task_id: &Uuid,
+        target: TargetState,
+    ) -> Result<TaskStatus, Error> {
+        let task = self.task_mut(task_id)?;
+        let previous_status = task.get_status();
+        let changed = match target {
+            TargetState::Failed => task.fail(
+                FailureReason::Cancelled,
+                "Task failed (cancelled by administrator)",
+            ),
+            TargetState::Restarted => task.restart().unwrap_or(false),
+        };
+        if !changed {
+            return Err(Error::FailedPrecondition(format!(
+                "Task cannot be {}",
+                target.as_str_name().to_lowercase()
+            )));
+        }
+        self.send_updates(task_id);
+        Ok(previous_status)
+    }
+
+    #[cfg(test)]
+    pub fn add_group(&mut self, group: Group) -> Result<(), Error> {
+        self.insert_group(group)
+    }
+
+    pub fn get_config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn add_subscriber(
+        &mut self,
+        device_id: Vec<u8>,
+        tx: Sender<Result<crate::proto::Task, Status>>,
+    ) {
+        self.subscribers.insert(device_id, tx);
+    }
+
+    /// Get the buffered updates of `device_id` which follow the update numbered `last_sequence`
+    ///
+    /// Fails if some of the following updates are no longer buffered.
+    pub fn missed_updates(
+        &self,
+        device_id: &[u8],
+        last_sequence: u64,
+    ) -> Result<Vec<crate::proto::Task>, Error> {
+        let log = self.update_log.get(device_id);
+        let sequence_at =
+            |update: Option<&crate::proto::Task>| update.and_then(|update| update.update_sequence);
+        let latest = sequence_at(log.and_then(VecDeque::back)).unwrap_or(0);
+        let oldest = sequence_at(log.and_then(VecDeque::front)).unwrap_or(latest + 1);
+        if last_sequence > latest || last_sequence + 1 < oldest {
+            debug!(
+                device_id = %logging::log_id(device_id),
+                last_sequence,
+                oldest,
+                latest,
+                "Missed updates are no longer buffered"
+            );
+            return Err(Error::FailedPrecondition(
+                "Missed updates are no longer available, fetch the tasks again".into(),
+            ));
+        }
+        Ok(log
+            .into_iter()
+            .flatten()
+            .filter(|update| update.update_sequence > Some(last_sequence))
+            .cloned()
+            .collect())
+    }
+
+    /// Set the compression of task data in the updates sent to the subscriber `device_id`
+    pub fn set_data_compression(&mut self, device_id: Vec<u8>, compression: Compression) {
+        self.data_compression.insert(device_id, compression);
+    }
+
+    /// Remove the subscriber of `device_id` unless it has subscribed again through another channel
+    pub fn remove_closed_subscriber(
+        &mut self,
+        device_id: &Vec<u8>,
+        tx: &Sender<Result<crate::proto::Task, Status>>,
+    ) {
+        if self
+            .subscribers
+            .get(device_id)
+            .is_some_and(|current| current.same_channel(tx))
+        {
+            self.remove_subscriber(device_id);
+        }
+    }
+
+    pub fn remove_subscriber(&mut self, device_id: &Vec<u8>) {
+        self.subscribers.remove(device_id);
+        self.data_compression.remove(device_id);
+        debug!(
+            device_id = %logging::log_id(device_id),
+            "Removing subscriber"
+        );
+
+        match self.config.disconnect_policy {
+            DisconnectPolicy::Fail => self.handle_disconnected(device_id, false),
+            DisconnectPolicy::Reselect => self.handle_disconnected(device_id, true),
+            DisconnectPolicy::Wait => {}
+        }
+    }
+
+    /// Handle running tasks which are waiting on a message from a disconnected device
+    ///
+    /// If `reselect` is set, the device is replaced by another accepting participant
+    /// when possible; the remaining tasks fail.
+    fn handle_disconnected(&mut self, device_id: &[u8], reselect: bool) {
+        let task_ids: Vec<_> = self.tasks.keys().copied().collect();
+        let changed: Vec<_> = task_ids
+            .into_iter()
+            .filter(|task_id| self.disconnect_participant(task_id, device_id, reselect))
+            .collect();
+        self.send_updates_in_order(changed);
+    }
+
+    /// Replace or fail a disconnected device in a running task waiting on it
+    ///
+    /// A checked out task is handled once its update is stored.
+    ///
+    /// # Returns
+    /// `true` if the task changed
+    fn disconnect_participant(&mut self, task_id: &Uuid, device_id: &[u8], reselect: bool) -> bool {
+        let task = self.tasks.get_mut(task_id).unwrap();
+        if !matches!(task.get_status(), TaskStatus::Running(_)) || !task.waiting_for(device_id) {
+            return false;
+        }
+        if self.tasks_in_update.contains_key(task_id) {
+            self.deferred_disconnects
+                .entry(*task_id)
+                .or_default()
+                .push((device_id.to_vec(), reselect));
+            return false;
+        }
+        if reselect && task.reselect(device_id).unwrap_or(false) {
+            info!(
+                task_id = %logging::log_id(task_id.as_bytes()),
+                device_id = %logging::log_id(device_id),
+                "Disconnected participant replaced"
+            );
+            true
+        } else if task.fail(
+            FailureReason::ParticipantDisconnected,
+            "Task failed (participant disconnected)",
+        ) {
+            info!(
+                task_id = %logging::log_id(task_id.as_bytes()),
+                device_id = %logging::log_id(device_id),
+                "Task failed due to disconnected participant"
+            );
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Handle the devices which disconnected from `task_id` while it was checked out
+    /// and have not subscribed again since
+    fn handle_deferred_disconnects(&mut self, task_id: &Uuid) {
+        let mut changed = false;
+        for (device_id, reselect) in self
+            .deferred_disconnects
+            .remove(task_id)
+            .unwrap_or_default()
+        {
+            if !self.subscribers.contains_key(&device_id) {
+                changed |= self.disconnect_participant(task_id, &device_id, reselect);
+            }
+        }
+        if changed {
+            self.send_updates(task_id);
+        }
+    }
+
+    /// Get the standing of each participant of `task_id` in the current phase of the task
+    pub fn get_task_participants(
+        &self,
+        task_id: &Uuid,
+    ) -> Result<Vec<crate::proto::TaskParticipant>, Error> {
+        use crate::proto::task_participant::Status;
+
+        let task = self.tasks.get(task_id).ok_or_else(|| {
+            Error::NotFound(format!("Unknown task {}", hex::encode(task_id.as_bytes())))
+        })?;
+        let mut participants: Vec<crate::proto::TaskParticipant> = Vec::new();
+        for device in task.get_devices() {
+            let device_id = device.identifier();
+            if participants
+                .iter()
+                .any(|participant| participant.device_id == device_id)
+            {
+                continue;
+            }
+            let status = match task.participant_status(device_id) {
+                ParticipantStatus::Pending if !self.is_subscribed(device_id) => Status::Offline,
+                ParticipantStatus::Pending => Status::Pending,
+                ParticipantStatus::Submitted => Status::Submitted,
+                ParticipantStatus::Declined => Status::Declined,
+                ParticipantStatus::Idle => Status::Idle,
+            };
+            participants.push(crate::proto::TaskParticipant {
+                device_id: device_id.to_vec(),
+                status: status.into(),
+            });
+        }
+        Ok(participants)
+    }
+
+    /// Set how task updates reach `device_id`
+    pub fn set_delivery_mode(&mut self, device_id: &[u8], mode: DeliveryMode) {
+        match mode {
+            DeliveryMode::Push => self.pull_devices.remove(device_id),
+            DeliveryMode::Pull => self.pull_devices.insert(device_id.to_vec()),
+        };
+    }
+
+    /// Get how task updates reach `device_id`
+    pub fn delivery_mode(&self, device_id: &[u8]) -> DeliveryMode {
+        if self.pull_devices.contains(device_id) {
+            DeliveryMode::Pull
+        } else {
+            DeliveryMode::Push
+        }
+    }
+
+    /// True if `device_id` has a live update stream
+    pub fn is_subscribed(&self, device_id: &[u8]) -> bool {
+        self.subscribers.contains_key(device_id)
+    }
+
+    pub fn get_subscribers(&self) -> &HashMap<Vec<u8>, Sender<Result<crate::proto::Task, Status>>> {
+        &self.subscribers
+    }
+
+    fn send_updates(&mut self, task_id: &Uuid) {
+        let _span = debug_span!("send_updates", %task_id).entered();
+        self.track_failure(task_id);
+        self.audit_task_outcome(task_id);
+        self.resolve_dependents(task_id);
+        let task = self.tasks[task_id].as_ref();
+        let mut remove = Vec::new();
+
+        for device_id in task.get_devices().iter().map(|device| device.identifier()) {
+            if self.pull_devices.contains(device_id) {
+                continue;
+            }
+            let mut formatted = match format_task(task_id, task, Some(device_id), None) {
+                Ok(formatted) => formatted,
+                Err(message) => {
+                    error!(
+                        task_id = %logging::log_id(task_id.as_bytes()),
+                        device_id = %logging::log_id(device_id),
+                        message,
+                        "Could not format task update"
+                    );
+                    continue;
+                }
+            };
+            let log = self.update_log.entry(device_id.to_vec()).or_default();
+            let last_sequence = log.back().and_then(|update| update.update_sequence);
+            formatted.update_sequence = Some(last_sequence.unwrap_or(0) + 1);
+            if log.len() >= MAX_BUFFERED_UPDATES {
+                log.pop_front();
+            }
+            log.push_back(formatted.clone());
+
+            if let Some(tx) = self.subscribers.get(device_id) {
+                // a backed-up device receives only the updates it has to act on;
+                // the others remain in the update log for replay
+                if tx.capacity() <= RESERVED_UPDATE_SLOTS && !requires_action(task, device_id) {
+                    debug!(
+                        device_id = %logging::log_id(device_id),
+                        "Progress update deferred for a backlogged device"
+                    );
+                    continue;
+                }
+                if let Some(&compression) = self.data_compression.get(device_id) {
+                    compress_data(&mut formatted, compression);
+                }
+                match tx.try_send(Ok(formatted)) {
+                    Ok(()) => {}
+                    // the update remains in the update log for replay
+                    Err(TrySendError::Full(_)) => debug!(
+                        device_id = %logging::log_id(device_id),
+                        "Update dropped for a full channel"
+                    ),
+                    Err(TrySendError::Closed(_)) => {
+                        debug!(
+                            device_id = %logging::log_id(device_id),
+                            "Closed channel detected"
+                        );
+                        remove.push(device_id.to_vec());
+                    }
+                }
+            }
+        }
+
+        for device_id in remove {
+            self.remove_subscriber(&device_id);
+        }
+    }
+}
+
+/// Check whether an update of `task` asks `device_id` for a decision, work or acknowledgement,
+/// or tells it to abandon the task
+fn requires_action(task: &dyn Task, device_id: &[u8]) -> bool {
+    task.waiting_for(device_id) || matches!(task.get_status(), TaskStatus::Failed(..))
+}
+
+fn check_deadline(deadline_seconds: Option<u64>) -> Result<(), Error> {
+    match deadline_seconds {
+        Some(0) => Err(Error::InvalidArgument("Deadline must be positive".into())),
+        Some(seconds) if seconds > MAX_DEADLINE_SECONDS => Err(Error::InvalidArgument(format!(
+            "Deadline must not exceed {} seconds",
+            MAX_DEADLINE_SECONDS
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Check that `group` holds complete key material which can be used for signing
+fn check_signable(group: &Group) -> Result<(), Error> {
+    if group.identifier().is_empty() {
+        warn!("Signing requested from a group without a public key");
+        return Err(Error::FailedPrecondition(
+            "Group has no valid public key".into(),
+        ));
+    }
+    if group.is_degraded() {
+        warn!(
+            group_id = %logging::log_id(group.identifier()),
+            "Signing requested from a degraded group"
+        );
+        return Err(Error::FailedPrecondition(
+            "Group is degraded and cannot sign".into(),
+        ));
+    }
+    Ok(())
+}
+
+/// Check that `group` supports hashing the signed data by `hash_algorithm`
+fn check_hash_algorithm(group: &Group, hash_algorithm: HashAlgorithm) -> Result<(), Error> {
+    // Schnorr protocols and PDF signing hash the data on their own
+    if hash_algorithm != HashAlgorithm::Default
+        && (group.protocol() != ProtocolType::Gg18 || group.key_type() != KeyType::SignChallenge)
+    {
+        warn!(
+            group_id = %logging::log_id(group.identifier()),
+            ?hash_algorithm,
+            "Unsupported hash algorithm requested"
+        );
+        return Err(Error::InvalidArgument(format!(
+            "Hash algorithm {} is not supported by the group",
+            hash_algorithm.as_str_name()
+        )));
+    }
+    Ok(())
+}
+
+/// Validate the kinds of tasks a group may be restricted to, in a canonical order
+pub fn check_allowed_tasks(allowed_tasks: &[TaskType]) -> Result<Vec<TaskType>, Error> {
+    if allowed_tasks.contains(&TaskType::Group) {
+        return Err(Error::InvalidArgument(
+            "Groups cannot be restricted to group tasks".into(),
+        ));
+    }
+    let mut allowed_tasks = allowed_tasks.to_vec();
+    allowed_tasks.sort();
+    allowed_tasks.dedup();
+    Ok(allowed_tasks)
+}
+
+/// Reject a task of `task_type` which `group` is not permitted to perform
+fn check_group_usage(group: &Group, task_type: TaskType) -> Result<(), Error> {
+    if !group.permits(task_type) {
+        warn!(
+            group_id = %logging::log_id(group.identifier()),
+            ?task_type,
+            "Task not permitted to the group"
+        );
+        return Err(Error::PermissionDenied(format!(
+            "Group is not permitted to perform {} tasks",
+            task_type.as_str_name()
+        )));
+    }
+    Ok(())
+}
+
+/// Order in which tasks are scheduled: higher priority first, then older tasks first
+fn scheduling_key(task_id: &Uuid, task: &dyn Task) -> (std::cmp::Reverse<u32>, u64, Uuid) {
+    (
+        std::cmp::Reverse(task.priority()),
+        task.created_at(),
+        *task_id,
+    )
+}
+
+/// Apply an update of `device` to a task, running the protocol step off the state lock
+///
+/// The task is checked out of the state while its update runs on a blocking thread,
+/// so that costly protocol steps do not stall other requests. The update runs detached
+/// from the caller, so the task is returned to the state even if the request is dropped.
+pub async fn update_task_unlocked(
+    state: Arc<Mutex<State>>,
+    task_id: Uuid,
+    device: Vec<u8>,
+    data: Vec<Vec<u8>>,
+    attempt: u32,
+    sequence: Option<u64>,
+) -> Result<bool, Error> {
+    tokio::spawn(apply_update(
+        state, task_id, device, data, attempt, sequence,
+    ))
+    .await
+    .unwrap_or_else(|err| {
+        error!(
+            task_id = %logging::log_id(task_id.as_bytes()),
+            %err,
+            "Task update aborted"
+        );
+        Err(Error::FailedPrecondition("Task update failed".into()))
+    })
+}
+
+async fn apply_update(
+    state: Arc<Mutex<State>>,
+    task_id: Uuid,
+    device: Vec<u8>,
+    data: Vec<Vec<u8>>,
+    attempt: u32,
+    sequence: Option<u64>,
+) -> Result<bool, Error> {
+    let mut task = loop {
+        let mut guard = state.lock().await;
+        match guard.check_out_for_update(&task_id, &device, &data, attempt, sequence)? {
+            UpdateCheckout::Ready(task) => break task,
+            UpdateCheckout::Busy(notify) => {
+                let finished = notify.notified();
+                drop(guard);
+                finished.await;
+            }
+        }
+    };
+
+    let updating_device = device.clone();
+    let outcome = tokio::task::spawn_blocking(move || {
+        let previous_status = task.get_status();
+        let update_result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            task.update(&updating_device, &data)
+        }))
+        .ok();
+        (task, previous_status, update_result)
+    })
+    .await;
+
+    let mut guard = state.lock().await;
+    match outcome {
+        Ok((task, previous_status, Some(update_result))) => guard.return_updated_task(
+            &task_id,
+            task,
+            &device,
+            sequence,
+            previous_status,
+            update_result,
+        ),
+        Ok((task, _, None)) => Err(guard.return_panicked_task(&task_id, task, &device)),
+        Err(err) => {
+            error!(
+                task_id = %logging::log_id(task_id.as_bytes()),
+                device_id = %logging::log_id(&device),
+                %err,
+                "Task lost while being updated"
+            );
+            guard.abandon_checkout(&task_id);
+            Err(Error::FailedPrecondition("Task update failed".into()))
+        }
+    }
+}
+
+/// Apply a batch of task updates from `device`, one item after another
+///
+/// Work submitted for a different round or attempt than the one the task is in is rejected.
+/// Each item waits for a running update of its task and is then applied off the state lock
+/// like [`update_task_unlocked`].
+///
+/// # Returns
+/// Outcome of each submitted item, in the submission order
+pub async fn submit_device_work(
+    state: Arc<Mutex<State>>,
+    device: &[u8],
+    work: &[crate::proto::Work],
+) -> Vec<Result<bool, String>> {
+    let mut results = Vec::with_capacity(work.len());
+    for work in work {
+        results.push(submit_work_item(state.clone(), device, work).await);
+    }
+    results
+}
+
+async fn submit_work_item(
+    state: Arc<Mutex<State>>,
+    device: &[u8],
+    work: &crate::proto::Work,
+) -> Result<bool, String> {
+    let task_id = Uuid::from_slice(&work.task_id).map_err(|_| "Invalid task id".to_string())?;
+    lock_with_task(&state, &task_id)
+        .await
+        .check_submitted_work(&task_id, device, work)?;
+    update_task_unlocked(
+        state,
+        task_id,
+        device.to_vec(),
+        work.data.clone(),
+        work.attempt,
+        work.sequence,
+    )
+    .await
+    .map_err(|error| error.to_string())
+}
+
+/// Lock the state once `task_id` is not checked out for an update
+///
+/// Requests reading or changing a single task wait for its running update instead of
+/// seeing the stand-in of the task.
+pub async fn lock_with_task<'a>(state: &'a Mutex<State>, task_id: &Uuid) -> MutexGuard<'a, State> {
+    loop {
+        let guard = state.lock().await;
+        let Some(notify) = guard.pending_update(task_id) else {
+            return guard;
+        };
+        let finished = notify.notified();
+        drop(guard);
+        finished.await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::get_timestamp;
+    use meesign_crypto::proto::ClientMessage;
+
+    #[test]
+    fn duplicate_group_devices() {
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 2);
+        let result = state.add_group_task(
+            "Sample Group",
+            &[devices[0].clone(), devices[0].clone(), devices[1].clone()],
+            2,
+            ProtocolType::Gg18,
+            KeyType::SignChallenge,
+            &None,
+            &[],
+            None,
+        );
+        assert!(
+            matches!(result, Err(Error::InvalidArgument(message)) if message.starts_with("Duplicate device"))
+        );
+        assert!(state.get_tasks().is_empty());
+    }
+
+    #[test]
+    fn distinct_group_devices() {
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 3);
+        let result = state.add_group_task(
+            "Sample Group",
+            &devices,
+            2,
+            ProtocolType::Gg18,
+            KeyType::SignChallenge,
+            &None,
+            &[],
+            None,
+        );
+        assert!(result.is_ok());
+        assert_eq!(state.get_tasks().len(), 1);
+    }
+
+    #[test]
+    fn threshold_policy() {
+        let mut state = State::new(Config {
+            min_threshold: Some(2),
+            ..Config::default()
+        });
+        let devices = prepare_devices(&mut state, 3);
+        let mut add_group_task = |threshold| {
+            state.add_group_task(
+                "Sample Group",
+                &devices,
+                threshold,
+                ProtocolType::Gg18,
+                KeyType::SignChallenge,
+                &None,
+                &[],
+                None,
+            )
+        };
+        assert!(matches!(add_group_task(1), Err(Error::InvalidArgument(_))));
+        assert!(add_group_task(2).is_ok());
+
+        let mut state = State::new(Config {
+            min_threshold_ratio: Some(0.75),
+            ..Config::default()
+        });
+        let devices = prepare_devices(&mut state, 4);
+        let mut add_group_task = |threshold| {
+            state.add_group_task(
+                "Sample Group",
+                &devices,
+                threshold,
+                ProtocolType::Gg18,
+                KeyType::SignChallenge,
+                &None,
+                &[],
+                None,
+            )
+        };
+        assert!(matches!(add_group_task(2), Err(Error::InvalidArgument(_))));
+        assert!(add_group_task(3).is_ok());
+    }
+
+    #[test]
+    fn group_size_policy() {
+        let mut state = State::new(Config {
+            max_group_size: 3,
+            ..Config::default()
+        });
+        let devices = prepare_devices(&mut state, 4);
+        let mut add_group_task = |members| {
+            state.add_group_task(
+                "Sample Group",
+                &devices[..members],
+                2,
+                ProtocolType::Gg18,
+                KeyType::SignChallenge,
+                &None,
+                &[],
+                None,
+            )
+        };
+        assert!(matches!(add_group_task(4), Err(Error::InvalidArgument(_))));
+        assert!(add_group_task(3).is_ok());
+    }
+
+    #[test]
+    fn protocol_key_type_compatibility() {
+        let compatible = [
+            (ProtocolType::Gg18, KeyType::SignPdf),
+            (ProtocolType::Gg18, KeyType::SignChallenge),
+            (ProtocolType::Frost, KeyType::SignChallenge),
+            (ProtocolType::Musig2, KeyType::SignChallenge),
+            (ProtocolType::Elgamal, KeyType::Decrypt),
+        ];
+        for protocol in [
+            ProtocolType::Gg18,
+            ProtocolType::Elgamal,
+            ProtocolType::Frost,
+            ProtocolType::Musig2,
+        ] {
+            for key_type in [KeyType::SignPdf, KeyType::SignChallenge, KeyType::Decrypt] {
+                let mut state = State::new(Config::default());
+                let devices = prepare_devices(&mut state, 2);
+                let result = state.add_group_task(
+                    "Sample Group",
+                    &devices,
+                    2,
+                    protocol,
+                    key_type,
+                    &None,
+                    &[],
+                    None,
+                );
+                if compatible.contains(&(protocol, key_type)) {
+                    assert!(result.is_ok(), "{:?} {:?}", protocol, key_type);
+                } else {
+                    assert!(
+                        matches!(result, Err(Error::InvalidArgument(_))),
+                        "{:?} {:?}",
+                        protocol,
+                        key_type
+                    );
+                    assert!(state.get_tasks().is_empty());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn participant_disconnect_fails_task() {
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 2);
+        let task_id = prepare_running_sign_task(&mut state, &devices, 2);
+
+        disconnect(&mut state, &devices[0]);
+
+        assert_eq!(
+            state.get_task(&task_id).unwrap().get_status(),
+            TaskStatus::Failed(
+                FailureReason::ParticipantDisconnected,
+                "Task failed (participant disconnected)".into()
+            )
+        );
+    }
+
+    #[test]
+    fn participant_disconnect_wait_policy() {
+        let mut state = State::new(Config {
+            disconnect_policy: DisconnectPolicy::Wait,
+            ..Config::default()
+        });
+        let devices = prepare_devices(&mut state, 2);
+        let task_id = prepare_running_sign_task(&mut state, &devices, 2);
+
+        disconnect(&mut state, &devices[0]);
+
+        assert_eq!(
+            state.get_task(&task_id).unwrap().get_status(),
+            TaskStatus::Running(1)
+        );
+    }
+
+    #[test]
+    fn participant_disconnect_reselects_signer() {
+        let mut state = State::new(Config {
+            disconnect_policy: DisconnectPolicy::Reselect,
+            ..Config::default()
+        });
+        let devices = prepare_devices(&mut state, 3);
+        let task_id = prepare_running_sign_task(&mut state, &devices, 2);
+
+        let task = state.get_task(&task_id).unwrap();
+        let selected = devices
+            .iter()
+            .find(|device| task.waiting_for(device))
+            .unwrap()
+            .clone();
+        disconnect(&mut state, &selected);
+
+        let task = state.get_task(&task_id).unwrap();
+        assert_eq!(task.get_status(), TaskStatus::Running(1));
+        assert_eq!(task.get_attempts(), 1);
+        for device in &devices {
+            assert_eq!(task.waiting_for(device), device != &selected);
+        }
+    }
+
+    #[test]
+    fn participant_disconnect_reselect_below_threshold() {
+        let mut state = State::new(Config {
+            disconnect_policy: DisconnectPolicy::Reselect,
+            ..Config::default()
+        });
+        let devices = prepare_devices(&mut state, 2);
+        let task_id = prepare_running_sign_task(&mut state, &devices, 2);
+
+        disconnect(&mut state, &devices[1]);
+
+        assert_eq!(
+            state.get_task(&task_id).unwrap().get_status(),
+            TaskStatus::Failed(
+                FailureReason::ParticipantDisconnected,
+                "Task failed (participant disconnected)".into()
+            )
+        );
+    }
+
+    #[test]
+    fn sign_decline_recruits_remaining_member() {
+        let mut state = State::new(Config {
+            recruit_signers: true,
+            ..Config::default()
+        });
+        let devices = prepare_devices(&mut state, 3);
+        let _receivers: Vec<_> = devices
+            .iter()
+            .map(|device| {
+                let (tx, rx) = tokio::sync::mpsc::channel(64);
+                state.add_subscriber(device.clone(), tx);
+                rx
+            })
+            .collect();
+        let group_id = prepare_group(
+            &mut state,
+            &devices,
+            2,
+            ProtocolType::Gg18,
+            KeyType::SignChallenge,
+        );
+        let task_id = state
+            .add_sign_task(
+                &group_id,
+                "Sample Task",
+                &[0x01, 0x02],
+                None,
+                HashAlgorithm::Default,
+            )
+            .unwrap();
+
+        let task = state.get_task(&task_id).unwrap();
+        let (asked, reserve): (Vec<_>, Vec<_>) = devices
+            .iter()
+            .cloned()
+            .partition(|device| task.waiting_for(device));
+        assert_eq!(asked.len(), 2);
+        assert_eq!(
+            task.participant_status(&reserve[0]),
+            ParticipantStatus::Idle
+        );
+        assert!(!state.decide_task(&task_id, &reserve[0], true));
+
+        state.decide_task(&task_id, &asked[0], true);
+        assert!(state.decide_task(&task_id, &asked[1], false));
+        let task = state.get_task(&task_id).unwrap();
+        assert_eq!(task.get_status(), TaskStatus::Created);
+        assert!(task.waiting_for(&reserve[0]));
+        state.decide_task(&task_id, &reserve[0], true);
+
+        let task = state.get_task(&task_id).unwrap();
+        assert_eq!(task.get_status(), TaskStatus::Running(1));
+        assert!(task.waiting_for(&asked[0]));
+        assert!(!task.waiting_for(&asked[1]));
+        assert!(task.waiting_for(&reserve[0]));
+    }
+
+    #[test]
+    fn pdf_sign_decline_recruits_remaining_member() {
+        let mut state = State::new(Config {
+            recruit_signers: true,
+            ..Config::default()
+        });
+        let devices = prepare_devices(&mut state, 3);
+        let _receivers: Vec<_> = devices
+            .iter()
+            .map(|device| {
+                let (tx, rx) = tokio::sync::mpsc::channel(64);
+                state.add_subscriber(device.clone(), tx);
+                rx
+            })
+            .collect();
+        let group_id = prepare_group(
+            &mut state,
+            &devices,
+            2,
+            ProtocolType::Gg18,
+            KeyType::SignPdf,
+        );
+        let task_id = state
+            .add_sign_task(
+                &group_id,
+                "Sample Task",
+                &[0x01, 0x02],
+                None,
+                HashAlgorithm::Default,
+            )
+            .unwrap();
+
+        let task = state.get_task(&task_id).unwrap();
+        let (asked, reserve): (Vec<_>, Vec<_>) = devices
+            .iter()
+            .cloned()
+            .partition(|device| task.waiting_for(device));
+        assert_eq!(asked.len(), 2);
+
+        state.decide_task(&task_id, &asked[0], false);
+        let task = state.get_task(&task_id).unwrap();
+        assert_eq!(task.get_status(), TaskStatus::Created);
+        assert!(task.waiting_for(&reserve[0]));
+    }
+
+    #[test]
+    fn sign_decline_fails_without_online_member() {
+        let mut state = State::new(Config {
+            recruit_signers: true,
+            ..Config::default()
+        });
+        let devices = prepare_devices(&mut state, 3);
+        let _receivers: Vec<_> = devices[..2]
+            .iter()
+            .map(|device| {
+                let (tx, rx) = tokio::sync::mpsc::channel(64);
+                state.add_subscriber(device.clone(), tx);
+                rx
+            })
+            .collect();
+        let group_id = prepare_group(
+            &mut state,
+            &devices,
+            2,
+            ProtocolType::Gg18,
+            KeyType::SignChallenge,
+        );
+        let task_id = state
+            .add_sign_task(
+                &group_id,
+                "Sample Task",
+                &[0x01, 0x02],
+                None,
+                HashAlgorithm::Default,
+            )
+            .unwrap();
+        assert!(!state.get_task(&task_id).unwrap().waiting_for(&devices[2]));
+
+        state.decide_task(&task_id, &devices[0], false);
+
+        assert_eq!(
+            state.get_task(&task_id).unwrap().get_status(),
+            TaskStatus::Failed(
+                FailureReason::Declined,
+                "Task declined (no members left to recruit)".into()
+            )
+        );
+    }
+
+    #[test]
+    fn active_task_limit() {
+        let mut state = State::new(Config {
+            max_active_tasks: 2,
+            ..Config::default()
+        });
+        let devices = prepare_devices(&mut state, 2);
+        let group_id = prepare_group(
+            &mut state,
+            &devices,
+            2,
+            ProtocolType::Gg18,
+            KeyType::SignChallenge,
+        );
+        let first = state
+            .add_sign_task(&group_id, "First", &[0x01], None, HashAlgorithm::Default)
+            .unwrap();
+        state
+            .add_sign_task(&group_id, "Second", &[0x02], None, HashAlgorithm::Default)
+            .unwrap();
+
+        let result = state.add_sign_task(&group_id, "Third", &[0x03], None, HashAlgorithm::Default);
+        assert!(matches!(result, Err(Error::ResourceExhausted(_))));
+        let result = state.add_group_task(
+            "Sample Group",
+            &devices,
+            2,
+            ProtocolType::Gg18,
+            KeyType::SignChallenge,
+            &None,
+            &[],
+            None,
+        );
+        assert!(matches!(result, Err(Error::ResourceExhausted(_))));
+        assert_eq!(state.get_tasks().len(), 2);
+
+        state.decide_task(&first, &devices[0], false);
+        assert_eq!(
+            state.get_task(&first).unwrap().get_status(),
+            TaskStatus::Failed(FailureReason::Declined, "Task declined".into())
+        );
+        assert!(state
+            .add_sign_task(&group_id, "Third", &[0x03], None, HashAlgorithm::Default)
+            .is_ok());
+    }
+
+    #[test]
+    fn requester_deadline_exceeded() {
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 2);
+        let group_id = prepare_group(
+            &mut state,
+            &devices,
+            2,
+            ProtocolType::Gg18,
+            KeyType::SignChallenge,
+        );
+        let task_id = state
+            .add_sign_task(
+                &group_id,
+                "Sample Task",
+                &[0x01, 0x02],
+                Some(5),
+                HashAlgorithm::Default,
+            )
+            .unwrap();
+        state.decide_task(&task_id, &devices[0], true);
+
+        state.fail_overdue_tasks(get_timestamp());
+        assert_eq!(
+            state.get_task(&task_id).unwrap().get_status(),
+            TaskStatus::Created
+        );
+        state.fail_overdue_tasks(get_timestamp() + 5);
+        assert_eq!(
+            state.get_task(&task_id).unwrap().get_status(),
+            TaskStatus::Failed(
+                FailureReason::Timeout,
+                "Task failed (requester deadline exceeded)".into()
+            )
+        );
+    }
+
+    #[test]
+    fn approval_timeout_exceeded() {
+        let mut state = State::new(Config {
+            approval_timeout: Some(std::time::Duration::from_secs(10 * 60)),
+            ..Config::default()
+        });
+        let devices = prepare_devices(&mut state, 2);
+        let group_id = prepare_group(
+            &mut state,
+            &devices,
+            2,
+            ProtocolType::Gg18,
+            KeyType::SignChallenge,
+        );
+        let task_id = state
+            .add_sign_task(
+                &group_id,
+                "Sample Task",
+                &[0x01, 0x02],
+                None,
+                HashAlgorithm::Default,
+            )
+            .unwrap();
+        state.decide_task(&task_id, &devices[0], true);
+        let created_at = state.get_task(&task_id).unwrap().created_at();
+
+        // longer than a protocol round, but still within the approval timeout
+        state.fail_overdue_tasks(created_at + 60);
+        assert_eq!(
+            state.get_task(&task_id).unwrap().get_status(),
+            TaskStatus::Created
+        );
+        state.fail_overdue_tasks(created_at + 10 * 60);
+        assert_eq!(
+            state.get_task(&task_id).unwrap().get_status(),
+            TaskStatus::Failed(
+                FailureReason::ApprovalTimeout,
+                "Task failed (approval timed out)".into()
+            )
+        );
+    }
+
+    #[test]
+    fn failure_reason_codes() {
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 2);
+        let group_id = prepare_group(
+            &mut state,
+            &devices,
+            2,
+            ProtocolType::Gg18,
+            KeyType::SignChallenge,
+        );
+        let declined = state
+            .add_sign_task(
+                &group_id,
+                "Declined Task",
+                &[0x01],
+                None,
+                HashAlgorithm::Default,
+            )
+            .unwrap();
+        let timed_out = state
+            .add_sign_task(
+                &group_id,
+                "Timed Out Task",
+                &[0x02],
+                Some(1),
+                HashAlgorithm::Default,
+            )
+            .unwrap();
+        state.decide_task(&declined, &devices[1], false);
+        state.fail_overdue_tasks(get_timestamp() + 1);
+
+        for (task_id, reason) in [
+            (declined, crate::proto::task::FailureReason::Declined),
+            (timed_out, crate::proto::task::FailureReason::Timeout),
+        ] {
+            let task =
+                format_task(&task_id, state.get_task(&task_id).unwrap(), None, None).unwrap();
+            assert_eq!(task.state, crate::proto::task::TaskState::Failed as i32);
+            assert_eq!(task.failure_reason, Some(reason as i32));
+        }
+    }
+
+    #[test]
+    fn restart_limit_fails_task() {
+        let mut state = State::new(Config {
+            max_restarts: Some(1),
+            ..Config::default()
+        });
+        let devices = prepare_devices(&mut state, 2);
+        let task_id = prepare_running_sign_task(&mut state, &devices, 2);
+
+        assert!(state.restart_task(&task_id));
+        assert!(!state.restart_task(&task_id));
+
+        let task = state.get_task(&task_id).unwrap();
+        assert_eq!(task.get_attempts(), 1);
+        assert_eq!(
+            task.get_status(),
+            TaskStatus::Failed(
+                FailureReason::RestartLimitExceeded,
+                "Task failed (restart limit exceeded)".into()
+            )
+        );
+    }
+
+    #[test]
+    fn stalled_keygen_names_devices() {
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 3);
+        let task_id = state
+            .add_group_task(
+                "Sample Group",
+                &devices,
+                2,
+                ProtocolType::Gg18,
+                KeyType::SignChallenge,
+                &None,
+                &[],
+                None,
+            )
+            .unwrap();
+        for device in &devices {
+            state.decide_task(&task_id, device, true);
+        }
+        assert_eq!(
+            state.get_task(&task_id).unwrap().get_status(),
+            TaskStatus::Running(1)
+        );
+        let message = ClientMessage {
+            protocol_type: ProtocolType::Gg18.into(),
+            unicasts: HashMap::new(),
+            broadcast: Some(vec![]),
+        }
+        .encode_to_vec();
+        for device in &devices[..2] {
+            assert_eq!(
+                state.update_task(&task_id, device, &vec![message.clone()], 0, None),
+                Ok(false)
+            );
+        }
+
+        let task = format_task(&task_id, state.get_task(&task_id).unwrap(), None, None).unwrap();
+        assert_eq!(task.waiting_on, vec![devices[2].clone()]);
+
+        assert!(state.fail_stalled_keygen(&task_id));
+        assert_eq!(
+            state.get_task(&task_id).unwrap().get_status(),
+            TaskStatus::Failed(
+                FailureReason::Timeout,
+                "Task failed (devices did not complete keygen: 02)".into()
+            )
+        );
+    }
+
+    #[test]
+    fn committed_device_stalling_recorded() {
+        let mut state = State::new(Config {
+            require_commitments: true,
+            ..Config::default()
+        });
+        let devices = prepare_devices(&mut state, 3);
+        let group_id = prepare_group(
+            &mut state,
+            &devices,
+            2,
+            ProtocolType::Gg18,
+            KeyType::SignChallenge,
+        );
+        let task_id = state
+            .add_sign_task(
+                &group_id,
+                "Sample Task",
+                &[0x01],
+                None,
+                HashAlgorithm::Default,
+            )
+            .unwrap();
+        for device in &devices[..2] {
+            state.decide_task(&task_id, device, true);
+        }
+        assert!(matches!(
+            state.commit_task(&task_id, &devices[2]),
+            Err(Error::FailedPrecondition(_))
+        ));
+        state.commit_task(&task_id, &devices[0]).unwrap();
+        assert_eq!(
+            state.get_task(&task_id).unwrap().get_status(),
+            TaskStatus::Created
+        );
+        state.commit_task(&task_id, &devices[1]).unwrap();
+        assert_eq!(
+            state.get_task(&task_id).unwrap().get_status(),
+            TaskStatus::Running(1)
+        );
+
+        let message = ClientMessage {
+            protocol_type: ProtocolType::Gg18.into(),
+            unicasts: HashMap::new(),
+            broadcast: Some(vec![]),
+        }
+        .encode_to_vec();
+        assert_eq!(
+            state.update_task(&task_id, &devices[0], &vec![message], 0, None),
+            Ok(false)
+        );
+        state.record_commitment_violations(&task_id);
+        let violations = &state.commitment_violations;
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].task_id, task_id);
+        assert_eq!(violations[0].device_id, devices[1]);
+        assert_eq!(violations[0].round, 1);
+    }
+
+    #[test]
+    fn repeated_failures_alerted() {
+        struct CollectingSink(std::sync::Mutex<Vec<FailureAlert>>);
+        impl AlertSink for CollectingSink {
+            fn group_failures(&self, alert: &FailureAlert) {
+                self.0.lock().unwrap().push(alert.clone());
+            }
+        }
+
+        let mut state = State::new(Config {
+            alert_threshold: Some(3),
+            alert_window: std::time::Duration::from_secs(60),
+            ..Config::default()
+        });
+        let sink = Arc::new(CollectingSink(std::sync::Mutex::new(Vec::new())));
+        state.set_alert_sink(sink.clone());
+        let devices = prepare_devices(&mut state, 2);
+        let group_id = prepare_group(
+            &mut state,
+            &devices,
+            2,
+            ProtocolType::Gg18,
+            KeyType::SignChallenge,
+        );
+
+        let mut declined = Vec::new();
+        for _ in 0..3 {
+            assert!(sink.0.lock().unwrap().is_empty());
+            let task_id = state
+                .add_sign_task(
+                    &group_id,
+                    "Sample Task",
+                    &[0x01],
+                    None,
+                    HashAlgorithm::Default,
+                )
+                .unwrap();
+            state.decide_task(&task_id, &devices[0], false);
+            declined.push(task_id);
+        }
+
+        let alerts = sink.0.lock().unwrap();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].group_id, group_id);
+        assert_eq!(alerts[0].failures, 3);
+        assert_eq!(alerts[0].task_id, declined[2]);
+        assert_eq!(alerts[0].reason, FailureReason::Declined);
+    }
+
+    #[test]
+    fn cancel_created_group_task() {
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 3);
+        let task_id = state
+            .add_group_task(
+                "Sample Group",
+                &devices,
+                2,
+                ProtocolType::Gg18,
+                KeyType::SignChallenge,
+                &None,
+                &[],
+                None,
+            )
+            .unwrap();
+        state.decide_task(&task_id, &devices[0], true);
+        let (tx, mut rx) = tokio::sync::mpsc::channel(8);
+        state.add_subscriber(devices[1].clone(), tx);
+
+        state.cancel_group_task(&task_id).unwrap();
+        assert!(state.get_task(&task_id).is_none());
+        for device in &devices {
+            assert!(state.get_device_tasks(device).is_empty());
+        }
+        let update = rx.try_recv().unwrap().unwrap();
+        assert_eq!(update.id, task_id.as_bytes());
+        assert_eq!(update.state, crate::proto::task::TaskState::Failed as i32);
+        assert!(matches!(
+            state.cancel_group_task(&task_id),
+            Err(Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn group_quota_freed_by_cancellation() {
+        let mut quotas = crate::config::Quotas::default();
+        quotas.requesters.insert(
+            "alice".into(),
+            crate::config::Quota {
+                max_groups: Some(1),
+                ..Default::default()
+            },
+        );
+        let mut state = State::new(Config {
+            quotas,
+            ..Config::default()
+        });
+        let devices = prepare_devices(&mut state, 2);
+        let create_group = |state: &mut State| {
+            state.check_quota(Some("alice"), QuotaResource::Groups, 1)?;
+            let task_id = state.add_group_task(
+                "Sample Group",
+                &devices,
+                2,
+                ProtocolType::Gg18,
+                KeyType::SignChallenge,
+                &None,
+                &[],
+                None,
+            )?;
+            state.set_task_requester(&task_id, Some("alice".into()));
+            Ok::<_, Error>(task_id)
+        };
+
+        let task_id = create_group(&mut state).unwrap();
+        assert!(matches!(
+            create_group(&mut state),
+            Err(Error::ResourceExhausted(_))
+        ));
+        assert!(state
+            .check_quota(Some("bob"), QuotaResource::Groups, 1)
+            .is_ok());
+
+        state.cancel_group_task(&task_id).unwrap();
+        assert!(create_group(&mut state).is_ok());
+    }
+
+    #[test]
+    fn requested_task_attributed_and_limited() {
+        let mut quotas = crate::config::Quotas::default();
+        quotas.requesters.insert(
+            "alice".into(),
+            crate::config::Quota {
+                max_active_tasks: Some(1),
+                ..Default::default()
+            },
+        );
+        let mut state = State::new(Config {
+            quotas,
+            ..Config::default()
+        });
+        let devices = prepare_devices(&mut state, 2);
+        let group_id = prepare_group(
+            &mut state,
+            &devices,
+            2,
+            ProtocolType::Gg18,
+            KeyType::SignChallenge,
+        );
+        let create = |state: &mut State| {
+            state.add_sign_task(
+                &group_id,
+                "Sample Task",
+                &[0x01],
+                None,
+                HashAlgorithm::Default,
+            )
+        };
+
+        let task_id = state
+            .add_requested_task(Some("alice".into()), Some(5), create)
+            .unwrap();
+        let task = state.get_task(&task_id).unwrap();
+        assert_eq!(task.requester(), Some("alice"));
+        assert_eq!(task.priority(), 5);
+        assert_eq!(state.audit_trail.len(), 1);
+
+        assert!(matches!(
+            state.add_requested_task(Some("alice".into()), None, create),
+            Err(Error::ResourceExhausted(_))
+        ));
+        assert_eq!(state.get_tasks().len(), 1);
+        assert_eq!(state.audit_trail.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn slow_update_does_not_block_state() {
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 2);
+        let task_id = add_slow_task(&mut state, &devices, 500, false);
+        let state = Arc::new(Mutex::new(state));
+
+        let update = tokio::spawn(update_task_unlocked(
+            state.clone(),
+            task_id,
+            devices[0].clone(),
+            vec![vec![0x01]],
+            0,
+            None,
+        ));
+        wait_for_checkout(&state, &task_id).await;
+
+        let query = async { state.lock().await.get_device_tasks(&devices[1]).len() };
+        let tasks = tokio::time::timeout(std::time::Duration::from_millis(100), query)
+            .await
+            .expect("state stayed locked during the update");
+        assert_eq!(tasks, 1);
+        assert_eq!(
+            state
+                .lock()
+                .await
+                .update_task(&task_id, &devices[1], &vec![vec![0x01]], 0, None),
+            Err(Error::FailedPrecondition(
+                "Another update of the task is being processed, try again".into()
+            ))
+        );
+        assert_eq!(
+            state
+                .lock()
+                .await
+                .force_task_state(&task_id, TargetState::Failed),
+            Err(Error::FailedPrecondition(
+                "Task is being updated, try again".into()
+            ))
+        );
+
+        let guard = lock_with_task(&state, &task_id).await;
+        assert!(guard.get_task(&task_id).is_some());
+        drop(guard);
+        assert_eq!(update.await.unwrap(), Ok(false));
+    }
+
+    #[tokio::test]
+    async fn dropped_update_request_returns_task() {
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 2);
+        let task_id = add_slow_task(&mut state, &devices, 200, false);
+        let state = Arc::new(Mutex::new(state));
+
+        let update = tokio::spawn(update_task_unlocked(
+            state.clone(),
+            task_id,
+            devices[0].clone(),
+            vec![vec![0x01]],
+            0,
+            None,
+        ));
+        wait_for_checkout(&state, &task_id).await;
+        update.abort();
+
+        let guard = tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            lock_with_task(&state, &task_id),
+        )
+        .await
+        .expect("task was never returned");
+        assert_eq!(
+            guard.get_task(&task_id).unwrap().get_status(),
+            TaskStatus::Running(1)
+        );
+    }
+
+    #[tokio::test]
+    async fn panicking_update_fails_task() {
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 2);
+        let task_id = add_slow_task(&mut state, &devices, 0, true);
+        let state = Arc::new(Mutex::new(state));
+
+        let result = update_task_unlocked(
+            state.clone(),
+            task_id,
+            devices[0].clone(),
+            vec![vec![0x01]],
+            0,
+            None,
+        )
+        .await;
+        assert_eq!(
+            result,
+            Err(Error::FailedPrecondition("Task update failed".into()))
+        );
+        let guard = lock_with_task(&state, &task_id).await;
+        assert!(matches!(
+            guard.get_task(&task_id).unwrap().get_status(),
+            TaskStatus::Failed(FailureReason::ProtocolError, _)
+        ));
+    }
+
+    #[tokio::test]
+    async fn disconnect_during_update_handled_after_return() {
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 2);
+        let task_id = add_slow_task(&mut state, &devices, 200, false);
+        let state = Arc::new(Mutex::new(state));
+
+        let update = tokio::spawn(update_task_unlocked(
+            state.clone(),
+            task_id,
+            devices[0].clone(),
+            vec![vec![0x01]],
+            0,
+            None,
+        ));
+        wait_for_checkout(&state, &task_id).await;
+        {
+            let mut guard = state.lock().await;
+            disconnect(&mut guard, &devices[1]);
+            guard.fail_overdue_tasks(u64::MAX);
+            assert_eq!(
+                guard.get_task(&task_id).unwrap().get_status(),
+                TaskStatus::Running(1)
+            );
+        }
+
+        assert_eq!(update.await.unwrap(), Ok(false));
+        assert!(matches!(
+            state.lock().await.get_task(&task_id).unwrap().get_status(),
+            TaskStatus::Failed(FailureReason::ParticipantDisconnected, _)
+        ));
+    }
+
+    #[test]
+    fn ethereum_only_group_rejects_raw_signing() {
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 2);
+        let group_id = prepare_group(
+            &mut state,
+            &devices,
+            2,
+            ProtocolType::Gg18,
+            KeyType::SignChallenge,
+        );
+        assert!(matches!(
+            state.set_group_usage(&group_id, &[TaskType::Group]),
+            Err(Error::InvalidArgument(_))
+        ));
+        let group = state
+            .set_group_usage(
+                &group_id,
+                &[TaskType::SignEthereumTx, TaskType::SignEthereumTx],
+            )
+            .unwrap();
+        assert_eq!(group.allowed_tasks(), &[TaskType::SignEthereumTx]);
+
+        let result = state.add_sign_task(
+            &group_id,
+            "Sample Task",
+            &[0x01],
+            None,
+            HashAlgorithm::Default,
+        );
+        assert_eq!(
+            result,
+            Err(Error::PermissionDenied(
+                "Group is not permitted to perform SIGN_CHALLENGE tasks".into()
+            ))
+        );
+        assert!(state.get_tasks().is_empty());
+
+        state.set_group_usage(&group_id, &[]).unwrap();
+        assert!(state
+            .add_sign_task(
+                &group_id,
+                "Sample Task",
+                &[0x01],
+                None,
+                HashAlgorithm::Default
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn participants_after_first_submission() {
+        use crate::proto::task_participant::Status;
+
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 3);
+        let task_id = prepare_running_sign_task(&mut state, &devices, 3);
+        let mut receivers = Vec::new();
+        for device in &devices[..2] {
+            let (tx, rx) = tokio::sync::mpsc::channel(8);
+            state.add_subscriber(device.clone(), tx);
+            receivers.push(rx);
+        }
+        let message = ClientMessage {
+            protocol_type: ProtocolType::Gg18.into(),
+            unicasts: HashMap::new(),
+            broadcast: Some(vec![]),
+        }
+        .encode_to_vec();
+        assert_eq!(
+            state.update_task(&task_id, &devices[0], &vec![message], 0, None),
+            Ok(false)
+        );
+
+        let statuses: Vec<_> = state
+            .get_task_participants(&task_id)
+            .unwrap()
+            .into_iter()
+            .map(|participant| (participant.device_id.clone(), participant.status()))
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![
+                (devices[0].clone(), Status::Submitted),
+                (devices[1].clone(), Status::Pending),
+                (devices[2].clone(), Status::Offline),
+            ]
+        );
+    }
+
+    #[test]
+    fn force_fail_waiting_task() {
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 2);
+        let task_id = prepare_running_sign_task(&mut state, &devices, 2);
+        let (tx, mut rx) = tokio::sync::mpsc::channel(8);
+        state.add_subscriber(devices[0].clone(), tx);
+
+        assert_eq!(
+            state.force_task_state(&task_id, TargetState::Failed),
+            Ok(TaskStatus::Running(1))
+        );
+        assert_eq!(
+            state.get_task(&task_id).unwrap().get_status(),
+            TaskStatus::Failed(
+                FailureReason::Cancelled,
+                "Task failed (cancelled by administrator)".into()
+            )
+        );
+        let update = rx.try_recv().unwrap().unwrap();
+        assert_eq!(update.state, crate::proto::task::TaskState::Failed as i32);
+        assert!(matches!(
+            state.force_task_state(&task_id, TargetState::Failed),
+            Err(Error::FailedPrecondition(_))
+        ));
+    }
+
+    #[test]
+    fn sign_estimate_from_history() {
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 3);
+        let group_id = prepare_group(
+            &mut state,
+            &devices,
+            2,
+            ProtocolType::Frost,
+            KeyType::SignChallenge,
+        );
+        let last_round =
+            crate::protocols::Protocol::last_round(&crate::protocols::frost::FROSTSign::new());
+
+        let estimate = state.estimate_sign(&group_id).unwrap();
+        assert_eq!(estimate.rounds, u32::from(last_round));
+        assert_eq!(estimate.messages, u32::from(last_round) * 2);
+        assert_eq!(estimate.duration, None);
+        assert_eq!(estimate.samples, 0);
+        assert!(matches!(
+            state.estimate_sign(&[0xbb; 4]),
+            Err(Error::NotFound(_))
+        ));
+
+        let task_id = state
+            .add_sign_task(
+                &group_id,
+                "Sample Task",
+                &[0x01],
+                None,
+                HashAlgorithm::Default,
+            )
+            .unwrap();
+        for device in &devices[..2] {
+            state.decide_task(&task_id, device, true);
+        }
+        let message = vec![ClientMessage {
+            protocol_type: ProtocolType::Frost.into(),
+            unicasts: HashMap::new(),
+            broadcast: Some(vec![0x01]),
+        }
+        .encode_to_vec()];
+        while state.get_task(&task_id).unwrap().get_status() != TaskStatus::Finished {
+            for device in &devices[..2] {
+                state
+                    .update_task(&task_id, device, &message, 0, None)
+                    .unwrap();
+            }
+        }
+
+        let estimate = state.estimate_sign(&group_id).unwrap();
+        assert_eq!(estimate.rounds, u32::from(last_round));
+        assert!(estimate.duration.is_some());
+        assert_eq!(estimate.samples, 1);
+    }
+
+    #[test]
+    fn reused_musig2_nonce_fails_task() {
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 2);
+        let group_id = prepare_group(
+            &mut state,
+            &devices,
+            2,
+            ProtocolType::Musig2,
+            KeyType::SignChallenge,
+        );
+        let commitment = |nonce: u8| {
+            vec![meesign_crypto::proto::ClientMessage {
+                protocol_type: meesign_crypto::proto::ProtocolType::Musig2.into(),
+                unicasts: HashMap::new(),
+                broadcast: Some(vec![nonce; 66]),
+            }
+            .encode_to_vec()]
+        };
+        let start_task = |state: &mut State| {
+            let task_id = state
+                .add_sign_task(
+                    &group_id,
+                    "Sample Task",
+                    &[0x01],
+                    None,
+                    HashAlgorithm::Default,
+                )
+                .unwrap();
+            for device in &devices {
+                state.decide_task(&task_id, device, true);
+            }
+            task_id
+        };
+
+        let first = start_task(&mut state);
+        state
+            .update_task(&first, &devices[0], &commitment(0x01), 0, None)
+            .unwrap();
+        state
+            .update_task(&first, &devices[1], &commitment(0x02), 0, None)
+            .unwrap();
+
+        let second = start_task(&mut state);
+        state
+            .update_task(&second, &devices[1], &commitment(0x03), 0, None)
+            .unwrap();
+        assert!(matches!(
+            state.update_task(&second, &devices[0], &commitment(0x01), 0, None),
+            Err(Error::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            state.get_task(&second).unwrap().get_status(),
+            TaskStatus::Failed(FailureReason::NonceReuse, _)
+        ));
+        assert_eq!(state.nonce_reuses.len(), 1);
+        assert_eq!(state.nonce_reuses[0].task_id, second);
+        assert_eq!(state.nonce_reuses[0].device_id, devices[0]);
+    }
+
+    #[test]
+    fn rejected_update_records_no_nonce_commitment() {
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 2);
+        let group_id = prepare_group(
+            &mut state,
+            &devices,
+            2,
+            ProtocolType::Musig2,
+            KeyType::SignChallenge,
+        );
+        let commitment = |nonce: u8| {
+            vec![meesign_crypto::proto::ClientMessage {
+                protocol_type: meesign_crypto::proto::ProtocolType::Musig2.into(),
+                unicasts: HashMap::new(),
+                broadcast: Some(vec![nonce; 66]),
+            }
+            .encode_to_vec()]
+        };
+        let start_task = |state: &mut State| {
+            let task_id = state
+                .add_sign_task(
+                    &group_id,
+                    "Sample Task",
+                    &[0x01],
+                    None,
+                    HashAlgorithm::Default,
+                )
+                .unwrap();
+            for device in &devices {
+                state.decide_task(&task_id, device, true);
+            }
+            task_id
+        };
+
+        let first = start_task(&mut state);
+        state
+            .update_task(&first, &devices[0], &commitment(0x01), 0, None)
+            .unwrap();
+        // the task is not waiting for another message of the device
+        assert!(state
+            .update_task(&first, &devices[0], &commitment(0x02), 0, None)
+            .is_err());
+
+        let second = start_task(&mut state);
+        state
+            .update_task(&second, &devices[0], &commitment(0x02), 0, None)
+            .unwrap();
+        assert!(state.nonce_reuses.is_empty());
+        assert_eq!(state.musig2_commitments[&group_id].sessions.len(), 2);
+    }
+
+    #[test]
+    fn nonce_commitment_history_is_bounded() {
+        let mut commitments = NonceCommitments::default();
+        let task_id = Uuid::new_v4();
+        for index in 0..MAX_NONCE_COMMITMENTS + 10 {
+            let digest: [u8; 32] = Sha256::digest(index.to_be_bytes()).into();
+            commitments.record(digest, (task_id, 0));
+        }
+        assert_eq!(commitments.sessions.len(), MAX_NONCE_COMMITMENTS);
+        assert_eq!(commitments.order.len(), MAX_NONCE_COMMITMENTS);
+        let oldest: [u8; 32] = Sha256::digest(0usize.to_be_bytes()).into();
+        let newest: [u8; 32] = Sha256::digest((MAX_NONCE_COMMITMENTS + 9).to_be_bytes()).into();
+        assert_eq!(commitments.session(&oldest), None);
+        assert_eq!(commitments.session(&newest), Some((task_id, 0)));
+    }
+
+    #[test]
+    fn device_groups_listed_in_stable_order() {
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 2);
+        for (identifier, name) in [
+            (vec![0x03], "Beta"),
+            (vec![0x01], "Gamma"),
+            (vec![0x04], "Alpha"),
+            (vec![0x02], "Beta"),
+        ] {
+            let group = Group::new(
+                identifier.clone(),
+                name.into(),
+                devices
+                    .iter()
+                    .map(|device| state.devices[device].clone())
+                    .collect(),
+                2,
+                ProtocolType::Gg18,
+                KeyType::SignChallenge,
+                None,
+                None,
+            );
+            state.groups.insert(identifier, group);
+        }
+
+        let listing = |state: &State| -> Vec<Vec<u8>> {
+            state
+                .get_device_groups(&devices[0])
+                .iter()
+                .map(|group| group.identifier().to_vec())
+                .collect()
+        };
+        let first = listing(&state);
+        assert_eq!(first, vec![vec![0x04], vec![0x02], vec![0x03], vec![0x01]]);
+        assert_eq!(listing(&state), first);
+    }
+
+    #[test]
+    fn pull_device_receives_no_pushed_updates() {
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 2);
+        state.set_delivery_mode(&devices[0], DeliveryMode::Pull);
+        let task_id = prepare_running_sign_task(&mut state, &devices, 2);
+        let mut receivers = Vec::new();
+        for device in &devices {
+            let (tx, rx) = tokio::sync::mpsc::channel(8);
+            state.add_subscriber(device.clone(), tx);
+            receivers.push(rx);
+        }
+
+        state
+            .force_task_state(&task_id, TargetState::Failed)
+            .unwrap();
+        assert!(receivers[0].try_recv().is_err());
+        assert!(receivers[1].try_recv().is_ok());
+        assert_eq!(state.delivery_mode(&devices[0]), DeliveryMode::Pull);
+        assert!(state
+            .get_device_tasks(&devices[0])
+            .iter()
+            .any(|(id, _)| *id == task_id));
+    }
+
+    #[test]
+    fn update_interleaved_with_restart() {
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 2);
+        let task_id = prepare_running_sign_task(&mut state, &devices, 2);
+        let message = vec![ClientMessage {
+            protocol_type: ProtocolType::Gg18.into(),
+            unicasts: HashMap::new(),
+            broadcast: Some(vec![]),
+        }
+        .encode_to_vec()];
+
+        assert_eq!(
+            state.update_task(&task_id, &devices[0], &message, 0, None),
+            Ok(false)
+        );
+        assert!(state.restart_task(&task_id));
+        assert_eq!(
+            state.update_task(&task_id, &devices[1], &message, 0, None),
+            Err(Error::FailedPrecondition(
+                "Stale update (task was restarted, current attempt is 1)".into()
+            ))
+        );
+        assert_eq!(
+            state.get_task(&task_id).unwrap().get_status(),
+            TaskStatus::Running(1)
+        );
+
+        assert_eq!(
+            state.update_task(&task_id, &devices[0], &message, 1, None),
+            Ok(false)
+        );
+        state
+            .force_task_state(&task_id, TargetState::Failed)
+            .unwrap();
+        assert_eq!(
+            state.update_task(&task_id, &devices[1], &message, 1, None),
+            Err(Error::FailedPrecondition(
+                "Task is no longer running".into()
+            ))
+        );
+        assert!(!state.restart_task(&task_id));
+        assert!(matches!(
+            state.get_task(&task_id).unwrap().get_status(),
+            TaskStatus::Failed(FailureReason::Cancelled, _)
+        ));
+        assert!(matches!(
+            state.update_task(&Uuid::new_v4(), &devices[0], &message, 0, None),
+            Err(Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn successful_signings_counted() {
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 2);
+        let message = vec![ClientMessage {
+            protocol_type: ProtocolType::Gg18.into(),
+            unicasts: HashMap::new(),
+            broadcast: Some(vec![0x01]),
+        }
+        .encode_to_vec()];
+        let group_id = prepare_group(
+            &mut state,
+            &devices,
+            2,
+            ProtocolType::Gg18,
+            KeyType::SignChallenge,
+        );
+        for expected in 1..=2 {
+            let task_id = state
+                .add_sign_task(
+                    &group_id,
+                    "Sample Task",
+                    &[0x01],
+                    None,
+                    HashAlgorithm::Default,
+                )
+                .unwrap();
+            for device in &devices {
+                state.decide_task(&task_id, device, true);
+            }
+            while state.get_task(&task_id).unwrap().get_status() != TaskStatus::Finished {
+                for device in &devices {
+                    state
+                        .update_task(&task_id, device, &message, 0, None)
+                        .unwrap();
+                }
+            }
+            let group = &state.get_groups()[&group_id];
+            assert_eq!(group.sign_count(), expected);
+            assert!(group.last_signed().is_some());
+        }
+    }
+
+    #[test]
+    fn withdraw_acceptance() {
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 3);
+        let group_id = prepare_group(
+            &mut state,
+            &devices,
+            2,
+            ProtocolType::Gg18,
+            KeyType::SignChallenge,
+        );
+        let task_id = state
+            .add_sign_task(
+                &group_id,
+                "Sample Task",
+                &[0x01],
+                None,
+                HashAlgorithm::Default,
+            )
+            .unwrap();
+
+        assert!(matches!(
+            state.withdraw_decision(&task_id, &devices[0]),
+            Err(Error::FailedPrecondition(_))
+        ));
+        state.decide_task(&task_id, &devices[0], true);
+        assert!(!state.get_task(&task_id).unwrap().waiting_for(&devices[0]));
+        assert_eq!(state.withdraw_decision(&task_id, &devices[0]), Ok(()));
+        let task = state.get_task(&task_id).unwrap();
+        assert!(task.waiting_for(&devices[0]));
+        assert_eq!(task.get_decisions(), (0, 0));
+        assert_eq!(task.get_status(), TaskStatus::Created);
+
+        state.decide_task(&task_id, &devices[0], true);
+        state.decide_task(&task_id, &devices[1], true);
+        assert_eq!(
+            state.get_task(&task_id).unwrap().get_status(),
+            TaskStatus::Running(1)
+        );
+        assert!(matches!(
+            state.withdraw_decision(&task_id, &devices[1]),
+            Err(Error::FailedPrecondition(_))
+        ));
+        assert!(matches!(
+            state.withdraw_decision(&Uuid::new_v4(), &devices[1]),
+            Err(Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn oversized_update_rejected() {
+        let mut state = State::new(Config {
+            max_message_size: 64,
+            ..Config::default()
+        });
+        let devices = prepare_devices(&mut state, 2);
+        let task_id = prepare_running_sign_task(&mut state, &devices, 2);
+        let message = vec![ClientMessage {
+            protocol_type: ProtocolType::Gg18.into(),
+            unicasts: HashMap::new(),
+            broadcast: Some(vec![0; 64]),
+        }
+        .encode_to_vec()];
+
+        for _ in 1..MAX_OVERSIZED_MESSAGES {
+            assert!(matches!(
+                state.update_task(&task_id, &devices[0], &message, 0, None),
+                Err(Error::InvalidArgument(_))
+            ));
+            let task = state.get_task(&task_id).unwrap();
+            assert_eq!(task.get_status(), TaskStatus::Running(1));
+            assert!(task.waiting_for(&devices[0]));
+        }
+        assert!(matches!(
+            state.update_task(&task_id, &devices[0], &message, 0, None),
+            Err(Error::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            state.get_task(&task_id).unwrap().get_status(),
+            TaskStatus::Failed(FailureReason::ProtocolError, _)
+        ));
+    }
+
+    #[test]
+    fn replayed_update_rejected() {
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 2);
+        let task_id = prepare_running_sign_task(&mut state, &devices, 2);
+        let message = vec![ClientMessage {
+            protocol_type: ProtocolType::Gg18.into(),
+            unicasts: HashMap::new(),
+            broadcast: Some(vec![0x01]),
+        }
+        .encode_to_vec()];
+
+        for device in &devices {
+            assert!(state
+                .update_task(&task_id, device, &message, 0, Some(1))
+                .is_ok());
+        }
+        assert_eq!(
+            state.get_task(&task_id).unwrap().get_status(),
+            TaskStatus::Running(2)
+        );
+        for sequence in [Some(1), Some(0), None] {
+            assert!(matches!(
+                state.update_task(&task_id, &devices[0], &message, 0, sequence),
+                Err(Error::InvalidArgument(_))
+            ));
+        }
+        assert!(state.get_task(&task_id).unwrap().waiting_for(&devices[0]));
+        assert_eq!(
+            state.update_task(&task_id, &devices[0], &message, 0, Some(2)),
+            Ok(false)
+        );
+    }
+
+    #[test]
+    fn failed_round_reported() {
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 2);
+        let task_id = prepare_running_sign_task(&mut state, &devices, 2);
+        let message = ClientMessage {
+            protocol_type: ProtocolType::Gg18.into(),
+            unicasts: HashMap::new(),
+            broadcast: Some(vec![]),
+        }
+        .encode_to_vec();
+        for device in &devices {
+            state
+                .update_task(&task_id, device, &vec![message.clone()], 0, None)
+                .unwrap();
+        }
+        assert_eq!(
+            state.get_task(&task_id).unwrap().get_status(),
+            TaskStatus::Running(2)
+        );
+        assert_eq!(state.get_task(&task_id).unwrap().failed_round(), None);
+
+        state
+            .force_task_state(&task_id, TargetState::Failed)
+            .unwrap();
+        let task = format_task(&task_id, state.get_task(&task_id).unwrap(), None, None).unwrap();
+        assert_eq!(task.state, crate::proto::task::TaskState::Failed as i32);
+        assert_eq!(task.failed_round, Some(2));
+    }
+
+    #[test]
+    fn malformed_message_fails_task() {
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 2);
+        let task_id = prepare_running_sign_task(&mut state, &devices, 2);
+        let (tx, mut rx) = tokio::sync::mpsc::channel(8);
+        state.add_subscriber(devices[1].clone(), tx);
+
+        // the message is addressed to a party which does not take part in the task
+        let message = ClientMessage {
+            protocol_type: ProtocolType::Gg18.into(),
+            unicasts: HashMap::from([(7, vec![])]),
+            broadcast: Some(vec![]),
+        }
+        .encode_to_vec();
+        assert!(state
+            .update_task(&task_id, &devices[0], &vec![message], 0, None)
+            .is_err());
+        assert!(matches!(
+            state.get_task(&task_id).unwrap().get_status(),
+            TaskStatus::Failed(FailureReason::ProtocolError, _)
+        ));
+        assert!(rx.try_recv().is_ok());
+    }
+
+    #[test]
+    fn register_devices_batch() {
+        let mut state = State::new(Config {
+            max_batch_size: 3,
+            ..Config::default()
+        });
+        let registration = |name: &str, certificate: &[u8]| DeviceRegistration {
+            name: name.into(),
+            kind: DeviceKind::User.into(),
+            certificate: certificate.to_vec(),
+            public_key: None,
+        };
+        state.add_device(&cert_to_id([1]), "existing", DeviceKind::User, &[1], None);
+
+        let outcomes = state
+            .register_devices(&[
+                registration("fresh", &[2]),
+                registration("existing", &[1]),
+                registration("in/valid", &[3]),
+            ])
+            .unwrap();
+        assert_eq!(
+            outcomes,
+            vec![
+                (cert_to_id([2]), RegistrationOutcome::Registered),
+                (cert_to_id([1]), RegistrationOutcome::AlreadyExists),
+                (cert_to_id([3]), RegistrationOutcome::Invalid),
+            ]
+        );
+        assert!(state.get_devices().contains_key(&cert_to_id([2])));
+        assert!(!state.get_devices().contains_key(&cert_to_id([3])));
+        assert_eq!(state.get_devices().len(), 2);
+
+        let result = state.register_devices(&vec![registration("device", &[4]); 4]);
+        assert!(matches!(result, Err(Error::InvalidArgument(_))));
+        assert_eq!(state.get_devices().len(), 2);
+    }
+
+    #[test]
+    fn snapshot_omits_sensitive_data() {
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 2);
+        let group_id = prepare_group(
+            &mut state,
+            &devices,
+            2,
+            ProtocolType::Gg18,
+            KeyType::SignChallenge,
+        );
+        let data = b"confidential payload";
+        let task_id = state
+            .add_sign_task(&group_id, "Sample Task", data, None, HashAlgorithm::Default)
+            .unwrap();
+        for device in &devices {
+            state.decide_task(&task_id, device, true);
+        }
+
+        let snapshot = state.snapshot();
+        let mut names: Vec<_> = snapshot.devices.iter().map(|x| x.name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, vec!["d0", "d1"]);
+        assert_eq!(snapshot.groups.len(), 1);
+        assert_eq!(snapshot.groups[0].id, hex::encode(&group_id));
+        assert_eq!(snapshot.groups[0].device_ids.len(), 2);
+        assert_eq!(snapshot.tasks.len(), 1);
+        assert_eq!(snapshot.tasks[0].id, hex::encode(task_id.as_bytes()));
+        assert_eq!(snapshot.tasks[0].state, "RUNNING");
+
+        let json = serde_json::to_value(&snapshot).unwrap();
+        let text = json.to_string();
+        assert!(!text.contains("confidential"));
+        assert!(!text.contains(&hex::encode(data)));
+        let keys = |value: &serde_json::Value| -> Vec<String> {
+            value.as_object().unwrap().keys().cloned().collect()
+        };
+        for device in json["devices"].as_array().unwrap() {
+            assert!(!keys(device).contains(&"certificate".to_string()));
+        }
+        for task in json["tasks"].as_array().unwrap() {
+            for field in ["data", "request", "result", "work"] {
+                assert!(!keys(task).contains(&field.to_string()));
+            }
+        }
+    }
+
+    #[test]
+    fn registration_allowlist() {
+        let mut state = State::new(Config {
+            device_allowlist: Some(HashSet::from([vec![0x01]])),
+            ..Config::default()
+        });
+        assert_eq!(
+            state.add_device(&[0x01], "allowed", DeviceKind::User, &[0xf1], None),
+            RegistrationOutcome::Registered
+        );
+        assert_eq!(
+            state.add_device(&[0x02], "unknown", DeviceKind::User, &[0xf2], None),
+            RegistrationOutcome::NotAllowed
+        );
+        assert!(!state.get_devices().contains_key([0x02].as_slice()));
+
+        let allowlist = state.update_allowlist(&[vec![0x02]], &[vec![0x01]], false);
+        assert_eq!(allowlist, Ok(Some(&HashSet::from([vec![0x02]]))));
+        assert_eq!(
+            state.add_device(&[0x02], "unknown", DeviceKind::User, &[0xf2], None),
+            RegistrationOutcome::Registered
+        );
+        assert_eq!(
+            state.add_device(&[0x03], "other", DeviceKind::User, &[0xf3], None),
+            RegistrationOutcome::NotAllowed
+        );
+
+        assert_eq!(state.update_allowlist(&[], &[], true), Ok(None));
+        assert_eq!(
+            state.add_device(&[0x03], "other", DeviceKind::User, &[0xf3], None),
+            RegistrationOutcome::Registered
+        );
+        assert_eq!(state.update_allowlist(&[], &[vec![0x03]], false), Ok(None));
+    }
+
+    #[test]
+    fn leave_all_groups() {
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 3);
+        let reshareable = prepare_group(
+            &mut state,
+            &devices,
+            2,
+            ProtocolType::Gg18,
+            KeyType::SignChallenge,
+        );
+        let below_threshold = vec![0xbb; 4];
+        state.groups.insert(
+            below_threshold.clone(),
+            Group::new(
+                below_threshold.clone(),
+                String::from("Pair Group"),
+                devices[..2]
+                    .iter()
+                    .map(|device| state.devices[device].clone())
+                    .collect(),
+                2,
+                ProtocolType::Gg18,
+                KeyType::SignChallenge,
+                None,
+                None,
+            ),
+        );
+
+        let mut outcomes = state.leave_all_groups(&devices[0]).unwrap();
+        outcomes.sort();
+        assert_eq!(
+            outcomes,
+            vec![
+                (reshareable.clone(), LeaveOutcome::ReshareRequired),
+                (below_threshold.clone(), LeaveOutcome::Degraded),
+            ]
+        );
+        for group_id in [&reshareable, &below_threshold] {
+            assert!(!state.get_groups()[group_id].contains(&devices[0]));
+        }
+        assert!(state.get_device_groups(&devices[0]).is_empty());
+        assert_eq!(state.get_groups()[&reshareable].devices().len(), 2);
+        assert!(!state.get_groups()[&reshareable].is_degraded());
+        assert!(state.get_groups()[&below_threshold].is_degraded());
+        assert_eq!(state.leave_all_groups(&[0xff]), Ok(Vec::new()));
+
+        let task_id = state
+            .add_sign_task(&reshareable, "Sign", &[0x01], None, HashAlgorithm::Default)
+            .unwrap();
+        assert!(!state.get_task(&task_id).unwrap().has_device(&devices[0]));
+        assert_eq!(
+            state.leave_all_groups(&devices[1]),
+            Err(Error::FailedPrecondition(
+                "Device participates in unfinished tasks".into()
+            ))
+        );
+    }
+
+    #[tokio::test]
+    async fn batched_work() {
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 2);
+        let first = prepare_running_sign_task(&mut state, &devices, 2);
+        let second = prepare_running_sign_task(&mut state, &devices, 2);
+
+        let work = state.get_device_work(&devices[0]);
+        assert_eq!(work.len(), 2);
+        let mut task_ids: Vec<_> = work
+            .iter()
+            .map(|work| Uuid::from_slice(&work.task_id).unwrap())
+            .collect();
+        task_ids.sort();
+        let mut expected = vec![first, second];
+        expected.sort();
+        assert_eq!(task_ids, expected);
+        assert!(work.iter().all(|work| work.round == 1 && work.attempt == 0));
+
+        let message = ClientMessage {
+            protocol_type: ProtocolType::Gg18.into(),
+            unicasts: HashMap::new(),
+            broadcast: Some(vec![]),
+        }
+        .encode_to_vec();
+        let mut submission: Vec<_> = work
+            .iter()
+            .map(|work| crate::proto::Work {
+                data: vec![message.clone()],
+                ..work.clone()
+            })
+            .collect();
+        submission.push(crate::proto::Work {
+            round: 2,
+            ..submission[0].clone()
+        });
+        let state = Arc::new(Mutex::new(state));
+        assert_eq!(
+            submit_device_work(state.clone(), &devices[0], &submission).await,
+            vec![Ok(false), Ok(false), Err("Stale round".to_string())]
+        );
+        assert!(state.lock().await.get_device_work(&devices[0]).is_empty());
+        assert_eq!(
+            submit_device_work(state.clone(), &devices[1], &submission[..2]).await,
+            vec![Ok(true), Ok(true)]
+        );
+        let state = state.lock().await;
+        for task_id in [first, second] {
+            assert_eq!(
+                state.get_task(&task_id).unwrap().get_status(),
+                TaskStatus::Running(2)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn batched_slow_work_does_not_block_state() {
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 2);
+        let task_id = add_slow_task(&mut state, &devices, 500, false);
+        let state = Arc::new(Mutex::new(state));
+        let submission = vec![crate::proto::Work {
+            task_id: task_id.as_bytes().to_vec(),
+            round: 1,
+            attempt: 0,
+            data: vec![vec![0x01]],
+            version: WORK_VERSION.into(),
+            sequence: None,
+        }];
+
+        let batch = tokio::spawn({
+            let state = state.clone();
+            let device = devices[0].clone();
+            async move { submit_device_work(state, &device, &submission).await }
+        });
+        wait_for_checkout(&state, &task_id).await;
+
+        let query = async { state.lock().await.get_device_tasks(&devices[1]).len() };
+        let tasks = tokio::time::timeout(std::time::Duration::from_millis(100), query)
+            .await
+            .expect("state stayed locked during the batch");
+        assert_eq!(tasks, 1);
+        assert_eq!(batch.await.unwrap(), vec![Ok(false)]);
+    }
+
+    #[test]
+    fn auto_approved_task_starts_without_decisions() {
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 3);
+        let group_id = prepare_group(
+            &mut state,
+            &devices,
+            2,
+            ProtocolType::Gg18,
+            KeyType::SignChallenge,
+        );
+        state
+            .groups
+            .get_mut(&group_id)
+            .unwrap()
+            .set_auto_approve(true);
+        let (tx, mut rx) = tokio::sync::mpsc::channel(8);
+        state.add_subscriber(devices[0].clone(), tx);
+
+        let task_id = state
+            .add_sign_task(
+                &group_id,
+                "Sample Task",
+                &[0x01],
+                None,
+                HashAlgorithm::Default,
+            )
+            .unwrap();
+        let task = state.get_task(&task_id).unwrap();
+        assert_eq!(task.get_status(), TaskStatus::Running(1));
+        assert_eq!(task.get_decisions(), (3, 0));
+        assert!(task.is_approved());
+        let update = rx.try_recv().unwrap().unwrap();
+        assert_eq!(update.state(), crate::proto::task::TaskState::Running);
+        assert_eq!(update.round, 1);
+    }
+
+    #[test]
+    fn all_signers_required() {
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 3);
+        let group_id = prepare_group(
+            &mut state,
+            &devices,
+            2,
+            ProtocolType::Frost,
+            KeyType::SignChallenge,
+        );
+        state
+            .groups
+            .get_mut(&group_id)
+            .unwrap()
+            .set_require_all_signers(true);
+        let mut sign = || {
+            state
+                .add_sign_task(
+                    &group_id,
+                    "Sample Task",
+                    &[0x01],
+                    None,
+                    HashAlgorithm::Default,
+                )
+                .unwrap()
+        };
+        let task_id = sign();
+        let declined_id = sign();
+
+        for device in &devices[..2] {
+            state.decide_task(&task_id, device, true);
+            state.decide_task(&declined_id, device, true);
+        }
+        assert_eq!(
+            state.get_task(&task_id).unwrap().get_status(),
+            TaskStatus::Created
+        );
+        state.decide_task(&task_id, &devices[2], true);
+        let task = state.get_task(&task_id).unwrap();
+        assert_eq!(task.get_status(), TaskStatus::Running(1));
+        for device in &devices {
+            assert_eq!(task.participant_status(device), ParticipantStatus::Pending);
+        }
+
+        state.decide_task(&declined_id, &devices[2], false);
+        assert!(matches!(
+            state.get_task(&declined_id).unwrap().get_status(),
+            TaskStatus::Failed(FailureReason::Declined, _)
+        ));
+    }
+
+    #[test]
+    fn degraded_group_cannot_sign() {
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 2);
+        let group_id = prepare_group(
+            &mut state,
+            &devices,
+            2,
+            ProtocolType::Gg18,
+            KeyType::SignChallenge,
+        );
+        state.groups.get_mut(&group_id).unwrap().mark_degraded();
+
+        let result = state.add_sign_task(
+            &group_id,
+            "Sample Task",
+            &[0x01, 0x02],
+            None,
+            HashAlgorithm::Default,
+        );
+        assert_eq!(
+            result,
+            Err(Error::FailedPrecondition(
+                "Group is degraded and cannot sign".into()
+            ))
+        );
+        assert!(state.get_tasks().is_empty());
+    }
+
+    #[test]
+    fn group_found_by_public_key() {
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 2);
+        let members: Vec<_> = devices.iter().map(|id| state.devices[id].clone()).collect();
+        // generator of secp256k1
+        let x = hex::decode("79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798")
+            .unwrap();
+        let compressed = [&[0x02], x.as_slice()].concat();
+        let group = |identifier: &[u8], protocol| {
+            Group::new(
+                identifier.to_vec(),
+                String::from("Sample Group"),
+                members.clone(),
+                2,
+                protocol,
+                KeyType::SignChallenge,
+                None,
+                None,
+            )
+        };
+        state
+            .add_group(group(&compressed, ProtocolType::Gg18))
+            .unwrap();
+
+        let spki = state
+            .export_group_key(&compressed, KeyFormat::SpkiDer)
+            .unwrap();
+        let task_id = state
+            .add_sign_task(&spki, "Sample Task", &[0x01], None, HashAlgorithm::Default)
+            .unwrap();
+        assert_eq!(
+            state.get_task(&task_id).unwrap().get_group_id(),
+            Some(compressed.as_slice())
+        );
+
+        let mut other = compressed.clone();
+        other[0] = 0x03;
+        state.add_group(group(&x, ProtocolType::Frost)).unwrap();
+        assert!(state.add_group(group(&other, ProtocolType::Frost)).is_err());
+        assert_eq!(state.get_groups().len(), 2);
+    }
+
+    #[test]
+    fn rotated_identifier_kept_in_group() {
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 3);
+        let group_id = prepare_group(
+            &mut state,
+            &devices[..2],
+            2,
+            ProtocolType::Gg18,
+            KeyType::SignChallenge,
+        );
+        let new_id = vec![0xee];
+
+        assert!(matches!(
+            state.rotate_device_identifier(&devices[0], &devices[2], &[0xfe]),
+            Err(Error::InvalidArgument(_))
+        ));
+        let task_id = state
+            .add_sign_task(
+                &group_id,
+                "Sample Task",
+                &[0x01],
+                None,
+                HashAlgorithm::Default,
+            )
+            .unwrap();
+        assert!(matches!(
+            state.rotate_device_identifier(&devices[0], &new_id, &[0xfe]),
+            Err(Error::FailedPrecondition(_))
+        ));
+        state.decide_task(&task_id, &devices[1], false);
+
+        state
+            .rotate_device_identifier(&devices[0], &new_id, &[0xfe])
+            .unwrap();
+        let group = &state.get_groups()[&group_id];
+        assert_eq!(group.devices()[0].identifier(), new_id);
+        assert_eq!(group.devices()[0].name(), "d0");
+        assert!(!group.contains(&devices[0]));
+        assert!(state.get_devices().contains_key(&new_id));
+        assert!(!state.get_devices().contains_key(&devices[0]));
+        // a concurrent rotation with the old certificate finds the device gone
+        assert!(matches!(
+            state.rotate_device_identifier(&devices[0], &[0xef], &[0xfd]),
+            Err(Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn sign_from_template() {
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 2);
+        let group_id = prepare_group(
+            &mut state,
+            &devices,
+            2,
+            ProtocolType::Gg18,
+            KeyType::SignChallenge,
+        );
+        let template = SignTemplate {
+            group_id: group_id.clone(),
+            hash_algorithm: HashAlgorithm::Sha512,
+            deadline_seconds: Some(60),
+            priority: 5,
+        };
+        assert!(matches!(
+            state.add_sign_template(
+                "Payments",
+                SignTemplate {
+                    deadline_seconds: Some(0),
+                    ..template.clone()
+                }
+            ),
+            Err(Error::InvalidArgument(_))
+        ));
+        state.add_sign_template("Payments", template).unwrap();
+        assert!(matches!(
+            state.add_sign_task_from_template("Unknown", "Sample Task", &[0x01]),
+            Err(Error::NotFound(_))
+        ));
+
+        let task_id = state
+            .add_sign_task_from_template("Payments", "Sample Task", &[0x01])
+            .unwrap();
+        let task = state.get_task(&task_id).unwrap();
+        assert_eq!(task.get_group_id(), Some(group_id.as_slice()));
+        assert_eq!(task.priority(), 5);
+        assert_eq!(task.deadline(), Some(task.created_at() + 60));
+        assert_eq!(
+            task.get_data_digest(),
+            Some(<sha2::Sha512 as sha2::Digest>::digest([0x01]).to_vec())
+        );
+    }
+
+    #[test]
+    fn inactive_devices_unregistered() {
+        let mut state = State::new(Config {
+            device_inactivity_timeout: Some(std::time::Duration::from_secs(90 * 24 * 60 * 60)),
+            ..Config::default()
+        });
+        let members = prepare_devices(&mut state, 2);
+        prepare_group(
+            &mut state,
+            &members,
+            2,
+            ProtocolType::Gg18,
+            KeyType::SignChallenge,
+        );
+        state.add_device(&[0xff], "Idle", DeviceKind::User, &[0xf0], None);
+        let now = get_timestamp();
+
+        assert!(state.unregister_inactive_devices(now).is_empty());
+        assert_eq!(
+            state.unregister_inactive_devices(now + 90 * 24 * 60 * 60),
+            vec![vec![0xff]]
+        );
+        assert!(!state.get_devices().contains_key(&vec![0xff]));
+        assert_eq!(state.get_devices().len(), 2);
+
+        let mut disabled = State::new(Config::default());
+        disabled.add_device(&[0xff], "Idle", DeviceKind::User, &[0xf0], None);
+        assert!(disabled
+            .unregister_inactive_devices(u64::MAX / 2)
+            .is_empty());
+    }
+
+    #[test]
+    fn registered_device_not_inactive() {
+        let clock = Arc::new(crate::clock::MockClock::new(1_700_000_000));
+        let mut state = State::new(Config {
+            device_inactivity_timeout: Some(std::time::Duration::from_secs(60)),
+            ..Config::default()
+        });
+        state.set_clock(clock.clone());
+        state.add_device(&[0xff], "Idle", DeviceKind::User, &[0xf0], None);
+
+        clock.advance(1);
+        assert!(state.unregister_inactive_devices(state.now()).is_empty());
+        clock.advance(60);
+        assert_eq!(
+            state.unregister_inactive_devices(state.now()),
+            vec![vec![0xff]]
+        );
+    }
+
+    #[test]
+    fn canonically_equal_names_stored_alike() {
+        let mut state = State::new(Config::default());
+        let composed = "Andr\u{e9}";
+        let decomposed = "Andre\u{301}";
+        for (identifier, name) in [(0x01, composed), (0x02, decomposed)] {
+            assert_eq!(
+                state.add_device(&[identifier], name, DeviceKind::User, &[0xf0], None),
+                RegistrationOutcome::Registered
+            );
+        }
+
+        let devices = state.get_devices();
+        assert_eq!(devices[&vec![0x01]].name(), composed);
+        assert_eq!(devices[&vec![0x02]].name(), composed);
+    }
+
+    #[test]
+    fn configured_name_lengths() {
+        let mut state = State::new(Config {
+            max_device_name_length: 8,
+            max_group_name_length: 12,
+            ..Config::default()
+        });
+        assert_eq!(
+            state.add_device(
+                &[0x01],
+                &"\u{10d}".repeat(8),
+                DeviceKind::User,
+                &[0xf1],
+                None
+            ),
+            RegistrationOutcome::Registered
+        );
+        assert_eq!(
+            state.add_device(
+                &[0x02],
+                &"\u{10d}".repeat(9),
+                DeviceKind::User,
+                &[0xf2],
+                None
+            ),
+            RegistrationOutcome::Invalid
+        );
+        assert_eq!(
+            state.add_device(&[0x03], "Device", DeviceKind::User, &[0xf3], None),
+            RegistrationOutcome::Registered
+        );
+
+        let devices = vec![vec![0x01], vec![0x03]];
+        let add_group_task = |state: &mut State, name: &str| {
+            state.add_group_task(
+                name,
+                &devices,
+                2,
+                ProtocolType::Gg18,
+                KeyType::SignChallenge,
+                &None,
+                &[],
+                None,
+            )
+        };
+        assert!(add_group_task(&mut state, &"\u{17e}".repeat(12)).is_ok());
+        assert_eq!(
+            add_group_task(&mut state, &"\u{17e}".repeat(13)),
+            Err(Error::InvalidArgument("Invalid group name".into()))
+        );
+    }
+
+    #[test]
+    fn device_public_key() {
+        let mut state = State::new(Config::default());
+        let public_key = [0xab; 32];
+        assert_eq!(
+            state.add_device(
+                &[0x01],
+                "Keyed",
+                DeviceKind::User,
+                &[0xf1],
+                Some(&public_key)
+            ),
+            RegistrationOutcome::Registered
+        );
+        assert_eq!(
+            state.add_device(
+                &[0x02],
+                "Malformed",
+                DeviceKind::User,
+                &[0xf2],
+                Some(&[0xab; 31])
+            ),
+            RegistrationOutcome::Invalid
+        );
+
+        let device = crate::proto::Device::from(state.get_devices()[&vec![0x01]].as_ref());
+        assert_eq!(device.public_key, Some(public_key.to_vec()));
+        assert!(!state.get_devices().contains_key(&vec![0x02]));
+    }
+
+    #[test]
+    fn resend_current_work() {
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 2);
+        let task_id = prepare_running_sign_task(&mut state, &devices, 2);
+        let work = state.get_device_work(&devices[0]);
+
+        let resent = state.resend_work(&task_id, &devices[0]).unwrap();
+        assert_eq!(resent, work[0]);
+        assert_eq!(state.resend_work(&task_id, &devices[0]), Ok(resent));
+
+        let message = vec![ClientMessage {
+            protocol_type: ProtocolType::Gg18.into(),
+            unicasts: HashMap::new(),
+            broadcast: Some(vec![]),
+        }
+        .encode_to_vec()];
+        state
+            .update_task(&task_id, &devices[0], &message, 0, None)
+            .unwrap();
+        assert!(matches!(
+            state.resend_work(&task_id, &devices[0]),
+            Err(Error::FailedPrecondition(_))
+        ));
+        assert!(matches!(
+            state.resend_work(&task_id, &[0xee]),
+            Err(Error::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn update_traced_in_task_span() {
+        #[derive(Clone, Default)]
+        struct Buffer(Arc<std::sync::Mutex<Vec<u8>>>);
+
+        impl std::io::Write for Buffer {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 2);
+        let task_id = prepare_running_sign_task(&mut state, &devices, 2);
+        let message = vec![ClientMessage {
+            protocol_type: ProtocolType::Gg18.into(),
+            unicasts: HashMap::new(),
+            broadcast: Some(vec![]),
+        }
+        .encode_to_vec()];
+
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        tracing::subscriber::with_default(subscriber, || {
+            for device in &devices {
+                state
+                    .update_task(&task_id, device, &message, 0, None)
+                    .unwrap();
+            }
+        });
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let span = format!("update_task{{task_id={}", task_id);
+        assert!(output
+            .lines()
+            .any(|line| line.contains(&span) && line.contains("Task advanced")));
+    }
+
+    #[test]
+    fn work_ordered_by_priority() {
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 2);
+        let low = prepare_running_sign_task(&mut state, &devices, 2);
+        let high = prepare_running_sign_task(&mut state, &devices, 2);
+        state.set_task_priority(&low, 1);
+        state.set_task_priority(&high, 5);
+
+        let task_ids: Vec<_> = state
+            .get_device_tasks(&devices[0])
+            .iter()
+            .map(|(task_id, _)| *task_id)
+            .collect();
+        assert_eq!(task_ids, vec![high, low]);
+
+        let task_ids: Vec<_> = state
+            .get_device_work(&devices[0])
+            .iter()
+            .map(|work| Uuid::from_slice(&work.task_id).unwrap())
+            .collect();
+        assert_eq!(task_ids, vec![high, low]);
+    }
+
+    #[test]
+    fn unformattable_task_update_skipped() {
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 2);
+        let mut receivers = Vec::new();
+        for device in &devices {
+            let (tx, rx) = tokio::sync::mpsc::channel(8);
+            state.add_subscriber(device.clone(), tx);
+            receivers.push(rx);
+        }
+        let broken = state.add_task(Box::new(BrokenTask {
+            devices: vec![state.devices[&devices[0]].clone()],
+        }));
+        state.send_updates(&broken);
+        assert!(receivers[0].try_recv().is_err());
+        assert!(devices.iter().all(|device| state.is_subscribed(device)));
+
+        let task_id = prepare_running_sign_task(&mut state, &devices, 2);
+        for rx in &mut receivers {
+            let update = rx.try_recv().unwrap().unwrap();
+            assert_eq!(update.id, task_id.as_bytes());
+        }
+    }
+
+    #[test]
+    fn reshare_threshold() {
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 4);
+        let group_id = prepare_group(
+            &mut state,
+            &devices[..3],
+            2,
+            ProtocolType::Gg18,
+            KeyType::SignChallenge,
+        );
+        assert_eq!(state.check_reshare(&group_id, &devices, 3), Ok(()));
+        assert!(matches!(
+            state.check_reshare(&group_id, &devices, 5),
+            Err(Error::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            state.check_reshare(&group_id, &devices[..1], 1),
+            Err(Error::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            state.check_reshare(&[0xbb], &devices, 3),
+            Err(Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn unsupported_hash_algorithm() {
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 2);
+        let group_id = prepare_group(
+            &mut state,
+            &devices,
+            2,
+            ProtocolType::Frost,
+            KeyType::SignChallenge,
+        );
+        let result = state.add_sign_task(
+            &group_id,
+            "Sample Task",
+            &[0x01],
+            None,
+            HashAlgorithm::Keccak256,
+        );
+        assert!(matches!(result, Err(Error::InvalidArgument(_))));
+        assert!(state.get_tasks().is_empty());
+    }
+
+    #[test]
+    fn query_tasks_by_state_and_group() {
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 2);
+        let running = prepare_running_sign_task(&mut state, &devices, 2);
+        let group_id = state
+            .get_task(&running)
+            .unwrap()
+            .get_group_id()
+            .unwrap()
+            .to_vec();
+        let created = state
+            .add_sign_task(
+                &group_id,
+                "Created Task",
+                &[0x03],
+                None,
+                HashAlgorithm::Default,
+            )
+            .unwrap();
+        let other_group = vec![0xbb; 4];
+        state.groups.insert(
+            other_group.clone(),
+            Group::new(
+                other_group.clone(),
+                String::from("Other Group"),
+                devices
+                    .iter()
+                    .map(|device| state.devices[device].clone())
+                    .collect(),
+                2,
+                ProtocolType::Gg18,
+                KeyType::SignChallenge,
+                None,
+                None,
+            ),
+        );
+        let other_running = state
+            .add_sign_task(
+                &other_group,
+                "Other Task",
+                &[0x04],
+                None,
+                HashAlgorithm::Default,
+            )
+            .unwrap();
+        for device in &devices {
+            state.decide_task(&other_running, device, true);
+        }
+
+        let filter = TaskFilter {
+            state: Some(crate::proto::task::TaskState::Running),
+            group_id: Some(group_id.clone()),
+            ..TaskFilter::default()
+        };
+        let (tasks, total) = state.query_tasks(&filter, 0, 10);
+        assert_eq!(total, 1);
+        assert_eq!(tasks[0].0, running);
+
+        let filter = TaskFilter {
+            group_id: Some(group_id),
+            created_before: Some(get_timestamp() + 1),
+            device_id: Some(devices[0].clone()),
+            ..TaskFilter::default()
+        };
+        let (tasks, total) = state.query_tasks(&filter, 1, 10);
+        assert_eq!(total, 2);
+        assert_eq!(tasks.len(), 1);
+        assert!([running, created].contains(&tasks[0].0));
+
+        let filter = TaskFilter {
+            created_after: Some(get_timestamp() + 1),
+            ..TaskFilter::default()
+        };
+        assert_eq!(state.query_tasks(&filter, 0, 10).1, 0);
+    }
+
+    #[test]
+    fn invalid_deadline() {
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 2);
+        let group_id = prepare_group(
+            &mut state,
+            &devices,
+            2,
+            ProtocolType::Gg18,
+            KeyType::SignChallenge,
+        );
+        for deadline in [0, MAX_DEADLINE_SECONDS + 1] {
+            let result = state.add_sign_task(
+                &group_id,
+                "Sample Task",
+                &[0x01],
+                Some(deadline),
+                HashAlgorithm::Default,
+            );
+            assert!(matches!(result, Err(Error::InvalidArgument(_))));
+        }
+        assert!(state.get_tasks().is_empty());
+    }
+
+    /// Task which claims to be finished without holding a result
+    struct BrokenTask {
+        devices: Vec<Arc<Device>>,
+    }
+
+    impl Task for BrokenTask {
+        fn get_status(&self) -> TaskStatus {
+            TaskStatus::Finished
+        }
+        fn failed_round(&self) -> Option<u16> {
+            None
+        }
+        fn get_type(&self) -> TaskType {
+            TaskType::SignChallenge
+        }
+        fn get_protocol(&self) -> ProtocolType {
+            ProtocolType::Gg18
+        }
+        fn get_work(&self, _: Option<&[u8]>) -> Vec<Vec<u8>> {
+            Vec::new()
+        }
+        fn get_result(&self) -> Option<TaskResult> {
+            None
+        }
+        fn get_decisions(&self) -> (u32, u32) {
+            (0, 0)
+        }
+        fn get_data_digest(&self) -> Option<Vec<u8>> {
+            None
+        }
+        fn update(&mut self, _: &[u8], _: &Vec<Vec<u8>>) -> Result<bool, String> {
+            Ok(false)
+        }
+        fn restart(&mut self) -> Result<bool, String> {
+            Ok(false)
+        }
+        fn fail(&mut self, _: FailureReason, _: &str) -> bool {
+            false
+        }
+        fn reselect(&mut self, _: &[u8]) -> Result<bool, String> {
+            Ok(false)
+        }
+        fn last_update(&self) -> u64 {
+            0
+        }
+        fn created_at(&self) -> u64 {
+            0
+        }
+        fn get_group_id(&self) -> Option<&[u8]> {
+            None
+        }
+        fn requester(&self) -> Option<&str> {
+            None
+        }
+        fn set_requester(&mut self, _: String) {}
+        fn priority(&self) -> u32 {
+            0
+        }
+        fn set_priority(&mut self, _: u32) {}
+        fn deadline(&self) -> Option<u64> {
+            None
+        }
+        fn is_approved(&self) -> bool {
+            true
+        }
+        fn has_device(&self, device_id: &[u8]) -> bool {
+            self.devices
+                .iter()
+                .any(|device| device.identifier() == device_id)
+        }
+        fn get_devices(&self) -> Vec<Arc<Device>> {
+            self.devices.clone()
+        }
+        fn waiting_for(&self, _: &[u8]) -> bool {
+            false
+        }
+        fn decide(&mut self, _: &[u8], _: bool) -> Option<bool> {
+            None
+        }
+        fn withdraw(&mut self, _: &[u8]) -> bool {
+            false
+        }
+        fn acknowledge(&mut self, _: &[u8]) {}
+        fn device_acknowledged(&self, _: &[u8]) -> bool {
+            false
+        }
+        fn get_request(&self) -> &[u8] {
+            &[]
+        }
+        fn get_attempts(&self) -> u32 {
+            0
+        }
+        fn relay_stats(&self) -> CommunicatorStats {
+            CommunicatorStats::default()
+        }
+        fn participant_status(&self, _: &[u8]) -> ParticipantStatus {
+            ParticipantStatus::Pending
+        }
+    }
+
+    /// Running task whose every update takes `delay` to process, panicking if `panics` is set
+    struct SlowTask {
+        devices: Vec<Arc<Device>>,
+        delay: std::time::Duration,
+        panics: bool,
+        status: TaskStatus,
+    }
+
+    impl SlowTask {
+        fn new(devices: Vec<Arc<Device>>, delay: std::time::Duration, panics: bool) -> Self {
+            SlowTask {
+                devices,
+                delay,
+                panics,
+                status: TaskStatus::Running(1),
+            }
+        }
+    }
+
+    impl Task for SlowTask {
+        fn get_status(&self) -> TaskStatus {
+            self.status.clone()
+        }
+        fn failed_round(&self) -> Option<u16> {
+            None
+        }
+        fn get_type(&self) -> TaskType {
+            TaskType::SignChallenge
+        }
+        fn get_protocol(&self) -> ProtocolType {
+            ProtocolType::Gg18
+        }
+        fn get_work(&self, _: Option<&[u8]>) -> Vec<Vec<u8>> {
+            Vec::new()
+        }
+        fn get_result(&self) -> Option<TaskResult> {
+            None
+        }
+        fn get_decisions(&self) -> (u32, u32) {
+            (self.devices.len() as u32, 0)
+        }
+        fn get_data_digest(&self) -> Option<Vec<u8>> {
+            None
+        }
+        fn update(&mut self, _: &[u8], _: &Vec<Vec<u8>>) -> Result<bool, String> {
+            std::thread::sleep(self.delay);
+            assert!(!self.panics, "update could not be processed");
+            Ok(false)
+        }
+        fn restart(&mut self) -> Result<bool, String> {
+            Ok(false)
+        }
+        fn fail(&mut self, reason: FailureReason, message: &str) -> bool {
+            if self.status != TaskStatus::Running(1) {
+                return false;
+            }
+            self.status = TaskStatus::Failed(reason, message.into());
+            true
+        }
+        fn reselect(&mut self, _: &[u8]) -> Result<bool, String> {
+            Ok(false)
+        }
+        fn last_update(&self) -> u64 {
+            get_timestamp()
+        }
+        fn created_at(&self) -> u64 {
+            0
+        }
+        fn get_group_id(&self) -> Option<&[u8]> {
+            None
+        }
+        fn requester(&self) -> Option<&str> {
+            None
+        }
+        fn set_requester(&mut self, _: String) {}
+        fn priority(&self) -> u32 {
+            0
+        }
+        fn set_priority(&mut self, _: u32) {}
+        fn deadline(&self) -> Option<u64> {
+            None
+        }
+        fn is_approved(&self) -> bool {
+            true
+        }
+        fn has_device(&self, device_id: &[u8]) -> bool {
+            self.devices
+                .iter()
+                .any(|device| device.identifier() == device_id)
+        }
+        fn get_devices(&self) -> Vec<Arc<Device>> {
+            self.devices.clone()
+        }
+        fn waiting_for(&self, device_id: &[u8]) -> bool {
+            self.status == TaskStatus::Running(1) && self.has_device(device_id)
+        }
+        fn decide(&mut self, _: &[u8], _: bool) -> Option<bool> {
+            None
+        }
+        fn withdraw(&mut self, _: &[u8]) -> bool {
+            false
+        }
+        fn acknowledge(&mut self, _: &[u8]) {}
+        fn device_acknowledged(&self, _: &[u8]) -> bool {
+            false
+        }
+        fn get_request(&self) -> &[u8] {
+            &[]
+        }
+        fn get_attempts(&self) -> u32 {
+            0
+        }
+        fn relay_stats(&self) -> CommunicatorStats {
+            CommunicatorStats::default()
+        }
+        fn participant_status(&self, _: &[u8]) -> ParticipantStatus {
+            ParticipantStatus::Pending
+        }
+    }
+
+    fn add_slow_task(state: &mut State, devices: &[Vec<u8>], delay_ms: u64, panics: bool) -> Uuid {
+        let devices = devices
+            .iter()
+            .map(|device| state.devices[device].clone())
+            .collect();
+        state.add_task(Box::new(SlowTask::new(
+            devices,
+            std::time::Duration::from_millis(delay_ms),
+            panics,
+        )))
+    }
+
+    async fn wait_for_checkout(state: &Mutex<State>, task_id: &Uuid) {
+        while state.lock().await.pending_update(task_id).is_none() {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    fn disconnect(state: &mut State, device: &[u8]) {
+        let (tx, rx) = tokio::sync::mpsc::channel(8);
+        state.add_subscriber(device.to_vec(), tx);
+        drop(rx);
+        state.remove_subscriber(&device.to_vec());
+    }
+
+    fn prepare_running_sign_task(state: &mut State, devices: &[Vec<u8>], threshold: u32) -> Uuid {
+        let group_id = prepare_group(
+            state,
+            devices,
+            threshold,
+            ProtocolType::Gg18,
+            KeyType::SignChallenge,
+        );
+        let task_id = state
+            .add_sign_task(
+                &group_id,
+                "Sample Task",
+                &[0x01, 0x02],
+                None,
+                HashAlgorithm::Default,
+            )
+            .unwrap();
+        for device in devices {
+            state.decide_task(&task_id, device, true);
+        }
+        assert_eq!(
+            state.get_task(&task_id).unwrap().get_status(),
+            TaskStatus::Running(1)
+        );
+        task_id
+    }
+
+    #[test]
+    fn full_channel_keeps_subscriber() {
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 2);
+        let (tx, _rx) = tokio::sync::mpsc::channel(1);
+        tx.try_send(Ok(crate::proto::Task::default())).unwrap();
+        state.add_subscriber(devices[1].clone(), tx);
+
+        let task_id = prepare_running_sign_task(&mut state, &devices, 2);
+        assert!(state.is_subscribed(&devices[1]));
+        assert_eq!(
+            state.get_task(&task_id).unwrap().get_status(),
+            TaskStatus::Running(1)
+        );
+        let missed = state.missed_updates(&devices[1], 0).unwrap();
+        assert_eq!(
+            missed.last().unwrap().state(),
+            crate::proto::task::TaskState::Running
+        );
+    }
+
+    #[test]
+    fn backlogged_device_receives_actionable_updates() {
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 3);
+        let group_id = prepare_group(
+            &mut state,
+            &devices,
+            2,
+            ProtocolType::Gg18,
+            KeyType::SignChallenge,
+        );
+        let (tx, mut rx) = tokio::sync::mpsc::channel(8);
+        state.add_subscriber(devices[2].clone(), tx.clone());
+        for _ in 0..6 {
+            tx.try_send(Ok(crate::proto::Task::default())).unwrap();
+        }
+
+        let task_id = state
+            .add_sign_task(
+                &group_id,
+                "Sample Task",
+                &[0x01],
+                None,
+                HashAlgorithm::Default,
+            )
+            .unwrap();
+        for device in &devices[..2] {
+            state.decide_task(&task_id, device, true);
+        }
+        assert!(matches!(
+            state.get_task(&task_id).unwrap().get_status(),
+            TaskStatus::Running(_)
+        ));
+
+        for _ in 0..6 {
+            assert_eq!(rx.try_recv().unwrap().unwrap().id, Vec::<u8>::new());
+        }
+        let update = rx.try_recv().unwrap().unwrap();
+        assert_eq!(update.id, task_id.as_bytes());
+        assert_eq!(update.state(), crate::proto::task::TaskState::Created);
+        assert!(rx.try_recv().is_err());
+
+        let deferred = state.missed_updates(&devices[2], 1).unwrap();
+        assert_eq!(deferred.len(), 1);
+        assert_eq!(deferred[0].state(), crate::proto::task::TaskState::Running);
+    }
+
+    #[test]
+    fn dependent_task_starts_after_group_task() {
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 2);
+        let group_task_id = state
+            .add_group_task(
+                "Sample Group",
+                &devices,
+                2,
+                ProtocolType::Frost,
+                KeyType::SignChallenge,
+                &None,
+                &[],
+                None,
+            )
+            .unwrap();
+        let task_id = state
+            .add_dependent_sign_task(
+                &group_task_id,
+                "Sample Task",
+                &[0x01],
+                None,
+                HashAlgorithm::Default,
+            )
+            .unwrap();
+        let task = state.get_task(&task_id).unwrap();
+        assert_eq!(task.get_type(), TaskType::SignChallenge);
+        assert!(task.get_devices().is_empty());
+
+        for device in &devices {
+            state.decide_task(&group_task_id, device, true);
+        }
+        let group_key = vec![0x02; 32];
+        let message = vec![ClientMessage {
+            protocol_type: ProtocolType::Frost.into(),
+            unicasts: HashMap::new(),
+            broadcast: Some(group_key.clone()),
+        }
+        .encode_to_vec()];
+        while state.get_task(&group_task_id).unwrap().get_status() != TaskStatus::Finished {
+            let task = state.get_task(&task_id).unwrap();
+            assert_eq!(task.get_status(), TaskStatus::Created);
+            assert_eq!(task.get_group_id(), None);
+            for device in &devices {
+                state
+                    .update_task(&group_task_id, device, &message, 0, None)
+                    .unwrap();
+            }
+        }
+
+        let task = state.get_task(&task_id).unwrap();
+        assert_eq!(task.get_group_id(), Some(group_key.as_slice()));
+        assert_eq!(task.get_devices().len(), 2);
+        assert_eq!(task.get_status(), TaskStatus::Created);
+        for device in &devices {
+            state.decide_task(&task_id, device, true);
+        }
+        assert_eq!(
+            state.get_task(&task_id).unwrap().get_status(),
+            TaskStatus::Running(1)
+        );
+    }
+
+    #[test]
+    fn dependent_task_fails_with_group_task() {
+        let mut state = State::new(Config::default());
+        let devices = prepare_devices(&mut state, 2);
+        let group_task_id = state
+            .add_group_task(
+                "Sample Group",
+                &devices,
+                2,
+                ProtocolType::Frost,
+                KeyType::SignChallenge,
+                &None,
+                &[],
+                None,
+            )
+            .unwrap();
+        let task_id = state
+            .add_dependent_sign_task(
+                &group_task_id,
+                "Sample Task",
+                &[0x01],
+                None,
+                HashAlgorithm::Default,
+            )
+            .unwrap();
+
+        state.cancel_group_task(&group_task_id).unwrap();
+        assert!(matches!(
+            state.get_task(&task_id).unwrap().get_status(),
+            TaskStatus::Failed(FailureReason::DependencyFailed, _)
+        ));
+        assert!(matches!(
+            state.add_dependent_sign_task(
+                &task_id,
+                "Sample Task",
+                &[0x01],
+                None,
+                HashAlgorithm::Default,
+            ),
+            Err(Error::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn failing_device_left_out_of_signing() {
+        let mut state = State::new(Config {
+            breaker_threshold: Some(2),
+            ..Config::default()
+        });
+        let devices = prepare_devices(&mut state, 3);
+        let group_id = prepare_group(
+            &mut state,
+            &devices,
+            3,
+            ProtocolType::Frost,
+            KeyType::SignChallenge,
+        );
+        let message = vec![ClientMessage {
+            protocol_type: ProtocolType::Frost.into(),
+            unicasts: HashMap::new(),
+            broadcast: Some(vec![0x01]),
+        }
+        .encode_to_vec()];
+        let sign = |state: &mut State, group_id: &[u8]| {
+            let task_id = state
+                .add_sign_task(
+                    group_id,
+                    "Sample Task",
+                    &[0x01],
+                    None,
+                    HashAlgorithm::Default,
+                )
+                .unwrap();
+            for device in &devices {
+                state.decide_task(&task_id, device, true);
+            }
+            task_id
+        };
+
+        for _ in 0..2 {
+            assert!(!state.devices[&devices[2]].breaker_tripped());
+            let task_id = sign(&mut state, &group_id);
+            for device in &devices[..2] {
+                state
+                    .update_task(&task_id, device, &message, 0, None)
+                    .unwrap();
+            }
+            state.record_stalled_devices(&task_id);
+        }
+        assert!(state.devices[&devices[2]].breaker_tripped());
+        assert_eq!(state.device_failures(&devices[2]), 2);
+        assert_eq!(state.device_failures(&devices[0]), 0);
+
+        let subset_group = Group::new(
+            vec![0xbb; 4],
+            String::from("Subset Group"),
+            devices
+                .iter()
+                .map(|device| state.devices[device].clone())
+                .collect(),
+            2,
+            ProtocolType::Frost,
+            KeyType::SignChallenge,
+            None,
+            None,
+        );
+        state.groups.insert(vec![0xbb; 4], subset_group);
+        let task_id = sign(&mut state, &[0xbb; 4]);
+        let task = state.get_task(&task_id).unwrap();
+        assert_eq!(task.get_status(), TaskStatus::Running(1));
+        assert_eq!(
+            task.participant_status(&devices[2]),
+            ParticipantStatus::Idle
+        );
+        assert_eq!(
+            task.participant_status(&devices[0]),
+            ParticipantStatus::Pending
+        );
+
+        state.reset_circuit_breaker(&devices[2]).unwrap();
+        assert!(!state.devices[&devices[2]].breaker_tripped());
+        assert_eq!(state.device_failures(&devices[2]), 0);
+    }
+
+    fn prepare_group(
+        state: &mut State,
+        devices: &[Vec<u8>],
+        threshold: u32,
+        protocol: ProtocolType,
+        key_type: KeyType,
+    ) -> Vec<u8> {
+        let identifier = vec![0xaa; 4];
+        let group = Group::new(
+            identifier.clone(),
+            String::from("Sample Group"),
+            devices
+                .iter()
+                .map(|device| state.devices[device].clone())
+                .collect(),
+            threshold,
+            protocol,
+            key_type,
+            None,
+            None,
+        );
+        state.groups.insert(identifier.clone(), group);
+        identifier
+    }
+
+    fn prepare_devices(state: &mut State, n: usize) -> Vec<Vec<u8>> {
+        assert!(n < u8::MAX as usize);
+        (0..n)
+            .map(|i| {
+                let identifier = vec![i as u8];
+                assert_eq!(
+                    state.add_device(
+                        &identifier,
+                        &format!("d{}", i),
+                        DeviceKind::User,
+                        &[0xf0 | i as u8],
+                        None,
+                    ),
+                    RegistrationOutcome::Registered
+                );
+                identifier
+            })
+            .collect()
     }
 }
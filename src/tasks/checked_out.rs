@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+
+use crate::communicator::{CommunicatorStats, ParticipantStatus};
+use crate::device::Device;
+use crate::proto::{ProtocolType, TaskType};
+use crate::tasks::{FailureReason, Task, TaskResult, TaskStatus};
+use tonic::codegen::Arc;
+
+/// Per-device view of a task
+struct DeviceView {
+    work: Vec<Vec<u8>>,
+    waiting: bool,
+    acknowledged: bool,
+    committed: bool,
+    status: ParticipantStatus,
+}
+
+/// Read-only stand-in for a task which is checked out for an update
+///
+/// The stand-in answers queries as the task did when it was checked out, so that the task
+/// stays visible to listings, limits and timers while its update runs. It does not accept
+/// any change; those are up to the task once it is returned.
+pub struct CheckedOutTask {
+    status: TaskStatus,
+    failed_round: Option<u16>,
+    task_type: TaskType,
+    protocol: ProtocolType,
+    work: Vec<Vec<u8>>,
+    result: Option<TaskResult>,
+    decisions: (u32, u32),
+    data_digest: Option<Vec<u8>>,
+    last_update: u64,
+    created_at: u64,
+    group_id: Option<Vec<u8>>,
+    requester: Option<String>,
+    priority: u32,
+    deadline: Option<u64>,
+    approved: bool,
+    devices: Vec<Arc<Device>>,
+    views: HashMap<Vec<u8>, DeviceView>,
+    request: Vec<u8>,
+    attempts: u32,
+    relay_stats: CommunicatorStats,
+}
+
+impl CheckedOutTask {
+    pub fn of(task: &dyn Task) -> Self {
+        let devices = task.get_devices();
+        let views = devices
+            .iter()
+            .map(|device| {
+                let device_id = device.identifier();
+                let view = DeviceView {
+                    work: task.get_work(Some(device_id)),
+                    waiting: task.waiting_for(device_id),
+                    acknowledged: task.device_acknowledged(device_id),
+                    committed: task.committed(device_id),
+                    status: task.participant_status(device_id),
+                };
+                (device_id.to_vec(), view)
+            })
+            .collect();
+        CheckedOutTask {
+            status: task.get_status(),
+            failed_round: task.failed_round(),
+            task_type: task.get_type(),
+            protocol: task.get_protocol(),
+            work: task.get_work(None),
+            result: task.get_result(),
+            decisions: task.get_decisions(),
+            data_digest: task.get_data_digest(),
+            last_update: task.last_update(),
+            created_at: task.created_at(),
+            group_id: task.get_group_id().map(<[u8]>::to_vec),
+            requester: task.requester().map(str::to_owned),
+            priority: task.priority(),
+            deadline: task.deadline(),
+            approved: task.is_approved(),
+            devices,
+            views,
+            request: task.get_request().to_vec(),
+            attempts: task.get_attempts(),
+            relay_stats: task.relay_stats(),
+        }
+    }
+
+    /// Turn the stand-in into the failed record of a task whose update never returned
+    pub fn abandoned(mut self, reason: FailureReason, message: &str) -> Self {
+        if let TaskStatus::Running(round) = self.status {
+            self.failed_round = Some(round);
+        }
+        self.status = TaskStatus::Failed(reason, message.into());
+        for view in self.views.values_mut() {
+            view.work.clear();
+            view.waiting = false;
+        }
+        self.work.clear();
+        self
+    }
+}
+
+impl Task for CheckedOutTask {
+    fn get_status(&self) -> TaskStatus {
+        self.status.clone()
+    }
+
+    fn failed_round(&self) -> Option<u16> {
+        self.failed_round
+    }
+
+    fn get_type(&self) -> TaskType {
+        self.task_type
+    }
+
+    fn get_protocol(&self) -> ProtocolType {
+        self.protocol
+    }
+
+    fn get_work(&self, device_id: Option<&[u8]>) -> Vec<Vec<u8>> {
+        match device_id {
+            Some(device_id) => self
+                .views
+                .get(device_id)
+                .map(|view| view.work.clone())
+                .unwrap_or_default(),
+            None => self.work.clone(),
+        }
+    }
+
+    fn get_result(&self) -> Option<TaskResult> {
+        self.result.clone()
+    }
+
+    fn get_decisions(&self) -> (u32, u32) {
+        self.decisions
+    }
+
+    fn get_data_digest(&self) -> Option<Vec<u8>> {
+        self.data_digest.clone()
+    }
+
+    fn update(&mut self, _device_id: &[u8], _data: &Vec<Vec<u8>>) -> Result<bool, String> {
+        Err("Task is being updated".into())
+    }
+
+    fn restart(&mut self) -> Result<bool, String> {
+        Err("Task is being updated".into())
+    }
+
+    fn fail(&mut self, _reason: FailureReason, _message: &str) -> bool {
+        false
+    }
+
+    fn reselect(&mut self, _device_id: &[u8]) -> Result<bool, String> {
+        Err("Task is being updated".into())
+    }
+
+    fn last_update(&self) -> u64 {
+        self.last_update
+    }
+
+    fn created_at(&self) -> u64 {
+        self.created_at
+    }
+
+    fn get_group_id(&self) -> Option<&[u8]> {
+        self.group_id.as_deref()
+    }
+
+    fn requester(&self) -> Option<&str> {
+        self.requester.as_deref()
+    }
+
+    fn set_requester(&mut self, _requester: String) {}
+
+    fn priority(&self) -> u32 {
+        self.priority
+    }
+
+    fn set_priority(&mut self, _priority: u32) {}
+
+    fn deadline(&self) -> Option<u64> {
+        self.deadline
+    }
+
+    fn is_approved(&self) -> bool {
+        self.approved
+    }
+
+    fn has_device(&self, device_id: &[u8]) -> bool {
+        self.views.contains_key(device_id)
+    }
+
+    fn get_devices(&self) -> Vec<Arc<Device>> {
+        self.devices.clone()
+    }
+
+    fn waiting_for(&self, device_id: &[u8]) -> bool {
+        self.views.get(device_id).is_some_and(|view| view.waiting)
+    }
+
+    fn decide(&mut self, _device_id: &[u8], _decision: bool) -> Option<bool> {
+        None
+    }
+
+    fn withdraw(&mut self, _device_id: &[u8]) -> bool {
+        false
+    }
+
+    fn acknowledge(&mut self, _device_id: &[u8]) {}
+
+    fn device_acknowledged(&self, device_id: &[u8]) -> bool {
+        self.views
+            .get(device_id)
+            .is_some_and(|view| view.acknowledged)
+    }
+
+    fn get_request(&self) -> &[u8] {
+        &self.request
+    }
+
+    fn get_attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    fn relay_stats(&self) -> CommunicatorStats {
+        self.relay_stats.clone()
+    }
+
+    fn participant_status(&self, device_id: &[u8]) -> ParticipantStatus {
+        self.views
+            .get(device_id)
+            .map(|view| view.status)
+            .unwrap_or(ParticipantStatus::Pending)
+    }
+
+    fn commit(&mut self, _device_id: &[u8]) -> Result<bool, String> {
+        Err("Task is being updated".into())
+    }
+
+    fn committed(&self, device_id: &[u8]) -> bool {
+        self.views.get(device_id).is_some_and(|view| view.committed)
+    }
+}
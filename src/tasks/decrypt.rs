@@ -1,37 +1,48 @@
-use crate::communicator::Communicator;
+use crate::clock::Clock;
+use crate::communicator::{Communicator, CommunicatorStats, ParticipantStatus};
 use crate::device::Device;
 use crate::group::Group;
+use crate::logging;
 use crate::proto::{DecryptRequest, ProtocolType, TaskType};
 use crate::protocols::elgamal::ElgamalDecrypt;
 use crate::protocols::Protocol;
-use crate::tasks::{Task, TaskResult, TaskStatus};
-use crate::{get_timestamp, utils};
-use log::info;
+use crate::tasks::{FailureReason, Task, TaskResult, TaskStatus};
 use meesign_crypto::proto::{ClientMessage, Message as _};
 use prost::Message as _;
 use tonic::codegen::Arc;
+use tracing::{info, warn};
 
 pub struct DecryptTask {
     group: Group,
     communicator: Communicator,
-    result: Option<Result<Vec<u8>, String>>,
+    result: Option<Result<Vec<u8>, (FailureReason, String, u16)>>,
     pub(super) data: Vec<u8>,
     pub(super) protocol: Box<dyn Protocol + Send + Sync>,
     request: Vec<u8>,
     pub(super) last_update: u64,
+    created_at: u64,
+    requester: Option<String>,
+    priority: u32,
     pub(super) attempts: u32,
+    clock: Arc<dyn Clock>,
 }
 
 impl DecryptTask {
-    pub fn new(group: Group, name: String, data: Vec<u8>, data_type: String) -> Self {
-        let communicator =
-            Communicator::new(group.devices(), group.threshold(), ProtocolType::Elgamal);
+    pub fn new(
+        group: Group,
+        name: String,
+        data: Vec<u8>,
+        data_type: String,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        let communicator = Communicator::for_group(&group, clock.clone());
 
         let request = (DecryptRequest {
             group_id: group.identifier().to_vec(),
             name,
             data: data.clone(),
             data_type,
+            priority: 0,
         })
         .encode_to_vec();
 
@@ -42,37 +53,63 @@ impl DecryptTask {
             data,
             protocol: Box::new(ElgamalDecrypt::new()),
             request,
-            last_update: get_timestamp(),
+            last_update: clock.now(),
+            created_at: clock.now(),
+            requester: None,
+            priority: 0,
             attempts: 0,
+            clock,
         }
     }
 
     pub(super) fn start_task(&mut self) {
-        assert!(self.communicator.accept_count() >= self.group.threshold());
+        assert!(self.communicator.accept_count() >= self.group.signer_count());
         self.protocol.initialize(&mut self.communicator, &self.data);
     }
 
     pub(super) fn advance_task(&mut self) {
-        self.protocol.advance(&mut self.communicator)
+        if let Err(message) = self.protocol.advance(&mut self.communicator) {
+            self.protocol_failed(message);
+        }
     }
 
     pub(super) fn finalize_task(&mut self) {
-        let decrypted = self.protocol.finalize(&mut self.communicator);
+        let decrypted = match self.protocol.finalize(&mut self.communicator) {
+            Ok(output) => output.filter(|output| !output.is_empty()),
+            Err(message) => {
+                self.protocol_failed(message);
+                return;
+            }
+        };
         if decrypted.is_none() {
-            self.result = Some(Err("Task failed (data not output)".to_string()));
+            warn!("Protocol produced no result");
+            self.result = Some(Err((
+                FailureReason::ProtocolError,
+                "Task failed (protocol produced no result)".to_string(),
+                self.protocol.last_round(),
+            )));
             return;
         }
         let decrypted = decrypted.unwrap();
 
         info!(
-            "Data decrypted by group_id={}",
-            utils::hextrunc(self.group.identifier())
+            group_id = %logging::log_id(self.group.identifier()),
+            "Data decrypted"
         );
 
         self.result = Some(Ok(decrypted));
         self.communicator.clear_input();
     }
 
+    fn protocol_failed(&mut self, message: String) {
+        warn!(message, "Protocol failed");
+        self.result = Some(Err((
+            FailureReason::ProtocolError,
+            format!("Task failed ({})", message),
+            self.protocol.round(),
+        )));
+    }
+
     pub(super) fn next_round(&mut self) {
         if self.protocol.round() == 0 {
             self.start_task();
@@ -88,7 +125,7 @@ impl DecryptTask {
         device_id: &[u8],
         data: &Vec<Vec<u8>>,
     ) -> Result<bool, String> {
-        if self.communicator.accept_count() < self.group.threshold() {
+        if self.communicator.accept_count() < self.group.signer_count() {
             return Err("Not enough agreements to proceed with the protocol.".to_string());
         }
 
@@ -102,8 +139,11 @@ impl DecryptTask {
             .collect::<Result<Vec<_>, _>>()
             .map_err(|_| "Failed to decode messages".to_string())?;
 
-        self.communicator.receive_messages(device_id, messages);
-        self.last_update = get_timestamp();
+        if !self.communicator.receive_messages(device_id, messages) {
+            self.protocol_failed("participant sent malformed messages".into());
+            return Err("Malformed messages".to_string());
+        }
+        self.last_update = self.clock.now();
 
         if self.communicator.round_received() && self.protocol.round() <= self.protocol.last_round()
         {
@@ -114,12 +154,16 @@ impl DecryptTask {
 
     pub(super) fn decide_internal(&mut self, device_id: &[u8], decision: bool) -> Option<bool> {
         self.communicator.decide(device_id, decision);
-        self.last_update = get_timestamp();
+        self.last_update = self.clock.now();
         if self.result.is_none() && self.protocol.round() == 0 {
             if self.communicator.reject_count() >= self.group.reject_threshold() {
-                self.result = Some(Err("Task declined".to_string()));
+                self.result = Some(Err((
+                    FailureReason::Declined,
+                    "Task declined".to_string(),
+                    self.protocol.round(),
+                )));
                 return Some(false);
-            } else if self.communicator.accept_count() >= self.group.threshold() {
+            } else if self.communicator.accept_count() >= self.group.signer_count() {
                 return Some(true);
             }
         }
@@ -130,7 +174,7 @@ impl DecryptTask {
 impl Task for DecryptTask {
     fn get_status(&self) -> TaskStatus {
         match &self.result {
-            Some(Err(e)) => TaskStatus::Failed(e.clone()),
+            Some(Err((reason, message, _))) => TaskStatus::Failed(*reason, message.clone()),
             Some(Ok(_)) => TaskStatus::Finished,
             None => {
                 if self.protocol.round() == 0 {
@@ -142,16 +186,27 @@ impl Task for DecryptTask {
         }
     }
 
+    fn failed_round(&self) -> Option<u16> {
+        match &self.result {
+            Some(Err((_, _, round))) => Some(*round),
+            _ => None,
+        }
+    }
+
     fn get_type(&self) -> TaskType {
         TaskType::Decrypt
     }
 
+    fn get_protocol(&self) -> ProtocolType {
+        self.group.protocol()
+    }
+
     fn get_work(&self, device_id: Option<&[u8]>) -> Vec<Vec<u8>> {
         if device_id.is_none() || !self.waiting_for(device_id.unwrap()) {
             return Vec::new();
         }
 
-        self.communicator.get_messages(device_id.unwrap())
+        self.communicator.get_work(device_id.unwrap())
     }
 
     fn get_result(&self) -> Option<TaskResult> {
@@ -162,6 +217,10 @@ impl Task for DecryptTask {
         }
     }
 
+    fn get_data_digest(&self) -> Option<Vec<u8>> {
+        None
+    }
+
     fn get_decisions(&self) -> (u32, u32) {
         (
             self.communicator.accept_count(),
@@ -178,7 +237,7 @@ impl Task for DecryptTask {
     }
 
     fn restart(&mut self) -> Result<bool, String> {
-        self.last_update = get_timestamp();
+        self.last_update = self.clock.now();
         if self.result.is_some() {
             return Ok(false);
         }
@@ -192,12 +251,63 @@ impl Task for DecryptTask {
         }
     }
 
+    fn fail(&mut self, reason: FailureReason, message: &str) -> bool {
+        if self.result.is_some() {
+            return false;
+        }
+        self.result = Some(Err((reason, message.to_string(), self.protocol.round())));
+        self.last_update = self.clock.now();
+        true
+    }
+
+    fn reselect(&mut self, device_id: &[u8]) -> Result<bool, String> {
+        if self.result.is_some()
+            || self.protocol.round() == 0
+            || !self.communicator.is_active(device_id)
+            || !self.communicator.exclude_device(device_id)
+        {
+            return Ok(false);
+        }
+        self.last_update = self.clock.now();
+        self.attempts += 1;
+        self.start_task();
+        Ok(true)
+    }
+
     fn last_update(&self) -> u64 {
         self.last_update
     }
 
+    fn created_at(&self) -> u64 {
+        self.created_at
+    }
+
+    fn get_group_id(&self) -> Option<&[u8]> {
+        Some(self.group.identifier())
+    }
+
+    fn requester(&self) -> Option<&str> {
+        self.requester.as_deref()
+    }
+
+    fn set_requester(&mut self, requester: String) {
+        self.requester = Some(requester);
+    }
+
+    fn priority(&self) -> u32 {
+        self.priority
+    }
+
+    fn set_priority(&mut self, priority: u32) {
+        self.priority = priority;
+    }
+
+    fn deadline(&self) -> Option<u64> {
+        None
+    }
+
     fn is_approved(&self) -> bool {
-        self.communicator.accept_count() >= self.group.threshold()
+        self.communicator.accept_count() >= self.group.signer_count()
     }
 
     fn has_device(&self, device_id: &[u8]) -> bool {
@@ -226,6 +336,14 @@ impl Task for DecryptTask {
         result
     }
 
+    fn withdraw(&mut self, device_id: &[u8]) -> bool {
+        if self.result.is_some() || self.protocol.round() != 0 {
+            return false;
+        }
+        self.last_update = self.clock.now();
+        self.communicator.withdraw(device_id)
+    }
+
     fn acknowledge(&mut self, device_id: &[u8]) {
         self.communicator.acknowledge(device_id);
     }
@@ -241,4 +359,13 @@ impl Task for DecryptTask {
     fn get_attempts(&self) -> u32 {
         self.attempts
     }
+
+    fn relay_stats(&self) -> CommunicatorStats {
+        self.communicator.stats().clone()
+    }
+
+    fn participant_status(&self, device_id: &[u8]) -> ParticipantStatus {
+        self.communicator
+            .participant_status(device_id, self.protocol.round() > 0)
+    }
 }
@@ -0,0 +1,181 @@
+use crate::clock::Clock;
+use crate::communicator::{CommunicatorStats, ParticipantStatus};
+use crate::device::Device;
+use crate::proto::{ProtocolType, TaskType};
+use crate::tasks::{FailureReason, Task, TaskResult, TaskStatus};
+use tonic::codegen::Arc;
+
+/// Signing task waiting for the group task which establishes its group
+///
+/// The task has no participants; it is replaced by the actual signing task
+/// once the group is established, or fails together with the group task.
+pub struct DependentTask {
+    task_type: TaskType,
+    /// Protocol of the group being established
+    protocol: ProtocolType,
+    /// Encoded `SignRequest` of the task to start
+    request: Vec<u8>,
+    failure: Option<(FailureReason, String)>,
+    last_update: u64,
+    created_at: u64,
+    requester: Option<String>,
+    priority: u32,
+    clock: Arc<dyn Clock>,
+}
+
+impl DependentTask {
+    pub fn new(
+        task_type: TaskType,
+        protocol: ProtocolType,
+        request: Vec<u8>,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        DependentTask {
+            task_type,
+            protocol,
+            request,
+            failure: None,
+            last_update: clock.now(),
+            created_at: clock.now(),
+            requester: None,
+            priority: 0,
+            clock,
+        }
+    }
+}
+
+impl Task for DependentTask {
+    fn get_status(&self) -> TaskStatus {
+        match &self.failure {
+            Some((reason, message)) => TaskStatus::Failed(*reason, message.clone()),
+            None => TaskStatus::Created,
+        }
+    }
+
+    fn failed_round(&self) -> Option<u16> {
+        self.failure.as_ref().map(|_| 0)
+    }
+
+    fn get_type(&self) -> TaskType {
+        self.task_type
+    }
+
+    fn get_protocol(&self) -> ProtocolType {
+        self.protocol
+    }
+
+    fn get_work(&self, _device_id: Option<&[u8]>) -> Vec<Vec<u8>> {
+        Vec::new()
+    }
+
+    fn get_result(&self) -> Option<TaskResult> {
+        None
+    }
+
+    fn get_decisions(&self) -> (u32, u32) {
+        (0, 0)
+    }
+
+    fn get_data_digest(&self) -> Option<Vec<u8>> {
+        None
+    }
+
+    fn update(&mut self, _device_id: &[u8], _data: &Vec<Vec<u8>>) -> Result<bool, String> {
+        Err("Task waits for its dependency".into())
+    }
+
+    fn restart(&mut self) -> Result<bool, String> {
+        Ok(false)
+    }
+
+    fn fail(&mut self, reason: FailureReason, message: &str) -> bool {
+        if self.failure.is_some() {
+            return false;
+        }
+        self.failure = Some((reason, message.to_string()));
+        self.last_update = self.clock.now();
+        true
+    }
+
+    fn reselect(&mut self, _device_id: &[u8]) -> Result<bool, String> {
+        Ok(false)
+    }
+
+    fn last_update(&self) -> u64 {
+        self.last_update
+    }
+
+    fn created_at(&self) -> u64 {
+        self.created_at
+    }
+
+    fn get_group_id(&self) -> Option<&[u8]> {
+        None
+    }
+
+    fn requester(&self) -> Option<&str> {
+        self.requester.as_deref()
+    }
+
+    fn set_requester(&mut self, requester: String) {
+        self.requester = Some(requester);
+    }
+
+    fn priority(&self) -> u32 {
+        self.priority
+    }
+
+    fn set_priority(&mut self, priority: u32) {
+        self.priority = priority;
+    }
+
+    fn deadline(&self) -> Option<u64> {
+        None
+    }
+
+    fn is_approved(&self) -> bool {
+        false
+    }
+
+    fn has_device(&self, _device_id: &[u8]) -> bool {
+        false
+    }
+
+    fn get_devices(&self) -> Vec<Arc<Device>> {
+        Vec::new()
+    }
+
+    fn waiting_for(&self, _device_id: &[u8]) -> bool {
+        false
+    }
+
+    fn decide(&mut self, _device_id: &[u8], _decision: bool) -> Option<bool> {
+        None
+    }
+
+    fn withdraw(&mut self, _device_id: &[u8]) -> bool {
+        false
+    }
+
+    fn acknowledge(&mut self, _device_id: &[u8]) {}
+
+    fn device_acknowledged(&self, _device_id: &[u8]) -> bool {
+        false
+    }
+
+    fn get_request(&self) -> &[u8] {
+        &self.request
+    }
+
+    fn get_attempts(&self) -> u32 {
+        0
+    }
+
+    fn relay_stats(&self) -> CommunicatorStats {
+        CommunicatorStats::default()
+    }
+
+    fn participant_status(&self, _device_id: &[u8]) -> ParticipantStatus {
+        ParticipantStatus::Idle
+    }
+}
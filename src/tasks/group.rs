@@ -1,20 +1,30 @@
-use crate::communicator::Communicator;
+use crate::clock::Clock;
+use crate::communicator::{Communicator, CommunicatorStats, ParticipantStatus};
 use crate::device::Device;
 use crate::group::Group;
+use crate::logging;
 use crate::proto::{KeyType, ProtocolType, TaskType};
 use crate::protocols::elgamal::ElgamalGroup;
 use crate::protocols::frost::FROSTGroup;
 use crate::protocols::gg18::GG18Group;
 use crate::protocols::musig2::Musig2Group;
 use crate::protocols::Protocol;
-use crate::tasks::{Task, TaskResult, TaskStatus};
-use crate::{get_timestamp, utils};
-use log::{info, warn};
+use crate::tasks::{FailureReason, Task, TaskResult, TaskStatus};
 use meesign_crypto::proto::{ClientMessage, Message as _};
 use prost::Message as _;
 use std::io::Read;
 use std::process::{Command, Stdio};
 use tonic::codegen::Arc;
+use tracing::{info, warn};
+
+/// Combinations of protocols and key types for which groups can be established
+pub const SUPPORTED_SCHEMES: [(ProtocolType, KeyType); 5] = [
+    (ProtocolType::Gg18, KeyType::SignPdf),
+    (ProtocolType::Gg18, KeyType::SignChallenge),
+    (ProtocolType::Frost, KeyType::SignChallenge),
+    (ProtocolType::Musig2, KeyType::SignChallenge),
+    (ProtocolType::Elgamal, KeyType::Decrypt),
+];
 
 pub struct GroupTask {
     name: String,
@@ -22,15 +32,22 @@ pub struct GroupTask {
     key_type: KeyType,
     devices: Vec<Arc<Device>>,
     communicator: Communicator,
-    result: Option<Result<Group, String>>,
+    result: Option<Result<Group, (FailureReason, String, u16)>>,
     protocol: Box<dyn Protocol + Send + Sync>,
     request: Vec<u8>,
     last_update: u64,
+    created_at: u64,
+    requester: Option<String>,
+    priority: u32,
     attempts: u32,
     note: Option<String>,
+    tags: Vec<String>,
+    deadline: Option<u64>,
+    clock: Arc<dyn Clock>,
 }
 
 impl GroupTask {
+    #[allow(clippy::too_many_arguments)]
     pub fn try_new(
         name: &str,
         devices: &[Arc<Device>],
@@ -38,6 +55,9 @@ impl GroupTask {
         protocol_type: ProtocolType,
         key_type: KeyType,
         note: &Option<String>,
+        tags: &[String],
+        deadline_seconds: Option<u64>,
+        clock: Arc<dyn Clock>,
     ) -> Result<Self, String> {
         let devices_len = devices.len() as u32;
         let protocol: Box<dyn Protocol + Send + Sync> = match (protocol_type, key_type) {
@@ -58,23 +78,33 @@ impl GroupTask {
             }
             _ => {
                 warn!(
-                    "Protocol {:?} does not support {:?} key type",
-                    protocol_type, key_type
+                    ?protocol_type,
+                    ?key_type,
+                    "Protocol does not support key type"
                 );
-                return Err("Unsupported protocol type and key type combination".into());
+                return Err(format!(
+                    "Key type {} is not supported by protocol {}",
+                    key_type.as_str_name(),
+                    protocol_type.as_str_name()
+                ));
             }
         };
 
         if devices_len < 1 {
-            warn!("Invalid number of devices {}", devices_len);
+            warn!(devices_len, "Invalid number of devices");
             return Err("Invalid input".into());
         }
         if !protocol.get_type().check_threshold(threshold, devices_len) {
-            warn!("Invalid group threshold {}-of-{}", threshold, devices_len);
+            warn!(threshold, devices_len, "Invalid group threshold");
             return Err("Invalid input".into());
         }
 
-        let communicator = Communicator::new(&devices, devices.len() as u32, protocol.get_type());
+        let communicator = Communicator::new(
+            devices,
+            devices.len() as u32,
+            protocol.get_type(),
+            clock.clone(),
+        );
 
         let request = (crate::proto::GroupRequest {
             device_ids: devices.iter().map(|x| x.identifier().to_vec()).collect(),
@@ -83,6 +113,12 @@ impl GroupTask {
             protocol: protocol.get_type() as i32,
             key_type: key_type as i32,
             note: note.to_owned(),
+            deadline_seconds,
+            tags: tags.to_vec(),
+            priority: 0,
+            allowed_tasks: Vec::new(),
+            auto_approve: false,
+            require_all_signers: false,
         })
         .encode_to_vec();
 
@@ -95,9 +131,15 @@ impl GroupTask {
             result: None,
             protocol,
             request,
-            last_update: get_timestamp(),
+            last_update: clock.now(),
+            created_at: clock.now(),
+            requester: None,
+            priority: 0,
             attempts: 0,
             note: note.to_owned(),
+            tags: tags.to_vec(),
+            deadline: deadline_seconds.map(|seconds| clock.now() + seconds),
+            clock,
         })
     }
 
@@ -106,13 +148,26 @@ impl GroupTask {
     }
 
     fn advance_task(&mut self) {
-        self.protocol.advance(&mut self.communicator);
+        if let Err(message) = self.protocol.advance(&mut self.communicator) {
+            self.protocol_failed(message);
+        }
     }
 
     fn finalize_task(&mut self) {
-        let identifier = self.protocol.finalize(&mut self.communicator);
+        let identifier = match self.protocol.finalize(&mut self.communicator) {
+            Ok(output) => output.filter(|output| !output.is_empty()),
+            Err(message) => {
+                self.protocol_failed(message);
+                return;
+            }
+        };
         if identifier.is_none() {
-            self.result = Some(Err("Task failed (group key not output)".to_string()));
+            warn!("Protocol produced no result");
+            self.result = Some(Err((
+                FailureReason::ProtocolError,
+                "Task failed (protocol produced no result)".to_string(),
+                self.protocol.last_round(),
+            )));
             return;
         }
         let identifier = identifier.unwrap();
@@ -124,15 +179,16 @@ impl GroupTask {
         };
 
         info!(
-            "Group established group_id={} devices={:?}",
-            utils::hextrunc(&identifier),
-            self.devices
+            group_id = %logging::log_id(&identifier),
+            devices = ?self
+                .devices
                 .iter()
-                .map(|device| utils::hextrunc(device.identifier()))
-                .collect::<Vec<_>>()
+                .map(|device| logging::log_id(device.identifier()))
+                .collect::<Vec<_>>(),
+            "Group established"
         );
 
-        self.result = Some(Ok(Group::new(
+        let mut group = Group::new(
             identifier,
             self.name.clone(),
             self.devices.iter().map(Arc::clone).collect(),
@@ -141,11 +197,22 @@ impl GroupTask {
             self.key_type,
             certificate,
             self.note.clone(),
-        )));
+        );
+        group.set_tags(self.tags.clone());
+        self.result = Some(Ok(group));
 
         self.communicator.clear_input();
     }
 
+    fn protocol_failed(&mut self, message: String) {
+        warn!(message, "Protocol failed");
+        self.result = Some(Err((
+            FailureReason::ProtocolError,
+            format!("Task failed ({})", message),
+            self.protocol.round(),
+        )));
+    }
+
     fn next_round(&mut self) {
         if self.protocol.round() == 0 {
             self.start_task();
@@ -160,7 +227,7 @@ impl GroupTask {
 impl Task for GroupTask {
     fn get_status(&self) -> TaskStatus {
         match &self.result {
-            Some(Err(e)) => TaskStatus::Failed(e.clone()),
+            Some(Err((reason, message, _))) => TaskStatus::Failed(*reason, message.clone()),
             Some(Ok(_)) => TaskStatus::Finished,
             None => {
                 if self.protocol.round() == 0 {
@@ -172,26 +239,41 @@ impl Task for GroupTask {
         }
     }
 
+    fn failed_round(&self) -> Option<u16> {
+        match &self.result {
+            Some(Err((_, _, round))) => Some(*round),
+            _ => None,
+        }
+    }
+
     fn get_type(&self) -> TaskType {
         TaskType::Group
     }
 
+    fn get_protocol(&self) -> ProtocolType {
+        self.protocol.get_type()
+    }
+
     fn get_work(&self, device_id: Option<&[u8]>) -> Vec<Vec<u8>> {
         if device_id.is_none() || !self.waiting_for(device_id.unwrap()) {
             return Vec::new();
         }
 
-        self.communicator.get_messages(device_id.unwrap())
+        self.communicator.get_work(device_id.unwrap())
     }
 
     fn get_result(&self) -> Option<TaskResult> {
         if let Some(Ok(group)) = &self.result {
-            Some(TaskResult::GroupEstablished(group.clone()))
+            Some(TaskResult::GroupEstablished(Box::new(group.clone())))
         } else {
             None
         }
     }
 
+    fn get_data_digest(&self) -> Option<Vec<u8>> {
+        None
+    }
+
     fn get_decisions(&self) -> (u32, u32) {
         (
             self.communicator.accept_count(),
@@ -214,8 +296,11 @@ impl Task for GroupTask {
             .collect::<Result<Vec<_>, _>>()
             .map_err(|_| "Failed to decode messages".to_string())?;
 
-        self.communicator.receive_messages(device_id, messages);
-        self.last_update = get_timestamp();
+        if !self.communicator.receive_messages(device_id, messages) {
+            self.protocol_failed("participant sent malformed messages".into());
+            return Err("Malformed messages".to_string());
+        }
+        self.last_update = self.clock.now();
 
         if self.communicator.round_received() && self.protocol.round() <= self.protocol.last_round()
         {
@@ -227,7 +312,7 @@ impl Task for GroupTask {
     }
 
     fn restart(&mut self) -> Result<bool, String> {
-        self.last_update = get_timestamp();
+        self.last_update = self.clock.now();
         if self.result.is_some() {
             return Ok(false);
         }
@@ -241,10 +326,52 @@ impl Task for GroupTask {
         }
     }
 
+    fn fail(&mut self, reason: FailureReason, message: &str) -> bool {
+        if self.result.is_some() {
+            return false;
+        }
+        self.result = Some(Err((reason, message.to_string(), self.protocol.round())));
+        self.last_update = self.clock.now();
+        true
+    }
+
+    fn reselect(&mut self, _device_id: &[u8]) -> Result<bool, String> {
+        // all devices take part in key generation, there is nobody to replace them with
+        Ok(false)
+    }
+
     fn last_update(&self) -> u64 {
         self.last_update
     }
 
+    fn created_at(&self) -> u64 {
+        self.created_at
+    }
+
+    fn get_group_id(&self) -> Option<&[u8]> {
+        None
+    }
+
+    fn requester(&self) -> Option<&str> {
+        self.requester.as_deref()
+    }
+
+    fn set_requester(&mut self, requester: String) {
+        self.requester = Some(requester);
+    }
+
+    fn priority(&self) -> u32 {
+        self.priority
+    }
+
+    fn set_priority(&mut self, priority: u32) {
+        self.priority = priority;
+    }
+
+    fn deadline(&self) -> Option<u64> {
+        self.deadline
+    }
+
     fn is_approved(&self) -> bool {
         self.communicator.accept_count() == self.devices.len() as u32
     }
@@ -273,10 +400,14 @@ impl Task for GroupTask {
 
     fn decide(&mut self, device_id: &[u8], decision: bool) -> Option<bool> {
         self.communicator.decide(device_id, decision);
-        self.last_update = get_timestamp();
+        self.last_update = self.clock.now();
         if self.result.is_none() && self.protocol.round() == 0 {
             if self.communicator.reject_count() > 0 {
-                self.result = Some(Err("Task declined".to_string()));
+                self.result = Some(Err((
+                    FailureReason::Declined,
+                    "Task declined".to_string(),
+                    self.protocol.round(),
+                )));
                 return Some(false);
             } else if self.communicator.accept_count() == self.devices.len() as u32 {
                 self.next_round();
@@ -286,6 +417,14 @@ impl Task for GroupTask {
         None
     }
 
+    fn withdraw(&mut self, device_id: &[u8]) -> bool {
+        if self.result.is_some() || self.protocol.round() != 0 {
+            return false;
+        }
+        self.last_update = self.clock.now();
+        self.communicator.withdraw(device_id)
+    }
+
     fn acknowledge(&mut self, device_id: &[u8]) {
         self.communicator.acknowledge(device_id);
     }
@@ -301,6 +440,15 @@ impl Task for GroupTask {
     fn get_attempts(&self) -> u32 {
         self.attempts
     }
+
+    fn relay_stats(&self) -> CommunicatorStats {
+        self.communicator.stats().clone()
+    }
+
+    fn participant_status(&self, device_id: &[u8]) -> ParticipantStatus {
+        self.communicator
+            .participant_status(device_id, self.protocol.round() > 0)
+    }
 }
 
 fn issue_certificate(name: &str, public_key: &[u8]) -> Vec<u8> {
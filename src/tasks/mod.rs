@@ -1,26 +1,128 @@
+pub(crate) mod checked_out;
 pub(crate) mod decrypt;
+pub(crate) mod dependent;
 pub(crate) mod group;
 pub(crate) mod sign;
+pub(crate) mod sign_csr;
+pub(crate) mod sign_ethereum;
 pub(crate) mod sign_pdf;
 
+use crate::communicator::{CommunicatorStats, ParticipantStatus};
 use crate::device::Device;
 use crate::group::Group;
 use tonic::codegen::Arc;
 
-#[derive(Clone, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum TaskStatus {
     Created,
     Running(u16),
     // round
     Finished,
-    Failed(String),
+    Failed(FailureReason, String),
+}
+
+/// Machine-readable cause of a task failure
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FailureReason {
+    /// The task did not finish in time
+    Timeout,
+    /// Too many participants declined the task
+    Declined,
+    /// A participant disconnected while the task was waiting on it
+    ParticipantDisconnected,
+    /// The task input or output did not pass validation
+    VerificationFailed,
+    /// The task was cancelled before it finished
+    Cancelled,
+    /// The protocol did not produce a result
+    ProtocolError,
+    /// The task was restarted too many times
+    RestartLimitExceeded,
+    /// The participants did not decide on the task in time
+    ApprovalTimeout,
+    /// A participant reused a signing nonce of an earlier session
+    NonceReuse,
+    /// The task which the task depended on did not finish
+    DependencyFailed,
+}
+
+impl From<FailureReason> for crate::proto::task::FailureReason {
+    fn from(reason: FailureReason) -> Self {
+        match reason {
+            FailureReason::Timeout => Self::Timeout,
+            FailureReason::Declined => Self::Declined,
+            FailureReason::ParticipantDisconnected => Self::ParticipantDisconnected,
+            FailureReason::VerificationFailed => Self::VerificationFailed,
+            FailureReason::Cancelled => Self::Cancelled,
+            FailureReason::ProtocolError => Self::ProtocolError,
+            FailureReason::RestartLimitExceeded => Self::RestartLimitExceeded,
+            FailureReason::ApprovalTimeout => Self::ApprovalTimeout,
+            FailureReason::NonceReuse => Self::NonceReuse,
+            FailureReason::DependencyFailed => Self::DependencyFailed,
+        }
+    }
+}
+
+impl From<&TaskStatus> for crate::proto::task::TaskState {
+    fn from(status: &TaskStatus) -> Self {
+        match status {
+            TaskStatus::Created => Self::Created,
+            TaskStatus::Running(_) => Self::Running,
+            TaskStatus::Finished => Self::Finished,
+            TaskStatus::Failed(_, _) => Self::Failed,
+        }
+    }
+}
+
+/// Criteria for selecting tasks; criteria which are not set match any task
+#[derive(Clone, Debug, Default)]
+pub struct TaskFilter {
+    pub state: Option<crate::proto::task::TaskState>,
+    pub group_id: Option<Vec<u8>>,
+    /// Match tasks created at or after this timestamp
+    pub created_after: Option<u64>,
+    /// Match tasks created before this timestamp
+    pub created_before: Option<u64>,
+    pub device_id: Option<Vec<u8>>,
+}
+
+impl TaskFilter {
+    pub fn matches(&self, task: &dyn Task) -> bool {
+        let predicates: [&dyn Fn() -> bool; 5] = [
+            &|| {
+                self.state
+                    .is_none_or(|state| state == (&task.get_status()).into())
+            },
+            &|| {
+                self.group_id
+                    .as_ref()
+                    .is_none_or(|group_id| task.get_group_id() == Some(group_id))
+            },
+            &|| {
+                self.created_after
+                    .is_none_or(|timestamp| task.created_at() >= timestamp)
+            },
+            &|| {
+                self.created_before
+                    .is_none_or(|timestamp| task.created_at() < timestamp)
+            },
+            &|| {
+                self.device_id
+                    .as_ref()
+                    .is_none_or(|device_id| task.has_device(device_id))
+            },
+        ];
+        predicates.iter().all(|predicate| predicate())
+    }
 }
 
 #[derive(Clone)]
 pub enum TaskResult {
-    GroupEstablished(Group),
+    GroupEstablished(Box<Group>),
     Signed(Vec<u8>),
     SignedPdf(Vec<u8>),
+    SignedCertificate(Vec<u8>),
+    SignedEthereumTx(Vec<u8>),
     Decrypted(Vec<u8>),
 }
 
@@ -30,6 +132,8 @@ impl TaskResult {
             TaskResult::GroupEstablished(group) => group.identifier(),
             TaskResult::Signed(data) => data,
             TaskResult::SignedPdf(data) => data,
+            TaskResult::SignedCertificate(data) => data,
+            TaskResult::SignedEthereumTx(data) => data,
             TaskResult::Decrypted(data) => data,
         }
     }
@@ -37,10 +141,27 @@ impl TaskResult {
 
 pub trait Task {
     fn get_status(&self) -> TaskStatus;
+
+    /// Get the protocol round in which the task failed
+    ///
+    /// # Returns
+    /// `None` unless the task has failed
+    fn failed_round(&self) -> Option<u16>;
+
     fn get_type(&self) -> crate::proto::TaskType;
+
+    /// Get the protocol which the task runs
+    fn get_protocol(&self) -> crate::proto::ProtocolType;
+
     fn get_work(&self, device_id: Option<&[u8]>) -> Vec<Vec<u8>>;
     fn get_result(&self) -> Option<TaskResult>;
     fn get_decisions(&self) -> (u32, u32);
+
+    /// Get the digest of the data which the task signs
+    ///
+    /// # Returns
+    /// `None` for tasks which do not produce signatures
+    fn get_data_digest(&self) -> Option<Vec<u8>>;
     /// Update protocol state with `data` from `device_id`
     ///
     /// # Returns
@@ -53,9 +174,41 @@ pub trait Task {
     /// Ok(true) if task restarted successfully; Ok(false) otherwise.
     fn restart(&mut self) -> Result<bool, String>;
 
+    /// Fail the task with the given `reason` and human-readable `message`
+    ///
+    /// # Returns
+    /// `true` if this call failed the task; `false` if the task had already ended.
+    fn fail(&mut self, reason: FailureReason, message: &str) -> bool;
+
+    /// Exclude an active participant and restart the protocol with a new subset of devices
+    ///
+    /// # Returns
+    /// `Ok(true)` if the task restarted without the device; `Ok(false)` otherwise.
+    fn reselect(&mut self, device_id: &[u8]) -> Result<bool, String>;
+
     /// Get timestamp of the most recent task update
     fn last_update(&self) -> u64;
 
+    /// Get timestamp of the task creation
+    fn created_at(&self) -> u64;
+
+    /// Get identifier of the group performing the task
+    ///
+    /// # Returns
+    /// `None` for tasks which establish a new group
+    fn get_group_id(&self) -> Option<&[u8]>;
+
+    /// Get identity of the client which requested the task, if known
+    fn requester(&self) -> Option<&str>;
+    fn set_requester(&mut self, requester: String);
+
+    /// Get priority of the task; work of tasks with higher priority is handed out first
+    fn priority(&self) -> u32;
+    fn set_priority(&mut self, priority: u32);
+
+    /// Get timestamp after which the task fails unless it has finished
+    fn deadline(&self) -> Option<u64>;
+
     /// True if the task has been approved
     fn is_approved(&self) -> bool;
 
@@ -71,9 +224,46 @@ pub trait Task {
     /// `None` otherwise.
     fn decide(&mut self, device_id: &[u8], decision: bool) -> Option<bool>;
 
+    /// Ask connected members to decide on the task in place of those who declined
+    ///
+    /// Only members for which `online` holds are recruited.
+    ///
+    /// # Returns
+    /// `Some(true)` if members were recruited;
+    /// `Some(false)` if too few members remain to approve the task and it failed;
+    /// `None` otherwise.
+    fn recruit(&mut self, _online: &dyn Fn(&[u8]) -> bool) -> Option<bool> {
+        None
+    }
+
+    /// Withdraw an acceptance by `device_id` while the protocol has not started yet
+    ///
+    /// # Returns
+    /// `true` if the task awaits the decision of `device_id` again
+    fn withdraw(&mut self, device_id: &[u8]) -> bool;
+
     fn acknowledge(&mut self, device_id: &[u8]);
     fn device_acknowledged(&self, device_id: &[u8]) -> bool;
     fn get_request(&self) -> &[u8];
 
     fn get_attempts(&self) -> u32;
+
+    /// Get the traffic relayed between the participants so far
+    fn relay_stats(&self) -> CommunicatorStats;
+
+    /// Get the standing of `device_id` in the decision phase or in the current protocol round
+    fn participant_status(&self, device_id: &[u8]) -> ParticipantStatus;
+
+    /// Store a commitment of the accepting device `device_id` to participate in the protocol
+    ///
+    /// # Returns
+    /// `Ok(true)` if this commitment caused the protocol to start; `Ok(false)` otherwise.
+    fn commit(&mut self, _device_id: &[u8]) -> Result<bool, String> {
+        Err("Task has no commitment phase".into())
+    }
+
+    /// Check whether `device_id` committed to participate in the protocol
+    fn committed(&self, _device_id: &[u8]) -> bool {
+        false
+    }
 }
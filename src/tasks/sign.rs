@@ -1,40 +1,64 @@
-use crate::communicator::Communicator;
+use crate::clock::Clock;
+use crate::communicator::{Communicator, CommunicatorStats, ParticipantStatus};
 use crate::device::Device;
 use crate::group::Group;
-use crate::proto::{ProtocolType, SignRequest, TaskType};
+use crate::logging;
+use crate::proto::{HashAlgorithm, ProtocolType, SignRequest, TaskType};
 use crate::protocols::frost::FROSTSign;
 use crate::protocols::gg18::GG18Sign;
 use crate::protocols::musig2::Musig2Sign;
 use crate::protocols::Protocol;
-use crate::tasks::{Task, TaskResult, TaskStatus};
-use crate::{get_timestamp, utils};
-use log::{info, warn};
+use crate::tasks::{FailureReason, Task, TaskResult, TaskStatus};
 use meesign_crypto::proto::{ClientMessage, Message as _};
 use prost::Message as _;
+use sha2::{Digest, Sha256, Sha512};
+use sha3::Keccak256;
+use std::collections::HashSet;
 use tonic::codegen::Arc;
+use tracing::{info, warn};
 
 pub struct SignTask {
     group: Group,
     communicator: Communicator,
-    result: Option<Result<Vec<u8>, String>>,
+    result: Option<Result<Vec<u8>, (FailureReason, String, u16)>>,
     pub(super) data: Vec<u8>,
     preprocessed: Option<Vec<u8>>,
     pub(super) protocol: Box<dyn Protocol + Send + Sync>,
     request: Vec<u8>,
     pub(super) last_update: u64,
+    created_at: u64,
+    requester: Option<String>,
+    priority: u32,
     pub(super) attempts: u32,
+    deadline: Option<u64>,
+    /// Devices committed to participate; the commitment phase is skipped when unset
+    commitments: Option<HashSet<Vec<u8>>>,
+    /// Members asked to decide on the task; every member is asked when unset
+    recruited: Option<HashSet<Vec<u8>>>,
+    pub(super) clock: Arc<dyn Clock>,
 }
 
 impl SignTask {
-    pub fn try_new(group: Group, name: String, data: Vec<u8>) -> Result<Self, String> {
+    pub fn try_new(
+        group: Group,
+        name: String,
+        data: Vec<u8>,
+        deadline_seconds: Option<u64>,
+        hash_algorithm: HashAlgorithm,
+        clock: Arc<dyn Clock>,
+    ) -> Result<Self, String> {
         let protocol_type = group.protocol();
 
-        let communicator = Communicator::new(&group.devices(), group.threshold(), protocol_type);
+        let communicator = Communicator::for_group(&group, clock.clone());
 
         let request = (SignRequest {
             group_id: group.identifier().to_vec(),
             name,
             data: data.clone(),
+            deadline_seconds,
+            hash_algorithm: hash_algorithm.into(),
+            priority: 0,
+            depends_on: None,
         })
         .encode_to_vec();
 
@@ -42,20 +66,27 @@ impl SignTask {
             group,
             communicator,
             result: None,
+            preprocessed: hash_data(hash_algorithm, &data),
             data,
-            preprocessed: None,
             protocol: match protocol_type {
                 ProtocolType::Gg18 => Box::new(GG18Sign::new()),
                 ProtocolType::Frost => Box::new(FROSTSign::new()),
                 ProtocolType::Musig2 => Box::new(Musig2Sign::new()),
                 _ => {
-                    warn!("Protocol type {:?} does not support signing", protocol_type);
+                    warn!(?protocol_type, "Protocol type does not support signing");
                     return Err("Unsupported protocol type for signing".into());
                 }
             },
             request,
-            last_update: get_timestamp(),
+            last_update: clock.now(),
+            created_at: clock.now(),
+            requester: None,
+            priority: 0,
             attempts: 0,
+            deadline: deadline_seconds.map(|seconds| clock.now() + seconds),
+            commitments: None,
+            recruited: None,
+            clock,
         })
     }
 
@@ -68,8 +99,84 @@ impl SignTask {
         self.preprocessed = Some(preprocessed);
     }
 
+    /// Hold the protocol back until enough accepting devices commit to participate
+    pub fn require_commitments(&mut self) {
+        self.commitments = Some(HashSet::new());
+    }
+
+    /// Ask only as many members as signing needs to decide on the task
+    ///
+    /// Members for which `online` holds are preferred; others are asked only when too few
+    /// members are connected.
+    pub fn recruit_signers(&mut self, online: &dyn Fn(&[u8]) -> bool) {
+        self.recruited = Some(HashSet::new());
+        self.ask_members(online);
+        self.ask_members(&|_| true);
+    }
+
+    /// Ask members for which `online` holds until the asked members can approve the task
+    ///
+    /// # Returns
+    /// `true` if any member was asked
+    fn ask_members(&mut self, online: &dyn Fn(&[u8]) -> bool) -> bool {
+        let mut asked = false;
+        for device in self.group.devices().to_vec() {
+            if self.recruited_shares() >= self.group.signer_count() {
+                break;
+            }
+            let device_id = device.identifier();
+            if !self.asked(device_id) && online(device_id) {
+                self.recruited
+                    .get_or_insert_with(HashSet::new)
+                    .insert(device_id.to_vec());
+                asked = true;
+            }
+        }
+        asked
+    }
+
+    /// Check whether `device_id` was asked to decide on the task
+    fn asked(&self, device_id: &[u8]) -> bool {
+        self.recruited
+            .as_ref()
+            .is_none_or(|recruited| recruited.contains(device_id))
+    }
+
+    /// Get the number of shares held by asked members which have not declined
+    fn recruited_shares(&self) -> u32 {
+        self.group
+            .devices()
+            .iter()
+            .filter(|device| {
+                self.asked(device.identifier()) && !self.communicator.declined(device.identifier())
+            })
+            .count() as u32
+    }
+
+    /// Check whether the task is approved but still lacks commitments
+    fn awaiting_commitments(&self) -> bool {
+        let Some(commitments) = &self.commitments else {
+            return false;
+        };
+        let committed_shares = self
+            .group
+            .devices()
+            .iter()
+            .filter(|device| commitments.contains(device.identifier()))
+            .count();
+        committed_shares < self.group.signer_count() as usize
+    }
+
     pub(super) fn start_task(&mut self) {
-        assert!(self.communicator.accept_count() >= self.group.threshold());
+        assert!(self.communicator.accept_count() >= self.group.signer_count());
+        if let Some(commitments) = &self.commitments {
+            // only committed devices may be selected to sign
+            for device in self.group.devices() {
+                if !commitments.contains(device.identifier()) {
+                    self.communicator.exclude_device(device.identifier());
+                }
+            }
+        }
         self.protocol.initialize(
             &mut self.communicator,
             self.preprocessed.as_ref().unwrap_or(&self.data),
@@ -77,29 +184,70 @@ impl SignTask {
     }
 
     pub(super) fn advance_task(&mut self) {
-        self.protocol.advance(&mut self.communicator)
+        if let Err(message) = self.protocol.advance(&mut self.communicator) {
+            self.protocol_failed(message);
+        }
     }
 
     pub(super) fn finalize_task(&mut self) {
-        let signature = self.protocol.finalize(&mut self.communicator);
+        let signature = match self.protocol.finalize(&mut self.communicator) {
+            Ok(output) => output.filter(|output| !output.is_empty()),
+            Err(message) => {
+                self.protocol_failed(message);
+                return;
+            }
+        };
         if signature.is_none() {
-            self.result = Some(Err("Task failed (signature not output)".to_string()));
+            warn!("Protocol produced no result");
+            self.result = Some(Err((
+                FailureReason::ProtocolError,
+                "Task failed (protocol produced no result)".to_string(),
+                self.protocol.last_round(),
+            )));
             return;
         }
         let signature = signature.unwrap();
 
         info!(
-            "Signature created by group_id={}",
-            utils::hextrunc(self.group.identifier())
+            group_id = %logging::log_id(self.group.identifier()),
+            "Signature created"
         );
 
         self.result = Some(Ok(signature));
         self.communicator.clear_input();
     }
 
+    /// Exclude an active participant from the running protocol
+    ///
+    /// # Returns
+    /// `true` if enough accepting devices remain and the protocol should be started again
+    pub(super) fn exclude_participant(&mut self, device_id: &[u8]) -> bool {
+        if self.result.is_some()
+            || self.protocol.round() == 0
+            || !self.communicator.is_active(device_id)
+            || !self.communicator.exclude_device(device_id)
+        {
+            return false;
+        }
+        self.last_update = self.clock.now();
+        self.attempts += 1;
+        true
+    }
+
+    fn protocol_failed(&mut self, message: String) {
+        warn!(message, "Protocol failed");
+        self.result = Some(Err((
+            FailureReason::ProtocolError,
+            format!("Task failed ({})", message),
+            self.protocol.round(),
+        )));
+    }
+
     pub(super) fn next_round(&mut self) {
         if self.protocol.round() == 0 {
-            self.start_task();
+            if !self.awaiting_commitments() {
+                self.start_task();
+            }
         } else if self.protocol.round() < self.protocol.last_round() {
             self.advance_task()
         } else {
@@ -112,7 +260,7 @@ impl SignTask {
         device_id: &[u8],
         data: &Vec<Vec<u8>>,
     ) -> Result<bool, String> {
-        if self.communicator.accept_count() < self.group.threshold() {
+        if self.communicator.accept_count() < self.group.signer_count() {
             return Err("Not enough agreements to proceed with the protocol.".to_string());
         }
 
@@ -126,8 +274,11 @@ impl SignTask {
             .collect::<Result<Vec<_>, _>>()
             .map_err(|_| "Failed to decode messages".to_string())?;
 
-        self.communicator.receive_messages(device_id, messages);
-        self.last_update = get_timestamp();
+        if !self.communicator.receive_messages(device_id, messages) {
+            self.protocol_failed("participant sent malformed messages".into());
+            return Err("Malformed messages".to_string());
+        }
+        self.last_update = self.clock.now();
 
         if self.communicator.round_received() && self.protocol.round() <= self.protocol.last_round()
         {
@@ -137,13 +288,20 @@ impl SignTask {
     }
 
     pub(super) fn decide_internal(&mut self, device_id: &[u8], decision: bool) -> Option<bool> {
+        if !self.asked(device_id) {
+            return None;
+        }
         self.communicator.decide(device_id, decision);
-        self.last_update = get_timestamp();
+        self.last_update = self.clock.now();
         if self.result.is_none() && self.protocol.round() == 0 {
             if self.communicator.reject_count() >= self.group.reject_threshold() {
-                self.result = Some(Err("Task declined".to_string()));
+                self.result = Some(Err((
+                    FailureReason::Declined,
+                    "Task declined".to_string(),
+                    self.protocol.round(),
+                )));
                 return Some(false);
-            } else if self.communicator.accept_count() >= self.group.threshold() {
+            } else if self.communicator.accept_count() >= self.group.signer_count() {
                 return Some(true);
             }
         }
@@ -154,7 +312,7 @@ impl SignTask {
 impl Task for SignTask {
     fn get_status(&self) -> TaskStatus {
         match &self.result {
-            Some(Err(e)) => TaskStatus::Failed(e.clone()),
+            Some(Err((reason, message, _))) => TaskStatus::Failed(*reason, message.clone()),
             Some(Ok(_)) => TaskStatus::Finished,
             None => {
                 if self.protocol.round() == 0 {
@@ -166,16 +324,27 @@ impl Task for SignTask {
         }
     }
 
+    fn failed_round(&self) -> Option<u16> {
+        match &self.result {
+            Some(Err((_, _, round))) => Some(*round),
+            _ => None,
+        }
+    }
+
     fn get_type(&self) -> TaskType {
         TaskType::SignChallenge
     }
 
+    fn get_protocol(&self) -> ProtocolType {
+        self.group.protocol()
+    }
+
     fn get_work(&self, device_id: Option<&[u8]>) -> Vec<Vec<u8>> {
         if device_id.is_none() || !self.waiting_for(device_id.unwrap()) {
             return Vec::new();
         }
 
-        self.communicator.get_messages(device_id.unwrap())
+        self.communicator.get_work(device_id.unwrap())
     }
 
     fn get_result(&self) -> Option<TaskResult> {
@@ -186,6 +355,14 @@ impl Task for SignTask {
         }
     }
 
+    fn get_data_digest(&self) -> Option<Vec<u8>> {
+        // preprocessed data, such as the hash from PDFHelper, is already a digest
+        match &self.preprocessed {
+            Some(preprocessed) => Some(preprocessed.clone()),
+            None => Some(Sha256::digest(&self.data).to_vec()),
+        }
+    }
+
     fn get_decisions(&self) -> (u32, u32) {
         (
             self.communicator.accept_count(),
@@ -202,12 +379,12 @@ impl Task for SignTask {
     }
 
     fn restart(&mut self) -> Result<bool, String> {
-        self.last_update = get_timestamp();
+        self.last_update = self.clock.now();
         if self.result.is_some() {
             return Ok(false);
         }
 
-        if self.is_approved() {
+        if self.is_approved() && !self.awaiting_commitments() {
             self.attempts += 1;
             self.start_task();
             Ok(true)
@@ -216,12 +393,58 @@ impl Task for SignTask {
         }
     }
 
+    fn fail(&mut self, reason: FailureReason, message: &str) -> bool {
+        if self.result.is_some() {
+            return false;
+        }
+        self.result = Some(Err((reason, message.to_string(), self.protocol.round())));
+        self.last_update = self.clock.now();
+        true
+    }
+
+    fn reselect(&mut self, device_id: &[u8]) -> Result<bool, String> {
+        if self.exclude_participant(device_id) {
+            self.start_task();
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
     fn last_update(&self) -> u64 {
         self.last_update
     }
 
+    fn created_at(&self) -> u64 {
+        self.created_at
+    }
+
+    fn get_group_id(&self) -> Option<&[u8]> {
+        Some(self.group.identifier())
+    }
+
+    fn requester(&self) -> Option<&str> {
+        self.requester.as_deref()
+    }
+
+    fn set_requester(&mut self, requester: String) {
+        self.requester = Some(requester);
+    }
+
+    fn priority(&self) -> u32 {
+        self.priority
+    }
+
+    fn set_priority(&mut self, priority: u32) {
+        self.priority = priority;
+    }
+
+    fn deadline(&self) -> Option<u64> {
+        self.deadline
+    }
+
     fn is_approved(&self) -> bool {
-        self.communicator.accept_count() >= self.group.threshold()
+        self.communicator.accept_count() >= self.group.signer_count()
     }
 
     fn has_device(&self, device_id: &[u8]) -> bool {
@@ -234,7 +457,7 @@ impl Task for SignTask {
 
     fn waiting_for(&self, device: &[u8]) -> bool {
         if self.protocol.round() == 0 {
-            return !self.communicator.device_decided(device);
+            return self.asked(device) && !self.communicator.device_decided(device);
         } else if self.protocol.round() >= self.protocol.last_round() {
             return !self.communicator.device_acknowledged(device);
         }
@@ -250,6 +473,34 @@ impl Task for SignTask {
         result
     }
 
+    fn recruit(&mut self, online: &dyn Fn(&[u8]) -> bool) -> Option<bool> {
+        if self.recruited.is_none() || self.result.is_some() || self.protocol.round() != 0 {
+            return None;
+        }
+        let recruited = self.ask_members(online);
+        if self.recruited_shares() >= self.group.signer_count() {
+            if recruited {
+                self.last_update = self.clock.now();
+            }
+            return recruited.then_some(true);
+        }
+        self.result = Some(Err((
+            FailureReason::Declined,
+            "Task declined (no members left to recruit)".to_string(),
+            self.protocol.round(),
+        )));
+        self.last_update = self.clock.now();
+        Some(false)
+    }
+
+    fn withdraw(&mut self, device_id: &[u8]) -> bool {
+        if self.result.is_some() || self.protocol.round() != 0 {
+            return false;
+        }
+        self.last_update = self.clock.now();
+        self.communicator.withdraw(device_id)
+    }
+
     fn acknowledge(&mut self, device_id: &[u8]) {
         self.communicator.acknowledge(device_id);
     }
@@ -265,4 +516,197 @@ impl Task for SignTask {
     fn get_attempts(&self) -> u32 {
         self.attempts
     }
+
+    fn relay_stats(&self) -> CommunicatorStats {
+        self.communicator.stats().clone()
+    }
+
+    fn participant_status(&self, device_id: &[u8]) -> ParticipantStatus {
+        if self.protocol.round() == 0 && !self.asked(device_id) {
+            return ParticipantStatus::Idle;
+        }
+        self.communicator
+            .participant_status(device_id, self.protocol.round() > 0)
+    }
+
+    fn commit(&mut self, device_id: &[u8]) -> Result<bool, String> {
+        let Some(commitments) = &mut self.commitments else {
+            return Err("Task has no commitment phase".into());
+        };
+        if self.result.is_some() || self.protocol.round() != 0 {
+            return Err("Commitments are accepted only before the protocol starts".into());
+        }
+        if !self.communicator.accepted(device_id) {
+            return Err("Only accepting devices may commit".into());
+        }
+        commitments.insert(device_id.to_vec());
+        self.last_update = self.clock.now();
+        if !self.is_approved() || self.awaiting_commitments() {
+            return Ok(false);
+        }
+        self.start_task();
+        Ok(true)
+    }
+
+    fn committed(&self, device_id: &[u8]) -> bool {
+        self.commitments
+            .as_ref()
+            .is_some_and(|commitments| commitments.contains(device_id))
+    }
+}
+
+/// Hash `data` with the requested algorithm
+///
+/// # Returns
+/// `None` if the data are to be passed to the protocol unchanged
+fn hash_data(algorithm: HashAlgorithm, data: &[u8]) -> Option<Vec<u8>> {
+    match algorithm {
+        HashAlgorithm::Default => None,
+        HashAlgorithm::Sha256 => Some(Sha256::digest(data).to_vec()),
+        HashAlgorithm::Sha512 => Some(Sha512::digest(data).to_vec()),
+        HashAlgorithm::Keccak256 => Some(Keccak256::digest(data).to_vec()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::SystemClock;
+    use crate::proto::{DeviceKind, KeyType};
+
+    #[test]
+    fn data_digest() {
+        let data = b"Sample challenge".to_vec();
+        let mut task = SignTask::try_new(
+            prepare_group(),
+            "Sample Task".into(),
+            data.clone(),
+            None,
+            HashAlgorithm::Default,
+            Arc::new(SystemClock),
+        )
+        .unwrap();
+        assert_eq!(
+            task.get_data_digest(),
+            Some(openssl::sha::sha256(&data).to_vec())
+        );
+
+        task.set_preprocessed(vec![0x01; 32]);
+        assert_eq!(task.get_data_digest(), Some(vec![0x01; 32]));
+    }
+
+    #[test]
+    fn hash_algorithms() {
+        let data = b"Sample challenge".to_vec();
+        let prepare_task = |algorithm| {
+            SignTask::try_new(
+                prepare_group(),
+                "Sample Task".into(),
+                data.clone(),
+                None,
+                algorithm,
+                Arc::new(SystemClock),
+            )
+            .unwrap()
+        };
+        let sha256 = prepare_task(HashAlgorithm::Sha256)
+            .get_data_digest()
+            .unwrap();
+        let keccak256 = prepare_task(HashAlgorithm::Keccak256)
+            .get_data_digest()
+            .unwrap();
+        assert_eq!(sha256, openssl::sha::sha256(&data).to_vec());
+        assert_eq!(keccak256.len(), 32);
+        assert_ne!(sha256, keccak256);
+        assert_eq!(
+            hex::encode(hash_data(HashAlgorithm::Keccak256, b"").unwrap()),
+            "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+        );
+    }
+
+    #[test]
+    fn missing_result_fails_task() {
+        struct FixedOutput(Option<Vec<u8>>);
+
+        impl Protocol for FixedOutput {
+            fn initialize(&mut self, _: &mut Communicator, _: &[u8]) {}
+            fn advance(&mut self, _: &mut Communicator) -> Result<(), String> {
+                Ok(())
+            }
+            fn finalize(&mut self, _: &mut Communicator) -> Result<Option<Vec<u8>>, String> {
+                Ok(self.0.clone())
+            }
+            fn round(&self) -> u16 {
+                1
+            }
+            fn last_round(&self) -> u16 {
+                1
+            }
+            fn get_type(&self) -> ProtocolType {
+                ProtocolType::Gg18
+            }
+        }
+
+        for output in [None, Some(Vec::new())] {
+            let mut task = SignTask::try_new(
+                prepare_group(),
+                "Sample Task".into(),
+                b"Sample challenge".to_vec(),
+                None,
+                HashAlgorithm::Default,
+                Arc::new(SystemClock),
+            )
+            .unwrap();
+            task.protocol = Box::new(FixedOutput(output));
+            task.finalize_task();
+            assert!(matches!(
+                task.get_status(),
+                TaskStatus::Failed(FailureReason::ProtocolError, _)
+            ));
+            assert!(task.get_result().is_none());
+        }
+    }
+
+    #[test]
+    fn finalize_out_of_order_fails_task() {
+        let mut task = SignTask::try_new(
+            prepare_group(),
+            "Sample Task".into(),
+            b"Sample challenge".to_vec(),
+            None,
+            HashAlgorithm::Default,
+            Arc::new(SystemClock),
+        )
+        .unwrap();
+        task.finalize_task();
+        assert!(matches!(
+            task.get_status(),
+            TaskStatus::Failed(FailureReason::ProtocolError, _)
+        ));
+        assert_eq!(task.failed_round(), Some(0));
+    }
+
+    fn prepare_group() -> Group {
+        let devices = (0..2)
+            .map(|i| {
+                Arc::new(Device::new(
+                    vec![i as u8],
+                    format!("d{}", i),
+                    DeviceKind::User,
+                    vec![0xf0 | i as u8],
+                    0,
+                ))
+            })
+            .collect();
+        Group::new(
+            vec![0xaa; 4],
+            String::from("Sample Group"),
+            devices,
+            2,
+            ProtocolType::Gg18,
+            KeyType::SignChallenge,
+            None,
+            None,
+        )
+    }
 }
@@ -0,0 +1,533 @@
+use crate::clock::Clock;
+use crate::communicator::{CommunicatorStats, ParticipantStatus};
+use crate::device::Device;
+use crate::group::Group;
+use crate::logging;
+use crate::proto::{HashAlgorithm, KeyType, ProtocolType, TaskType};
+use crate::tasks::sign::SignTask;
+use crate::tasks::{FailureReason, Task, TaskResult, TaskStatus};
+use openssl::asn1::{Asn1Integer, Asn1Time};
+use openssl::bn::BigNum;
+use openssl::ec::{EcGroup, EcKey};
+use openssl::ecdsa::EcdsaSig;
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::PKey;
+use openssl::x509::extension::BasicConstraints;
+use openssl::x509::{X509Builder, X509NameBuilder, X509Req, X509ReqRef};
+use rand::Rng;
+use sha2::{Digest, Sha256};
+use tonic::codegen::Arc;
+use tracing::{info, warn};
+
+/// Maximal size of a certificate signing request
+pub const MAX_CSR_SIZE: usize = 64 * 1024;
+
+/// Validity of issued certificates
+const CERTIFICATE_VALIDITY_DAYS: u32 = 365;
+
+/// DER-encoded object identifiers of the accepted CSR signature algorithms
+const SUPPORTED_CSR_ALGORITHMS: [&[u8]; 7] = [
+    // ecdsa-with-SHA256, ecdsa-with-SHA384, ecdsa-with-SHA512
+    &[0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02],
+    &[0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x03],
+    &[0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x04],
+    // sha256WithRSAEncryption, sha384WithRSAEncryption, sha512WithRSAEncryption
+    &[
+        0x06, 0x09, 0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0b,
+    ],
+    &[
+        0x06, 0x09, 0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0c,
+    ],
+    &[
+        0x06, 0x09, 0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x0d,
+    ],
+    // Ed25519
+    &[0x06, 0x03, 0x2b, 0x65, 0x70],
+];
+
+/// Issue an X.509 certificate for a CSR signed by a threshold ECDSA group
+pub struct SignCsrTask {
+    sign_task: SignTask,
+    template: Vec<u8>,
+    result: Option<Result<Vec<u8>, (FailureReason, String, u16)>>,
+}
+
+impl SignCsrTask {
+    pub fn try_new(
+        group: Group,
+        name: String,
+        csr: Vec<u8>,
+        deadline_seconds: Option<u64>,
+        clock: Arc<dyn Clock>,
+    ) -> Result<Self, String> {
+        if csr.len() > MAX_CSR_SIZE {
+            warn!(len = csr.len(), "CSR too large");
+            return Err("CSR is too large".into());
+        }
+        if group.protocol() != ProtocolType::Gg18 || group.key_type() != KeyType::SignChallenge {
+            warn!(
+                protocol = ?group.protocol(),
+                key_type = ?group.key_type(),
+                "Group cannot issue certificates"
+            );
+            return Err("Only GG18 challenge-signing groups can issue certificates".into());
+        }
+
+        let request = X509Req::from_pem(&csr)
+            .or_else(|_| X509Req::from_der(&csr))
+            .map_err(|_| "Malformed CSR".to_string())?;
+        check_csr(&request)?;
+        let template = prepare_certificate(&request, &group)?;
+        let (_, certificate, _) = split_der(&template).ok_or("Malformed certificate template")?;
+        let (tbs, _, _) = split_der(certificate).ok_or("Malformed certificate template")?;
+        let digest = Sha256::digest(tbs).to_vec();
+
+        let mut sign_task = SignTask::try_new(
+            group,
+            name,
+            csr,
+            deadline_seconds,
+            HashAlgorithm::Default,
+            clock,
+        )?;
+        sign_task.set_preprocessed(digest);
+
+        Ok(SignCsrTask {
+            sign_task,
+            template,
+            result: None,
+        })
+    }
+
+    /// Ask only as many members as signing needs to decide on the task
+    ///
+    /// See [`SignTask::recruit_signers`].
+    pub fn recruit_signers(&mut self, online: &dyn Fn(&[u8]) -> bool) {
+        self.sign_task.recruit_signers(online);
+    }
+
+    /// Assemble the certificate once the signing protocol has output a signature
+    fn issue_certificate(&mut self) {
+        if self.result.is_some() {
+            return;
+        }
+        if let Some(TaskResult::Signed(signature)) = self.sign_task.get_result() {
+            self.result = Some(
+                assemble_certificate(&self.template, &signature)
+                    .inspect(|_| {
+                        info!(
+                            group_id = %logging::log_id(self.sign_task.get_group().identifier()),
+                            "Certificate issued"
+                        );
+                    })
+                    .map_err(|message| {
+                        warn!(message, "Could not assemble certificate");
+                        (
+                            FailureReason::VerificationFailed,
+                            "Task failed (invalid signature)".to_string(),
+                            self.sign_task.protocol.last_round(),
+                        )
+                    }),
+            );
+        }
+    }
+}
+
+impl Task for SignCsrTask {
+    fn get_status(&self) -> TaskStatus {
+        match &self.result {
+            Some(Err((reason, message, _))) => TaskStatus::Failed(*reason, message.clone()),
+            _ => self.sign_task.get_status(),
+        }
+    }
+
+    fn failed_round(&self) -> Option<u16> {
+        match &self.result {
+            Some(Err((_, _, round))) => Some(*round),
+            _ => self.sign_task.failed_round(),
+        }
+    }
+
+    fn get_type(&self) -> TaskType {
+        TaskType::SignCsr
+    }
+
+    fn get_protocol(&self) -> ProtocolType {
+        self.sign_task.get_protocol()
+    }
+
+    fn get_work(&self, device_id: Option<&[u8]>) -> Vec<Vec<u8>> {
+        self.sign_task.get_work(device_id)
+    }
+
+    fn get_result(&self) -> Option<TaskResult> {
+        if let Some(Ok(certificate)) = &self.result {
+            Some(TaskResult::SignedCertificate(certificate.clone()))
+        } else {
+            None
+        }
+    }
+
+    fn get_data_digest(&self) -> Option<Vec<u8>> {
+        self.sign_task.get_data_digest()
+    }
+
+    fn get_decisions(&self) -> (u32, u32) {
+        self.sign_task.get_decisions()
+    }
+
+    fn update(&mut self, device_id: &[u8], data: &Vec<Vec<u8>>) -> Result<bool, String> {
+        let result = self.sign_task.update(device_id, data);
+        if let Ok(true) = result {
+            self.issue_certificate();
+        }
+        result
+    }
+
+    fn restart(&mut self) -> Result<bool, String> {
+        self.sign_task.restart()
+    }
+
+    fn fail(&mut self, reason: FailureReason, message: &str) -> bool {
+        self.result.is_none() && self.sign_task.fail(reason, message)
+    }
+
+    fn reselect(&mut self, device_id: &[u8]) -> Result<bool, String> {
+        self.sign_task.reselect(device_id)
+    }
+
+    fn last_update(&self) -> u64 {
+        self.sign_task.last_update()
+    }
+
+    fn created_at(&self) -> u64 {
+        self.sign_task.created_at()
+    }
+
+    fn get_group_id(&self) -> Option<&[u8]> {
+        self.sign_task.get_group_id()
+    }
+
+    fn requester(&self) -> Option<&str> {
+        self.sign_task.requester()
+    }
+
+    fn set_requester(&mut self, requester: String) {
+        self.sign_task.set_requester(requester)
+    }
+
+    fn priority(&self) -> u32 {
+        self.sign_task.priority()
+    }
+
+    fn set_priority(&mut self, priority: u32) {
+        self.sign_task.set_priority(priority)
+    }
+
+    fn deadline(&self) -> Option<u64> {
+        self.sign_task.deadline()
+    }
+
+    fn is_approved(&self) -> bool {
+        self.sign_task.is_approved()
+    }
+
+    fn has_device(&self, device_id: &[u8]) -> bool {
+        self.sign_task.has_device(device_id)
+    }
+
+    fn get_devices(&self) -> Vec<Arc<Device>> {
+        self.sign_task.get_devices()
+    }
+
+    fn waiting_for(&self, device: &[u8]) -> bool {
+        self.sign_task.waiting_for(device)
+    }
+
+    fn decide(&mut self, device_id: &[u8], decision: bool) -> Option<bool> {
+        self.sign_task.decide(device_id, decision)
+    }
+
+    fn recruit(&mut self, online: &dyn Fn(&[u8]) -> bool) -> Option<bool> {
+        self.sign_task.recruit(online)
+    }
+
+    fn withdraw(&mut self, device_id: &[u8]) -> bool {
+        self.sign_task.withdraw(device_id)
+    }
+
+    fn acknowledge(&mut self, device_id: &[u8]) {
+        self.sign_task.acknowledge(device_id);
+    }
+
+    fn device_acknowledged(&self, device_id: &[u8]) -> bool {
+        self.sign_task.device_acknowledged(device_id)
+    }
+
+    fn get_request(&self) -> &[u8] {
+        self.sign_task.get_request()
+    }
+
+    fn get_attempts(&self) -> u32 {
+        self.sign_task.get_attempts()
+    }
+
+    fn relay_stats(&self) -> CommunicatorStats {
+        self.sign_task.relay_stats()
+    }
+
+    fn participant_status(&self, device_id: &[u8]) -> ParticipantStatus {
+        self.sign_task.participant_status(device_id)
+    }
+}
+
+/// Check that the CSR is self-signed with a supported algorithm
+fn check_csr(request: &X509ReqRef) -> Result<(), String> {
+    let der = request.to_der().map_err(|e| e.to_string())?;
+    let algorithm = split_der(&der)
+        .and_then(|(_, request, _)| split_der(request))
+        .and_then(|(_, _, rest)| split_der(rest))
+        .and_then(|(_, algorithm, _)| split_der(algorithm))
+        .map(|(oid, _, _)| oid)
+        .ok_or("Malformed CSR")?;
+    if !SUPPORTED_CSR_ALGORITHMS.contains(&algorithm) {
+        warn!(
+            algorithm = hex::encode(algorithm),
+            "Unsupported CSR signature algorithm"
+        );
+        return Err("Unsupported CSR signature algorithm".into());
+    }
+
+    let public_key = request
+        .public_key()
+        .map_err(|_| "Malformed CSR public key".to_string())?;
+    if !request.verify(&public_key).unwrap_or(false) {
+        return Err("Invalid CSR signature".into());
+    }
+    Ok(())
+}
+
+/// Build the certificate to be issued for `request`
+///
+/// The certificate is signed with a throwaway key so that its to-be-signed part declares
+/// ecdsa-with-SHA256; the group signature replaces the throwaway one later.
+fn prepare_certificate(request: &X509ReqRef, group: &Group) -> Result<Vec<u8>, String> {
+    let build = || -> Result<Vec<u8>, openssl::error::ErrorStack> {
+        let mut builder = X509Builder::new()?;
+        builder.set_version(2)?;
+
+        let serial: [u8; 16] = rand::thread_rng().gen();
+        let serial = BigNum::from_slice(&serial)?;
+        let serial = Asn1Integer::from_bn(&serial)?;
+        builder.set_serial_number(&serial)?;
+
+        let mut issuer = X509NameBuilder::new()?;
+        issuer.append_entry_by_nid(Nid::COMMONNAME, group.name())?;
+        builder.set_issuer_name(&issuer.build())?;
+        builder.set_subject_name(request.subject_name())?;
+        let public_key = request.public_key()?;
+        builder.set_pubkey(&public_key)?;
+        let not_before = Asn1Time::days_from_now(0)?;
+        builder.set_not_before(&not_before)?;
+        let not_after = Asn1Time::days_from_now(CERTIFICATE_VALIDITY_DAYS)?;
+        builder.set_not_after(&not_after)?;
+        builder.append_extension(BasicConstraints::new().critical().build()?)?;
+
+        let curve = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
+        let throwaway = PKey::from_ec_key(EcKey::generate(&curve)?)?;
+        builder.sign(&throwaway, MessageDigest::sha256())?;
+        builder.build().to_der()
+    };
+    build().map_err(|e| e.to_string())
+}
+
+/// Replace the signature of the certificate `template` with the 64-byte ECDSA `signature`
+fn assemble_certificate(template: &[u8], signature: &[u8]) -> Result<Vec<u8>, String> {
+    if signature.len() != 64 {
+        return Err(format!("Unexpected signature length {}", signature.len()));
+    }
+    let (_, certificate, _) = split_der(template).ok_or("Malformed certificate template")?;
+    let (tbs, _, rest) = split_der(certificate).ok_or("Malformed certificate template")?;
+    let (algorithm, _, _) = split_der(rest).ok_or("Malformed certificate template")?;
+
+    let signature = BigNum::from_slice(&signature[..32])
+        .and_then(|r| Ok((r, BigNum::from_slice(&signature[32..])?)))
+        .and_then(|(r, s)| EcdsaSig::from_private_components(r, s))
+        .and_then(|signature| signature.to_der())
+        .map_err(|e| e.to_string())?;
+    let mut bit_string = vec![0x00]; // no unused bits
+    bit_string.extend_from_slice(&signature);
+
+    let mut contents = Vec::new();
+    contents.extend_from_slice(tbs);
+    contents.extend_from_slice(algorithm);
+    contents.extend(encode_der(0x03, &bit_string));
+    Ok(encode_der(0x30, &contents))
+}
+
+/// Split the first DER element off `data`
+///
+/// # Returns
+/// The whole element, its contents and the data following the element
+fn split_der(data: &[u8]) -> Option<(&[u8], &[u8], &[u8])> {
+    let length_byte = *data.get(1)?;
+    let (header, length) = if length_byte < 0x80 {
+        (2, length_byte as usize)
+    } else {
+        let count = (length_byte & 0x7f) as usize;
+        if count == 0 || count > 4 {
+            return None;
+        }
+        let length = data
+            .get(2..2 + count)?
+            .iter()
+            .fold(0, |length, byte| length << 8 | *byte as usize);
+        (2 + count, length)
+    };
+    let end = header.checked_add(length)?;
+    let element = data.get(..end)?;
+    Some((element, &element[header..], &data[end..]))
+}
+
+fn encode_der(tag: u8, contents: &[u8]) -> Vec<u8> {
+    let mut encoded = vec![tag];
+    if contents.len() < 0x80 {
+        encoded.push(contents.len() as u8);
+    } else {
+        let length = contents.len().to_be_bytes();
+        let skip = length.iter().take_while(|byte| **byte == 0).count();
+        encoded.push(0x80 | (length.len() - skip) as u8);
+        encoded.extend_from_slice(&length[skip..]);
+    }
+    encoded.extend_from_slice(contents);
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::SystemClock;
+    use crate::proto::DeviceKind;
+    use openssl::bn::BigNumContext;
+    use openssl::ec::PointConversionForm;
+    use openssl::pkey::Private;
+    use openssl::x509::{X509ReqBuilder, X509};
+
+    #[test]
+    fn issued_certificate_verifies() {
+        let group_key =
+            EcKey::generate(&EcGroup::from_curve_name(Nid::SECP256K1).unwrap()).unwrap();
+        let task = SignCsrTask::try_new(
+            prepare_group(&group_key, ProtocolType::Gg18),
+            "Sample Task".into(),
+            prepare_csr(MessageDigest::sha256()).to_pem().unwrap(),
+            None,
+            Arc::new(SystemClock),
+        )
+        .unwrap();
+
+        let digest = task.get_data_digest().unwrap();
+        let signature = EcdsaSig::sign(&digest, &group_key).unwrap();
+        let mut raw_signature = signature.r().to_vec_padded(32).unwrap();
+        raw_signature.extend(signature.s().to_vec_padded(32).unwrap());
+        let certificate = assemble_certificate(&task.template, &raw_signature).unwrap();
+
+        let certificate = X509::from_der(&certificate).unwrap();
+        let group_public =
+            EcKey::from_public_key(group_key.group(), group_key.public_key()).unwrap();
+        assert!(certificate
+            .verify(&PKey::from_ec_key(group_public).unwrap())
+            .unwrap());
+        let subject = certificate
+            .subject_name()
+            .entries_by_nid(Nid::COMMONNAME)
+            .next()
+            .unwrap();
+        assert_eq!(subject.data().as_slice(), b"example.com");
+        assert!(assemble_certificate(&task.template, &raw_signature[..63]).is_err());
+    }
+
+    #[test]
+    fn invalid_requests() {
+        let group_key =
+            EcKey::generate(&EcGroup::from_curve_name(Nid::SECP256K1).unwrap()).unwrap();
+        let csr = prepare_csr(MessageDigest::sha256()).to_der().unwrap();
+        let try_new = |protocol, csr: &[u8]| {
+            SignCsrTask::try_new(
+                prepare_group(&group_key, protocol),
+                "Sample Task".into(),
+                csr.to_vec(),
+                None,
+                Arc::new(SystemClock),
+            )
+        };
+
+        assert!(try_new(ProtocolType::Gg18, &csr).is_ok());
+        assert_eq!(
+            try_new(ProtocolType::Gg18, &csr[..csr.len() - 1]).err(),
+            Some("Malformed CSR".into())
+        );
+        let sha1_csr = prepare_csr(MessageDigest::sha1()).to_der().unwrap();
+        assert_eq!(
+            try_new(ProtocolType::Gg18, &sha1_csr).err(),
+            Some("Unsupported CSR signature algorithm".into())
+        );
+        assert!(try_new(ProtocolType::Frost, &csr).is_err());
+    }
+
+    #[test]
+    fn der_lengths() {
+        for length in [0, 0x7f, 0x80, 0x1234] {
+            let encoded = encode_der(0x04, &vec![0xab; length]);
+            let (element, contents, rest) = split_der(&encoded).unwrap();
+            assert_eq!(element, encoded.as_slice());
+            assert_eq!(contents.len(), length);
+            assert!(rest.is_empty());
+        }
+        assert!(split_der(&[0x30, 0x05, 0x00]).is_none());
+    }
+
+    fn prepare_csr(digest: MessageDigest) -> X509Req {
+        let curve = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let key = PKey::from_ec_key(EcKey::generate(&curve).unwrap()).unwrap();
+        let mut subject = X509NameBuilder::new().unwrap();
+        subject
+            .append_entry_by_nid(Nid::COMMONNAME, "example.com")
+            .unwrap();
+        let mut builder = X509ReqBuilder::new().unwrap();
+        builder.set_subject_name(&subject.build()).unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder.sign(&key, digest).unwrap();
+        builder.build()
+    }
+
+    fn prepare_group(key: &EcKey<Private>, protocol: ProtocolType) -> Group {
+        let mut ctx = BigNumContext::new().unwrap();
+        let identifier = key
+            .public_key()
+            .to_bytes(key.group(), PointConversionForm::COMPRESSED, &mut ctx)
+            .unwrap();
+        let devices = (0..2)
+            .map(|i| {
+                Arc::new(Device::new(
+                    vec![i as u8],
+                    format!("d{}", i),
+                    DeviceKind::User,
+                    vec![0xf0 | i as u8],
+                    0,
+                ))
+            })
+            .collect();
+        Group::new(
+            identifier,
+            String::from("Sample Group"),
+            devices,
+            2,
+            protocol,
+            KeyType::SignChallenge,
+            None,
+            None,
+        )
+    }
+}
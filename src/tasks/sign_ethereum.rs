@@ -0,0 +1,576 @@
+use crate::clock::Clock;
+use crate::communicator::{CommunicatorStats, ParticipantStatus};
+use crate::device::Device;
+use crate::group::Group;
+use crate::logging;
+use crate::proto::{HashAlgorithm, KeyType, ProtocolType, TaskType};
+use crate::tasks::sign::SignTask;
+use crate::tasks::{FailureReason, Task, TaskResult, TaskStatus};
+use openssl::bn::{BigNum, BigNumContext, BigNumRef};
+use openssl::ec::{EcGroup, EcPoint};
+use openssl::error::ErrorStack;
+use openssl::nid::Nid;
+use sha3::{Digest, Keccak256};
+use tonic::codegen::Arc;
+use tracing::{info, warn};
+
+/// Maximal size of an unsigned transaction
+pub const MAX_TRANSACTION_SIZE: usize = 128 * 1024;
+
+/// Sign an RLP-encoded Ethereum transaction with a threshold ECDSA group
+///
+/// The result consists of the 32-byte `r` and `s` followed by `v` as a big-endian `u64`.
+pub struct SignEthereumTxTask {
+    sign_task: SignTask,
+    /// `v` of a signature with recovery id 0
+    v_offset: u64,
+    result: Option<Result<Vec<u8>, (FailureReason, String, u16)>>,
+}
+
+impl SignEthereumTxTask {
+    pub fn try_new(
+        group: Group,
+        name: String,
+        transaction: Vec<u8>,
+        chain_id: u64,
+        deadline_seconds: Option<u64>,
+        clock: Arc<dyn Clock>,
+    ) -> Result<Self, String> {
+        if transaction.len() > MAX_TRANSACTION_SIZE {
+            warn!(len = transaction.len(), "Transaction too large");
+            return Err("Transaction is too large".into());
+        }
+        if group.protocol() != ProtocolType::Gg18 || group.key_type() != KeyType::SignChallenge {
+            warn!(
+                protocol = ?group.protocol(),
+                key_type = ?group.key_type(),
+                "Group cannot sign Ethereum transactions"
+            );
+            return Err("Only GG18 challenge-signing groups can sign Ethereum transactions".into());
+        }
+
+        let (digest, v_offset) = signing_digest(&transaction, chain_id)?;
+        let mut sign_task = SignTask::try_new(
+            group,
+            name,
+            transaction,
+            deadline_seconds,
+            HashAlgorithm::Default,
+            clock,
+        )?;
+        sign_task.set_preprocessed(digest);
+
+        Ok(SignEthereumTxTask {
+            sign_task,
+            v_offset,
+            result: None,
+        })
+    }
+
+    /// Ask only as many members as signing needs to decide on the task
+    ///
+    /// See [`SignTask::recruit_signers`].
+    pub fn recruit_signers(&mut self, online: &dyn Fn(&[u8]) -> bool) {
+        self.sign_task.recruit_signers(online);
+    }
+
+    /// Attach `v` once the signing protocol has output a signature
+    fn complete_signature(&mut self) {
+        if self.result.is_some() {
+            return;
+        }
+        if let Some(TaskResult::Signed(signature)) = self.sign_task.get_result() {
+            let digest = self.sign_task.get_data_digest().unwrap();
+            let group_key = self.sign_task.get_group().identifier();
+            self.result = Some(
+                recoverable_signature(&signature, &digest, group_key)
+                    .map(|(mut signature, recovery_id)| {
+                        info!(
+                            group_id = %logging::log_id(group_key),
+                            "Ethereum transaction signed"
+                        );
+                        let v = self.v_offset + u64::from(recovery_id);
+                        signature.extend_from_slice(&v.to_be_bytes());
+                        signature
+                    })
+                    .map_err(|message| {
+                        warn!(message, "Could not recover signature parity");
+                        (
+                            FailureReason::VerificationFailed,
+                            "Task failed (invalid signature)".to_string(),
+                            self.sign_task.protocol.last_round(),
+                        )
+                    }),
+            );
+        }
+    }
+}
+
+impl Task for SignEthereumTxTask {
+    fn get_status(&self) -> TaskStatus {
+        match &self.result {
+            Some(Err((reason, message, _))) => TaskStatus::Failed(*reason, message.clone()),
+            _ => self.sign_task.get_status(),
+        }
+    }
+
+    fn failed_round(&self) -> Option<u16> {
+        match &self.result {
+            Some(Err((_, _, round))) => Some(*round),
+            _ => self.sign_task.failed_round(),
+        }
+    }
+
+    fn get_type(&self) -> TaskType {
+        TaskType::SignEthereumTx
+    }
+
+    fn get_protocol(&self) -> ProtocolType {
+        self.sign_task.get_protocol()
+    }
+
+    fn get_work(&self, device_id: Option<&[u8]>) -> Vec<Vec<u8>> {
+        self.sign_task.get_work(device_id)
+    }
+
+    fn get_result(&self) -> Option<TaskResult> {
+        if let Some(Ok(signature)) = &self.result {
+            Some(TaskResult::SignedEthereumTx(signature.clone()))
+        } else {
+            None
+        }
+    }
+
+    fn get_data_digest(&self) -> Option<Vec<u8>> {
+        self.sign_task.get_data_digest()
+    }
+
+    fn get_decisions(&self) -> (u32, u32) {
+        self.sign_task.get_decisions()
+    }
+
+    fn update(&mut self, device_id: &[u8], data: &Vec<Vec<u8>>) -> Result<bool, String> {
+        let result = self.sign_task.update(device_id, data);
+        if let Ok(true) = result {
+            self.complete_signature();
+        }
+        result
+    }
+
+    fn restart(&mut self) -> Result<bool, String> {
+        self.sign_task.restart()
+    }
+
+    fn fail(&mut self, reason: FailureReason, message: &str) -> bool {
+        self.result.is_none() && self.sign_task.fail(reason, message)
+    }
+
+    fn reselect(&mut self, device_id: &[u8]) -> Result<bool, String> {
+        self.sign_task.reselect(device_id)
+    }
+
+    fn last_update(&self) -> u64 {
+        self.sign_task.last_update()
+    }
+
+    fn created_at(&self) -> u64 {
+        self.sign_task.created_at()
+    }
+
+    fn get_group_id(&self) -> Option<&[u8]> {
+        self.sign_task.get_group_id()
+    }
+
+    fn requester(&self) -> Option<&str> {
+        self.sign_task.requester()
+    }
+
+    fn set_requester(&mut self, requester: String) {
+        self.sign_task.set_requester(requester)
+    }
+
+    fn priority(&self) -> u32 {
+        self.sign_task.priority()
+    }
+
+    fn set_priority(&mut self, priority: u32) {
+        self.sign_task.set_priority(priority)
+    }
+
+    fn deadline(&self) -> Option<u64> {
+        self.sign_task.deadline()
+    }
+
+    fn is_approved(&self) -> bool {
+        self.sign_task.is_approved()
+    }
+
+    fn has_device(&self, device_id: &[u8]) -> bool {
+        self.sign_task.has_device(device_id)
+    }
+
+    fn get_devices(&self) -> Vec<Arc<Device>> {
+        self.sign_task.get_devices()
+    }
+
+    fn waiting_for(&self, device: &[u8]) -> bool {
+        self.sign_task.waiting_for(device)
+    }
+
+    fn decide(&mut self, device_id: &[u8], decision: bool) -> Option<bool> {
+        self.sign_task.decide(device_id, decision)
+    }
+
+    fn recruit(&mut self, online: &dyn Fn(&[u8]) -> bool) -> Option<bool> {
+        self.sign_task.recruit(online)
+    }
+
+    fn withdraw(&mut self, device_id: &[u8]) -> bool {
+        self.sign_task.withdraw(device_id)
+    }
+
+    fn acknowledge(&mut self, device_id: &[u8]) {
+        self.sign_task.acknowledge(device_id);
+    }
+
+    fn device_acknowledged(&self, device_id: &[u8]) -> bool {
+        self.sign_task.device_acknowledged(device_id)
+    }
+
+    fn get_request(&self) -> &[u8] {
+        self.sign_task.get_request()
+    }
+
+    fn get_attempts(&self) -> u32 {
+        self.sign_task.get_attempts()
+    }
+
+    fn relay_stats(&self) -> CommunicatorStats {
+        self.sign_task.relay_stats()
+    }
+
+    fn participant_status(&self, device_id: &[u8]) -> ParticipantStatus {
+        self.sign_task.participant_status(device_id)
+    }
+}
+
+/// Compute the digest to be signed for an unsigned `transaction`
+///
+/// Legacy transactions are hashed with the EIP-155 chain id fields appended, typed
+/// transactions (EIP-2718) are hashed as they are and must declare `chain_id` themselves.
+///
+/// # Returns
+/// The digest and the value of `v` for recovery id 0
+fn signing_digest(transaction: &[u8], chain_id: u64) -> Result<(Vec<u8>, u64), String> {
+    if chain_id == 0 {
+        return Err("Chain id must be positive".into());
+    }
+    match transaction.first() {
+        Some(0xc0..=0xff) => {
+            let fields = split_rlp_list(transaction)?;
+            let mut payload = fields.concat();
+            match fields.len() {
+                6 => {
+                    payload.extend(encode_rlp(false, &encode_integer(chain_id)));
+                    payload.extend([0x80, 0x80]);
+                }
+                9 if fields[6] == encode_rlp(false, &encode_integer(chain_id))
+                    && fields[7..] == [[0x80], [0x80]] => {}
+                9 => return Err("Transaction is not prepared for the requested chain".into()),
+                _ => return Err("Malformed legacy transaction".into()),
+            }
+            let offset = chain_id
+                .checked_mul(2)
+                .and_then(|v| v.checked_add(35))
+                .ok_or("Chain id is too large")?;
+            Ok((
+                Keccak256::digest(encode_rlp(true, &payload)).to_vec(),
+                offset,
+            ))
+        }
+        Some(0x01 | 0x02) => {
+            let fields = split_rlp_list(&transaction[1..])?;
+            let declared = fields.first().ok_or("Malformed typed transaction")?;
+            if *declared != encode_rlp(false, &encode_integer(chain_id)) {
+                return Err("Transaction is not prepared for the requested chain".into());
+            }
+            Ok((Keccak256::digest(transaction).to_vec(), 0))
+        }
+        _ => Err("Unsupported transaction type".into()),
+    }
+}
+
+/// Split an RLP list spanning all of `data` into its encoded items
+fn split_rlp_list(data: &[u8]) -> Result<Vec<&[u8]>, String> {
+    let (true, mut payload, []) = split_rlp(data).ok_or("Malformed transaction")? else {
+        return Err("Malformed transaction".into());
+    };
+    let mut items = Vec::new();
+    while !payload.is_empty() {
+        let (_, _, rest) = split_rlp(payload).ok_or("Malformed transaction")?;
+        items.push(&payload[..payload.len() - rest.len()]);
+        payload = rest;
+    }
+    Ok(items)
+}
+
+/// Split the first RLP item off `data`
+///
+/// # Returns
+/// Whether the item is a list, its payload and the data following the item
+fn split_rlp(data: &[u8]) -> Option<(bool, &[u8], &[u8])> {
+    let prefix = *data.first()?;
+    let (is_list, header, length) = match prefix {
+        0x00..=0x7f => return Some((false, &data[..1], &data[1..])),
+        0x80..=0xb7 => (false, 1, (prefix - 0x80) as usize),
+        0xc0..=0xf7 => (true, 1, (prefix - 0xc0) as usize),
+        _ => {
+            let is_list = prefix >= 0xf8;
+            let count = (prefix - if is_list { 0xf7 } else { 0xb7 }) as usize;
+            if count > 4 {
+                return None;
+            }
+            let length = data
+                .get(1..1 + count)?
+                .iter()
+                .fold(0, |length, byte| length << 8 | *byte as usize);
+            (is_list, 1 + count, length)
+        }
+    };
+    let end = header.checked_add(length)?;
+    let item = data.get(..end)?;
+    Some((is_list, &item[header..], &data[end..]))
+}
+
+fn encode_rlp(is_list: bool, payload: &[u8]) -> Vec<u8> {
+    let offset = if is_list { 0xc0 } else { 0x80 };
+    let mut encoded = Vec::new();
+    if !is_list && payload.len() == 1 && payload[0] < 0x80 {
+        // a single byte below 0x80 is its own encoding
+    } else if payload.len() <= 55 {
+        encoded.push(offset + payload.len() as u8);
+    } else {
+        let length = encode_integer(payload.len() as u64);
+        encoded.push(offset + 55 + length.len() as u8);
+        encoded.extend(length);
+    }
+    encoded.extend_from_slice(payload);
+    encoded
+}
+
+/// Big-endian encoding of `value` without leading zeros
+fn encode_integer(value: u64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    let skip = bytes.iter().take_while(|byte| **byte == 0).count();
+    bytes[skip..].to_vec()
+}
+
+/// Normalize the 64-byte ECDSA `signature` of `digest` to a low `s` and find its recovery id
+///
+/// # Returns
+/// The normalized signature and the parity of the signature point's y-coordinate
+fn recoverable_signature(
+    signature: &[u8],
+    digest: &[u8],
+    public_key: &[u8],
+) -> Result<(Vec<u8>, u8), String> {
+    if signature.len() != 64 {
+        return Err(format!("Unexpected signature length {}", signature.len()));
+    }
+    let recover = || -> Result<Option<(Vec<u8>, u8)>, ErrorStack> {
+        let curve = EcGroup::from_curve_name(Nid::SECP256K1)?;
+        let mut ctx = BigNumContext::new()?;
+        let mut order = BigNum::new()?;
+        curve.order(&mut order, &mut ctx)?;
+        let public_key = match EcPoint::from_bytes(&curve, public_key, &mut ctx) {
+            Ok(point) => point,
+            Err(_) => return Ok(None),
+        };
+
+        let r = BigNum::from_slice(&signature[..32])?;
+        let mut s = BigNum::from_slice(&signature[32..])?;
+        let mut half_order = BigNum::new()?;
+        half_order.rshift1(&order)?;
+        if s > half_order {
+            let mut low_s = BigNum::new()?;
+            low_s.checked_sub(&order, &s)?;
+            s = low_s;
+        }
+        let e = BigNum::from_slice(digest)?;
+
+        for parity in 0..2u8 {
+            let Some(candidate) = recover_key(&curve, &order, &r, &s, &e, parity, &mut ctx)? else {
+                continue;
+            };
+            if candidate.eq(&curve, &public_key, &mut ctx)? {
+                let mut normalized = r.to_vec_padded(32)?;
+                normalized.extend(s.to_vec_padded(32)?);
+                return Ok(Some((normalized, parity)));
+            }
+        }
+        Ok(None)
+    };
+    recover()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "Signature does not match the group key".into())
+}
+
+/// Recover the public key of a signature whose point has the y-coordinate `parity`
+///
+/// Computes `r^-1 (s R - e G)`.
+fn recover_key(
+    curve: &EcGroup,
+    order: &BigNumRef,
+    r: &BigNumRef,
+    s: &BigNumRef,
+    e: &BigNumRef,
+    parity: u8,
+    ctx: &mut BigNumContext,
+) -> Result<Option<EcPoint>, ErrorStack> {
+    let mut encoded = vec![0x02 | parity];
+    encoded.extend(r.to_vec_padded(32)?);
+    let Ok(point) = EcPoint::from_bytes(curve, &encoded, ctx) else {
+        return Ok(None);
+    };
+
+    let mut r_inverse = BigNum::new()?;
+    r_inverse.mod_inverse(r, order, ctx)?;
+    let mut e_reduced = BigNum::new()?;
+    e_reduced.nnmod(e, order, ctx)?;
+    let mut neg_e = BigNum::new()?;
+    neg_e.checked_sub(order, &e_reduced)?;
+    let mut generator_scalar = BigNum::new()?;
+    generator_scalar.mod_mul(&neg_e, &r_inverse, order, ctx)?;
+    let mut point_scalar = BigNum::new()?;
+    point_scalar.mod_mul(s, &r_inverse, order, ctx)?;
+
+    let mut key = EcPoint::new(curve)?;
+    key.mul_full(curve, &generator_scalar, &point, &point_scalar, ctx)?;
+    Ok(Some(key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::SystemClock;
+    use crate::proto::DeviceKind;
+    use openssl::ec::{EcKey, PointConversionForm};
+
+    /// Unsigned transaction from the EIP-155 example, prepared for chain id 1
+    const EIP155_TRANSACTION: &str =
+        "ec098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a764000080018080";
+    const EIP155_DIGEST: &str = "daf5a779ae972f972197303d7b574746c7ef83eadac0f2791ad23db92e4c8e53";
+    const EIP155_R: &str = "28ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276";
+    const EIP155_S: &str = "67cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83";
+
+    #[test]
+    fn eip155_signature() {
+        let transaction = hex::decode(EIP155_TRANSACTION).unwrap();
+        let (digest, v_offset) = signing_digest(&transaction, 1).unwrap();
+        assert_eq!(hex::encode(&digest), EIP155_DIGEST);
+        assert_eq!(v_offset, 37);
+
+        let legacy = split_rlp_list(&transaction).unwrap()[..6].concat();
+        let legacy = encode_rlp(true, &legacy);
+        assert_eq!(signing_digest(&legacy, 1).unwrap(), (digest.clone(), 37));
+        assert!(signing_digest(&transaction, 3).is_err());
+
+        let signature = hex::decode(format!("{}{}", EIP155_R, EIP155_S)).unwrap();
+        let public_key = signer_key(&[0x46; 32]);
+        let (normalized, recovery_id) =
+            recoverable_signature(&signature, &digest, &public_key).unwrap();
+        assert_eq!(normalized, signature);
+        assert_eq!(v_offset + u64::from(recovery_id), 37);
+        assert!(recoverable_signature(&signature, &digest, &signer_key(&[0x47; 32])).is_err());
+    }
+
+    #[test]
+    fn high_s_normalized() {
+        let curve = EcGroup::from_curve_name(Nid::SECP256K1).unwrap();
+        let mut ctx = BigNumContext::new().unwrap();
+        let mut order = BigNum::new().unwrap();
+        curve.order(&mut order, &mut ctx).unwrap();
+        let digest = hex::decode(EIP155_DIGEST).unwrap();
+        let s = BigNum::from_slice(&hex::decode(EIP155_S).unwrap()).unwrap();
+        let mut high_s = BigNum::new().unwrap();
+        high_s.checked_sub(&order, &s).unwrap();
+        let mut signature = hex::decode(EIP155_R).unwrap();
+        signature.extend(high_s.to_vec_padded(32).unwrap());
+
+        let (normalized, recovery_id) =
+            recoverable_signature(&signature, &digest, &signer_key(&[0x46; 32])).unwrap();
+        assert_eq!(hex::encode(&normalized[32..]), EIP155_S);
+        assert_eq!(recovery_id, 0);
+    }
+
+    #[test]
+    fn typed_transactions() {
+        let mut fields = encode_rlp(false, &encode_integer(5));
+        fields.extend(encode_rlp(false, &[0x01; 60]));
+        let mut transaction = vec![0x02];
+        transaction.extend(encode_rlp(true, &fields));
+
+        let (digest, v_offset) = signing_digest(&transaction, 5).unwrap();
+        assert_eq!(digest, Keccak256::digest(&transaction).to_vec());
+        assert_eq!(v_offset, 0);
+        assert!(signing_digest(&transaction, 1).is_err());
+        assert!(signing_digest(&transaction[..transaction.len() - 1], 5).is_err());
+        assert!(signing_digest(&[0x05, 0xc0], 5).is_err());
+    }
+
+    #[test]
+    fn group_requirements() {
+        let transaction = hex::decode(EIP155_TRANSACTION).unwrap();
+        let public_key = signer_key(&[0x46; 32]);
+        for (protocol, accepted) in [(ProtocolType::Gg18, true), (ProtocolType::Frost, false)] {
+            let task = SignEthereumTxTask::try_new(
+                prepare_group(&public_key, protocol),
+                "Sample Task".into(),
+                transaction.clone(),
+                1,
+                None,
+                Arc::new(SystemClock),
+            );
+            assert_eq!(task.is_ok(), accepted);
+        }
+    }
+
+    fn signer_key(private_key: &[u8]) -> Vec<u8> {
+        let curve = EcGroup::from_curve_name(Nid::SECP256K1).unwrap();
+        let mut ctx = BigNumContext::new().unwrap();
+        let private_key = BigNum::from_slice(private_key).unwrap();
+        let mut public_key = EcPoint::new(&curve).unwrap();
+        public_key
+            .mul_generator2(&curve, &private_key, &mut ctx)
+            .unwrap();
+        EcKey::from_public_key(&curve, &public_key)
+            .unwrap()
+            .public_key()
+            .to_bytes(&curve, PointConversionForm::COMPRESSED, &mut ctx)
+            .unwrap()
+    }
+
+    fn prepare_group(identifier: &[u8], protocol: ProtocolType) -> Group {
+        let devices = (0..2)
+            .map(|i| {
+                Arc::new(Device::new(
+                    vec![i as u8],
+                    format!("d{}", i),
+                    DeviceKind::User,
+                    vec![0xf0 | i as u8],
+                    0,
+                ))
+            })
+            .collect();
+        Group::new(
+            identifier.to_vec(),
+            String::from("Sample Group"),
+            devices,
+            2,
+            protocol,
+            KeyType::SignChallenge,
+            None,
+            None,
+        )
+    }
+}
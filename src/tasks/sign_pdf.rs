@@ -1,30 +1,47 @@
+use crate::clock::Clock;
+use crate::communicator::{CommunicatorStats, ParticipantStatus};
 use crate::device::Device;
-use crate::get_timestamp;
 use crate::group::Group;
-use crate::proto::TaskType;
+use crate::logging;
+use crate::proto::{HashAlgorithm, ProtocolType, TaskType};
 use crate::tasks::sign::SignTask;
-use crate::tasks::{Task, TaskResult, TaskStatus};
-use log::{error, info, warn};
+use crate::tasks::{FailureReason, Task, TaskResult, TaskStatus};
 use std::io::{Read, Write};
 use std::process::{Child, Command, Stdio};
 use tempfile::NamedTempFile;
 use tonic::codegen::Arc;
+use tracing::{error, info, warn};
+
+/// Maximal size of a document to be signed
+pub const MAX_PDF_SIZE: usize = 8 * 1024 * 1024;
 
 pub struct SignPDFTask {
     sign_task: SignTask,
-    result: Option<Result<Vec<u8>, String>>,
+    result: Option<Result<Vec<u8>, (FailureReason, String, u16)>>,
     pdfhelper: Option<Child>,
 }
 
 impl SignPDFTask {
-    pub fn try_new(group: Group, name: String, data: Vec<u8>) -> Result<Self, String> {
-        if data.len() > 8 * 1024 * 1024 || name.len() > 256 || name.chars().any(|x| x.is_control())
-        {
-            warn!("Invalid input name={} len={}", name, data.len());
+    pub fn try_new(
+        group: Group,
+        name: String,
+        data: Vec<u8>,
+        deadline_seconds: Option<u64>,
+        clock: Arc<dyn Clock>,
+    ) -> Result<Self, String> {
+        if data.len() > MAX_PDF_SIZE || name.len() > 256 || name.chars().any(|x| x.is_control()) {
+            warn!(name, len = data.len(), "Invalid input");
             return Err("Invalid input".to_string());
         }
 
-        let sign_task = SignTask::try_new(group, name, data)?;
+        let sign_task = SignTask::try_new(
+            group,
+            name,
+            data,
+            deadline_seconds,
+            HashAlgorithm::Default,
+            clock,
+        )?;
 
         Ok(SignPDFTask {
             sign_task,
@@ -33,17 +50,32 @@ impl SignPDFTask {
         })
     }
 
+    /// Ask only as many members as signing needs to decide on the task
+    ///
+    /// See [`SignTask::recruit_signers`].
+    pub fn recruit_signers(&mut self, online: &dyn Fn(&[u8]) -> bool) {
+        self.sign_task.recruit_signers(online);
+    }
+
     fn start_task(&mut self) {
         let file = NamedTempFile::new();
         if file.is_err() {
             error!("Could not create temporary file");
-            self.result = Some(Err("Task failed (server error)".to_string()));
+            self.result = Some(Err((
+                FailureReason::ProtocolError,
+                "Task failed (server error)".to_string(),
+                self.sign_task.protocol.round(),
+            )));
             return;
         }
         let mut file = file.unwrap();
         if file.write_all(&self.sign_task.data).is_err() {
             error!("Could not write in temporary file");
-            self.result = Some(Err("Task failed (server error)".to_string()));
+            self.result = Some(Err((
+                FailureReason::ProtocolError,
+                "Task failed (server error)".to_string(),
+                self.sign_task.protocol.round(),
+            )));
             return;
         }
 
@@ -58,7 +90,11 @@ impl SignPDFTask {
 
         if pdfhelper.is_err() {
             error!("Could not start PDFHelper");
-            self.result = Some(Err("Task failed (server error)".to_string()));
+            self.result = Some(Err((
+                FailureReason::ProtocolError,
+                "Task failed (server error)".to_string(),
+                self.sign_task.protocol.round(),
+            )));
             return;
         }
         let mut pdfhelper = pdfhelper.unwrap();
@@ -68,7 +104,11 @@ impl SignPDFTask {
             self.sign_task.get_group().certificate().unwrap(),
         );
         if hash.is_empty() {
-            self.result = Some(Err("Task failed (invalid PDF)".to_string()));
+            self.result = Some(Err((
+                FailureReason::VerificationFailed,
+                "Task failed (invalid PDF)".to_string(),
+                self.sign_task.protocol.round(),
+            )));
             return;
         }
         self.pdfhelper = Some(pdfhelper);
@@ -82,17 +122,31 @@ impl SignPDFTask {
 
     fn finalize_task(&mut self) {
         self.sign_task.finalize_task();
-        if let Some(TaskResult::Signed(signature)) = self.sign_task.get_result() {
-            let signed = include_signature(self.pdfhelper.as_mut().unwrap(), &signature);
-            self.pdfhelper = None;
-
+        let Some(TaskResult::Signed(signature)) = self.sign_task.get_result() else {
+            self.result = Some(Err((
+                FailureReason::ProtocolError,
+                "Task failed (protocol produced no result)".to_string(),
+                self.sign_task.protocol.last_round(),
+            )));
+            return;
+        };
+        let signed = self
+            .pdfhelper
+            .take()
+            .and_then(|mut pdfhelper| include_signature(&mut pdfhelper, &signature));
+        if let Some(signed) = signed {
             info!(
-                "PDF signed by group_id={}",
-                hex::encode(self.sign_task.get_group().identifier())
+                group_id = %logging::log_id(self.sign_task.get_group().identifier()),
+                "PDF signed"
             );
             self.result = Some(Ok(signed));
         } else {
-            self.result = Some(Err("Task failed (signature not output)".to_string()));
+            error!("Could not include signature in PDF");
+            self.result = Some(Err((
+                FailureReason::ProtocolError,
+                "Task failed (server error)".to_string(),
+                self.sign_task.protocol.last_round(),
+            )));
         }
     }
 
@@ -109,13 +163,27 @@ impl SignPDFTask {
 
 impl Task for SignPDFTask {
     fn get_status(&self) -> TaskStatus {
-        self.sign_task.get_status()
+        match &self.result {
+            Some(Err((reason, message, _))) => TaskStatus::Failed(*reason, message.clone()),
+            _ => self.sign_task.get_status(),
+        }
+    }
+
+    fn failed_round(&self) -> Option<u16> {
+        match &self.result {
+            Some(Err((_, _, round))) => Some(*round),
+            _ => self.sign_task.failed_round(),
+        }
     }
 
     fn get_type(&self) -> TaskType {
         TaskType::SignPdf
     }
 
+    fn get_protocol(&self) -> ProtocolType {
+        self.sign_task.get_protocol()
+    }
+
     fn get_work(&self, device_id: Option<&[u8]>) -> Vec<Vec<u8>> {
         self.sign_task.get_work(device_id)
     }
@@ -128,6 +196,10 @@ impl Task for SignPDFTask {
         }
     }
 
+    fn get_data_digest(&self) -> Option<Vec<u8>> {
+        self.sign_task.get_data_digest()
+    }
+
     fn get_decisions(&self) -> (u32, u32) {
         self.sign_task.get_decisions()
     }
@@ -141,7 +213,7 @@ impl Task for SignPDFTask {
     }
 
     fn restart(&mut self) -> Result<bool, String> {
-        self.sign_task.last_update = get_timestamp();
+        self.sign_task.last_update = self.sign_task.clock.now();
         if self.result.is_some() {
             return Ok(false);
         }
@@ -159,10 +231,67 @@ impl Task for SignPDFTask {
         }
     }
 
+    fn fail(&mut self, reason: FailureReason, message: &str) -> bool {
+        if self.result.is_some() || !self.sign_task.fail(reason, message) {
+            return false;
+        }
+        if let Some(mut pdfhelper) = self.pdfhelper.take() {
+            if pdfhelper.kill().is_err() {
+                warn!("Could not stop PDFHelper");
+            }
+        }
+        self.result = Some(Err((
+            reason,
+            message.to_string(),
+            self.sign_task.protocol.round(),
+        )));
+        true
+    }
+
+    fn reselect(&mut self, device_id: &[u8]) -> Result<bool, String> {
+        if self.result.is_some() || !self.sign_task.exclude_participant(device_id) {
+            return Ok(false);
+        }
+        if let Some(pdfhelper) = self.pdfhelper.as_mut() {
+            pdfhelper.kill().unwrap();
+            self.pdfhelper = None;
+        }
+        self.start_task();
+        Ok(true)
+    }
+
     fn last_update(&self) -> u64 {
         self.sign_task.last_update()
     }
 
+    fn created_at(&self) -> u64 {
+        self.sign_task.created_at()
+    }
+
+    fn get_group_id(&self) -> Option<&[u8]> {
+        self.sign_task.get_group_id()
+    }
+
+    fn requester(&self) -> Option<&str> {
+        self.sign_task.requester()
+    }
+
+    fn set_requester(&mut self, requester: String) {
+        self.sign_task.set_requester(requester)
+    }
+
+    fn priority(&self) -> u32 {
+        self.sign_task.priority()
+    }
+
+    fn set_priority(&mut self, priority: u32) {
+        self.sign_task.set_priority(priority)
+    }
+
+    fn deadline(&self) -> Option<u64> {
+        self.sign_task.deadline()
+    }
+
     fn is_approved(&self) -> bool {
         self.sign_task.is_approved()
     }
@@ -187,6 +316,14 @@ impl Task for SignPDFTask {
         result
     }
 
+    fn recruit(&mut self, online: &dyn Fn(&[u8]) -> bool) -> Option<bool> {
+        self.sign_task.recruit(online)
+    }
+
+    fn withdraw(&mut self, device_id: &[u8]) -> bool {
+        self.sign_task.withdraw(device_id)
+    }
+
     fn acknowledge(&mut self, device_id: &[u8]) {
         self.sign_task.acknowledge(device_id);
     }
@@ -202,6 +339,14 @@ impl Task for SignPDFTask {
     fn get_attempts(&self) -> u32 {
         self.sign_task.get_attempts()
     }
+
+    fn relay_stats(&self) -> CommunicatorStats {
+        self.sign_task.relay_stats()
+    }
+
+    fn participant_status(&self, device_id: &[u8]) -> ParticipantStatus {
+        self.sign_task.participant_status(device_id)
+    }
 }
 
 fn request_hash(process: &mut Child, certificate: &[u8]) -> Vec<u8> {
@@ -219,17 +364,20 @@ fn request_hash(process: &mut Child, certificate: &[u8]) -> Vec<u8> {
     }
 }
 
-fn include_signature(process: &mut Child, signature: &[u8]) -> Vec<u8> {
-    let process_stdin = process.stdin.as_mut().unwrap();
-    let process_stdout = process.stdout.as_mut().unwrap();
-
+fn include_signature(process: &mut Child, signature: &[u8]) -> Option<Vec<u8>> {
+    if signature.len() != 64 {
+        return None;
+    }
+    let process_stdin = process.stdin.as_mut()?;
     let mut out_buffer = [0u8; 129];
     out_buffer[..128].copy_from_slice(hex::encode(signature).as_bytes());
     out_buffer[128] = b'\n';
+    process_stdin.write_all(&out_buffer).ok()?;
 
-    process_stdin.write_all(&out_buffer).unwrap();
-
+    let process_stdout = process.stdout.as_mut()?;
     let mut result = Vec::new();
-    process_stdout.read_to_end(&mut result).unwrap();
-    hex::decode(&result).unwrap()
+    process_stdout.read_to_end(&mut result).ok()?;
+    hex::decode(&result)
+        .ok()
+        .filter(|signed| !signed.is_empty())
 }
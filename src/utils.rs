@@ -1,12 +1,94 @@
-pub fn hextrunc<T: AsRef<[u8]>>(s: T) -> String {
-    let trunc_len = std::env::var("TRUNC")
-        .ok()
-        .as_ref()
-        .and_then(|x| x.parse().ok())
-        .unwrap_or(0);
-    if trunc_len == 0 {
-        hex::encode(s.as_ref())
-    } else {
-        format!("{}...", hex::encode(&s.as_ref()[..trunc_len]))
+use crate::config::NamePolicy;
+use unicode_normalization::UnicodeNormalization;
+
+/// Default limit of device and group names, in Unicode scalar values
+pub const DEFAULT_MAX_NAME_LENGTH: usize = 64;
+
+/// Bring a device or group `name` to Unicode normalization form C
+///
+/// Names are normalized before validation and storage, so that canonically equal names compare equal.
+pub fn normalize_name(name: &str) -> String {
+    name.nfc().collect()
+}
+
+/// Check that a device or group `name` has at most `max_length` characters allowed by `policy`
+pub fn validate_name(name: &str, policy: NamePolicy, max_length: usize) -> bool {
+    name.chars().count() <= max_length
+        && name.chars().all(|x| {
+            !x.is_control()
+                && match policy {
+                    NamePolicy::Strict => !x.is_ascii_punctuation(),
+                    NamePolicy::Extended => {
+                        x.is_alphanumeric() || matches!(x, ' ' | '-' | '\'' | '\u{2019}')
+                    }
+                }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strict_names() {
+        assert!(validate_name(
+            "Sample Device",
+            NamePolicy::Strict,
+            DEFAULT_MAX_NAME_LENGTH
+        ));
+        assert!(!validate_name(
+            "laptop-01",
+            NamePolicy::Strict,
+            DEFAULT_MAX_NAME_LENGTH
+        ));
+        assert!(!validate_name(
+            "Alice's iPhone",
+            NamePolicy::Strict,
+            DEFAULT_MAX_NAME_LENGTH
+        ));
+        assert!(!validate_name(
+            &"a".repeat(65),
+            NamePolicy::Strict,
+            DEFAULT_MAX_NAME_LENGTH
+        ));
+    }
+
+    #[test]
+    fn extended_names() {
+        assert!(validate_name(
+            "laptop-01",
+            NamePolicy::Extended,
+            DEFAULT_MAX_NAME_LENGTH
+        ));
+        assert!(validate_name(
+            "Alice's iPhone",
+            NamePolicy::Extended,
+            DEFAULT_MAX_NAME_LENGTH
+        ));
+        assert!(validate_name(
+            "Příliš žluťoučký kůň",
+            NamePolicy::Extended,
+            DEFAULT_MAX_NAME_LENGTH
+        ));
+        assert!(!validate_name(
+            "laptop\n01",
+            NamePolicy::Extended,
+            DEFAULT_MAX_NAME_LENGTH
+        ));
+        assert!(!validate_name(
+            "laptop\u{7}",
+            NamePolicy::Extended,
+            DEFAULT_MAX_NAME_LENGTH
+        ));
+        assert!(!validate_name(
+            "laptop;01",
+            NamePolicy::Extended,
+            DEFAULT_MAX_NAME_LENGTH
+        ));
+        assert!(!validate_name(
+            &"a".repeat(65),
+            NamePolicy::Extended,
+            DEFAULT_MAX_NAME_LENGTH
+        ));
     }
 }